@@ -0,0 +1,342 @@
+//! Solving acrostics.
+//!
+//! An acrostic hides a quotation whose letters are shared out between the
+//! answers to a set of clues. Each letter of each answer is numbered with its
+//! position in the quote, and the first letters of the answers spell out
+//! something else, usually the author and source of the quote. Given the
+//! numbering, the first letters and the letters of the quote, we look for
+//! dictionary words which use up exactly those letters, and then check that
+//! the resulting quote can be broken into words.
+
+use fst::Automaton;
+use log::{debug, trace};
+use std::cmp::Reverse;
+
+use crate::dictionary::{Dictionary, SearchOptions};
+use crate::error::{Error, Result};
+use crate::probability::Prob;
+use crate::segment::{best_breaking, SegmentOptions};
+
+/// An acrostic puzzle.
+#[derive(Clone, Debug)]
+pub struct Acrostic {
+    /// The position in the quote of each letter of each answer.
+    answers: Vec<Vec<usize>>,
+    /// The first letter of each answer.
+    initials: Vec<u8>,
+    /// How many of each byte are in the quote.
+    letters: [u16; 256],
+    /// The number of letters in the quote.
+    quote_len: usize,
+}
+
+impl Acrostic {
+    /// Create a puzzle from `numbering`, which has one line per answer
+    /// containing the 1-based quote position of each letter of that answer,
+    /// `initials`, which contains the first letter of each answer, and
+    /// `letters`, which contains the letters of the quote in any order.
+    /// Whitespace and punctuation in `initials` and `letters` are ignored.
+    pub fn new(numbering: &str, initials: &str, letters: &str) -> Result<Acrostic> {
+        let mut answers = vec![];
+        for line in numbering.lines().filter(|l| !l.trim().is_empty()) {
+            let answer = line
+                .split_whitespace()
+                .map(|n| match n.parse::<usize>() {
+                    Ok(n) if n > 0 => Ok(n - 1),
+                    _ => Err(Error::InvalidPuzzle(format!(
+                        "expected a quote position, found {:?}",
+                        n
+                    ))),
+                })
+                .collect::<Result<Vec<_>>>()?;
+            answers.push(answer);
+        }
+
+        // Every position in the quote should be used by exactly one answer.
+        let quote_len = answers.iter().map(Vec::len).sum::<usize>();
+        let mut used = vec![false; quote_len];
+        for &pos in answers.iter().flatten() {
+            match used.get_mut(pos) {
+                Some(used) if !*used => *used = true,
+                Some(_) => {
+                    return Err(Error::InvalidPuzzle(format!(
+                        "quote position {} is used more than once",
+                        pos + 1
+                    )))
+                }
+                None => {
+                    return Err(Error::InvalidPuzzle(format!(
+                        "quote position {} is past the end of the {}-letter quote",
+                        pos + 1,
+                        quote_len,
+                    )))
+                }
+            }
+        }
+
+        let initials = letters_of(initials)?;
+        if initials.len() != answers.len() {
+            return Err(Error::InvalidPuzzle(format!(
+                "there are {} answers, but {} initials",
+                answers.len(),
+                initials.len(),
+            )));
+        }
+        let mut letter_counts = [0; 256];
+        let letters = letters_of(letters)?;
+        if letters.len() != quote_len {
+            return Err(Error::InvalidPuzzle(format!(
+                "the answers have {} letters, but the quote has {}",
+                quote_len,
+                letters.len(),
+            )));
+        }
+        for b in letters {
+            letter_counts[b as usize] += 1;
+        }
+        Ok(Acrostic {
+            answers,
+            initials,
+            letters: letter_counts,
+            quote_len,
+        })
+    }
+
+    /// Find up to `opts.limit` ways to fill in the answers and the quote,
+    /// most probable first. We find answers by backtracking, trying more
+    /// probable words first, and then keep the ones whose quote can be broken
+    /// into words.
+    pub fn solve(
+        &self,
+        dict: &Dictionary,
+        opts: &AcrosticOptions,
+    ) -> Result<Vec<Solution>> {
+        let mut solver = Solver {
+            puzzle: self,
+            dict,
+            opts,
+            letters: self.letters,
+            assigned: vec![None; self.answers.len()],
+            solutions: vec![],
+        };
+        solver.search()?;
+        let mut solutions = solver.solutions;
+        solutions.sort_by_key(|s| Reverse(s.prob));
+        Ok(solutions)
+    }
+}
+
+/// The lowercase ASCII letters in `text`.
+fn letters_of(text: &str) -> Result<Vec<u8>> {
+    let mut letters = vec![];
+    for c in text.chars() {
+        if c.is_ascii_alphabetic() {
+            letters.push(c.to_ascii_lowercase() as u8);
+        } else if c.is_alphabetic() {
+            return Err(Error::InvalidPuzzle(format!(
+                "{:?} is not an ASCII letter",
+                c
+            )));
+        }
+    }
+    Ok(letters)
+}
+
+/// Options for `Acrostic::solve`.
+#[derive(Clone, Debug)]
+pub struct AcrosticOptions {
+    /// The maximum number of candidate words to consider for each answer. We
+    /// keep the most probable candidates.
+    pub max_candidates: usize,
+    /// The maximum number of solutions to find.
+    pub limit: usize,
+    /// Options for breaking the quote into words.
+    pub segment: SegmentOptions,
+}
+
+impl Default for AcrosticOptions {
+    fn default() -> Self {
+        AcrosticOptions {
+            max_candidates: 1_000,
+            limit: 10,
+            segment: SegmentOptions::default(),
+        }
+    }
+}
+
+/// A solved acrostic.
+#[derive(Clone, Debug)]
+pub struct Solution {
+    /// The combined probability of the answers and the words of the quote.
+    pub prob: Prob,
+    /// The answer to each clue.
+    pub answers: Vec<String>,
+    /// The words of the quote.
+    pub quote: Vec<String>,
+}
+
+/// Backtracking search state for `Acrostic::solve`.
+struct Solver<'a> {
+    puzzle: &'a Acrostic,
+    dict: &'a Dictionary,
+    opts: &'a AcrosticOptions,
+    /// The letters which no answer has used yet.
+    letters: [u16; 256],
+    /// The word chosen for each answer so far.
+    assigned: Vec<Option<(Prob, String)>>,
+    /// The solutions we've found.
+    solutions: Vec<Solution>,
+}
+
+impl Solver<'_> {
+    /// The most probable words which could be answer `idx` using the remaining
+    /// letters.
+    fn candidates(&self, idx: usize) -> Result<Vec<(Prob, String)>> {
+        let automaton = FitsLetters {
+            initial: self.puzzle.initials[idx],
+            len: self.puzzle.answers[idx].len(),
+            letters: &self.letters,
+        };
+        let search_opts = SearchOptions {
+            limit: Some(self.opts.max_candidates),
+            most_probable: true,
+        };
+        Ok(self
+            .dict
+            .find_matches_by(automaton, &search_opts)?
+            .into_vec())
+    }
+
+    /// Take the letters of `word` from the remaining letters. Returns false
+    /// (and takes nothing) if there aren't enough of some letter.
+    fn take(&mut self, word: &str) -> bool {
+        for (i, b) in word.bytes().enumerate() {
+            if self.letters[b as usize] == 0 {
+                self.put_back(&word[..i]);
+                return false;
+            }
+            self.letters[b as usize] -= 1;
+        }
+        true
+    }
+
+    /// Return the letters of `word` to the remaining letters.
+    fn put_back(&mut self, word: &str) {
+        for b in word.bytes() {
+            self.letters[b as usize] += 1;
+        }
+    }
+
+    /// Look for solutions using the answers assigned so far. Returns false
+    /// once we've found enough solutions.
+    fn search(&mut self) -> Result<bool> {
+        // Choose the most constrained answer.
+        let mut best: Option<(usize, Vec<(Prob, String)>)> = None;
+        for idx in 0..self.assigned.len() {
+            if self.assigned[idx].is_some() {
+                continue;
+            }
+            let candidates = self.candidates(idx)?;
+            if candidates.is_empty() {
+                return Ok(true);
+            }
+            if best
+                .as_ref()
+                .is_none_or(|(_, b)| candidates.len() < b.len())
+            {
+                best = Some((idx, candidates));
+            }
+        }
+        let (idx, candidates) = match best {
+            Some(best) => best,
+            None => return self.check_quote(),
+        };
+        debug!("answer {} has {} candidates", idx + 1, candidates.len());
+
+        for (prob, word) in candidates {
+            if !self.take(&word) {
+                continue;
+            }
+            trace!("trying {} ({})", word, prob);
+            self.assigned[idx] = Some((prob, word));
+            let keep_going = self.search()?;
+            let (_, word) = self.assigned[idx].take().expect("answer was assigned");
+            self.put_back(&word);
+            if !keep_going {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+
+    /// Every answer has been assigned, so fill in the quote and record a
+    /// solution if it can be broken into words. Returns false once we've found
+    /// enough solutions.
+    fn check_quote(&mut self) -> Result<bool> {
+        let mut quote = vec![b'.'; self.puzzle.quote_len];
+        let mut answers = vec![];
+        let mut prob = Prob::always();
+        for (assigned, positions) in self.assigned.iter().zip(&self.puzzle.answers) {
+            let (answer_prob, answer) =
+                assigned.as_ref().expect("answer was assigned");
+            for (&pos, b) in positions.iter().zip(answer.bytes()) {
+                quote[pos] = b;
+            }
+            answers.push(answer.clone());
+            prob = prob * *answer_prob;
+        }
+        let quote = String::from_utf8(quote).expect("quote should be ASCII");
+        match best_breaking(self.dict, &quote, &self.opts.segment)? {
+            Some(breaking) => {
+                debug!("found {} ({})", breaking, answers.join(" "));
+                self.solutions.push(Solution {
+                    prob: prob * breaking.total,
+                    answers,
+                    quote: breaking.into_words(),
+                });
+            }
+            None => trace!("could not break {:?} into words", quote),
+        }
+        Ok(self.solutions.len() < self.opts.limit)
+    }
+}
+
+/// Matches words of a given length which start with a given letter and only
+/// use letters we have left.
+struct FitsLetters<'a> {
+    /// The first letter of the word.
+    initial: u8,
+    /// The length of the word.
+    len: usize,
+    /// The letters available.
+    letters: &'a [u16; 256],
+}
+
+impl Automaton for FitsLetters<'_> {
+    /// The number of bytes matched so far, or `None` if we can't match.
+    type State = Option<usize>;
+
+    fn start(&self) -> Self::State {
+        Some(0)
+    }
+
+    fn is_match(&self, state: &Self::State) -> bool {
+        *state == Some(self.len)
+    }
+
+    fn can_match(&self, state: &Self::State) -> bool {
+        state.is_some()
+    }
+
+    fn accept(&self, state: &Self::State, byte: u8) -> Self::State {
+        let len = (*state)?;
+        let fits = len < self.len
+            && (len > 0 || byte == self.initial)
+            && self.letters[byte as usize] > 0;
+        if fits {
+            Some(len + 1)
+        } else {
+            None
+        }
+    }
+}
@@ -0,0 +1,578 @@
+//! Anagrams and other letter rearrangements.
+
+use fst::automaton::{Automaton, Str};
+use itertools::Itertools;
+use log::{debug, info, trace};
+use serde::{Deserialize, Serialize};
+use std::{
+    cmp::Reverse,
+    collections::{BTreeSet, HashMap},
+};
+
+use crate::checkpoint::{Checkpoint, CheckpointOptions};
+use crate::dictionary::Dictionary;
+use crate::error::{Error, Result};
+use crate::metadata::content_hash;
+use crate::probability::{Dist, Prob, TopK};
+
+/// The sorted letters of `word`. Two words are anagrams of each other if and
+/// only if they have the same signature.
+pub fn signature(word: &str) -> String {
+    let mut chars = word.chars().collect::<Vec<_>>();
+    chars.sort_unstable();
+    chars.into_iter().collect()
+}
+
+/// Strip whitespace from `letters` and lowercase them.
+fn normalize_letters(letters: &str) -> String {
+    letters
+        .chars()
+        .filter(|c| !c.is_whitespace())
+        .flat_map(char::to_lowercase)
+        .collect()
+}
+
+/// Is `c` a blank tile, which can stand for any letter?
+pub(crate) fn is_blank(c: char) -> bool {
+    c == '.' || c == '?'
+}
+
+/// Normalize `letters`, and split them into the signature of the real letters
+/// and the number of blanks.
+fn split_blanks(letters: &str) -> (String, usize) {
+    let letters = normalize_letters(letters);
+    let blanks = letters.chars().filter(|&c| is_blank(c)).count();
+    let real = letters
+        .chars()
+        .filter(|&c| !is_blank(c))
+        .collect::<String>();
+    (signature(&real), blanks)
+}
+
+/// Find every single word which uses exactly the letters in `letters`, which
+/// may include "." or "?" blanks which stand for any letter.
+pub fn find_anagrams(dict: &Dictionary, letters: &str) -> Result<Dist<String>> {
+    let (sig, blanks) = split_blanks(letters);
+    if blanks == 0 {
+        return find_by_signature(dict, Str::new(&sig), 0);
+    }
+    let automaton = WithBlanks {
+        letters: sig.into_bytes(),
+        blanks,
+        use_all: true,
+    };
+    find_by_signature(dict, automaton, 0)
+}
+
+/// Find every word which can be spelled using some of the tiles in `rack`,
+/// using each tile at most once, as in Scrabble. The rack may include "." or
+/// "?" blanks which stand for any letter.
+pub fn find_rack_words(
+    dict: &Dictionary,
+    rack: &str,
+    min_length: usize,
+) -> Result<Dist<String>> {
+    let (sig, blanks) = split_blanks(rack);
+    let automaton = WithBlanks {
+        letters: sig.into_bytes(),
+        blanks,
+        use_all: false,
+    };
+    find_by_signature(dict, automaton, min_length)
+}
+
+/// Find every word at least `min_length` letters long which can be built
+/// from the letters of `seed`, using each letter at most once, grouped by
+/// length from longest to shortest. Words in each group are sorted from most
+/// to least probable.
+pub fn find_buildable_words(
+    dict: &Dictionary,
+    seed: &str,
+    min_length: usize,
+) -> Result<Vec<(usize, Dist<String>)>> {
+    let words = find_rack_words(dict, seed, min_length)?;
+    let mut groups = words.group_by(|word| word.chars().count());
+    groups.sort_by_key(|(len, _)| Reverse(*len));
+    Ok(groups)
+}
+
+/// The letters in `word` which must have come from blanks in `tiles`, with
+/// the byte offset of each one in `word`. When a letter appears more often in
+/// `word` than in `tiles`, the blanks are its last occurrences.
+pub fn blank_letters(tiles: &str, word: &str) -> Vec<(usize, char)> {
+    let mut available = normalize_letters(tiles)
+        .chars()
+        .filter(|&c| !is_blank(c))
+        .collect::<Vec<_>>();
+    let mut blanks = vec![];
+    for (offset, c) in word.char_indices() {
+        match available.iter().position(|&a| a == c) {
+            Some(idx) => {
+                available.swap_remove(idx);
+            }
+            None => blanks.push((offset, c)),
+        }
+    }
+    blanks
+}
+
+/// Options for `find_anagram_phrases`.
+#[derive(Clone, Debug)]
+pub struct PhraseOptions {
+    /// The maximum number of words in each phrase, not counting `include`.
+    pub max_words: usize,
+    /// Only use words at least this long.
+    pub min_length: usize,
+    /// Only consider this many of the most probable words which fit in the
+    /// letters.
+    pub max_candidates: usize,
+    /// Words which every phrase must contain. Their letters are taken out of
+    /// the pool before we search, and they needn't be in the dictionary.
+    pub include: Vec<String>,
+    /// Words which no phrase may contain.
+    pub exclude: BTreeSet<String>,
+    /// The maximum number of phrases to return.
+    pub limit: usize,
+    /// Save the state of the search here every so often, and resume from it
+    /// if it has already been saved.
+    pub checkpoint: Option<CheckpointOptions>,
+}
+
+impl Default for PhraseOptions {
+    fn default() -> Self {
+        PhraseOptions {
+            max_words: 3,
+            min_length: 1,
+            max_candidates: 2000,
+            include: vec![],
+            exclude: BTreeSet::new(),
+            limit: 100,
+            checkpoint: None,
+        }
+    }
+}
+
+/// Find phrases of one or more words which use exactly the letters in
+/// `letters`, plus the words in `opts.include`. Phrases are ranked by the
+/// product of the probabilities of their words, not counting included words,
+/// since every phrase has those.
+pub fn find_anagram_phrases(
+    dict: &Dictionary,
+    letters: &str,
+    opts: &PhraseOptions,
+) -> Result<Dist<String>> {
+    let (mut remaining, blanks) = split_blanks(letters);
+    if blanks > 0 {
+        return Err(Error::InvalidPuzzle(
+            "blanks can only be used when finding single-word anagrams".to_owned(),
+        ));
+    }
+    let include = opts
+        .include
+        .iter()
+        .map(|word| normalize_letters(word))
+        .collect::<Vec<_>>();
+    for word in &include {
+        remaining =
+            subtract_signature(&remaining, &signature(word)).ok_or_else(|| {
+                Error::InvalidPuzzle(format!("the letters don't include {:?}", word))
+            })?;
+    }
+    if remaining.is_empty() {
+        return Ok(Dist::from_vec(vec![(Prob::always(), include.join(" "))]));
+    }
+
+    let mut words = find_rack_words(dict, &remaining, opts.min_length)?.into_vec();
+    words.retain(|(_, word)| !opts.exclude.contains(word));
+    words.truncate(opts.max_candidates);
+    debug!(
+        "searching for phrases using {} candidate words",
+        words.len()
+    );
+    let checkpoint = opts.checkpoint.as_ref().map(|checkpoint_opts| {
+        // Identify the search by its candidates, which depend on the
+        // dictionary, its filters and our options.
+        let candidates = words.iter().map(|(_, word)| word.as_str()).join(" ");
+        let search = format!(
+            "anagram {:?} into {} words, keeping {}, using candidates {}",
+            remaining,
+            opts.max_words,
+            opts.limit,
+            content_hash(candidates.as_bytes()),
+        );
+        Checkpoint::new(checkpoint_opts, search)
+    });
+    let mut search = PhraseSearch {
+        sigs: words.iter().map(|(_, word)| signature(word)).collect(),
+        by_sig: HashMap::new(),
+        words,
+        include,
+        chosen: vec![],
+        phrases: Dist::top_k(opts.limit),
+        resume: vec![],
+        checkpoint,
+        tried: 0,
+    };
+    for (idx, sig) in search.sigs.iter().enumerate() {
+        search.by_sig.entry(sig.clone()).or_default().push(idx);
+    }
+    if let Some(checkpoint) = &search.checkpoint {
+        if let Some(state) = checkpoint.load::<PhraseState>()? {
+            debug!("resuming with {} phrases found", state.found.len());
+            search.resume = state.path;
+            for chosen in state.found {
+                if chosen.iter().any(|&idx| idx >= search.words.len()) {
+                    return Err(Error::InvalidPuzzle(
+                        "checkpoint was saved by a different search".to_owned(),
+                    ));
+                }
+                search.chosen = chosen;
+                search.record(search.prob_of_chosen());
+            }
+            search.chosen.clear();
+        }
+    }
+    search.extend(0, &remaining, opts.max_words, Prob::always())?;
+    info!(
+        tried = search.tried;
+        "phrase search tried {} words",
+        search.tried
+    );
+    if let Some(checkpoint) = &search.checkpoint {
+        checkpoint.finish()?;
+    }
+
+    let phrases = search
+        .phrases
+        .sorted()
+        .into_iter()
+        .map(|(prob, chosen)| (prob, search.phrase(chosen)))
+        .collect();
+    Ok(Dist::from_vec(phrases))
+}
+
+/// Take the letters of `sub` out of `from`, where both are signatures, or
+/// return `None` if `from` doesn't contain them all.
+fn subtract_signature(from: &str, sub: &str) -> Option<String> {
+    let mut rest = String::with_capacity(from.len());
+    let mut sub = sub.chars().peekable();
+    for c in from.chars() {
+        if sub.peek() == Some(&c) {
+            sub.next();
+        } else {
+            rest.push(c);
+        }
+    }
+    sub.next().is_none().then_some(rest)
+}
+
+/// The state of a search for anagram phrases.
+struct PhraseSearch {
+    /// The words we can use, from most to least probable.
+    words: Vec<(Prob, String)>,
+    /// The signature of each word.
+    sigs: Vec<String>,
+    /// The indices of the words with each signature.
+    by_sig: HashMap<String, Vec<usize>>,
+    /// Normalized words which every phrase must contain.
+    include: Vec<String>,
+    /// The indices of the words in the phrase we're building.
+    chosen: Vec<usize>,
+    /// The most probable phrases we've found, as the indices of their words.
+    phrases: TopK<Vec<usize>>,
+    /// The indices of the words to skip ahead to at each depth, when resuming
+    /// from a checkpoint. We clear each entry once we've moved past it.
+    resume: Vec<usize>,
+    /// Where to save our state, if anywhere.
+    checkpoint: Option<Checkpoint>,
+    /// How many words we've tried adding to a phrase.
+    tried: u64,
+}
+
+/// The saved state of a `PhraseSearch`.
+#[derive(Deserialize, Serialize)]
+struct PhraseState {
+    /// The phrase we were extending, as the indices of its words.
+    path: Vec<usize>,
+    /// The most probable phrases we'd already found, most probable first.
+    found: Vec<Vec<usize>>,
+}
+
+impl PhraseSearch {
+    /// Add up to `words_left` more words to our phrase, using exactly the
+    /// letters in the signature `remaining`. To find each set of words only
+    /// once, we only add words at or after index `first` in `self.words`.
+    fn extend(
+        &mut self,
+        first: usize,
+        remaining: &str,
+        words_left: usize,
+        prob: Prob,
+    ) -> Result<()> {
+        if let Some(checkpoint) = &mut self.checkpoint {
+            let (chosen, phrases) = (&self.chosen, &self.phrases);
+            checkpoint.save_if_due(|| PhraseState {
+                path: chosen.clone(),
+                found: phrases
+                    .sorted()
+                    .into_iter()
+                    .map(|(_, found)| found.clone())
+                    .collect(),
+            })?;
+        }
+        if words_left == 0 {
+            return Ok(());
+        }
+        // The last word must be an exact anagram of the remaining letters, so
+        // look it up instead of trying every word.
+        if words_left == 1 {
+            let matches = self.by_sig.get(remaining).cloned().unwrap_or_default();
+            for idx in matches.into_iter().filter(|&idx| idx >= first) {
+                self.chosen.push(idx);
+                self.record(prob * self.words[idx].0);
+                self.chosen.pop();
+            }
+            return Ok(());
+        }
+        // When resuming, skip the words we'd already tried.
+        let depth = self.chosen.len();
+        let start = self.resume.get(depth).copied().unwrap_or(first);
+        for idx in start..self.words.len() {
+            // Only the first word we try can lead to the resumed branch.
+            if idx > start {
+                self.resume.truncate(depth);
+            }
+            let rest = match subtract_signature(remaining, &self.sigs[idx]) {
+                Some(rest) => rest,
+                None => continue,
+            };
+            trace!(
+                event = "try", depth = depth, word = self.words[idx].1.as_str(),
+                remaining = rest.as_str();
+                "trying {}, leaving {:?}", self.words[idx].1, rest
+            );
+            self.tried += 1;
+            self.chosen.push(idx);
+            let prob = prob * self.words[idx].0;
+            if rest.is_empty() {
+                self.record(prob);
+            } else {
+                self.extend(idx, &rest, words_left - 1, prob)?;
+            }
+            self.chosen.pop();
+        }
+        self.resume.truncate(depth);
+        Ok(())
+    }
+
+    /// Record the phrase we've built.
+    fn record(&mut self, prob: Prob) {
+        if self.phrases.would_keep(prob) {
+            self.phrases.push(prob, self.chosen.clone());
+        }
+    }
+
+    /// The probability of the phrase we've built.
+    fn prob_of_chosen(&self) -> Prob {
+        self.chosen
+            .iter()
+            .fold(Prob::always(), |prob, &idx| prob * self.words[idx].0)
+    }
+
+    /// The text of a phrase made of the words at `chosen`.
+    fn phrase(&self, chosen: &[usize]) -> String {
+        self.include
+            .iter()
+            .map(String::as_str)
+            .chain(chosen.iter().map(|&idx| self.words[idx].1.as_str()))
+            .join(" ")
+    }
+}
+
+/// Find every word which can be spelled using only the letters in `bank`,
+/// using each letter as many times as we like, as in Spelling Bee.
+pub fn find_bank_words(
+    dict: &Dictionary,
+    bank: &str,
+    min_length: usize,
+) -> Result<Dist<String>> {
+    let automaton = OnlyLetters {
+        letters: normalize_letters(bank).bytes().collect(),
+    };
+    find_by_signature(dict, automaton, min_length)
+}
+
+/// Find every word at least `min_length` letters long whose signature is
+/// accepted by `automaton`. We use the anagram index if we have one, and
+/// otherwise check the signature of every word.
+fn find_by_signature<A: Automaton>(
+    dict: &Dictionary,
+    automaton: A,
+    min_length: usize,
+) -> Result<Dist<String>> {
+    let dist = match dict.find_by_signature(&automaton)? {
+        Some(dist) => dist,
+        None => {
+            debug!("no anagram index, so checking every word");
+            let mut events = vec![];
+            for entry in dict.words() {
+                let (word, prob) = entry?;
+                if accepts_signature(&automaton, &signature(&word)) {
+                    events.push((prob, word));
+                }
+            }
+            let mut dist = Dist::from_vec(events);
+            dist.sort_by_probability();
+            dist
+        }
+    };
+    Ok(Dist::from_vec(
+        dist.into_vec()
+            .into_iter()
+            .filter(|(_, word)| word.chars().count() >= min_length)
+            .collect(),
+    ))
+}
+
+/// Does `automaton` accept `sig`?
+fn accepts_signature<A: Automaton>(automaton: &A, sig: &str) -> bool {
+    let mut state = automaton.start();
+    for &byte in sig.as_bytes() {
+        if !automaton.can_match(&state) {
+            return false;
+        }
+        state = automaton.accept(&state, byte);
+    }
+    automaton.is_match(&state)
+}
+
+/// Matches signatures which use some of `letters`, which must be sorted, plus
+/// up to `blanks` other letters. If `use_all` is set, signatures must use
+/// every letter and every blank. Because signatures are sorted too, we can
+/// check this greedily, using a real letter whenever we have one, without
+/// trying every letter for each blank.
+struct WithBlanks {
+    letters: Vec<u8>,
+    blanks: usize,
+    use_all: bool,
+}
+
+impl Automaton for WithBlanks {
+    /// The index of the first letter we haven't used or skipped yet, and the
+    /// number of blanks we've used, or `None` if we can't match.
+    type State = Option<(usize, usize)>;
+
+    fn start(&self) -> Self::State {
+        Some((0, 0))
+    }
+
+    fn is_match(&self, state: &Self::State) -> bool {
+        match state {
+            Some((next, blanks_used)) if self.use_all => {
+                *next == self.letters.len() && *blanks_used == self.blanks
+            }
+            Some(_) => true,
+            None => false,
+        }
+    }
+
+    fn can_match(&self, state: &Self::State) -> bool {
+        state.is_some()
+    }
+
+    fn accept(&self, state: &Self::State, byte: u8) -> Self::State {
+        let (next, blanks_used) = (*state)?;
+        let rest = &self.letters[next..];
+        // Any letters before `byte` can't be used later, since signatures are
+        // sorted.
+        let skipped = rest.iter().take_while(|&&b| b < byte).count();
+        if self.use_all && skipped > 0 {
+            return None;
+        }
+        if rest.get(skipped) == Some(&byte) {
+            Some((next + skipped + 1, blanks_used))
+        } else if blanks_used < self.blanks {
+            Some((next + skipped, blanks_used + 1))
+        } else {
+            None
+        }
+    }
+}
+
+/// Matches signatures which use only `letters`, any number of times.
+struct OnlyLetters {
+    letters: BTreeSet<u8>,
+}
+
+impl Automaton for OnlyLetters {
+    /// Have we only seen letters in `letters` so far?
+    type State = bool;
+
+    fn start(&self) -> Self::State {
+        true
+    }
+
+    fn is_match(&self, state: &Self::State) -> bool {
+        *state
+    }
+
+    fn can_match(&self, state: &Self::State) -> bool {
+        *state
+    }
+
+    fn accept(&self, state: &Self::State, byte: u8) -> Self::State {
+        *state && self.letters.contains(&byte)
+    }
+}
+
+/// Options for `find_transposals`.
+#[derive(Clone, Debug, Default)]
+pub struct TransposalOptions {
+    /// Only consider words of exactly this length.
+    pub length: Option<usize>,
+    /// Only consider words at least this long.
+    pub min_length: Option<usize>,
+    /// Only consider words at least this probable.
+    pub min_prob: Option<Prob>,
+}
+
+/// Find every set of two or more dictionary words which are anagrams of each
+/// other. Each set is returned as a space-separated string, with the
+/// probability of its least probable word, so that sets of common words sort
+/// first.
+pub fn find_transposals(
+    dict: &Dictionary,
+    opts: &TransposalOptions,
+) -> Result<Dist<String>> {
+    let mut groups = HashMap::<String, Vec<(Prob, String)>>::new();
+    for entry in dict.words() {
+        let (word, prob) = entry?;
+        let len = word.chars().count();
+        if opts.length.is_some_and(|l| len != l)
+            || opts.min_length.is_some_and(|l| len < l)
+            || opts.min_prob.is_some_and(|p| prob < p)
+        {
+            continue;
+        }
+        groups
+            .entry(signature(&word))
+            .or_default()
+            .push((prob, word));
+    }
+
+    let mut transposals = vec![];
+    for group in groups.into_values().filter(|g| g.len() > 1) {
+        let mut group_dist = Dist::from_vec(group);
+        group_dist.sort_by_probability();
+        let mut least_probable = Prob::always();
+        let mut words = vec![];
+        for (prob, word) in &group_dist {
+            least_probable = prob;
+            words.push(word.as_str());
+        }
+        transposals.push((least_probable, words.join(" ")));
+    }
+    let mut transposals = Dist::from_vec(transposals);
+    transposals.sort_by_probability();
+    Ok(transposals)
+}
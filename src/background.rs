@@ -0,0 +1,92 @@
+//! Running slow dictionary work on a background thread, so that async code
+//! (such as a chat bot built on tokio) can wait for it without blocking its
+//! runtime.
+//!
+//! This doesn't depend on any particular async runtime. Each task runs on its
+//! own thread, and the returned future wakes whichever task awaits it once the
+//! work is done.
+
+use std::{
+    future::Future,
+    panic::{self, AssertUnwindSafe},
+    path::PathBuf,
+    pin::Pin,
+    sync::{Arc, Mutex},
+    task::{Context, Poll, Waker},
+    thread,
+};
+
+use crate::dictionary::Dictionary;
+use crate::error::Result;
+
+/// Work running on a background thread. Await it to get the result.
+#[must_use = "background work does nothing useful unless awaited"]
+pub struct Background<T> {
+    shared: Arc<Mutex<Shared<T>>>,
+}
+
+/// State shared between a `Background` and its thread.
+struct Shared<T> {
+    /// The result of the work, once it has finished, or the panic which
+    /// stopped it.
+    result: Option<thread::Result<T>>,
+    /// The task to wake once the work finishes.
+    waker: Option<Waker>,
+}
+
+impl<T: Send + 'static> Background<T> {
+    /// Start running `work` on a new thread.
+    pub fn spawn<F>(work: F) -> Background<T>
+    where
+        F: FnOnce() -> T + Send + 'static,
+    {
+        let shared = Arc::new(Mutex::new(Shared {
+            result: None,
+            waker: None,
+        }));
+        let thread_shared = shared.clone();
+        thread::spawn(move || {
+            let result = panic::catch_unwind(AssertUnwindSafe(work));
+            let mut shared = thread_shared.lock().expect("lock poisoned");
+            shared.result = Some(result);
+            if let Some(waker) = shared.waker.take() {
+                waker.wake();
+            }
+        });
+        Background { shared }
+    }
+}
+
+impl<T> Future for Background<T> {
+    type Output = T;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<T> {
+        let mut shared = self.shared.lock().expect("lock poisoned");
+        match shared.result.take() {
+            Some(Ok(value)) => Poll::Ready(value),
+            // Pass panics on to whoever is waiting, as `JoinHandle` would.
+            Some(Err(payload)) => panic::resume_unwind(payload),
+            None => {
+                shared.waker = Some(cx.waker().clone());
+                Poll::Pending
+            }
+        }
+    }
+}
+
+/// Load the dictionary at `path` on a background thread.
+pub fn load(path: impl Into<PathBuf>) -> Background<Result<Dictionary>> {
+    let path = path.into();
+    Background::spawn(move || Dictionary::load(&path))
+}
+
+/// Run `query` against `dict` on a background thread. The thread gets its own
+/// clone of `dict`, which shares the same indexes, so this is cheap.
+pub fn query<T, F>(dict: &Dictionary, query: F) -> Background<Result<T>>
+where
+    T: Send + 'static,
+    F: FnOnce(&Dictionary) -> Result<T> + Send + 'static,
+{
+    let dict = dict.clone();
+    Background::spawn(move || query(&dict))
+}
@@ -0,0 +1,94 @@
+//! An on-disk cache of query results, so that scripts which repeat the same
+//! query get their answer instantly.
+
+use anyhow::{format_err, Context, Result};
+use std::{
+    fs,
+    io::ErrorKind,
+    path::{Path, PathBuf},
+    process,
+};
+use xxhash_rust::xxh3::xxh3_128;
+
+/// A directory of cached query results.
+pub struct QueryCache {
+    dir: PathBuf,
+}
+
+impl QueryCache {
+    /// Open the cache in the user's standard cache directory.
+    pub fn open_default() -> Result<QueryCache> {
+        let dir = dirs::cache_dir()
+            .ok_or_else(|| format_err!("could not find a cache directory"))?
+            .join("word-puzzler")
+            .join("queries");
+        Ok(QueryCache { dir })
+    }
+
+    /// The directory containing our cache.
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// The file holding the result for `query` against the dictionary with
+    /// `content_hash`.
+    fn entry_path(&self, content_hash: &str, query: &str) -> PathBuf {
+        let key = xxh3_128(format!("{}\n{}", content_hash, query).as_bytes());
+        self.dir.join(format!("{:032x}", key))
+    }
+
+    /// Look up a cached result.
+    pub fn get(&self, content_hash: &str, query: &str) -> Result<Option<String>> {
+        let path = self.entry_path(content_hash, query);
+        match fs::read_to_string(&path) {
+            Ok(result) => Ok(Some(result)),
+            Err(err) if err.kind() == ErrorKind::NotFound => Ok(None),
+            Err(err) => {
+                Err(err).with_context(|| format!("could not read {}", path.display()))
+            }
+        }
+    }
+
+    /// Store a result in the cache.
+    pub fn put(&self, content_hash: &str, query: &str, result: &str) -> Result<()> {
+        fs::create_dir_all(&self.dir)
+            .with_context(|| format!("could not create {}", self.dir.display()))?;
+        let path = self.entry_path(content_hash, query);
+        // Write to a temporary file first, so that concurrent readers never
+        // see a partial result. Each process gets its own temporary file, so
+        // that two writing the same entry don't clobber each other's.
+        let tmp_path = path.with_extension(format!("tmp{}", process::id()));
+        let written = fs::write(&tmp_path, result)
+            .with_context(|| format!("could not write to {}", tmp_path.display()))
+            .and_then(|()| {
+                fs::rename(&tmp_path, &path)
+                    .with_context(|| format!("could not write to {}", path.display()))
+            });
+        if written.is_err() {
+            let _ = fs::remove_file(&tmp_path);
+        }
+        written
+    }
+
+    /// Remove every cached result, returning how many we removed.
+    pub fn clear(&self) -> Result<usize> {
+        let entries = match fs::read_dir(&self.dir) {
+            Ok(entries) => entries,
+            Err(err) if err.kind() == ErrorKind::NotFound => return Ok(0),
+            Err(err) => {
+                return Err(err)
+                    .with_context(|| format!("could not read {}", self.dir.display()))
+            }
+        };
+        let mut removed = 0;
+        for entry in entries {
+            let path = entry
+                .with_context(|| format!("could not read {}", self.dir.display()))?
+                .path();
+            fs::remove_file(&path)
+                .with_context(|| format!("could not remove {}", path.display()))?;
+            removed += 1;
+        }
+        Ok(removed)
+    }
+}
@@ -0,0 +1,136 @@
+//! Finding chains of words which overlap, where each word starts with the last
+//! few letters of the word before it, like "part", "tree", "eel".
+//!
+//! We search outwards from the first word, most probable chain first, like
+//! Dijkstra's algorithm. Each word's neighbors come from a prefix query on the
+//! dictionary, so we never have to look at words which can't follow it.
+
+use log::{debug, trace};
+use std::collections::{BinaryHeap, HashMap};
+
+use crate::dictionary::Dictionary;
+use crate::error::{Error, Result};
+use crate::probability::{Dist, Prob};
+
+/// Options for `find_chains`.
+#[derive(Clone, Debug)]
+pub struct ChainOptions {
+    /// The number of letters shared by each pair of neighboring words.
+    pub overlap: usize,
+    /// The maximum number of words in a chain, including the first and last.
+    pub max_words: usize,
+    /// The maximum number of words to consider after each word. We keep the
+    /// most probable candidates.
+    pub max_candidates: usize,
+    /// The maximum number of chains to return.
+    pub limit: usize,
+}
+
+impl Default for ChainOptions {
+    fn default() -> Self {
+        ChainOptions {
+            overlap: 2,
+            max_words: 6,
+            max_candidates: 100,
+            limit: 10,
+        }
+    }
+}
+
+/// Find the most probable chains of words from `from` to `to`, where each word
+/// starts with the last `opts.overlap` letters of the word before it. Each
+/// event in the result is a chain, with its words separated by spaces.
+pub fn find_chains(
+    dict: &Dictionary,
+    from: &str,
+    to: &str,
+    opts: &ChainOptions,
+) -> Result<Dist<String>> {
+    if opts.overlap == 0 {
+        return Err(Error::InvalidOption(
+            "chain overlap must be at least 1".to_owned(),
+        ));
+    }
+    for word in [from, to] {
+        if word.chars().count() < opts.overlap {
+            return Err(Error::InvalidPuzzle(format!(
+                "{:?} is shorter than the overlap of {}",
+                word, opts.overlap
+            )));
+        }
+    }
+    let to_prob = dict.probability_of(to).unwrap_or_else(Prob::always);
+    let to_head = head(to, opts.overlap);
+
+    let mut neighbors = HashMap::<String, Vec<(Prob, String)>>::new();
+    let mut expanded = HashMap::<String, usize>::new();
+    let mut queue = BinaryHeap::new();
+    let from_prob = dict.probability_of(from).unwrap_or_else(Prob::always);
+    queue.push((from_prob, vec![from.to_owned()]));
+    let mut chains = vec![];
+    while let Some((prob, chain)) = queue.pop() {
+        let last = chain.last().expect("chains are never empty");
+        if chain.len() > 1 && last == to {
+            debug!("found {} ({})", chain.join(" "), prob);
+            chains.push((prob, chain.join(" ")));
+            if chains.len() == opts.limit {
+                break;
+            }
+            continue;
+        }
+
+        // Like Dijkstra's algorithm, but we expand each word more than once so
+        // that we can find more than one chain.
+        let count = expanded.entry(last.clone()).or_default();
+        *count += 1;
+        if *count > opts.limit || chain.len() >= opts.max_words {
+            continue;
+        }
+        trace!("expanding {} ({})", chain.join(" "), prob);
+
+        let tail = tail(last, opts.overlap);
+        if tail == to_head {
+            let mut next = chain.clone();
+            next.push(to.to_owned());
+            queue.push((prob * to_prob, next));
+        }
+        // Leave room for the last word.
+        if chain.len() + 1 >= opts.max_words {
+            continue;
+        }
+        if !neighbors.contains_key(tail) {
+            let mut words = vec![];
+            for entry in dict.words_with_prefix(tail) {
+                let (word, word_prob) = entry?;
+                if word.chars().count() > opts.overlap {
+                    words.push((word_prob, word));
+                }
+            }
+            let mut words = Dist::from_vec(words);
+            words.sort_by_probability();
+            words.truncate(opts.max_candidates);
+            neighbors.insert(tail.to_owned(), words.into_vec());
+        }
+        for (word_prob, word) in &neighbors[tail] {
+            if word == to || chain.contains(word) {
+                continue;
+            }
+            let mut next = chain.clone();
+            next.push(word.clone());
+            queue.push((prob * *word_prob, next));
+        }
+    }
+    Ok(Dist::from_vec(chains))
+}
+
+/// The first `len` characters of `word`.
+fn head(word: &str, len: usize) -> &str {
+    let end = word.char_indices().nth(len).map_or(word.len(), |(i, _)| i);
+    &word[..end]
+}
+
+/// The last `len` characters of `word`.
+fn tail(word: &str, len: usize) -> &str {
+    let start = word.char_indices().rev().nth(len - 1).map_or(0, |(i, _)| i);
+    &word[start..]
+}
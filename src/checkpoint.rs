@@ -0,0 +1,129 @@
+//! Saving the state of long searches to a file, so that we can resume them
+//! after an interruption instead of starting again.
+//!
+//! Our searches are deterministic backtracking searches, so their state is
+//! just the path from the root of the search tree to the branch we're
+//! exploring, plus anything we've found so far. Searches save that state
+//! every so often, and when they're restarted, they skip ahead to the saved
+//! branch.
+
+use log::debug;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::{
+    fs,
+    io::ErrorKind,
+    path::PathBuf,
+    time::{Duration, Instant},
+};
+
+use crate::error::{Error, Result};
+
+/// Where and how often to save the state of a search.
+#[derive(Clone, Debug)]
+pub struct CheckpointOptions {
+    /// The file to save to. If it already exists, we resume the search it
+    /// describes. We remove it once the search finishes.
+    pub path: PathBuf,
+    /// How often to save.
+    pub interval: Duration,
+}
+
+impl CheckpointOptions {
+    /// Save to `path` once a minute.
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        CheckpointOptions {
+            path: path.into(),
+            interval: Duration::from_secs(60),
+        }
+    }
+}
+
+/// The contents of a checkpoint file.
+#[derive(Deserialize, Serialize)]
+struct Saved<S, T> {
+    /// A description of the search, so that we don't resume a different
+    /// search by mistake.
+    search: S,
+    /// The state of the search.
+    state: T,
+}
+
+/// A checkpoint file for one particular search.
+pub(crate) struct Checkpoint {
+    opts: CheckpointOptions,
+    /// A description of the search, including every input which affects it.
+    search: String,
+    /// When we last saved.
+    last_saved: Instant,
+}
+
+impl Checkpoint {
+    /// Prepare to save checkpoints for the search described by `search`.
+    pub(crate) fn new(opts: &CheckpointOptions, search: String) -> Checkpoint {
+        Checkpoint {
+            opts: opts.clone(),
+            search,
+            last_saved: Instant::now(),
+        }
+    }
+
+    /// Load the saved state of our search, if there is any. Fails if the file
+    /// was saved by a different search.
+    pub(crate) fn load<T: DeserializeOwned>(&self) -> Result<Option<T>> {
+        let path = &self.opts.path;
+        let data = match fs::read_to_string(path) {
+            Ok(data) => data,
+            Err(err) if err.kind() == ErrorKind::NotFound => return Ok(None),
+            Err(err) => return Err(Error::io(path)(err)),
+        };
+        let invalid = |message: String| Error::InvalidCheckpoint {
+            path: path.clone(),
+            message,
+        };
+        let saved = serde_json::from_str::<Saved<String, T>>(&data)
+            .map_err(|err| invalid(err.to_string()))?;
+        if saved.search != self.search {
+            return Err(invalid(format!(
+                "saved by a different search ({})",
+                saved.search
+            )));
+        }
+        Ok(Some(saved.state))
+    }
+
+    /// Save `state` if it's been long enough since we last saved. `state` is
+    /// only called if we need to save.
+    pub(crate) fn save_if_due<T, F>(&mut self, state: F) -> Result<()>
+    where
+        T: Serialize,
+        F: FnOnce() -> T,
+    {
+        if self.last_saved.elapsed() < self.opts.interval {
+            return Ok(());
+        }
+        let path = &self.opts.path;
+        let saved = Saved {
+            search: self.search.as_str(),
+            state: state(),
+        };
+        let data = serde_json::to_string(&saved)
+            .expect("search state should always serialize");
+        // Write to a temporary file first, so that an interruption while
+        // saving doesn't leave us with a partial checkpoint.
+        let tmp_path = path.with_extension("tmp");
+        fs::write(&tmp_path, data).map_err(Error::io(&tmp_path))?;
+        fs::rename(&tmp_path, path).map_err(Error::io(path))?;
+        debug!("saved checkpoint to {}", path.display());
+        self.last_saved = Instant::now();
+        Ok(())
+    }
+
+    /// Remove the checkpoint file, because our search has finished.
+    pub(crate) fn finish(&self) -> Result<()> {
+        let path = &self.opts.path;
+        match fs::remove_file(path) {
+            Err(err) if err.kind() != ErrorKind::NotFound => Err(Error::io(path)(err)),
+            _ => Ok(()),
+        }
+    }
+}
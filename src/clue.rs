@@ -0,0 +1,52 @@
+//! Historical crossword clues.
+
+use std::{
+    fs::File,
+    io::{BufRead, BufReader},
+    path::Path,
+};
+
+use crate::error::{Error, Result};
+
+/// Normalize a crossword answer the way we normalize dictionary words, by
+/// lowercasing it and removing anything that isn't a letter. This turns
+/// "New York" into "newyork".
+pub fn normalize_answer(answer: &str) -> String {
+    answer
+        .chars()
+        .filter(|c| c.is_alphabetic())
+        .flat_map(char::to_lowercase)
+        .collect()
+}
+
+/// Read a file of tab-separated `answer\tclue` lines, returning normalized
+/// `(answer, clue)` pairs for each answer where `keep(answer)` returns true.
+pub fn read_clues(
+    path: &Path,
+    keep: impl Fn(&str) -> bool,
+) -> Result<Vec<(String, String)>> {
+    let file = File::open(path).map_err(Error::io(path))?;
+    let mut clues = vec![];
+    for (i, line) in BufReader::new(file).lines().enumerate() {
+        let line = line.map_err(Error::io(path))?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let (answer, clue) = match line.split_once('\t') {
+            Some(fields) => fields,
+            None => {
+                return Err(Error::InvalidWordList {
+                    path: path.to_owned(),
+                    line: i + 1,
+                    message: format!("expected \"answer<TAB>clue\", found {:?}", line),
+                })
+            }
+        };
+        let answer = normalize_answer(answer);
+        let clue = clue.trim();
+        if !clue.is_empty() && keep(&answer) {
+            clues.push((answer, clue.to_owned()));
+        }
+    }
+    Ok(clues)
+}
@@ -0,0 +1,74 @@
+//! Finding dictionary words made of other dictionary words.
+
+use std::cmp::Reverse;
+
+use crate::dictionary::Dictionary;
+use crate::error::Result;
+use crate::probability::Prob;
+use crate::segment::{break_into_words, Breaking, SegmentOptions};
+
+/// Options for `find_compounds`.
+#[derive(Clone, Debug)]
+pub struct CompoundOptions {
+    /// The minimum length of each part.
+    pub min_part_len: usize,
+    /// The maximum number of parts.
+    pub max_parts: usize,
+}
+
+impl Default for CompoundOptions {
+    fn default() -> Self {
+        CompoundOptions {
+            min_part_len: 3,
+            max_parts: 2,
+        }
+    }
+}
+
+/// A word which is made of two or more other words.
+#[derive(Clone, Debug)]
+pub struct Compound {
+    /// The probability of the compound word itself.
+    pub prob: Prob,
+    /// The compound word.
+    pub word: String,
+    /// Every way to break the word into parts, most probable first.
+    pub breakings: Vec<Vec<String>>,
+}
+
+/// Find every dictionary word which can be broken into two or more other
+/// dictionary words, most probable first.
+pub fn find_compounds(
+    dict: &Dictionary,
+    opts: &CompoundOptions,
+) -> Result<Vec<Compound>> {
+    let segment_opts = SegmentOptions {
+        min_word_len: opts.min_part_len,
+        max_words: Some(opts.max_parts),
+        ..SegmentOptions::default()
+    };
+    let mut compounds = vec![];
+    for entry in dict.words() {
+        let (word, prob) = entry?;
+        if word.len() < 2 * opts.min_part_len
+            || !word.chars().all(|c| c.is_alphabetic())
+        {
+            continue;
+        }
+        let mut breakings = break_into_words(dict, &word, &segment_opts)?
+            .into_iter()
+            .filter(|breaking| breaking.len() >= 2)
+            .collect::<Vec<_>>();
+        if breakings.is_empty() {
+            continue;
+        }
+        breakings.sort_by_key(|breaking| Reverse(breaking.total));
+        compounds.push(Compound {
+            prob,
+            word,
+            breakings: breakings.into_iter().map(Breaking::into_words).collect(),
+        });
+    }
+    compounds.sort_by_key(|c| Reverse(c.prob));
+    Ok(compounds)
+}
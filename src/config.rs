@@ -0,0 +1,231 @@
+//! The user's configuration file, which defines named dictionaries and the
+//! default options to use with each.
+//!
+//! ```toml
+//! default = "common"
+//!
+//! [dictionaries.common]
+//! path = "~/words/en.fst"
+//! language = "en"
+//! band = "common"
+//!
+//! [dictionaries.everything]
+//! path = "~/words/en.fst"
+//! temperature = 1.5
+//! allow_proper_nouns = true
+//!
+//! [dictionaries.licensed]
+//! path = "~/words/licensed.fst"
+//! key_file = "~/.secrets/licensed.key"
+//! ```
+//!
+//! Programs built with the `embedded-dictionary` feature also have a
+//! dictionary called "embedded", which is the default if no other is.
+
+use anyhow::{format_err, Context, Result};
+use serde::Deserialize;
+use std::{
+    collections::BTreeMap,
+    fs,
+    io::ErrorKind,
+    path::{Path, PathBuf},
+};
+
+use letter_permutations::{
+    dictionary::{Dictionary, LoadOptions},
+    encryption::DictionaryKey,
+    filter::Band,
+    storage::{is_url, HttpStorage},
+};
+
+use crate::registry::Registry;
+
+/// The name of the dictionary built into the program, if it has one.
+pub const EMBEDDED_NAME: &str = "embedded";
+
+/// The contents of our configuration file.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    /// The dictionary to use when none is specified.
+    pub default: Option<String>,
+    /// Named dictionaries.
+    #[serde(default)]
+    pub dictionaries: BTreeMap<String, Profile>,
+}
+
+/// A named dictionary, and the options to use with it unless they're
+/// overridden on the command line.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Profile {
+    /// The dictionary file. Relative paths are relative to the directory
+    /// containing the configuration file. This may also be an HTTP or HTTPS
+    /// URL, in which case we download the dictionary and its indexes each
+    /// time we load it.
+    pub path: PathBuf,
+    /// The language of the dictionary, such as "en".
+    pub language: Option<String>,
+    /// Only consider words with this frequency rank or better.
+    pub max_rank: Option<u64>,
+    /// Only consider words in this frequency band.
+    pub band: Option<Band>,
+    /// Only consider words with this many syllables.
+    pub syllables: Option<usize>,
+    /// Rescale word probabilities with this temperature.
+    pub temperature: Option<f64>,
+    /// Consider words which the dictionary marks as proper nouns.
+    #[serde(default)]
+    pub allow_proper_nouns: bool,
+    /// Score words by their probability among words of the same length.
+    #[serde(default)]
+    pub normalize_within_length: bool,
+    /// Don't check the dictionary's checksums when loading it.
+    #[serde(default)]
+    pub no_verify: bool,
+    /// A file containing the key to decrypt the dictionary with, if it was
+    /// encrypted. Relative paths are resolved like `path`.
+    pub key_file: Option<PathBuf>,
+    /// Use the dictionary built into the program instead of `path`.
+    #[serde(skip)]
+    pub embedded: bool,
+}
+
+impl Profile {
+    /// How to load this profile's dictionary.
+    pub fn load_options(&self) -> Result<LoadOptions> {
+        let key = self
+            .key_file
+            .as_deref()
+            .map(DictionaryKey::from_file)
+            .transpose()?;
+        Ok(LoadOptions {
+            verify: !self.no_verify,
+            key,
+        })
+    }
+
+    /// Load this profile's dictionary, without applying any of its filters.
+    pub fn load(&self) -> Result<Dictionary> {
+        let opts = self.load_options()?;
+        if self.embedded {
+            return load_embedded(&opts);
+        }
+        match self.path.to_str().filter(|path| is_url(path)) {
+            Some(url) => Ok(Dictionary::load_from(&HttpStorage::new(url)?, &opts)?),
+            None => Ok(Dictionary::load_with(&self.path, &opts)?),
+        }
+    }
+}
+
+/// Load the dictionary built into the program.
+#[cfg(feature = "embedded-dictionary")]
+fn load_embedded(opts: &LoadOptions) -> Result<Dictionary> {
+    Ok(Dictionary::embedded(opts)?)
+}
+
+/// Report that the program has no built-in dictionary.
+#[cfg(not(feature = "embedded-dictionary"))]
+fn load_embedded(_opts: &LoadOptions) -> Result<Dictionary> {
+    Err(format_err!(
+        "this program was built without the embedded-dictionary feature"
+    ))
+}
+
+impl Config {
+    /// The path of the user's configuration file.
+    pub fn default_path() -> Result<PathBuf> {
+        Ok(dirs::config_dir()
+            .ok_or_else(|| format_err!("could not find a config directory"))?
+            .join("word-puzzler")
+            .join("config.toml"))
+    }
+
+    /// Load the user's configuration file, or an empty configuration if there
+    /// isn't one.
+    pub fn load_default() -> Result<Config> {
+        Config::load(&Config::default_path()?)
+    }
+
+    /// Load the configuration file at `path`, or an empty configuration if it
+    /// doesn't exist.
+    pub fn load(path: &Path) -> Result<Config> {
+        let text = match fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(err) if err.kind() == ErrorKind::NotFound => {
+                return Ok(Config::default())
+            }
+            Err(err) => {
+                return Err(err)
+                    .with_context(|| format!("could not read {}", path.display()))
+            }
+        };
+        let mut config = toml::from_str::<Config>(&text)
+            .with_context(|| format!("could not parse {}", path.display()))?;
+        let base_dir = path.parent().unwrap_or_else(|| Path::new(""));
+        for profile in config.dictionaries.values_mut() {
+            profile.path = resolve_path(base_dir, &profile.path);
+            if let Some(key_file) = &mut profile.key_file {
+                *key_file = resolve_path(base_dir, key_file);
+            }
+        }
+        if let Some(default) = &config.default {
+            if !config.dictionaries.contains_key(default) {
+                return Err(format_err!(
+                    "{}: default dictionary {:?} is not defined",
+                    path.display(),
+                    default,
+                ));
+            }
+        }
+        Ok(config)
+    }
+
+    /// Find the dictionary called `name`, which may be defined here or
+    /// installed in `registry`, or be the dictionary built into the program.
+    /// If there's no dictionary with that name, we treat `name` as a path.
+    /// If `name` is `None`, we use our default dictionary, or failing that,
+    /// the registry's, or failing that, the built-in dictionary.
+    pub fn profile(&self, name: Option<&str>, registry: &Registry) -> Result<Profile> {
+        let embedded = cfg!(feature = "embedded-dictionary").then_some(EMBEDDED_NAME);
+        let name = name
+            .or(self.default.as_deref())
+            .or_else(|| registry.default_name())
+            .or(embedded)
+            .ok_or_else(|| {
+                format_err!(
+                    "no dictionary specified; pass --dict, set a default in {}, or \
+                     install one with `install-dict`",
+                    Config::default_path()
+                        .map(|path| path.display().to_string())
+                        .unwrap_or_else(|_| "the config file".to_owned()),
+                )
+            })?;
+        if let Some(profile) = self.dictionaries.get(name) {
+            return Ok(profile.clone());
+        }
+        let path = match registry.get(name) {
+            Some(installed) => installed.path.clone(),
+            None => PathBuf::from(name),
+        };
+        Ok(Profile {
+            path,
+            embedded: registry.get(name).is_none() && embedded == Some(name),
+            ..Profile::default()
+        })
+    }
+}
+
+/// Expand a leading `~` in `path`, and make relative paths relative to
+/// `base_dir`. URLs are left alone.
+fn resolve_path(base_dir: &Path, path: &Path) -> PathBuf {
+    if path.to_str().is_some_and(is_url) {
+        return path.to_owned();
+    }
+    if let Ok(rest) = path.strip_prefix("~") {
+        if let Some(home) = dirs::home_dir() {
+            return home.join(rest);
+        }
+    }
+    base_dir.join(path)
+}
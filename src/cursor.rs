@@ -0,0 +1,193 @@
+//! Walking a dictionary one letter at a time, for solvers which build words
+//! as they go, like Boggle, Scrabble and crossword fill.
+//!
+//! Our dictionaries are finite state transducers, which share the states for
+//! common prefixes. A `DictCursor` is one of those states: the set of words
+//! starting with the letters we've seen so far. Advancing it costs a single
+//! lookup, so a solver can give up on a path as soon as no word starts with
+//! it, without asking the dictionary about whole patterns. If the words are
+//! split into shards, each shard is its own transducer, and the first letter
+//! decides which one we walk.
+
+use fst::raw::{Fst, Node, Output, Transition};
+use std::{fmt, str::from_utf8};
+
+use crate::dictionary::Dictionary;
+use crate::probability::Prob;
+use crate::storage::Bytes;
+
+/// A position in a dictionary, after some prefix of one or more words.
+///
+/// Cursors see every word stored in the dictionary, ignoring its filter and
+/// scorers, so that walking stays cheap. Check complete words with
+/// `Dictionary::probability_of`, which applies them.
+#[derive(Clone, Copy)]
+pub struct DictCursor<'a> {
+    dict: &'a Dictionary,
+    /// The shard of the dictionary's words we're walking.
+    shard: usize,
+    /// Our state in that shard's transducer, or `None` if we're at the start
+    /// of every word and there's more than one shard, so we don't know which
+    /// shard to walk yet.
+    node: Option<Node<'a>>,
+    /// The output accumulated on the way to `node`, which is part of the
+    /// stored probability of any word ending here.
+    output: Output,
+}
+
+impl<'a> DictCursor<'a> {
+    /// A cursor at the start of every word in `dict`.
+    pub fn new(dict: &'a Dictionary) -> DictCursor<'a> {
+        match dict.word_map().shards().len() {
+            1 => DictCursor::shard_root(dict, 0),
+            _ => DictCursor {
+                dict,
+                shard: 0,
+                node: None,
+                output: Output::zero(),
+            },
+        }
+    }
+
+    /// A cursor at the start of every word in shard `shard` of `dict`.
+    fn shard_root(dict: &'a Dictionary, shard: usize) -> DictCursor<'a> {
+        DictCursor {
+            dict,
+            shard,
+            node: Some(shard_fst(dict, shard).root()),
+            output: Output::zero(),
+        }
+    }
+
+    /// A cursor at the start of every word in each shard, if we don't know
+    /// which shard to walk yet, and otherwise just us.
+    fn shard_roots(&self) -> impl Iterator<Item = DictCursor<'a>> + '_ {
+        let shards = match self.node {
+            Some(_) => self.shard..self.shard + 1,
+            None => 0..self.dict.word_map().shards().len(),
+        };
+        shards.map(move |shard| match self.node {
+            Some(_) => *self,
+            None => DictCursor::shard_root(self.dict, shard),
+        })
+    }
+
+    /// Our state in our shard's transducer. If we don't know which shard to
+    /// walk yet, the only word which could end here is the empty word, which
+    /// would be in the first shard.
+    fn node(&self) -> Node<'a> {
+        self.node.unwrap_or_else(|| shard_fst(self.dict, 0).root())
+    }
+
+    /// Move past `letter`, or return `None` if no word continues with it.
+    pub fn advance(&self, letter: char) -> Option<DictCursor<'a>> {
+        let mut buf = [0; 4];
+        self.advance_str(letter.encode_utf8(&mut buf))
+    }
+
+    /// Move past every letter of `letters`, or return `None` if no word
+    /// continues with them.
+    pub fn advance_str(&self, letters: &str) -> Option<DictCursor<'a>> {
+        letters.bytes().try_fold(*self, |cursor, byte| {
+            let cursor = match cursor.node {
+                Some(_) => cursor,
+                None => {
+                    let shard = self.dict.word_map().shard_for(Some(byte));
+                    DictCursor::shard_root(self.dict, shard)
+                }
+            };
+            let node = cursor.node();
+            let idx = node.find_input(byte)?;
+            Some(cursor.follow(node.transition(idx)))
+        })
+    }
+
+    /// Do the letters we've seen so far spell a word?
+    pub fn is_word(&self) -> bool {
+        self.node().is_final()
+    }
+
+    /// The probability of the word spelled by the letters we've seen so far,
+    /// or `None` if they don't spell a word. Invalid probabilities are
+    /// treated as missing words.
+    pub fn prob(&self) -> Option<Prob> {
+        if !self.is_word() {
+            return None;
+        }
+        let bits = self.output.cat(self.node().final_output()).value();
+        self.dict.decode_prob(bits).ok()
+    }
+
+    /// Can any word continue past here?
+    pub fn has_children(&self) -> bool {
+        self.shard_roots().any(|root| !root.node().is_empty())
+    }
+
+    /// Every letter which some word continues with, in order, along with
+    /// the cursor after it.
+    pub fn children(&self) -> Vec<(char, DictCursor<'a>)> {
+        let mut children = vec![];
+        for root in self.shard_roots() {
+            root.collect_children(&mut [0; 4], 0, &mut children);
+        }
+        children
+    }
+
+    /// Implementation of `children`. `buf[..len]` holds the bytes of a
+    /// multi-byte letter we've seen part of.
+    fn collect_children(
+        &self,
+        buf: &mut [u8; 4],
+        len: usize,
+        children: &mut Vec<(char, DictCursor<'a>)>,
+    ) {
+        for transition in self.node().transitions() {
+            buf[len] = transition.inp;
+            let child = self.follow(transition);
+            match from_utf8(&buf[..=len]) {
+                Ok(letter) => {
+                    let letter =
+                        letter.chars().next().expect("letter should be a char");
+                    children.push((letter, child));
+                }
+                // We're part way through a multi-byte letter.
+                Err(err) if err.error_len().is_none() && len + 1 < buf.len() => {
+                    child.collect_children(buf, len + 1, children)
+                }
+                // Not valid UTF-8, which `mkdict` never writes.
+                Err(_) => {}
+            }
+        }
+    }
+
+    /// The cursor after following `transition`.
+    fn follow(&self, transition: Transition) -> DictCursor<'a> {
+        DictCursor {
+            dict: self.dict,
+            shard: self.shard,
+            node: Some(shard_fst(self.dict, self.shard).node(transition.addr)),
+            output: self.output.cat(transition.out),
+        }
+    }
+}
+
+/// The transducer for shard `shard` of the words in `dict`.
+fn shard_fst(dict: &Dictionary, shard: usize) -> &Fst<Bytes> {
+    dict.word_map().shards()[shard].as_fst()
+}
+
+/// Show what's here, rather than the transducer's internals.
+impl fmt::Debug for DictCursor<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("DictCursor")
+            .field("is_word", &self.is_word())
+            .field(
+                "children",
+                &self
+                    .shard_roots()
+                    .map(|root| root.node().len())
+                    .sum::<usize>(),
+            )
+            .finish()
+    }
+}
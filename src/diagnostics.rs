@@ -0,0 +1,143 @@
+//! Explaining syntax errors in search patterns and input files, by pointing
+//! at the part which is wrong and suggesting fixes for common mistakes.
+//!
+//! The regex engines we search with report errors in terms of the pattern
+//! after we've expanded character classes like `\v`, which the user never
+//! wrote. So when a pattern fails to compile, we parse the user's pattern
+//! again ourselves to find out where the problem is.
+
+use regex_syntax::ast::{parse::Parser, ErrorKind};
+use std::{fmt, ops::Range, path::PathBuf};
+
+use crate::pattern::CharClasses;
+
+/// A syntax error in a search pattern.
+#[derive(Clone, Debug)]
+pub struct PatternDiagnostic {
+    /// The pattern, as the user wrote it.
+    pub pattern: String,
+    /// The byte offsets of the part of `pattern` which is wrong.
+    pub span: Range<usize>,
+    /// What's wrong.
+    pub message: String,
+    /// A suggestion for how to fix it, if we have one.
+    pub hint: Option<String>,
+}
+
+impl fmt::Display for PatternDiagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "invalid pattern: {}", self.message)?;
+        write_underlined(f, &self.pattern, &self.span)?;
+        if let Some(hint) = &self.hint {
+            write!(f, "\nhint: {}", hint)?;
+        }
+        Ok(())
+    }
+}
+
+/// A line of an input file which we couldn't parse.
+#[derive(Clone, Debug)]
+pub struct LineDiagnostic {
+    /// The file.
+    pub path: PathBuf,
+    /// The 1-based line number.
+    pub line: usize,
+    /// The text of the line, without its line ending.
+    pub text: String,
+    /// The byte offsets of the part of `text` which is wrong.
+    pub span: Range<usize>,
+    /// What's wrong.
+    pub message: String,
+}
+
+impl fmt::Display for LineDiagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{}:{}: {}", self.path.display(), self.line, self.message)?;
+        write_underlined(f, &self.text, &self.span)
+    }
+}
+
+/// Write `text`, indented, with `span` underlined by carets on the next line.
+fn write_underlined(
+    f: &mut fmt::Formatter<'_>,
+    text: &str,
+    span: &Range<usize>,
+) -> fmt::Result {
+    // Tabs would throw off our carets, so show them as spaces.
+    writeln!(f, "    {}", text.replace('\t', " "))?;
+    let before = text[..span.start].chars().count();
+    let width = text[span.clone()].chars().count().max(1);
+    write!(f, "    {}{}", " ".repeat(before), "^".repeat(width))
+}
+
+/// Find the syntax error in `pattern`, which may use the classes in
+/// `classes`. Returns `None` if we can't find one, in which case the caller
+/// should report the regex engine's own error.
+pub fn diagnose(pattern: &str, classes: &CharClasses) -> Option<PatternDiagnostic> {
+    // Replace our class escapes and backreferences, which the regex parser
+    // doesn't support, with `\w`. This is valid everywhere and has the same
+    // length, so offsets still point into `pattern`.
+    let mut masked = String::with_capacity(pattern.len());
+    let mut chars = pattern.chars();
+    while let Some(c) = chars.next() {
+        masked.push(c);
+        if c == '\\' {
+            match chars.next() {
+                Some(name) if classes.contains(name) || name.is_ascii_digit() => {
+                    masked.push('w')
+                }
+                Some(name) => masked.push(name),
+                None => {}
+            }
+        }
+    }
+
+    let err = Parser::new().parse(&masked).err()?;
+    let kind = err.kind();
+    let span = err.span().start.offset..err.span().end.offset;
+    let at = pattern[span.start..].chars().next();
+    let hint = match kind {
+        ErrorKind::RepetitionMissing => match at {
+            Some('?') => Some(
+                "\"?\" makes the letter before it optional; use \".\" for one \
+                 unknown letter"
+                    .to_owned(),
+            ),
+            Some(c @ ('*' | '+')) => Some(format!(
+                "\"{}\" repeats the letter before it; use \".*\" for any \
+                 number of unknown letters",
+                c
+            )),
+            _ => None,
+        },
+        ErrorKind::ClassUnclosed => {
+            Some("close the set of letters with \"]\", as in \"[aeiou]\"".to_owned())
+        }
+        ErrorKind::ClassRangeInvalid => Some(
+            "ranges go from the earlier letter to the later, as in \"[a-m]\""
+                .to_owned(),
+        ),
+        ErrorKind::GroupUnclosed => Some("add a \")\" to close the group".to_owned()),
+        ErrorKind::GroupUnopened => {
+            Some("remove the \")\", or add a \"(\" to start a group".to_owned())
+        }
+        ErrorKind::RepetitionCountUnclosed | ErrorKind::RepetitionCountInvalid => {
+            Some("repeat counts look like \"{3}\" or \"{2,4}\"".to_owned())
+        }
+        ErrorKind::EscapeUnrecognized => Some(format!(
+            "\\{} isn't a known class; \\v (vowel) and \\c (consonant) are \
+             built in, and more can be defined with --classes-file",
+            pattern[span.clone()].chars().nth(1).unwrap_or('?')
+        )),
+        ErrorKind::EscapeUnexpectedEof => Some(
+            "remove the \"\\\" at the end, or use \"\\\\\" to match it".to_owned(),
+        ),
+        _ => None,
+    };
+    Some(PatternDiagnostic {
+        pattern: pattern.to_owned(),
+        span,
+        message: kind.to_string(),
+        hint,
+    })
+}
@@ -1,117 +1,2625 @@
 //! High-performance dictionary.
 
-use anyhow::{format_err, Context, Result};
-use fst::{IntoStreamer, Map, MapBuilder, Streamer};
-use memmap2::Mmap;
-use once_cell::sync::Lazy;
+use fst::{
+    automaton::{Automaton, Levenshtein, Str},
+    Map, MapBuilder, Streamer,
+};
+use log::debug;
+use once_cell::sync::OnceCell;
+use ordered_float::OrderedFloat;
 use regex::Regex;
 use regex_automata::dense;
 use std::{
-    collections::BTreeMap,
-    fs::File,
-    io::{BufRead, BufReader, BufWriter},
-    path::Path,
-    str::from_utf8,
+    collections::{btree_map::Entry, BTreeMap, BTreeSet},
+    ffi::OsString,
+    fmt,
+    fs::{self, File},
+    io::{self, BufRead, BufReader, BufWriter},
+    iter,
+    ops::Range,
+    path::{Path, PathBuf},
+    process,
+    str::{from_utf8, FromStr},
+    sync::Arc,
 };
 
-use crate::probability::{Dist, Prob};
+use crate::anagram::signature;
+use crate::clue::read_clues;
+use crate::cursor::DictCursor;
+use crate::diagnostics::{diagnose, LineDiagnostic};
+use crate::encryption::{self, Cipher, DictionaryKey, Encryption};
+use crate::error::{Error, Result};
+use crate::external_sort::ExternalSorter;
+use crate::filter::WordFilter;
+use crate::fuzzy::{edit_distance, FuzzyMatch};
+use crate::junk::junk_reason;
+use crate::metadata::{self, Metadata, FORMAT_VERSION};
+use crate::morphology::base_form;
+use crate::pattern::CharClasses;
+use crate::probability::{check_temperature, Dist, Prob, Quantization};
+use crate::pronunciation::{read_pronunciations, reverse_phones};
+use crate::scorer::Scorers;
+use crate::shard::{
+    shard_extension, MapStream, ShardWriter, Sharded, ShardedMap, ShardedSet,
+};
+use crate::storage::{sidecar_path, Bytes, FileStorage, MemoryStorage, Storage};
+use crate::syllable::{count_syllables, estimate_syllables};
 
 /// A high-performance dictionary of English-language words.
+///
+/// Dictionaries are `Send + Sync`, and every query takes `&self`, so a single
+/// dictionary can answer queries from many threads at once. Cloning a
+/// dictionary is cheap: clones share the same memory-mapped indexes, but each
+/// clone has its own filter, character classes, temperature and scorers.
+#[derive(Clone)]
 pub struct Dictionary {
-    words: Map<Mmap>,
+    /// The indexes we loaded from disk.
+    indexes: Arc<Indexes>,
+    /// Every probability in the dictionary, sorted from most to least
+    /// probable. Only computed if we need ranks and have no rank index, and
+    /// shared with any clones using the same temperature.
+    sorted_probs: Arc<OnceCell<Vec<OrderedFloat<f64>>>>,
+    /// Which words we should return.
+    filter: WordFilter,
+    /// Character classes which may be used in search patterns.
+    classes: CharClasses,
+    /// If we're rescaling probabilities, the power to raise them to and the
+    /// total of the raised probabilities.
+    rescaling: Option<(f64, Prob)>,
+    /// Adjustments to the probabilities of the words we return.
+    scorers: Scorers,
+}
+
+// Make sure that dictionaries stay safe to share between threads.
+const _: fn() = || {
+    fn assert_send_sync<T: Send + Sync>() {}
+    assert_send_sync::<Dictionary>();
+};
+
+/// The read-only indexes making up a dictionary on disk.
+struct Indexes {
+    words: ShardedMap,
+    /// Frequency ranks for each word, if `mkdict` wrote a rank index.
+    ranks: Option<ShardedMap>,
+    /// Each word spelled backwards, for looking up words by suffix.
+    reversed: Option<ShardedMap>,
+    /// `word\tphones` for every known pronunciation.
+    pronunciations: Option<ShardedSet>,
+    /// `reversed phones\tword` for every known pronunciation, for looking up
+    /// words by how they end.
+    rhymes: Option<ShardedSet>,
+    /// `answer\tclue` for every known crossword clue.
+    clues: Option<ShardedSet>,
+    /// `suffix\0word` for every suffix of every word, for looking up words by
+    /// substring.
+    substrings: Option<ShardedSet>,
+    /// `signature\0word` for every word, where the signature is the word's
+    /// letters in sorted order, for looking up words by their letters.
+    anagrams: Option<ShardedSet>,
+    /// A GADDAG: `reversed prefix\0rest of word` for every way of splitting
+    /// every word, for finding words which pass through a letter.
+    gaddag: Option<ShardedSet>,
+    /// Words which are usually capitalized, like names, if `mkdict` was
+    /// given a case-sensitive word list.
+    proper_nouns: Option<ShardedSet>,
+    /// Information recorded by `mkdict`, if present.
+    metadata: Option<Metadata>,
+}
+
+/// Options for `Dictionary::build`.
+#[derive(Clone, Debug, Default)]
+pub struct BuildOptions {
+    /// A pronouncing dictionary in CMU format, used to build a pronunciation
+    /// index.
+    pub pronunciations_path: Option<PathBuf>,
+    /// A file of `answer\tclue` lines, used to build a clue index.
+    pub clues_path: Option<PathBuf>,
+    /// Build an index of every suffix of every word, for finding words which
+    /// contain a substring. This is several times larger than the dictionary.
+    pub substrings: bool,
+    /// Build an index of words by their sorted letters, for finding anagrams
+    /// without scanning the whole dictionary.
+    pub anagrams: bool,
+    /// Build a GADDAG, which indexes every word once for each of its letters,
+    /// for finding words which extend in both directions from a letter, as
+    /// when placing Scrabble tiles.
+    pub gaddag: bool,
+    /// Store each probability in this many bits instead of 64, which makes
+    /// the dictionary smaller at the cost of some precision.
+    pub quantize_bits: Option<u32>,
+    /// A file listing words to leave out, such as stopwords, one per line.
+    /// Blank lines and lines starting with "#" are ignored.
+    pub exclude_path: Option<PathBuf>,
+    /// Leave out words which entirely match this regex.
+    pub exclude_regex: Option<String>,
+    /// Leave out words which `junk_reason` thinks are junk, such as tokens
+    /// containing digits.
+    pub exclude_junk: bool,
+    /// Treat the word list as case-sensitive, adding together the counts of
+    /// words which differ only in case, and mark words which are capitalized
+    /// at least this fraction of the time as proper nouns.
+    pub proper_noun_threshold: Option<f64>,
+    /// Add the counts of inflected forms like "cats" and "stopped" to their
+    /// base forms, so that a word's probability isn't split across its
+    /// inflections.
+    pub fold_inflections: bool,
+    /// When folding inflections, keep the inflected forms in the dictionary
+    /// with their own counts, as well as adding them to their base forms.
+    pub keep_inflected_forms: bool,
+    /// What to do when the word list has more than one entry for a word,
+    /// or, for case-sensitive word lists, for a spelling.
+    pub on_duplicate: OnDuplicate,
+    /// Skip lines of the word list which we can't parse, instead of failing,
+    /// and describe them in the `BuildReport`.
+    pub skip_invalid: bool,
+    /// Replace an existing dictionary at the output path, instead of failing.
+    pub overwrite: bool,
+    /// Keep memory use to about this many bytes by sorting words on disk,
+    /// in shards which fit in memory, instead of holding them all at once.
+    /// This is slower, and can't be combined with `fold_inflections`.
+    pub max_memory: Option<u64>,
+    /// The word list is already sorted by lowercase word in byte order (for
+    /// a lowercase list, as by `LC_ALL=C sort -k2`), so we can add up each
+    /// word's counts as we read them. This implies building the indexes on
+    /// disk, as with `max_memory`, using a default limit if there isn't
+    /// one.
+    pub sorted: bool,
+    /// Split each index into files of about this many bytes, so that no
+    /// single file is too large. A new file only starts between keys with
+    /// different first bytes, so a file can be larger if many keys start
+    /// with the same byte.
+    pub shard_size: Option<u64>,
+    /// Encrypt every file except the metadata with this key, so that the
+    /// dictionary can only be loaded by someone who has it.
+    pub encryption_key: Option<DictionaryKey>,
+}
+
+/// What `Dictionary::build` should do when a word list has more than one
+/// entry for the same word, as when "Cat" and "cat" are both listed.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum OnDuplicate {
+    /// Fail, reporting the duplicate entry.
+    #[default]
+    Error,
+    /// Add the counts together.
+    Sum,
+    /// Keep the largest count.
+    Max,
+    /// Keep the count of the first entry.
+    First,
+}
+
+impl OnDuplicate {
+    /// The count to keep for a word with entries for `old` and then `new`,
+    /// or `None` if that's an error.
+    fn merge(self, old: u64, new: u64) -> Option<u64> {
+        match self {
+            OnDuplicate::Error => None,
+            // If this overflows, so will the total count, which we report.
+            OnDuplicate::Sum => Some(old.saturating_add(new)),
+            OnDuplicate::Max => Some(old.max(new)),
+            OnDuplicate::First => Some(old),
+        }
+    }
+}
+
+/// Parse `error`, `sum`, `max` or `first`.
+impl FromStr for OnDuplicate {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "error" => Ok(OnDuplicate::Error),
+            "sum" => Ok(OnDuplicate::Sum),
+            "max" => Ok(OnDuplicate::Max),
+            "first" => Ok(OnDuplicate::First),
+            _ => Err(Error::InvalidOption(format!(
+                "expected \"error\", \"sum\", \"max\" or \"first\", found {:?}",
+                s
+            ))),
+        }
+    }
+}
+
+/// What `Dictionary::build` wrote.
+#[derive(Clone, Debug)]
+pub struct BuildReport {
+    /// Each file we wrote, and its size in bytes.
+    pub files: Vec<(PathBuf, u64)>,
+    /// How we quantized probabilities, if we did.
+    pub quantization: Option<Quantization>,
+    /// The number of words we left out because of an exclusion rule.
+    pub excluded: u64,
+    /// The number of inflected forms we added to their base forms.
+    pub folded: u64,
+    /// The number of duplicate entries we merged into earlier entries.
+    pub merged: u64,
+    /// The lines of the word list we skipped, if we were asked to.
+    pub skipped: SkippedLines,
+}
+
+/// Options for `Dictionary::load_with`.
+#[derive(Clone, Debug)]
+pub struct LoadOptions {
+    /// Check the dictionary and its indexes against the checksums recorded
+    /// by `mkdict`, so that truncated or partly written files are reported
+    /// as corrupt. This reads every file, which takes a moment for very large
+    /// dictionaries.
+    pub verify: bool,
+    /// The key to decrypt the dictionary with, if it was encrypted.
+    pub key: Option<DictionaryKey>,
+}
+
+impl Default for LoadOptions {
+    fn default() -> Self {
+        LoadOptions {
+            verify: true,
+            key: None,
+        }
+    }
+}
+
+/// Options for `Dictionary::find_matches_with`.
+#[derive(Clone, Debug, Default)]
+pub struct SearchOptions {
+    /// The maximum number of matches to return. By default, we stop walking
+    /// the dictionary as soon as we have this many matches.
+    pub limit: Option<usize>,
+    /// Return the `limit` most probable matches, instead of the first `limit`
+    /// matches in alphabetical order. This needs to examine every match.
+    pub most_probable: bool,
+}
+
+/// A word matched by `Dictionary::find_captures_with`.
+#[derive(Clone, Debug)]
+pub struct Captures {
+    /// The probability of the word.
+    pub prob: Prob,
+    /// The word itself.
+    pub word: String,
+    /// The text matched by each capture group in the regex, or `None` if a
+    /// group didn't participate in the match.
+    pub groups: Vec<Option<String>>,
+}
+
+/// The position of a word in the dictionary, sorted by descending frequency.
+/// Words with equal counts share the same rank.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Rank {
+    /// The 1-based rank of this word.
+    pub rank: u64,
+    /// The number of words in the dictionary.
+    pub out_of: u64,
+}
+
+impl Rank {
+    /// The percentage of the dictionary at or above this rank.
+    pub fn percentile(self) -> f64 {
+        self.rank as f64 / self.out_of as f64 * 100.0
+    }
+}
+
+impl fmt::Display for Rank {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // Round up, so that the most common words are in the "top 0.01%" and
+        // not the "top 0.00%".
+        let percentile = (self.percentile() * 100.0).ceil() / 100.0;
+        write!(f, "#{} by frequency, top {:.2}%", self.rank, percentile)
+    }
 }
 
 impl Dictionary {
     /// Build a new dictionary and write it to disk.
-    pub fn build(in_words_path: &Path, out_dict_path: &Path) -> Result<()> {
-        // Compile our regex.
-        static COUNT_RE: Lazy<Regex> = Lazy::new(|| {
-            // We permit leading whitespace for compatibility with `uniq -c`.
-            Regex::new("^\\s*([0-9]+)\\s+(.+)\r?\n?$")
-                .expect("invalid regex in source")
-        });
+    ///
+    /// We build every file under a temporary name in the same directory, and
+    /// only rename them into place once they're all complete, so that an
+    /// interrupted build never leaves a partial dictionary behind.
+    pub fn build(
+        in_words_path: &Path,
+        out_dict_path: &Path,
+        opts: &BuildOptions,
+    ) -> Result<BuildReport> {
+        if !opts.overwrite && out_dict_path.exists() {
+            return Err(Error::DictionaryExists {
+                path: out_dict_path.to_owned(),
+            });
+        }
+        let mut staging_name = OsString::from(".");
+        staging_name.push(out_dict_path.file_name().unwrap_or_default());
+        staging_name.push(format!(".tmp{}", process::id()));
+        let staging_path = out_dict_path.with_file_name(staging_name);
+
+        let built = Dictionary::build_files(in_words_path, &staging_path, opts)
+            .and_then(|report| match &opts.encryption_key {
+                Some(key) => encrypt_dictionary(&staging_path, key, report),
+                None => Ok(report),
+            });
+        let mut report = match built {
+            Ok(report) => report,
+            Err(err) => {
+                for extension in dictionary_extensions(&staging_path) {
+                    // This is only cleanup, so ignore errors.
+                    let _ = fs::remove_file(sidecar_path(&staging_path, &extension));
+                }
+                return Err(err);
+            }
+        };
+
+        // Move our files into place, and remove any indexes or shards left
+        // over from an older build, which would no longer match.
+        let mut extensions = dictionary_extensions(&staging_path);
+        extensions.extend(dictionary_extensions(out_dict_path));
+        extensions.sort();
+        extensions.dedup();
+        for extension in &extensions {
+            let staging_file = sidecar_path(&staging_path, extension);
+            let out_file = sidecar_path(out_dict_path, extension);
+            if staging_file.exists() {
+                fs::rename(&staging_file, &out_file).map_err(Error::io(&out_file))?;
+            } else if out_file.exists() {
+                fs::remove_file(&out_file).map_err(Error::io(&out_file))?;
+            }
+        }
+        for (path, _) in &mut report.files {
+            let extension = extensions
+                .iter()
+                .find(|extension| sidecar_path(&staging_path, extension) == *path);
+            if let Some(extension) = extension {
+                *path = sidecar_path(out_dict_path, extension);
+            }
+        }
+        Ok(report)
+    }
+
+    /// Build the files for `build`, using `out_dict_path` as given.
+    fn build_files(
+        in_words_path: &Path,
+        out_dict_path: &Path,
+        opts: &BuildOptions,
+    ) -> Result<BuildReport> {
+        if let Some(threshold) = opts.proper_noun_threshold {
+            if !(0.0..=1.0).contains(&threshold) {
+                return Err(Error::InvalidOption(format!(
+                    "proper noun threshold must be between 0 and 1, not {}",
+                    threshold
+                )));
+            }
+        }
+        if let Some(bits) = opts.quantize_bits {
+            if !(1..=32).contains(&bits) {
+                return Err(Error::InvalidOption(format!(
+                    "cannot quantize probabilities to {} bits",
+                    bits
+                )));
+            }
+        }
+        if opts.sorted || opts.max_memory.is_some() {
+            return Dictionary::build_files_on_disk(
+                in_words_path,
+                out_dict_path,
+                opts,
+                opts.max_memory.unwrap_or(DEFAULT_MAX_MEMORY),
+            );
+        }
 
         // Load our count information.
+        let exclusions = Exclusions::new(opts)?;
+        let mut excluded = 0;
+        let mut merged = 0;
         let mut total_count: u64 = 0;
-        let mut counts = BTreeMap::<String, u64>::new();
-        let in_words_file = File::open(in_words_path)
-            .with_context(|| format!("could not open {}", in_words_path.display()))?;
-        let in_words_rdr = BufReader::new(in_words_file);
-        for line in in_words_rdr.lines() {
-            let line = line.with_context(|| {
-                format!("could not read from {}", in_words_path.display())
-            })?;
-            if let Some(cap) = COUNT_RE.captures(&line) {
-                let count = cap[1]
-                    .parse::<u64>()
-                    .with_context(|| format!("could not parse count {:?}", &cap[1]))?;
-                let word = cap[2].to_ascii_lowercase();
-                if counts.insert(word, count).is_some() {
-                    return Err(format_err!("duplicate word {:?}", &cap[2]));
-                }
-                total_count = total_count.checked_add(count).ok_or_else(|| {
-                    format_err!("total word count is too large for u64")
+        // The count of each entry. For case-sensitive word lists, entries
+        // are keyed by their spelling, and otherwise by word.
+        let mut entries = BTreeMap::<String, u64>::new();
+        let skipped =
+            read_counts(in_words_path, opts.skip_invalid, |line, count, surface| {
+                let invalid = |message: String| Error::InvalidWordList {
+                    path: in_words_path.to_owned(),
+                    line,
+                    message,
+                };
+                let word = surface.to_ascii_lowercase();
+                if exclusions.excludes(&word) {
+                    excluded += 1;
+                    return Ok(());
+                }
+                let key = if opts.proper_noun_threshold.is_some() {
+                    surface.clone()
+                } else {
+                    word
+                };
+                let added = match entries.entry(key) {
+                    Entry::Vacant(entry) => *entry.insert(count),
+                    Entry::Occupied(mut entry) => {
+                        let old = *entry.get();
+                        let new =
+                            opts.on_duplicate.merge(old, count).ok_or_else(|| {
+                                invalid(format!("duplicate word {:?}", surface))
+                            })?;
+                        entry.insert(new);
+                        merged += 1;
+                        new - old
+                    }
+                };
+                total_count = total_count.checked_add(added).ok_or_else(|| {
+                    invalid("total word count is too large for u64".to_owned())
                 })?;
-            } else {
-                return Err(format_err!(
-                    "expected \"count\\s+word\", found {:?}",
-                    line
-                ));
+                Ok(())
+            })?;
+
+        // For case-sensitive word lists, "Paris" and "paris" are the same
+        // word, so add their counts together, and count how many times each
+        // word was capitalized.
+        let mut counts = BTreeMap::<String, u64>::new();
+        let mut capitalized = BTreeMap::<String, u64>::new();
+        if opts.proper_noun_threshold.is_some() {
+            for (surface, count) in entries {
+                let word = surface.to_ascii_lowercase();
+                if surface.starts_with(char::is_uppercase) {
+                    *capitalized.entry(word.clone()).or_default() += count;
+                }
+                *counts.entry(word).or_default() += count;
             }
+        } else {
+            counts = entries;
         }
 
-        // Open our output file.
-        let out_dict_file = File::create(out_dict_path).with_context(|| {
-            format!("could not create {}", out_dict_path.display())
-        })?;
-        let out_dict_wtr = BufWriter::new(out_dict_file);
-        let mut builder = MapBuilder::new(out_dict_wtr).with_context(|| {
-            format!("could not create dictionary {}", out_dict_path.display())
-        })?;
+        // Now that we know the total count of each word, decide which words
+        // are usually capitalized.
+        let mut proper_nouns = capitalized
+            .into_iter()
+            .filter(|(word, caps)| is_proper_noun(opts, *caps, counts[word]))
+            .map(|(word, _)| word)
+            .collect::<Vec<_>>();
 
-        // Add words to our dictionary.
-        for (word, count) in counts {
-            let prob = Prob::from_fraction(count, total_count);
-            builder
-                .insert(word.as_bytes(), prob.to_bits())
-                .with_context(|| {
-                    format!("could not write to {}", out_dict_path.display())
-                })?;
+        // Fold inflected forms into their base forms. A base may itself look
+        // inflected, as in "needs" and "need", so we follow each chain of
+        // bases to its end. Moving counts between words doesn't change the
+        // total, so other words keep the same probabilities. But if we keep the
+        // inflected forms too, their counts are now counted twice, so we add
+        // them to the total again to keep the probabilities summing to 1.
+        let mut folded = 0;
+        if opts.fold_inflections {
+            let bases = counts
+                .keys()
+                .filter_map(|word| {
+                    let base = base_form(word, |base| counts.contains_key(base))?;
+                    Some((word.clone(), base))
+                })
+                .collect::<BTreeMap<_, _>>();
+            for (word, mut base) in &bases {
+                while let Some(next) = bases.get(base) {
+                    base = next;
+                }
+                let count = counts[word];
+                *counts.get_mut(base).expect("base should be a word") += count;
+                if opts.keep_inflected_forms {
+                    total_count = total_count.checked_add(count).ok_or_else(|| {
+                        Error::InvalidOption(
+                            "total word count is too large for u64".to_owned(),
+                        )
+                    })?;
+                }
+                folded += 1;
+            }
+            if !opts.keep_inflected_forms {
+                for word in bases.keys() {
+                    counts.remove(word);
+                }
+                proper_nouns.retain(|word| counts.contains_key(word));
+            }
         }
 
-        // Finish writing our dictionary to disk.
-        builder.finish().with_context(|| {
-            format!("could not write to {}", out_dict_path.display())
-        })?;
-        Ok(())
+        // Decide how to store our probabilities.
+        let quantization = opts.quantize_bits.map(|bits| {
+            Quantization::covering(
+                bits,
+                counts
+                    .values()
+                    .map(|&count| Prob::from_fraction(count, total_count)),
+            )
+        });
+        let encode = |count: u64| encode_count(&quantization, count, total_count);
+
+        // Write our words to disk.
+        let word_paths = write_map(
+            out_dict_path,
+            "",
+            opts,
+            counts.iter().map(|(word, &count)| (word, encode(count))),
+        )?;
+        let mut written = word_paths.clone();
+
+        // Rank our words by descending count, giving equal counts equal ranks.
+        let mut by_count = counts.iter().collect::<Vec<_>>();
+        by_count.sort_by(|(_, c1), (_, c2)| c2.cmp(c1));
+        let mut ranks = BTreeMap::<&str, u64>::new();
+        let mut prev_count = None;
+        let mut rank = 0;
+        for (i, (word, &count)) in by_count.into_iter().enumerate() {
+            if prev_count != Some(count) {
+                rank = i as u64 + 1;
+                prev_count = Some(count);
+            }
+            ranks.insert(word, rank);
+        }
+        written.extend(write_map(out_dict_path, "rank", opts, ranks)?);
+
+        // Index our words by suffix.
+        let mut reversed = counts
+            .iter()
+            .map(|(word, &count)| {
+                (word.chars().rev().collect::<String>(), encode(count))
+            })
+            .collect::<Vec<_>>();
+        reversed.sort();
+        written.extend(write_map(out_dict_path, "rev", opts, reversed)?);
+
+        // Index every suffix of every word, so that a prefix search for a
+        // substring finds every word containing it.
+        if opts.substrings {
+            let mut suffixes = counts
+                .keys()
+                .flat_map(|word| substring_keys(word))
+                .collect::<Vec<_>>();
+            suffixes.sort();
+            written.extend(write_set(out_dict_path, "sub", opts, suffixes)?);
+        }
+
+        // Index our words by their sorted letters, so that all the anagrams of
+        // a word are stored together.
+        if opts.anagrams {
+            let mut by_signature = counts
+                .keys()
+                .map(|word| anagram_key(word))
+                .collect::<Vec<_>>();
+            by_signature.sort();
+            written.extend(write_set(out_dict_path, "anagram", opts, by_signature)?);
+        }
+
+        // Index each word once for each of its letters.
+        if opts.gaddag {
+            let mut paths = counts
+                .keys()
+                .flat_map(|word| gaddag_keys(word))
+                .collect::<Vec<_>>();
+            paths.sort();
+            written.extend(write_set(out_dict_path, "gaddag", opts, paths)?);
+        }
+
+        let is_word = |word: &str| counts.contains_key(word);
+        write_pronunciation_and_clue_indexes(
+            out_dict_path,
+            opts,
+            is_word,
+            &mut written,
+        )?;
+
+        // Index the words which are usually capitalized.
+        if opts.proper_noun_threshold.is_some() {
+            written.extend(write_set(out_dict_path, "proper", opts, &proper_nouns)?);
+        }
+
+        // Record what we built, including a hash identifying its contents and
+        // checksums of each index.
+        let mut length_totals = LengthTotals::default();
+        for (word, &count) in &counts {
+            length_totals.add(word, count);
+        }
+        let metadata = Metadata {
+            format_version: FORMAT_VERSION,
+            word_count: counts.len() as u64,
+            phrase_count: counts.keys().filter(|w| w.contains(' ')).count() as u64,
+            proper_noun_count: proper_nouns.len() as u64,
+            total_count,
+            content_hash: shards_content_hash(&word_paths)?,
+            checksums: index_checksums(&written)?,
+            quantization,
+            encryption: None,
+            length_totals: length_totals.finish(total_count),
+            shards: shard_counts(out_dict_path),
+        };
+        let path = sidecar_path(out_dict_path, "meta");
+        metadata.write(&path)?;
+        written.push(path);
+
+        Ok(BuildReport {
+            files: file_sizes(written)?,
+            quantization,
+            excluded,
+            folded,
+            merged,
+            skipped,
+        })
     }
 
+    /// Like `build_files`, but keep memory use to about `max_memory` bytes by
+    /// reading the word list as a stream and sorting everything else on disk.
+    /// We never hold the word list in memory, so we can't fold inflections,
+    /// which means looking up arbitrary words.
+    fn build_files_on_disk(
+        in_words_path: &Path,
+        out_dict_path: &Path,
+        opts: &BuildOptions,
+        max_memory: u64,
+    ) -> Result<BuildReport> {
+        if opts.fold_inflections {
+            return Err(Error::InvalidOption(
+                "folding inflections needs the whole word list in memory, so it \
+                 can't be combined with a memory limit or a sorted word list"
+                    .to_owned(),
+            ));
+        }
+        // We sometimes read from one sort while filling the next, so give
+        // each half of our memory.
+        let max_memory = (max_memory / 2).min(usize::MAX as u64) as usize;
+        let mut sort_count = 0;
+        let mut new_sorter = || {
+            sort_count += 1;
+            let prefix = sidecar_path(out_dict_path, &format!("sort{}", sort_count));
+            ExternalSorter::new(prefix, max_memory)
+        };
+
+        // Add together the counts of each word, and write them to a
+        // temporary map, which we'll read several times. We'll also need the
+        // total count before we can compute probabilities.
+        let exclusions = Exclusions::new(opts)?;
+        let mut excluded = 0;
+        let counts_file = TempFile(sidecar_path(out_dict_path, "counts"));
+        let counts_path = &counts_file.0;
+        let mut counts =
+            CountsWriter::new(in_words_path, out_dict_path, counts_path, opts)?;
+        let skipped = if opts.sorted {
+            // We can add up each word's entries as we read them.
+            read_counts(in_words_path, opts.skip_invalid, |line, count, surface| {
+                let word = surface.to_ascii_lowercase();
+                if exclusions.excludes(&word) {
+                    excluded += 1;
+                    return Ok(());
+                }
+                counts.add(word, surface, count, line)
+            })?
+        } else {
+            // Sort our word list by word, then by line, so that duplicates
+            // are merged in order. Case-sensitive word lists keep each
+            // spelling separate, so they sort by spelling before line. Each
+            // record is `word\0spelling\0line count surface`, with the
+            // numbers in big-endian order, and an empty spelling if we're
+            // not sorting by it.
+            let mut entries = new_sorter();
+            let skipped = read_counts(
+                in_words_path,
+                opts.skip_invalid,
+                |line, count, surface| {
+                    let word = surface.to_ascii_lowercase();
+                    if exclusions.excludes(&word) {
+                        excluded += 1;
+                        return Ok(());
+                    }
+                    let spelling = match opts.proper_noun_threshold {
+                        Some(_) => surface.as_str(),
+                        None => "",
+                    };
+                    let mut record = format!("{}\0{}\0", word, spelling).into_bytes();
+                    record.extend_from_slice(&(line as u64).to_be_bytes());
+                    record.extend_from_slice(&count.to_be_bytes());
+                    record.extend_from_slice(surface.as_bytes());
+                    entries.push(record)
+                },
+            )?;
+            for record in entries.finish()? {
+                let (word, surface, count, line) = parse_entry_record(&record?)?;
+                counts.add(word, surface, count, line)?;
+            }
+            skipped
+        };
+        let CountTotals {
+            total_count,
+            word_count,
+            phrase_count,
+            proper_noun_count,
+            merged,
+            proper_nouns_paths,
+        } = counts.finish()?;
+        let counts = Map::from(fst_file(counts_path)?);
+
+        // Decide how to store our probabilities, and write our words to
+        // disk.
+        let quantization = opts.quantize_bits.map(|bits| {
+            let mut stream = counts.stream();
+            let probs = iter::from_fn(|| {
+                let (_, count) = stream.next()?;
+                Some(Prob::from_fraction(count, total_count))
+            });
+            Quantization::covering(bits, probs)
+        });
+        let encode = |count: u64| encode_count(&quantization, count, total_count);
+        let mut length_totals = LengthTotals::default();
+        let mut stream = counts.stream();
+        let word_paths = write_map(
+            out_dict_path,
+            "",
+            opts,
+            iter::from_fn(|| {
+                let (word, count) = stream.next()?;
+                // `CountsWriter` only writes valid UTF-8.
+                length_totals.add(&String::from_utf8_lossy(word), count);
+                Some((word.to_owned(), encode(count)))
+            }),
+        )?;
+        let mut written = word_paths.clone();
+
+        // Rank our words by descending count, giving equal counts equal
+        // ranks. We sort by the complement of each count, so that the
+        // largest counts come first.
+        let mut by_count = new_sorter();
+        let mut stream = counts.stream();
+        while let Some((word, count)) = stream.next() {
+            let mut record = (u64::MAX - count).to_be_bytes().to_vec();
+            record.extend_from_slice(word);
+            by_count.push(record)?;
+        }
+        let mut ranks = new_sorter();
+        let mut prev_count = None;
+        let mut rank = 0;
+        for (i, record) in by_count.finish()?.enumerate() {
+            let record = record?;
+            let (count, word) = record.split_at(8);
+            let count = be_u64(count);
+            if prev_count != Some(count) {
+                rank = i as u64 + 1;
+                prev_count = Some(count);
+            }
+            ranks.push(map_record(word, rank))?;
+        }
+        written.extend(write_sorted_map(out_dict_path, "rank", opts, ranks)?);
+
+        // Index our words by suffix.
+        let mut reversed = new_sorter();
+        let mut stream = counts.stream();
+        while let Some((word, count)) = stream.next() {
+            let word = from_utf8(word).map_err(Error::invalid_utf8)?;
+            let reversed_word = word.chars().rev().collect::<String>();
+            reversed.push(map_record(reversed_word.as_bytes(), encode(count)))?;
+        }
+        written.extend(write_sorted_map(out_dict_path, "rev", opts, reversed)?);
+
+        // Build the optional indexes of words, in the same way as
+        // `build_files`.
+        type KeysFor = fn(&str) -> Vec<String>;
+        let word_indexes: [(bool, &str, KeysFor); 3] = [
+            (opts.substrings, "sub", |word| {
+                substring_keys(word).collect()
+            }),
+            (opts.anagrams, "anagram", |word| vec![anagram_key(word)]),
+            (opts.gaddag, "gaddag", |word| gaddag_keys(word).collect()),
+        ];
+        for (wanted, extension, keys_for) in word_indexes {
+            if !wanted {
+                continue;
+            }
+            let mut keys = new_sorter();
+            let mut stream = counts.keys();
+            while let Some(word) = stream.next() {
+                let word = from_utf8(word).map_err(Error::invalid_utf8)?;
+                for key in keys_for(word) {
+                    keys.push(key.into_bytes())?;
+                }
+            }
+            written.extend(itertools::process_results(keys.finish()?, |keys| {
+                write_set(out_dict_path, extension, opts, keys)
+            })??);
+        }
+
+        let is_word = |word: &str| counts.contains_key(word);
+        write_pronunciation_and_clue_indexes(
+            out_dict_path,
+            opts,
+            is_word,
+            &mut written,
+        )?;
+        written.extend(proper_nouns_paths);
+
+        // Record what we built.
+        let metadata = Metadata {
+            format_version: FORMAT_VERSION,
+            word_count,
+            phrase_count,
+            proper_noun_count,
+            total_count,
+            content_hash: shards_content_hash(&word_paths)?,
+            checksums: index_checksums(&written)?,
+            quantization,
+            encryption: None,
+            length_totals: length_totals.finish(total_count),
+            shards: shard_counts(out_dict_path),
+        };
+        let path = sidecar_path(out_dict_path, "meta");
+        metadata.write(&path)?;
+        written.push(path);
+
+        Ok(BuildReport {
+            files: file_sizes(written)?,
+            quantization,
+            excluded,
+            folded: 0,
+            merged,
+            skipped,
+        })
+    }
+
+    /// Load the dictionary at `dict_path`, along with any indexes built next
+    /// to it, and check them against their checksums.
     pub fn load(dict_path: &Path) -> Result<Dictionary> {
-        // We need to use `unsafe` because bad things can happen if someone
-        // modifies the file while we're using it.
-        let dict_file = File::open(dict_path)
-            .with_context(|| format!("error opening {}", dict_path.display()))?;
-        let mapped = unsafe { Mmap::map(&dict_file) }
-            .with_context(|| format!("error mapping {}", dict_path.display()))?;
-        let words = Map::new(mapped).with_context(|| {
-            format!("error initializing dictionary {}", dict_path.display())
+        Dictionary::load_with(dict_path, &LoadOptions::default())
+    }
+
+    /// Like `load`, but with extra options.
+    pub fn load_with(dict_path: &Path, opts: &LoadOptions) -> Result<Dictionary> {
+        Dictionary::load_from(&FileStorage::new(dict_path), opts)
+    }
+
+    /// Load a dictionary and its indexes from `storage`, which may hold them
+    /// in memory or fetch them from elsewhere instead of mapping files.
+    pub fn load_from(storage: &dyn Storage, opts: &LoadOptions) -> Result<Dictionary> {
+        let metadata = storage
+            .read("meta")?
+            .map(|json| Metadata::parse(&storage.name("meta"), json.as_ref()))
+            .transpose()?;
+        let encryption = metadata.as_ref().and_then(|m| m.encryption.as_ref());
+        let cipher = match (encryption, &opts.key) {
+            (Some(encryption), Some(key)) => Some(Cipher::new(key, encryption)?),
+            (Some(_), None) => {
+                return Err(Error::KeyRequired {
+                    path: storage.name(""),
+                })
+            }
+            (None, _) => None,
+        };
+        // Load the file with `extension`, decrypting it and checking it
+        // against its checksum if we need to.
+        let load = |extension: &str| -> Result<Option<fst::raw::Fst<Bytes>>> {
+            let bytes = match storage.read(extension)? {
+                Some(bytes) => bytes,
+                None => return Ok(None),
+            };
+            let path = storage.name(extension);
+            let bytes = decrypt(&path, extension, bytes, cipher.as_ref())?;
+            // We check the words against `content_hash` once we have every
+            // shard of them.
+            let expected = metadata.as_ref().and_then(|m| m.checksums.get(extension));
+            if let Some(expected) = expected.filter(|_| opts.verify) {
+                if metadata::content_hash(bytes.as_ref()) != *expected {
+                    return Err(checksum_mismatch(&path));
+                }
+            }
+            Ok(Some(new_fst(&path, bytes)?))
+        };
+        // Load every shard of the index with `extension`, which must have
+        // all of them if it has any.
+        let load_shards = |extension: &str| -> Result<Option<Vec<_>>> {
+            let count = metadata
+                .as_ref()
+                .and_then(|m| m.shards.get(extension))
+                .copied()
+                .unwrap_or(1);
+            let mut shards = Vec::with_capacity(count);
+            for shard in 0..count {
+                let shard_extension = shard_extension(extension, shard);
+                match load(&shard_extension)? {
+                    Some(fst) => shards.push(fst),
+                    None if shard == 0 => return Ok(None),
+                    None => {
+                        return Err(Error::Io {
+                            path: storage.name(&shard_extension),
+                            source: io::ErrorKind::NotFound.into(),
+                        })
+                    }
+                }
+            }
+            Ok(Some(shards))
+        };
+        let map = |extension: &str| -> Result<Option<ShardedMap>> {
+            load_shards(extension)?
+                .map(|shards| Sharded::new(&storage.name(extension), shards))
+                .transpose()
+        };
+        let set = |extension: &str| -> Result<Option<ShardedSet>> {
+            load_shards(extension)?
+                .map(|shards| Sharded::new(&storage.name(extension), shards))
+                .transpose()
+        };
+        let words = load_shards("")?.ok_or_else(|| Error::Io {
+            path: storage.name(""),
+            source: io::ErrorKind::NotFound.into(),
         })?;
-        Ok(Dictionary { words })
+        if let Some(m) = metadata.as_ref().filter(|_| opts.verify) {
+            let hash = metadata::content_hash_of(words.iter().map(|w| w.as_bytes()));
+            if hash != m.content_hash {
+                return Err(checksum_mismatch(&storage.name("")));
+            }
+        }
+        let indexes = Indexes {
+            words: Sharded::new(&storage.name(""), words)?,
+            ranks: map("rank")?,
+            reversed: map("rev")?,
+            pronunciations: set("pron")?,
+            rhymes: set("rhyme")?,
+            clues: set("clues")?,
+            substrings: set("sub")?,
+            anagrams: set("anagram")?,
+            gaddag: set("gaddag")?,
+            proper_nouns: set("proper")?,
+            metadata,
+        };
+        Ok(Dictionary {
+            indexes: Arc::new(indexes),
+            sorted_probs: Arc::default(),
+            filter: WordFilter::default(),
+            classes: CharClasses::default(),
+            rescaling: None,
+            scorers: Scorers::new(),
+        })
     }
 
-    pub fn find_matches(&self, regex: &str) -> Result<Dist<String>> {
-        let dfa = dense::Builder::new().anchored(true).build(&regex)?;
-        let mut stream = self.words.search(&dfa).into_stream();
+    /// Use `words` and `meta`, the contents of a dictionary file built by
+    /// `mkdict` and of its metadata, as a dictionary without any indexes.
+    /// This is meant for dictionaries built into the program with
+    /// `include_bytes!`, which don't need to be loaded from disk. We need the
+    /// metadata to decode quantized probabilities and to decrypt encrypted
+    /// dictionaries.
+    pub fn from_static(
+        words: &'static [u8],
+        meta: &'static [u8],
+        opts: &LoadOptions,
+    ) -> Result<Dictionary> {
+        let mut storage = MemoryStorage::new("built-in dictionary");
+        storage.insert("", words);
+        storage.insert("meta", meta);
+        Dictionary::load_from(&storage, opts)
+    }
+
+    /// The dictionary built into this program, if it was compiled with the
+    /// `embedded-dictionary` feature. `WORD_PUZZLER_EMBEDDED_DICT` must be
+    /// set to the absolute path of a dictionary built by `mkdict` when
+    /// compiling; there is no default. Its `.meta` file is built in too, but
+    /// not its indexes, so commands which need an index will ask for a
+    /// dictionary on disk.
+    #[cfg(feature = "embedded-dictionary")]
+    pub fn embedded(opts: &LoadOptions) -> Result<Dictionary> {
+        Dictionary::from_static(
+            include_bytes!(env!(
+                "WORD_PUZZLER_EMBEDDED_DICT",
+                "set WORD_PUZZLER_EMBEDDED_DICT to the absolute path of a \
+                 dictionary built by `mkdict` to use the embedded-dictionary \
+                 feature"
+            )),
+            include_bytes!(concat!(env!("WORD_PUZZLER_EMBEDDED_DICT"), ".meta")),
+            opts,
+        )
+    }
+
+    /// Only return words accepted by `filter` from now on.
+    pub fn set_filter(&mut self, filter: WordFilter) {
+        self.filter = filter;
+    }
+
+    /// The filter deciding which words we return.
+    pub fn filter(&self) -> &WordFilter {
+        &self.filter
+    }
+
+    /// Decode a probability stored in one of our maps.
+    pub(crate) fn decode_prob(&self, bits: u64) -> Result<Prob> {
+        let prob = self.decode_stored_prob(bits)?;
+        Ok(match self.rescaling {
+            Some((exponent, total)) => prob.powf(exponent) / total,
+            None => prob,
+        })
+    }
+
+    /// Decode a probability exactly as it was stored, without rescaling.
+    /// Fails if the stored probability is invalid.
+    fn decode_stored_prob(&self, bits: u64) -> Result<Prob> {
+        match self
+            .indexes
+            .metadata
+            .as_ref()
+            .and_then(|m| m.quantization.as_ref())
+        {
+            Some(quantization) => Ok(quantization.decode(bits)),
+            None => Prob::try_from_bits(bits),
+        }
+    }
+
+    /// The probability of `word` as it was stored, ignoring our filter and
+    /// any rescaling. Words with invalid probabilities are treated as missing.
+    pub(crate) fn raw_probability_of(&self, word: &str) -> Option<Prob> {
+        self.indexes
+            .words
+            .get(word)
+            .and_then(|bits| self.decode_stored_prob(bits).ok())
+    }
+
+    /// The map from each word to its stored probability, for merging
+    /// dictionaries. Use `decode_prob` to read the probabilities.
+    pub(crate) fn word_map(&self) -> &ShardedMap {
+        &self.indexes.words
+    }
+
+    /// Information recorded by `mkdict`, if this dictionary has any.
+    pub fn metadata(&self) -> Option<&Metadata> {
+        self.indexes.metadata.as_ref()
+    }
+
+    /// Rescale every probability in the dictionary as if by `Dist::rescale`.
+    /// Temperatures above 1 make rare words relatively more probable, which
+    /// stops multi-word answers from being swamped by single common words.
+    pub fn set_temperature(&mut self, temperature: f64) -> Result<()> {
+        check_temperature(temperature)?;
+        self.sorted_probs = Arc::default();
+        let exponent = 1.0 / temperature;
+        let mut probs = Vec::with_capacity(self.len());
+        let mut stream = self.indexes.words.stream();
+        while let Some((_, prob_bits)) = stream.next() {
+            probs.push(self.decode_stored_prob(prob_bits)?.powf(exponent));
+        }
+        self.rescaling = Some((exponent, Prob::sum(probs)));
+        Ok(())
+    }
+
+    /// The total probability of the words with each number of letters,
+    /// ignoring spaces and punctuation, and ignoring our filter. We use the
+    /// totals recorded by `mkdict` if we have them, and otherwise add up
+    /// every word.
+    pub fn length_totals(&self) -> Result<BTreeMap<usize, Prob>> {
+        let recorded = self
+            .indexes
+            .metadata
+            .as_ref()
+            .map(|m| &m.length_totals)
+            .filter(|totals| !totals.is_empty() && self.rescaling.is_none());
+        if let Some(totals) = recorded {
+            return totals
+                .iter()
+                .map(|(&len, &prob)| Ok((len, Prob::from_f64(prob)?)))
+                .collect();
+        }
+        let mut probs = BTreeMap::<usize, Vec<Prob>>::new();
+        let mut stream = self.indexes.words.stream();
+        while let Some((word, prob_bits)) = stream.next() {
+            let word = from_utf8(word).map_err(Error::invalid_utf8)?;
+            probs
+                .entry(letter_count(word))
+                .or_default()
+                .push(self.decode_prob(prob_bits)?);
+        }
+        Ok(probs
+            .into_iter()
+            .map(|(len, probs)| (len, Prob::sum(probs)))
+            .collect())
+    }
+
+    /// Adjust the probability of every word we return with `scorers`, such as
+    /// to boost theme words. Filters like `max_rank` still see each word's
+    /// probability in the dictionary.
+    pub fn set_scorers(&mut self, scorers: Scorers) {
+        self.scorers = scorers;
+    }
+
+    /// The adjustments we make to the probabilities of words we return.
+    pub fn scorers(&self) -> &Scorers {
+        &self.scorers
+    }
+
+    /// Allow `classes` to be used in search patterns from now on.
+    pub fn set_char_classes(&mut self, classes: CharClasses) {
+        self.classes = classes;
+    }
+
+    /// Does our filter accept `word`?
+    pub(crate) fn accepts(&self, word: &str, prob: Prob) -> bool {
+        self.filter.accepts_everything() || self.filter.accepts(self, word, prob)
+    }
+
+    /// A hash identifying the contents of this dictionary. We use the hash
+    /// recorded by `mkdict` if we have one, and otherwise hash the words.
+    pub fn content_hash(&self) -> String {
+        match &self.indexes.metadata {
+            Some(metadata) => metadata.content_hash.clone(),
+            None => metadata::content_hash_of(
+                self.indexes
+                    .words
+                    .shards()
+                    .iter()
+                    .map(|shard| shard.as_fst().as_bytes()),
+            ),
+        }
+    }
+
+    /// Does this dictionary have a GADDAG index?
+    pub fn has_gaddag(&self) -> bool {
+        self.indexes.gaddag.is_some()
+    }
+
+    /// Does this dictionary contain phrases of more than one word? We only
+    /// know this for dictionaries with metadata.
+    pub fn has_phrases(&self) -> bool {
+        self.indexes
+            .metadata
+            .as_ref()
+            .is_some_and(|m| m.phrase_count > 0)
+    }
+
+    /// Is `word` usually capitalized, like a name? We only know this for
+    /// dictionaries built from case-sensitive word lists.
+    pub fn is_proper_noun(&self, word: &str) -> bool {
+        self.indexes
+            .proper_nouns
+            .as_ref()
+            .is_some_and(|proper_nouns| proper_nouns.contains(word))
+    }
+
+    /// The number of words in this dictionary.
+    pub fn len(&self) -> usize {
+        self.indexes.words.len()
+    }
+
+    /// Is this dictionary empty?
+    pub fn is_empty(&self) -> bool {
+        self.indexes.words.is_empty()
+    }
+
+    /// Look up the probability of a single word, which should already be
+    /// lowercase. Returns `None` if the word is not in the dictionary, or if
+    /// its stored probability is invalid.
+    pub fn probability_of(&self, word: &str) -> Option<Prob> {
+        self.indexes
+            .words
+            .get(word)
+            .and_then(|bits| self.decode_prob(bits).ok())
+            .filter(|&prob| self.accepts(word, prob))
+            .map(|prob| self.scorers.score(word, prob))
+    }
+
+    /// The number of syllables in `word`. We use the pronunciation index if
+    /// we have one, and otherwise guess based on spelling.
+    pub fn syllables_of(&self, word: &str) -> usize {
+        if self.indexes.pronunciations.is_some() {
+            if let Some(phones) = self
+                .pronunciations_of(word)
+                .ok()
+                .and_then(|p| p.into_iter().next())
+            {
+                return count_syllables(&phones);
+            }
+        }
+        estimate_syllables(word)
+    }
+
+    /// Look up the frequency rank of a single word, which should already be
+    /// lowercase.
+    pub fn rank_of(&self, word: &str) -> Result<Option<Rank>> {
+        Ok(self
+            .probability_of(word)
+            .map(|prob| self.rank_given_prob(word, prob)))
+    }
+
+    /// The rank of `word`, which has probability `prob`. This ignores our
+    /// filter, so that filters can use it.
+    pub(crate) fn rank_given_prob(&self, word: &str, prob: Prob) -> Rank {
+        let out_of = self.len() as u64;
+        if let Some(rank) = self
+            .indexes
+            .ranks
+            .as_ref()
+            .and_then(|ranks| ranks.get(word))
+        {
+            return Rank { rank, out_of };
+        }
+
+        // We don't have a rank index, so fall back to counting how many words
+        // are more probable than this one.
+        let sorted_probs = self.sorted_probs.get_or_init(|| {
+            let mut probs = Vec::with_capacity(self.len());
+            let mut stream = self.indexes.words.stream();
+            while let Some((_, prob_bits)) = stream.next() {
+                if let Ok(prob) = self.decode_prob(prob_bits) {
+                    probs.push(OrderedFloat(prob.to_f64()));
+                }
+            }
+            probs.sort();
+            probs
+        });
+        let more_probable =
+            sorted_probs.partition_point(|&p| p < OrderedFloat(prob.to_f64()));
+        Rank {
+            rank: more_probable as u64 + 1,
+            out_of,
+        }
+    }
+
+    /// Find all words ending with `suffix`, using the reversed index.
+    pub fn find_by_suffix(&self, suffix: &str) -> Result<Dist<String>> {
         let mut events = vec![];
-        while let Some((word_bytes, prob_bits)) = stream.next() {
-            let prob = Prob::from_bits(prob_bits);
-            let word = from_utf8(word_bytes)
-                .context("dict contains invalid UTF-8")?
-                .to_owned();
+        for entry in self.words_with_suffix(suffix)? {
+            let (word, prob) = entry?;
             events.push((prob, word));
         }
         let mut dist = Dist::from_vec(events);
         dist.sort_by_probability();
         Ok(dist)
     }
+
+    /// Find every word or phrase spelled with exactly the letters in
+    /// `letters`, ignoring the spaces between the words of each phrase. So
+    /// "newyork" finds "new york" as well as "newyork".
+    pub fn find_spelled(&self, letters: &str) -> Result<Dist<String>> {
+        if !self.has_phrases() {
+            return Ok(Dist::from_vec(
+                self.probability_of(letters)
+                    .map(|prob| (prob, letters.to_owned()))
+                    .into_iter()
+                    .collect(),
+            ));
+        }
+        self.find_matches_by(SkipSpaces(Str::new(letters)), &SearchOptions::default())
+    }
+
+    /// Find all words containing `substring`. This uses the substring index
+    /// or the GADDAG if we have one, and otherwise searches the whole
+    /// dictionary.
+    pub fn find_containing(&self, substring: &str) -> Result<Dist<String>> {
+        let substrings = match &self.indexes.substrings {
+            Some(substrings) => substrings,
+            None if self.has_gaddag() && !substring.is_empty() => {
+                let mut events = self
+                    .words_through(substring)?
+                    .into_iter()
+                    .map(|m| (m.prob, m.word))
+                    .collect::<Vec<_>>();
+                // A word containing `substring` twice has two paths.
+                events.sort_by(|(_, w1), (_, w2)| w1.cmp(w2));
+                events.dedup_by(|(_, w1), (_, w2)| w1 == w2);
+                let mut dist = Dist::from_vec(events);
+                dist.sort_by_probability();
+                return Ok(dist);
+            }
+            None => {
+                debug!("no substring index, so scanning every word");
+                let regex = format!(".*{}.*", regex::escape(substring));
+                return self.find_matches(&regex);
+            }
+        };
+        let mut stream = substrings.search(Str::new(substring).starts_with());
+        let mut words = BTreeSet::new();
+        while let Some(key) = stream.next() {
+            let key = from_utf8(key).map_err(Error::invalid_utf8)?;
+            if let Some((_, word)) = key.split_once('\0') {
+                words.insert(word.to_owned());
+            }
+        }
+        let mut events = vec![];
+        for word in words {
+            if let Some(prob) = self.probability_of(&word) {
+                events.push((prob, word));
+            }
+        }
+        let mut dist = Dist::from_vec(events);
+        dist.sort_by_probability();
+        Ok(dist)
+    }
+
+    /// Find every word containing `fragment`, using the GADDAG. Each match's
+    /// `anchor` is the index of the last letter of `fragment` in the word,
+    /// and words containing `fragment` more than once are returned once for
+    /// each time.
+    pub fn words_through(&self, fragment: &str) -> Result<Vec<GaddagMatch>> {
+        let reversed = fragment.chars().rev().collect::<String>();
+        self.search_gaddag(Str::new(&reversed).starts_with())
+    }
+
+    /// Find every path through the GADDAG accepted by `automaton`. Each path
+    /// starts at some letter of a word, which we call the anchor, and reads
+    /// the letters before it in reverse, then a `\0` byte, then the letters
+    /// after it. This lets callers grow words outwards from a letter, as when
+    /// placing tiles next to a Scrabble tile.
+    pub fn search_gaddag<A: Automaton>(
+        &self,
+        automaton: A,
+    ) -> Result<Vec<GaddagMatch>> {
+        let gaddag = self.indexes.gaddag.as_ref().ok_or(Error::MissingIndex {
+            index: "GADDAG",
+            flag: " --with-gaddag",
+        })?;
+        let mut stream = gaddag.search(&automaton);
+        let mut matches = vec![];
+        while let Some(key) = stream.next() {
+            let key = from_utf8(key).map_err(Error::invalid_utf8)?;
+            let (left, right) = match key.split_once('\0') {
+                Some(parts) => parts,
+                None => continue,
+            };
+            // Every key starts with its anchor, so a damaged index is the
+            // only way to get a key starting with `\0`.
+            let anchor = left.chars().count().checked_sub(1).ok_or_else(|| {
+                Error::CorruptDictionary(format!("GADDAG key {:?} has no anchor", key))
+            })?;
+            let word = left.chars().rev().chain(right.chars()).collect::<String>();
+            if let Some(prob) = self.probability_of(&word) {
+                matches.push(GaddagMatch { anchor, word, prob });
+            }
+        }
+        Ok(matches)
+    }
+
+    /// Find all words whose signatures (see `anagram::signature`) are accepted
+    /// by `automaton`, using the anagram index. Returns `None` if we have no
+    /// anagram index, in which case the caller will need to scan every word.
+    pub(crate) fn find_by_signature<A: Automaton>(
+        &self,
+        automaton: A,
+    ) -> Result<Option<Dist<String>>> {
+        let anagrams = match &self.indexes.anagrams {
+            Some(anagrams) => anagrams,
+            None => return Ok(None),
+        };
+        let key = SignatureKey(automaton);
+        let mut stream = anagrams.search(&key);
+        let mut events = vec![];
+        while let Some(key) = stream.next() {
+            let key = from_utf8(key).map_err(Error::invalid_utf8)?;
+            if let Some((_, word)) = key.split_once('\0') {
+                if let Some(prob) = self.probability_of(word) {
+                    events.push((prob, word.to_owned()));
+                }
+            }
+        }
+        let mut dist = Dist::from_vec(events);
+        dist.sort_by_probability();
+        Ok(Some(dist))
+    }
+
+    /// Return all known pronunciations of `word`.
+    pub fn pronunciations_of(&self, word: &str) -> Result<Vec<String>> {
+        let pronunciations =
+            self.indexes
+                .pronunciations
+                .as_ref()
+                .ok_or(Error::MissingIndex {
+                    index: "pronunciation",
+                    flag: " --pronunciations",
+                })?;
+        values_for_key(pronunciations, word)
+    }
+
+    /// Return all known crossword clues for `answer`.
+    pub fn clues_for(&self, answer: &str) -> Result<Vec<String>> {
+        let clues = self.indexes.clues.as_ref().ok_or(Error::MissingIndex {
+            index: "clue",
+            flag: " --clues",
+        })?;
+        values_for_key(clues, answer)
+    }
+
+    /// Return `(word, pronunciation)` for every pronunciation ending with the
+    /// phones in `ending`.
+    pub fn pronunciations_ending_with(
+        &self,
+        ending: &str,
+    ) -> Result<Vec<(String, String)>> {
+        let rhymes = self.indexes.rhymes.as_ref().ok_or(Error::MissingIndex {
+            index: "pronunciation",
+            flag: " --pronunciations",
+        })?;
+        let reversed_ending = reverse_phones(ending);
+        let mut stream = rhymes.search(Str::new(&reversed_ending).starts_with());
+        let mut result = vec![];
+        while let Some(key) = stream.next() {
+            let key = from_utf8(key).map_err(Error::invalid_utf8)?;
+            // Make sure we matched whole phones, and not just part of a phone.
+            let rest = &key[reversed_ending.len()..];
+            if !rest.starts_with([' ', '\t']) {
+                continue;
+            }
+            if let Some((reversed_phones, word)) = key.split_once('\t') {
+                result.push((word.to_owned(), reverse_phones(reversed_phones)));
+            }
+        }
+        Ok(result)
+    }
+
+    /// Return every word pronounced exactly as `phones`.
+    pub fn words_pronounced(&self, phones: &str) -> Result<Vec<String>> {
+        let rhymes = self.indexes.rhymes.as_ref().ok_or(Error::MissingIndex {
+            index: "pronunciation",
+            flag: " --pronunciations",
+        })?;
+        let prefix = format!("{}\t", reverse_phones(phones));
+        let mut stream = rhymes.search(Str::new(&prefix).starts_with());
+        let mut result = vec![];
+        while let Some(key) = stream.next() {
+            let key = from_utf8(key).map_err(Error::invalid_utf8)?;
+            result.push(key[prefix.len()..].to_owned());
+        }
+        Ok(result)
+    }
+
+    /// Return `(pronunciation, words)` for every pronunciation shared by two
+    /// or more different words. This is a single pass over the pronunciation
+    /// index, which keeps words with the same phones next to each other.
+    pub fn homophone_groups(&self) -> Result<Vec<(String, Vec<String>)>> {
+        let rhymes = self.indexes.rhymes.as_ref().ok_or(Error::MissingIndex {
+            index: "pronunciation",
+            flag: " --pronunciations",
+        })?;
+        let mut stream = rhymes.stream();
+        let mut groups = vec![];
+        let mut current: Option<(String, Vec<String>)> = None;
+        while let Some(key) = stream.next() {
+            let key = from_utf8(key).map_err(Error::invalid_utf8)?;
+            let (reversed_phones, word) = match key.split_once('\t') {
+                Some(parts) => parts,
+                None => continue,
+            };
+            match &mut current {
+                Some((phones, words)) if phones == reversed_phones => {
+                    words.push(word.to_owned());
+                }
+                _ => {
+                    groups.extend(current.take().filter(|(_, w)| w.len() > 1));
+                    current =
+                        Some((reversed_phones.to_owned(), vec![word.to_owned()]));
+                }
+            }
+        }
+        groups.extend(current.filter(|(_, w)| w.len() > 1));
+        Ok(groups
+            .into_iter()
+            .map(|(reversed_phones, words)| (reverse_phones(&reversed_phones), words))
+            .collect())
+    }
+
+    /// A cursor at the start of every word, for walking the dictionary one
+    /// letter at a time.
+    pub fn cursor(&self) -> DictCursor<'_> {
+        DictCursor::new(self)
+    }
+
+    /// Iterate over every word in the dictionary, in alphabetical order.
+    pub fn words(&self) -> Words<'_> {
+        Words {
+            dict: self,
+            stream: self.indexes.words.stream(),
+            reversed: false,
+        }
+    }
+
+    /// Iterate over every word starting with `prefix`, in alphabetical order.
+    /// This is a range query on the dictionary, so it only visits matching
+    /// words.
+    pub fn words_with_prefix(&self, prefix: &str) -> Words<'_> {
+        Words {
+            dict: self,
+            stream: self.indexes.words.prefix(prefix.as_bytes()),
+            reversed: false,
+        }
+    }
+
+    /// Iterate over every word ending with `suffix`, in alphabetical order of
+    /// the reversed words. This uses the reversed index, so it only visits
+    /// matching words.
+    pub fn words_with_suffix(&self, suffix: &str) -> Result<Words<'_>> {
+        let reversed = self.indexes.reversed.as_ref().ok_or(Error::MissingIndex {
+            index: "suffix",
+            flag: "",
+        })?;
+        let reversed_suffix = suffix.chars().rev().collect::<String>();
+        Ok(Words {
+            dict: self,
+            stream: reversed.prefix(reversed_suffix.as_bytes()),
+            reversed: true,
+        })
+    }
+
+    pub fn find_matches(&self, regex: &str) -> Result<Dist<String>> {
+        self.find_matches_with(regex, &SearchOptions::default())
+    }
+
+    /// Like `find_matches`, but with extra options.
+    pub fn find_matches_with(
+        &self,
+        regex: &str,
+        opts: &SearchOptions,
+    ) -> Result<Dist<String>> {
+        if opts.limit == Some(0) {
+            return Ok(Dist::from_vec(vec![]));
+        }
+        let expanded = &self.classes.expand(regex);
+        // Our DFA can't handle back-references, so we search for a looser
+        // pattern and check each candidate against the real one.
+        let (dfa_regex, full_regex) = match approximate_backreferences(expanded) {
+            Some(approx) => {
+                let full_regex = compile_full_regex(expanded)
+                    .map_err(|err| self.diagnosed(regex, err))?;
+                (approx, Some(full_regex))
+            }
+            None => (expanded.to_owned(), None),
+        };
+        let dfa = dense::Builder::new()
+            .anchored(true)
+            .build(&dfa_regex)
+            .map_err(|source| {
+                let err = Error::InvalidRegex {
+                    pattern: regex.to_owned(),
+                    source: source.into(),
+                };
+                self.diagnosed(regex, err)
+            })?;
+        // Patterns describe the letters of a phrase, like a crossword answer,
+        // so we skip over the spaces between its words.
+        let dfa = SkipSpaces(&dfa);
+        match full_regex {
+            Some(full_regex) => self.collect_matches(dfa, opts, |word| {
+                full_regex
+                    .is_match(&phrase_letters(word))
+                    .map_err(|source| Error::InvalidRegex {
+                        pattern: regex.to_owned(),
+                        source: source.into(),
+                    })
+            }),
+            None => self.collect_matches(dfa, opts, |_| Ok(true)),
+        }
+    }
+
+    /// Like `find_matches_with`, but also return the text matched by each
+    /// capture group in `regex`. We use the fst to find candidates, and then
+    /// run a capturing regex over each one.
+    pub fn find_captures_with(
+        &self,
+        regex: &str,
+        opts: &SearchOptions,
+    ) -> Result<Vec<Captures>> {
+        let capturing = compile_full_regex(&self.classes.expand(regex))
+            .map_err(|err| self.diagnosed(regex, err))?;
+        let mut result = vec![];
+        for (prob, word) in self.find_matches_with(regex, opts)?.into_vec() {
+            let letters = phrase_letters(&word);
+            let caps = capturing.captures(&letters).map_err(|source| {
+                Error::InvalidRegex {
+                    pattern: regex.to_owned(),
+                    source: source.into(),
+                }
+            })?;
+            if let Some(caps) = caps {
+                let groups = caps
+                    .iter()
+                    .skip(1)
+                    .map(|group| group.map(|m| m.as_str().to_owned()))
+                    .collect();
+                result.push(Captures { prob, word, groups });
+            }
+        }
+        Ok(result)
+    }
+
+    /// Find all words within `max_distance` edits of `word`, sorted from most
+    /// to least probable, and then by distance.
+    pub fn find_within_distance(
+        &self,
+        word: &str,
+        max_distance: u32,
+    ) -> Result<Vec<FuzzyMatch>> {
+        let automaton = Levenshtein::new(word, max_distance).map_err(|source| {
+            Error::FuzzySearch {
+                word: word.to_owned(),
+                source,
+            }
+        })?;
+        let mut matches = self
+            .find_matches_by(automaton, &SearchOptions::default())?
+            .into_vec()
+            .into_iter()
+            .map(|(prob, candidate)| FuzzyMatch {
+                prob,
+                distance: edit_distance(word, &candidate),
+                word: candidate,
+            })
+            .collect::<Vec<_>>();
+        matches.sort_by(FuzzyMatch::cmp_by_probability);
+        Ok(matches)
+    }
+
+    /// Find all words accepted by `automaton`, which lets callers search for
+    /// things that would be awkward to express as a regex.
+    pub fn find_matches_by<A: Automaton>(
+        &self,
+        automaton: A,
+        opts: &SearchOptions,
+    ) -> Result<Dist<String>> {
+        self.collect_matches(automaton, opts, |_| Ok(true))
+    }
+
+    /// If `err` is an invalid regex error for `pattern`, try to explain
+    /// exactly what's wrong with it.
+    fn diagnosed(&self, pattern: &str, err: Error) -> Error {
+        if let Error::InvalidRegex { .. } = err {
+            if let Some(diagnostic) = diagnose(pattern, &self.classes) {
+                return Error::PatternSyntax(Box::new(diagnostic));
+            }
+        }
+        err
+    }
+
+    /// Find all words accepted by both `automaton` and `check`.
+    fn collect_matches<A, F>(
+        &self,
+        automaton: A,
+        opts: &SearchOptions,
+        check: F,
+    ) -> Result<Dist<String>>
+    where
+        A: Automaton,
+        F: Fn(&str) -> Result<bool>,
+    {
+        let mut stream = self.indexes.words.search(&automaton);
+        // When we want the most probable matches, only keep the best so far.
+        let limit = opts.limit.unwrap_or(usize::MAX);
+        let mut events = Dist::top_k(if opts.most_probable {
+            limit
+        } else {
+            usize::MAX
+        });
+        while let Some((word_bytes, prob_bits)) = stream.next() {
+            let prob = self.decode_prob(prob_bits)?;
+            // Our scorers might make this word more probable, so we can only
+            // skip it this early if we don't have any.
+            if self.scorers.is_empty() && !events.would_keep(prob) {
+                continue;
+            }
+            let word = from_utf8(word_bytes).map_err(Error::invalid_utf8)?;
+            if !self.accepts(word, prob) {
+                continue;
+            }
+            let prob = self.scorers.score(word, prob);
+            if !events.would_keep(prob) || !check(word)? {
+                continue;
+            }
+            events.push(prob, word.to_owned());
+            if !opts.most_probable && events.pushed() == limit {
+                break;
+            }
+        }
+        Ok(events.into_dist())
+    }
+}
+
+/// A word found by searching the GADDAG.
+#[derive(Clone, Debug)]
+pub struct GaddagMatch {
+    pub word: String,
+    /// The index of the letter the GADDAG path started from, in characters.
+    pub anchor: usize,
+    pub prob: Prob,
+}
+
+/// An iterator over words in a dictionary, and their probabilities.
+pub struct Words<'a> {
+    dict: &'a Dictionary,
+    stream: MapStream<'a>,
+    /// Are the keys in `stream` spelled backwards?
+    reversed: bool,
+}
+
+impl<'a> Iterator for Words<'a> {
+    type Item = Result<(String, Prob)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let (word_bytes, prob_bits) = self.stream.next()?;
+            let prob = match self.dict.decode_prob(prob_bits) {
+                Ok(prob) => prob,
+                Err(err) => return Some(Err(err)),
+            };
+            let word = match from_utf8(word_bytes) {
+                Ok(word) if self.reversed => word.chars().rev().collect(),
+                Ok(word) => word.to_owned(),
+                Err(err) => return Some(Err(Error::invalid_utf8(err))),
+            };
+            if self.dict.accepts(&word, prob) {
+                let prob = self.dict.scorers.score(&word, prob);
+                return Some(Ok((word, prob)));
+            }
+        }
+    }
+}
+
+/// If `regex` contains back-references like `\1`, which our DFA can't
+/// handle, return a looser version of `regex` where each back-reference
+/// matches anything.
+fn approximate_backreferences(regex: &str) -> Option<String> {
+    let mut approx = String::with_capacity(regex.len());
+    let mut found = false;
+    let mut chars = regex.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            approx.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some(d) if d.is_ascii_digit() && d != '0' => {
+                while chars.peek().is_some_and(|c| c.is_ascii_digit()) {
+                    chars.next();
+                }
+                approx.push_str("(?:.*)");
+                found = true;
+            }
+            Some(escaped) => {
+                approx.push(c);
+                approx.push(escaped);
+            }
+            None => approx.push(c),
+        }
+    }
+    if found {
+        Some(approx)
+    } else {
+        None
+    }
+}
+
+/// Compile `regex` with full support for captures and back-references,
+/// anchored at both ends.
+pub(crate) fn compile_full_regex(regex: &str) -> Result<fancy_regex::Regex> {
+    fancy_regex::Regex::new(&format!("^(?:{})$", regex)).map_err(|source| {
+        Error::InvalidRegex {
+            pattern: regex.to_owned(),
+            source: source.into(),
+        }
+    })
+}
+
+/// Normalize a word or phrase from a word list. Phrases like "new york" are
+/// stored with a single space between each word.
+fn normalize_entry(entry: &str) -> String {
+    normalize_spaces(entry).to_ascii_lowercase()
+}
+
+/// Read a list of words or phrases, one per line, normalized the same way as
+/// the words in a dictionary. Blank lines and lines starting with "#" are
+/// ignored.
+pub fn read_word_list(path: &Path) -> Result<Vec<String>> {
+    let text = fs::read_to_string(path).map_err(Error::io(path))?;
+    Ok(text
+        .lines()
+        .map(normalize_entry)
+        .filter(|word| !word.is_empty() && !word.starts_with('#'))
+        .collect())
+}
+
+/// Replace each run of whitespace in `entry` with a single space, without
+/// changing its case.
+fn normalize_spaces(entry: &str) -> String {
+    entry.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// The letters of `word`, without the spaces between the words of a phrase.
+pub fn phrase_letters(word: &str) -> String {
+    word.chars().filter(|&c| c != ' ').collect()
+}
+
+/// Adapts an automaton to match dictionary keys as if the spaces between the
+/// words of phrases weren't there.
+struct SkipSpaces<A>(A);
+
+impl<A> Automaton for SkipSpaces<A>
+where
+    A: Automaton,
+    A::State: Clone,
+{
+    type State = A::State;
+
+    fn start(&self) -> Self::State {
+        self.0.start()
+    }
+
+    fn is_match(&self, state: &Self::State) -> bool {
+        self.0.is_match(state)
+    }
+
+    fn can_match(&self, state: &Self::State) -> bool {
+        self.0.can_match(state)
+    }
+
+    fn will_always_match(&self, state: &Self::State) -> bool {
+        self.0.will_always_match(state)
+    }
+
+    fn accept(&self, state: &Self::State, byte: u8) -> Self::State {
+        if byte == b' ' {
+            state.clone()
+        } else {
+            self.0.accept(state, byte)
+        }
+    }
+}
+
+/// Adapts an automaton over signatures to match `signature\0word` keys in the
+/// anagram index.
+struct SignatureKey<A>(A);
+
+/// The state of a `SignatureKey` automaton.
+#[derive(Clone, Debug)]
+enum SignatureKeyState<S> {
+    /// We're still reading the signature.
+    Signature(S),
+    /// We've read an accepted signature, and will accept any word.
+    Word,
+    /// We can't match anything.
+    Dead,
+}
+
+impl<A: Automaton> Automaton for SignatureKey<A> {
+    type State = SignatureKeyState<A::State>;
+
+    fn start(&self) -> Self::State {
+        SignatureKeyState::Signature(self.0.start())
+    }
+
+    fn is_match(&self, state: &Self::State) -> bool {
+        matches!(state, SignatureKeyState::Word)
+    }
+
+    fn can_match(&self, state: &Self::State) -> bool {
+        match state {
+            SignatureKeyState::Signature(inner) => self.0.can_match(inner),
+            SignatureKeyState::Word => true,
+            SignatureKeyState::Dead => false,
+        }
+    }
+
+    fn will_always_match(&self, state: &Self::State) -> bool {
+        matches!(state, SignatureKeyState::Word)
+    }
+
+    fn accept(&self, state: &Self::State, byte: u8) -> Self::State {
+        match state {
+            SignatureKeyState::Signature(inner) if byte == 0 => {
+                if self.0.is_match(inner) {
+                    SignatureKeyState::Word
+                } else {
+                    SignatureKeyState::Dead
+                }
+            }
+            SignatureKeyState::Signature(inner) => {
+                SignatureKeyState::Signature(self.0.accept(inner, byte))
+            }
+            SignatureKeyState::Word => SignatureKeyState::Word,
+            SignatureKeyState::Dead => SignatureKeyState::Dead,
+        }
+    }
+}
+
+/// Given a `set` containing `key\tvalue` entries, return all the values for
+/// `key`.
+fn values_for_key(set: &ShardedSet, key: &str) -> Result<Vec<String>> {
+    let prefix = format!("{}\t", key);
+    let mut stream = set.search(Str::new(&prefix).starts_with());
+    let mut values = vec![];
+    while let Some(entry) = stream.next() {
+        let entry = from_utf8(entry).map_err(Error::invalid_utf8)?;
+        values.push(entry[prefix.len()..].to_owned());
+    }
+    Ok(values)
+}
+
+/// How much memory to use for sorting on disk, if we have to and weren't
+/// given a limit.
+const DEFAULT_MAX_MEMORY: u64 = 1 << 30;
+
+/// The extensions of every index file we may build next to a dictionary.
+const INDEX_EXTENSIONS: &[&str] = &[
+    "rank", "rev", "pron", "rhyme", "clues", "sub", "anagram", "gaddag", "proper",
+    "meta",
+];
+
+/// The extension of the dictionary at `dict_path`, which is empty, and of
+/// every index file which may go with it, including any extra shards of
+/// them on disk.
+fn dictionary_extensions(dict_path: &Path) -> Vec<String> {
+    let mut extensions = vec![];
+    for &extension in iter::once(&"").chain(INDEX_EXTENSIONS) {
+        extensions.extend(
+            (0..shard_count(dict_path, extension))
+                .map(|shard| shard_extension(extension, shard)),
+        );
+    }
+    extensions
+}
+
+/// The number of shards of the index with `extension` on disk next to
+/// `dict_path`, counting the first, even if it doesn't exist.
+fn shard_count(dict_path: &Path, extension: &str) -> usize {
+    (1..)
+        .find(|&shard| {
+            !sidecar_path(dict_path, &shard_extension(extension, shard)).exists()
+        })
+        .expect("there are only so many files")
+}
+
+/// The number of shards of every index written next to `dict_path` with
+/// more than one, for `Metadata::shards`.
+fn shard_counts(dict_path: &Path) -> BTreeMap<String, usize> {
+    iter::once(&"")
+        .chain(INDEX_EXTENSIONS)
+        .map(|&extension| (extension.to_owned(), shard_count(dict_path, extension)))
+        .filter(|&(_, count)| count > 1)
+        .collect()
+}
+
+/// The content hash of the words in the shards at `paths`, in order.
+fn shards_content_hash(paths: &[PathBuf]) -> Result<String> {
+    let shards = paths
+        .iter()
+        .map(|path| fst_file(path))
+        .collect::<Result<Vec<_>>>()?;
+    Ok(metadata::content_hash_of(
+        shards.iter().map(|shard| shard.as_bytes()),
+    ))
+}
+
+/// Memory-map the fst stored at `path`, which must not be encrypted.
+fn fst_file(path: &Path) -> Result<fst::raw::Fst<Bytes>> {
+    new_fst(path, Bytes::map_file(path)?)
+}
+
+/// If `bytes`, the contents of `path`, which is the index with `extension`,
+/// are encrypted, decrypt them into memory with `cipher`.
+fn decrypt(
+    path: &Path,
+    extension: &str,
+    bytes: Bytes,
+    cipher: Option<&Cipher>,
+) -> Result<Bytes> {
+    if !encryption::is_encrypted(bytes.as_ref()) {
+        return Ok(bytes);
+    }
+    let cipher = cipher.ok_or_else(|| Error::KeyRequired {
+        path: path.to_owned(),
+    })?;
+    Ok(Bytes::from(cipher.open(path, extension, bytes.as_ref())?))
+}
+
+/// The error for a file at `path` which doesn't match its checksum.
+fn checksum_mismatch(path: &Path) -> Error {
+    Error::CorruptDictionary(format!(
+        "{} doesn't match the checksum recorded when it was built; it may be \
+         truncated or partly written",
+        path.display()
+    ))
+}
+
+/// Check that `bytes`, the contents of `path`, hold an fst.
+fn new_fst(path: &Path, bytes: Bytes) -> Result<fst::raw::Fst<Bytes>> {
+    fst::raw::Fst::new(bytes).map_err(|err| {
+        Error::CorruptDictionary(format!("{}: {}", path.display(), err))
+    })
+}
+
+/// Adds up the counts of the words with each number of letters, for
+/// `Metadata::length_totals`.
+#[derive(Default)]
+struct LengthTotals(BTreeMap<usize, u64>);
+
+impl LengthTotals {
+    /// Add `count` occurrences of `word`.
+    fn add(&mut self, word: &str, count: u64) {
+        let total = self.0.entry(letter_count(word)).or_default();
+        // This can't overflow, because the total count doesn't.
+        *total = total.saturating_add(count);
+    }
+
+    /// The total probability of each length, as negative log probabilities,
+    /// if `total_count` is the total count of every word. We leave out
+    /// lengths whose words all have a count of 0, since JSON can't hold an
+    /// infinite total, and words of lengths we leave out aren't normalized.
+    fn finish(self, total_count: u64) -> BTreeMap<usize, f64> {
+        self.0
+            .into_iter()
+            .map(|(len, count)| {
+                (len, Prob::from_fraction(count, total_count).to_f64())
+            })
+            .filter(|(_, total)| total.is_finite())
+            .collect()
+    }
+}
+
+/// The number of letters in `word`, ignoring spaces and punctuation, as
+/// when fitting it into a crossword slot.
+fn letter_count(word: &str) -> usize {
+    phrase_letters(word).chars().count()
+}
+
+/// Words to leave out of a dictionary we're building.
+struct Exclusions {
+    words: BTreeSet<String>,
+    regex: Option<Regex>,
+    junk: bool,
+}
+
+impl Exclusions {
+    /// Load the exclusions described by `opts`.
+    fn new(opts: &BuildOptions) -> Result<Exclusions> {
+        let mut words = BTreeSet::new();
+        if let Some(exclude_path) = &opts.exclude_path {
+            words.extend(read_word_list(exclude_path)?);
+        }
+        let regex = opts
+            .exclude_regex
+            .as_ref()
+            .map(|pattern| {
+                Regex::new(&format!("^(?:{})$", pattern)).map_err(|source| {
+                    Error::InvalidRegex {
+                        pattern: pattern.to_owned(),
+                        source: source.into(),
+                    }
+                })
+            })
+            .transpose()?;
+        Ok(Exclusions {
+            words,
+            regex,
+            junk: opts.exclude_junk,
+        })
+    }
+
+    /// Should we leave out `word`?
+    fn excludes(&self, word: &str) -> bool {
+        self.words.contains(word)
+            || self.regex.as_ref().is_some_and(|re| re.is_match(word))
+            || (self.junk && junk_reason(word).is_some())
+    }
+}
+
+/// Lines of a word list which we skipped because we couldn't parse them.
+#[derive(Clone, Debug, Default)]
+pub struct SkippedLines {
+    /// How many lines we skipped.
+    pub count: u64,
+    /// The first few lines we skipped, and why.
+    pub examples: Vec<LineDiagnostic>,
+}
+
+/// How many skipped lines to describe in a `BuildReport`.
+const MAX_SKIPPED_EXAMPLES: usize = 10;
+
+/// Read a list of "count word" lines, calling `f` with the line number, count
+/// and spelling of each word, with its spaces normalized. If `skip_invalid`
+/// is true, we skip lines we can't parse instead of failing.
+fn read_counts<F>(path: &Path, skip_invalid: bool, mut f: F) -> Result<SkippedLines>
+where
+    F: FnMut(usize, u64, String) -> Result<()>,
+{
+    let file = File::open(path).map_err(Error::io(path))?;
+    let mut rdr = BufReader::new(file);
+    let mut skipped = SkippedLines::default();
+    let mut buf = vec![];
+    for line_number in 1.. {
+        buf.clear();
+        if rdr.read_until(b'\n', &mut buf).map_err(Error::io(path))? == 0 {
+            break;
+        }
+        while buf.last().is_some_and(|&b| b == b'\n' || b == b'\r') {
+            buf.pop();
+        }
+        let parsed = match from_utf8(&buf) {
+            Ok(line) => {
+                parse_count_line(line).map(|(count, word)| (count, word.to_owned()))
+            }
+            Err(err) => {
+                let valid = err.valid_up_to();
+                Err((valid..valid, "line isn't valid UTF-8".to_owned()))
+            }
+        };
+        match parsed {
+            Ok((count, word)) => f(line_number, count, normalize_spaces(&word))?,
+            Err((span, message)) => {
+                // Show the line as best we can, keeping `span` pointing at
+                // the same text.
+                let text = String::from_utf8_lossy(&buf[..span.start]).into_owned();
+                let start = text.len();
+                let text = text + &String::from_utf8_lossy(&buf[span.start..]);
+                let end = (start + span.len()).min(text.len());
+                let diagnostic = LineDiagnostic {
+                    path: path.to_owned(),
+                    line: line_number,
+                    text,
+                    span: start..end,
+                    message,
+                };
+                if !skip_invalid {
+                    return Err(Error::InvalidLine(Box::new(diagnostic)));
+                }
+                skipped.count += 1;
+                if skipped.examples.len() < MAX_SKIPPED_EXAMPLES {
+                    skipped.examples.push(diagnostic);
+                }
+            }
+        }
+    }
+    Ok(skipped)
+}
+
+/// Parse a "count word" line, returning the count and the word. We permit
+/// leading whitespace for compatibility with `uniq -c`. If the line is
+/// invalid, returns the byte offsets of the problem and a description.
+fn parse_count_line(
+    line: &str,
+) -> std::result::Result<(u64, &str), (Range<usize>, String)> {
+    let start = line.len() - line.trim_start().len();
+    let rest = &line[start..];
+    if rest.trim().is_empty() {
+        return Err((
+            start..line.len(),
+            "expected \"count word\", found a blank line".to_owned(),
+        ));
+    }
+    let digits_end = start
+        + rest
+            .find(|c: char| !c.is_ascii_digit())
+            .unwrap_or(rest.len());
+    if digits_end == start {
+        // Point at the first token, which should have been the count.
+        let token_end = start + rest.find(char::is_whitespace).unwrap_or(rest.len());
+        let message = if rest
+            .split_whitespace()
+            .last()
+            .is_some_and(|token| token.chars().all(|c| c.is_ascii_digit()))
+        {
+            "expected a count at the start of the line; the count goes before \
+             the word, as in \"12 cat\""
+        } else {
+            "expected a count at the start of the line"
+        };
+        return Err((start..token_end, message.to_owned()));
+    }
+    let count = line[start..digits_end]
+        .parse::<u64>()
+        .map_err(|_| (start..digits_end, "count is too large".to_owned()))?;
+    let after = &line[digits_end..];
+    match after.chars().next() {
+        None => {
+            return Err((
+                digits_end..digits_end,
+                "expected a word after the count".to_owned(),
+            ))
+        }
+        Some(c) if !c.is_whitespace() => {
+            return Err((
+                digits_end..digits_end + c.len_utf8(),
+                "expected a space between the count and the word".to_owned(),
+            ))
+        }
+        Some(_) => {}
+    }
+    let word = after.trim();
+    if word.is_empty() {
+        return Err((
+            line.len()..line.len(),
+            "expected a word after the count".to_owned(),
+        ));
+    }
+    // Our indexes use NUL to separate words from other information.
+    if let Some(nul) = line.find('\0') {
+        return Err((
+            nul..nul + 1,
+            "words can't contain NUL characters".to_owned(),
+        ));
+    }
+    Ok((count, word))
+}
+
+/// Is a word with `count` entries, `caps` of them capitalized, a proper noun?
+fn is_proper_noun(opts: &BuildOptions, caps: u64, count: u64) -> bool {
+    let threshold = opts.proper_noun_threshold.unwrap_or(1.0);
+    caps > 0 && caps as f64 >= threshold * count as f64
+}
+
+/// Encode the probability of a word with `count` for storage in a map.
+fn encode_count(
+    quantization: &Option<Quantization>,
+    count: u64,
+    total_count: u64,
+) -> u64 {
+    let prob = Prob::from_fraction(count, total_count);
+    match quantization {
+        Some(quantization) => quantization.encode(prob),
+        None => prob.to_bits(),
+    }
+}
+
+/// The substring index keys for `word`: every suffix of the word, followed
+/// by the word, so that a prefix search for a substring finds every word
+/// containing it.
+fn substring_keys(word: &str) -> impl Iterator<Item = String> + '_ {
+    word.char_indices()
+        .map(move |(i, _)| format!("{}\0{}", &word[i..], word))
+}
+
+/// The anagram index key for `word`: its sorted letters, followed by the
+/// word, so that all the anagrams of a word are stored together.
+fn anagram_key(word: &str) -> String {
+    format!("{}\0{}", signature(word), word)
+}
+
+/// The GADDAG keys for `word`: one for each of its letters, made of the
+/// letters up to and including that one in reverse, followed by the rest of
+/// the word.
+fn gaddag_keys(word: &str) -> impl Iterator<Item = String> {
+    let chars = word.chars().collect::<Vec<_>>();
+    (1..=chars.len()).map(move |i| {
+        let left = chars[..i].iter().rev().collect::<String>();
+        let right = chars[i..].iter().collect::<String>();
+        format!("{}\0{}", left, right)
+    })
+}
+
+/// Write the pronunciation and clue indexes requested by `opts`, for the
+/// words accepted by `is_word`, adding their paths to `written`.
+fn write_pronunciation_and_clue_indexes(
+    out_dict_path: &Path,
+    opts: &BuildOptions,
+    is_word: impl Fn(&str) -> bool,
+    written: &mut Vec<PathBuf>,
+) -> Result<()> {
+    // Index our pronunciations, both by word and by how they end.
+    if let Some(pronunciations_path) = &opts.pronunciations_path {
+        let pronunciations = read_pronunciations(pronunciations_path, &is_word)?;
+        let mut by_word = pronunciations
+            .iter()
+            .map(|(word, phones)| format!("{}\t{}", word, phones))
+            .collect::<Vec<_>>();
+        by_word.sort();
+        by_word.dedup();
+        written.extend(write_set(out_dict_path, "pron", opts, by_word)?);
+        let mut by_ending = pronunciations
+            .iter()
+            .map(|(word, phones)| format!("{}\t{}", reverse_phones(phones), word))
+            .collect::<Vec<_>>();
+        by_ending.sort();
+        by_ending.dedup();
+        written.extend(write_set(out_dict_path, "rhyme", opts, by_ending)?);
+    }
+
+    // Index our clues by answer.
+    if let Some(clues_path) = &opts.clues_path {
+        let mut clues = read_clues(clues_path, &is_word)?
+            .into_iter()
+            .map(|(answer, clue)| format!("{}\t{}", answer, clue))
+            .collect::<Vec<_>>();
+        clues.sort();
+        clues.dedup();
+        written.extend(write_set(out_dict_path, "clues", opts, clues)?);
+    }
+    Ok(())
+}
+
+/// Checksums of every index in `written` except the first, which is the
+/// dictionary itself, keyed by extension.
+fn index_checksums(written: &[PathBuf]) -> Result<BTreeMap<String, String>> {
+    let mut checksums = BTreeMap::new();
+    for path in &written[1..] {
+        if let Some(extension) = path.extension() {
+            checksums.insert(
+                extension.to_string_lossy().into_owned(),
+                metadata::content_hash(fst_file(path)?.as_bytes()),
+            );
+        }
+    }
+    Ok(checksums)
+}
+
+/// Encrypt every file of the dictionary at `dict_path` except its metadata
+/// with `key`, and record how we did it in the metadata. The checksums in
+/// the metadata are left alone, since we check them after decrypting.
+fn encrypt_dictionary(
+    dict_path: &Path,
+    key: &DictionaryKey,
+    report: BuildReport,
+) -> Result<BuildReport> {
+    let meta_path = sidecar_path(dict_path, "meta");
+    let mut metadata = Metadata::read(&meta_path)?.ok_or_else(|| {
+        Error::CorruptDictionary(format!("{} is missing", meta_path.display()))
+    })?;
+    let encryption = Encryption::generate()?;
+    let cipher = Cipher::new(key, &encryption)?;
+    for extension in dictionary_extensions(dict_path) {
+        let path = &sidecar_path(dict_path, &extension);
+        if extension == "meta" || !path.exists() {
+            continue;
+        }
+        let plaintext = fs::read(path).map_err(Error::io(path))?;
+        fs::write(path, cipher.seal(&extension, plaintext)?)
+            .map_err(Error::io(path))?;
+    }
+    metadata.encryption = Some(encryption);
+    metadata.write(&meta_path)?;
+    Ok(BuildReport {
+        files: file_sizes(report.files.into_iter().map(|(path, _)| path).collect())?,
+        ..report
+    })
+}
+
+/// The size of each file in `written`.
+fn file_sizes(written: Vec<PathBuf>) -> Result<Vec<(PathBuf, u64)>> {
+    written
+        .into_iter()
+        .map(|path| {
+            let len = fs::metadata(&path).map_err(Error::io(&path))?.len();
+            Ok((path, len))
+        })
+        .collect()
+}
+
+/// Adds up the counts of each word in a word list sorted by word, and writes
+/// them to a map, for `Dictionary::build_files_on_disk`.
+struct CountsWriter<'a> {
+    in_words_path: &'a Path,
+    opts: &'a BuildOptions,
+    counts_path: &'a Path,
+    counts: MapBuilder<BufWriter<File>>,
+    /// If we're looking for proper nouns, the index we're writing them to.
+    proper_nouns: Option<ShardWriter<'a>>,
+    totals: CountTotals,
+    /// The word we're adding up, its count, how often it was capitalized,
+    /// and the count of each spelling of it we've seen.
+    current: Option<(String, u64, u64, BTreeMap<String, u64>)>,
+}
+
+/// What `CountsWriter` found.
+#[derive(Default)]
+struct CountTotals {
+    total_count: u64,
+    word_count: u64,
+    phrase_count: u64,
+    proper_noun_count: u64,
+    /// The number of duplicate entries we merged.
+    merged: u64,
+    /// The shards of the index of proper nouns, if we wrote one.
+    proper_nouns_paths: Vec<PathBuf>,
+}
+
+impl<'a> CountsWriter<'a> {
+    /// Prepare to write the counts from `in_words_path` to `counts_path`,
+    /// and any proper nouns to an index next to `out_dict_path`.
+    fn new(
+        in_words_path: &'a Path,
+        out_dict_path: &'a Path,
+        counts_path: &'a Path,
+        opts: &'a BuildOptions,
+    ) -> Result<Self> {
+        let file = File::create(counts_path).map_err(Error::io(counts_path))?;
+        let counts = MapBuilder::new(BufWriter::new(file))
+            .map_err(Error::index(counts_path))?;
+        let proper_nouns = opts
+            .proper_noun_threshold
+            .map(|_| ShardWriter::new(out_dict_path, "proper", opts.shard_size))
+            .transpose()?;
+        Ok(CountsWriter {
+            in_words_path,
+            opts,
+            counts_path,
+            counts,
+            proper_nouns,
+            totals: CountTotals::default(),
+            current: None,
+        })
+    }
+
+    /// Add an entry from `line` of the word list. Entries must be sorted by
+    /// `word`.
+    fn add(
+        &mut self,
+        word: String,
+        surface: String,
+        count: u64,
+        line: usize,
+    ) -> Result<()> {
+        let in_words_path = self.in_words_path;
+        let invalid = |message: String| Error::InvalidWordList {
+            path: in_words_path.to_owned(),
+            line,
+            message,
+        };
+        let capitalized = surface.starts_with(char::is_uppercase);
+        let added = match &mut self.current {
+            Some((current_word, current_count, current_caps, surfaces))
+                if *current_word == word =>
+            {
+                // For case-sensitive word lists, "Paris" and "paris" are the
+                // same word, so add their counts together, and only a
+                // repeated spelling is a duplicate.
+                let case_sensitive = self.opts.proper_noun_threshold.is_some();
+                let old = if case_sensitive {
+                    surfaces.get(&surface).copied()
+                } else {
+                    Some(*current_count)
+                };
+                let added = match old {
+                    None => {
+                        surfaces.insert(surface, count);
+                        count
+                    }
+                    Some(old) => {
+                        let new =
+                            self.opts.on_duplicate.merge(old, count).ok_or_else(
+                                || invalid(format!("duplicate word {:?}", surface)),
+                            )?;
+                        if case_sensitive {
+                            surfaces.insert(surface, new);
+                        }
+                        self.totals.merged += 1;
+                        new - old
+                    }
+                };
+                *current_count += added;
+                if capitalized {
+                    *current_caps += added;
+                }
+                added
+            }
+            Some((current_word, ..)) if *current_word > word => {
+                return Err(invalid(format!(
+                    "{:?} comes after {:?}, but the word list should be sorted \
+                     by lowercase word in byte order (leave out --sorted to sort \
+                     it while building)",
+                    surface, current_word
+                )));
+            }
+            _ => {
+                self.finish_word()?;
+                let caps = if capitalized { count } else { 0 };
+                let surfaces = iter::once((surface, count)).collect();
+                self.current = Some((word, count, caps, surfaces));
+                count
+            }
+        };
+        self.totals.total_count =
+            self.totals.total_count.checked_add(added).ok_or_else(|| {
+                invalid("total word count is too large for u64".to_owned())
+            })?;
+        Ok(())
+    }
+
+    /// Write the word we're adding up, if there is one.
+    fn finish_word(&mut self) -> Result<()> {
+        if let Some((word, count, caps, _)) = self.current.take() {
+            self.counts
+                .insert(&word, count)
+                .map_err(Error::index(self.counts_path))?;
+            self.totals.word_count += 1;
+            if word.contains(' ') {
+                self.totals.phrase_count += 1;
+            }
+            if let Some(proper_nouns) = &mut self.proper_nouns {
+                if is_proper_noun(self.opts, caps, count) {
+                    proper_nouns.add(word.as_bytes())?;
+                    self.totals.proper_noun_count += 1;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Finish writing, and return our totals.
+    fn finish(mut self) -> Result<CountTotals> {
+        self.finish_word()?;
+        self.counts
+            .finish()
+            .map_err(Error::index(self.counts_path))?;
+        if let Some(proper_nouns) = self.proper_nouns {
+            self.totals.proper_nouns_paths = proper_nouns.finish()?;
+        }
+        Ok(self.totals)
+    }
+}
+
+/// A temporary file, which we remove when we're dropped.
+struct TempFile(PathBuf);
+
+impl Drop for TempFile {
+    fn drop(&mut self) {
+        // This is only cleanup, so ignore errors.
+        let _ = fs::remove_file(&self.0);
+    }
+}
+
+/// Parse a `word\0spelling\0line count surface` record sorted by
+/// `build_files_on_disk`, returning the word, surface, count and line.
+fn parse_entry_record(record: &[u8]) -> Result<(String, String, u64, usize)> {
+    let mut parts = record.splitn(3, |&b| b == 0);
+    let word = parts.next().unwrap_or_default();
+    let _spelling = parts.next();
+    let (numbers, surface) = parts.next().unwrap_or_default().split_at(16);
+    let (line, count) = numbers.split_at(8);
+    Ok((
+        from_utf8(word).map_err(Error::invalid_utf8)?.to_owned(),
+        from_utf8(surface).map_err(Error::invalid_utf8)?.to_owned(),
+        be_u64(count),
+        be_u64(line) as usize,
+    ))
+}
+
+/// A record for an `ExternalSorter` which sorts by `key`, carrying `value`.
+fn map_record(key: &[u8], value: u64) -> Vec<u8> {
+    let mut record = Vec::with_capacity(key.len() + 9);
+    record.extend_from_slice(key);
+    record.push(0);
+    record.extend_from_slice(&value.to_be_bytes());
+    record
+}
+
+/// Read a big-endian `u64`.
+fn be_u64(bytes: &[u8]) -> u64 {
+    let mut buf = [0; 8];
+    buf.copy_from_slice(bytes);
+    u64::from_be_bytes(buf)
+}
+
+/// Write the records built by `map_record` in `sorter` to the map with
+/// `extension` next to `dict_path`, returning the path of each shard.
+fn write_sorted_map(
+    dict_path: &Path,
+    extension: &str,
+    opts: &BuildOptions,
+    sorter: ExternalSorter,
+) -> Result<Vec<PathBuf>> {
+    itertools::process_results(sorter.finish()?, |records| {
+        write_map(
+            dict_path,
+            extension,
+            opts,
+            records.map(|record| {
+                let (key, value) = record.split_at(record.len() - 9);
+                (key.to_owned(), be_u64(&value[1..]))
+            }),
+        )
+    })?
+}
+
+/// Write `entries`, which must be sorted by key, to the `fst::Map` with
+/// `extension` next to `dict_path`, returning the path of each shard.
+fn write_map<K, I>(
+    dict_path: &Path,
+    extension: &str,
+    opts: &BuildOptions,
+    entries: I,
+) -> Result<Vec<PathBuf>>
+where
+    K: AsRef<[u8]>,
+    I: IntoIterator<Item = (K, u64)>,
+{
+    let mut writer = ShardWriter::new(dict_path, extension, opts.shard_size)?;
+    for (key, value) in entries {
+        writer.insert(key.as_ref(), value)?;
+    }
+    writer.finish()
+}
+
+/// Write `keys`, which must be sorted, to the `fst::Set` with `extension`
+/// next to `dict_path`, returning the path of each shard.
+fn write_set<K, I>(
+    dict_path: &Path,
+    extension: &str,
+    opts: &BuildOptions,
+    keys: I,
+) -> Result<Vec<PathBuf>>
+where
+    K: AsRef<[u8]>,
+    I: IntoIterator<Item = K>,
+{
+    let mut writer = ShardWriter::new(dict_path, extension, opts.shard_size)?;
+    for key in keys {
+        writer.add(key.as_ref())?;
+    }
+    writer.finish()
 }
@@ -0,0 +1,202 @@
+//! Combining compiled dictionaries with set operations, such as "words in a
+//! Scrabble dictionary but not in a dictionary of common words".
+//!
+//! We merge the dictionaries' word maps as streams, in alphabetical order,
+//! so combining large dictionaries doesn't need to hold them in memory. The
+//! result is a word list in the format `mkdict` reads, with counts chosen to
+//! give each word its combined probability.
+
+use fst::{map::OpBuilder, Streamer};
+use std::{
+    fmt,
+    fs::File,
+    io::{BufWriter, Write},
+    path::Path,
+    str::FromStr,
+};
+
+use crate::dictionary::Dictionary;
+use crate::error::{Error, Result};
+use crate::probability::Prob;
+
+/// Which words to keep when combining dictionaries.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SetOperation {
+    /// Words in any of the dictionaries.
+    Union,
+    /// Words in every dictionary.
+    Intersection,
+    /// Words in the first dictionary, but not in any of the others.
+    Difference,
+}
+
+/// Parse `union`, `intersection` or `difference`.
+impl FromStr for SetOperation {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "union" => Ok(SetOperation::Union),
+            "intersection" => Ok(SetOperation::Intersection),
+            "difference" => Ok(SetOperation::Difference),
+            _ => Err(Error::InvalidOption(format!(
+                "expected \"union\", \"intersection\" or \"difference\", found {:?}",
+                s
+            ))),
+        }
+    }
+}
+
+impl fmt::Display for SetOperation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SetOperation::Union => write!(f, "union"),
+            SetOperation::Intersection => write!(f, "intersection"),
+            SetOperation::Difference => write!(f, "difference"),
+        }
+    }
+}
+
+/// How to combine the probabilities of a word which is in more than one
+/// dictionary. Whatever we choose, the probabilities of the result are
+/// scaled to add up to 1.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Combine {
+    /// Use the probability from the first dictionary containing the word.
+    First,
+    /// Use the lowest probability of the word in any dictionary.
+    Min,
+    /// Use the highest probability of the word in any dictionary.
+    Max,
+    /// Average the word's probabilities over all the dictionaries, counting
+    /// dictionaries without the word as 0. This mixes the dictionaries
+    /// equally.
+    Mean,
+}
+
+/// Parse `first`, `min`, `max` or `mean`.
+impl FromStr for Combine {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "first" => Ok(Combine::First),
+            "min" => Ok(Combine::Min),
+            "max" => Ok(Combine::Max),
+            "mean" => Ok(Combine::Mean),
+            _ => Err(Error::InvalidOption(format!(
+                "expected \"first\", \"min\", \"max\" or \"mean\", found {:?}",
+                s
+            ))),
+        }
+    }
+}
+
+/// The rarest word in a combined word list gets roughly this count, so that
+/// rounding counts to integers keeps about six significant digits of every
+/// probability.
+const MIN_COUNT: f64 = 1e6;
+
+/// The largest total count we'll write, leaving plenty of room below
+/// `u64::MAX`.
+const MAX_TOTAL_COUNT: f64 = 1e18;
+
+/// Write the words resulting from applying `op` to `dicts` to `out_path`, as
+/// "count word" lines which `Dictionary::build` can read. Each dictionary's
+/// filter and temperature apply, so words it filters out count as missing.
+/// Returns the number of words written.
+///
+/// Only words and probabilities are combined. Pronunciations, clues and
+/// proper noun information aren't carried over.
+pub fn write_combined(
+    dicts: &[Dictionary],
+    op: SetOperation,
+    combine: Combine,
+    out_path: &Path,
+) -> Result<usize> {
+    if dicts.is_empty() {
+        return Err(Error::InvalidOption(
+            "need at least one dictionary to combine".to_owned(),
+        ));
+    }
+
+    // Find the range of probabilities, so we can choose counts which keep
+    // their precision without overflowing.
+    let mut min_p = f64::INFINITY;
+    let mut total_p = 0.0;
+    let mut word_count = 0;
+    for_each_combined(dicts, op, combine, |_, p| {
+        min_p = min_p.min(p);
+        total_p += p;
+        word_count += 1;
+        Ok(())
+    })?;
+    if word_count == 0 || total_p <= 0.0 {
+        return Err(Error::InvalidOption(format!(
+            "the {} of these dictionaries has no words",
+            op
+        )));
+    }
+    let scale = (MIN_COUNT / min_p).min(MAX_TOTAL_COUNT / total_p);
+
+    let out_file = File::create(out_path).map_err(Error::io(out_path))?;
+    let mut out = BufWriter::new(out_file);
+    for_each_combined(dicts, op, combine, |word, p| {
+        let count = (p * scale).round().max(1.0) as u64;
+        writeln!(out, "{} {}", count, word).map_err(Error::io(out_path))
+    })?;
+    out.flush().map_err(Error::io(out_path))?;
+    Ok(word_count)
+}
+
+/// Call `f` with each word in the result of applying `op` to `dicts`, in
+/// alphabetical order, with its combined probability as an ordinary `f64`.
+fn for_each_combined<F>(
+    dicts: &[Dictionary],
+    op: SetOperation,
+    combine: Combine,
+    mut f: F,
+) -> Result<()>
+where
+    F: FnMut(&str, f64) -> Result<()>,
+{
+    let mut builder = OpBuilder::new();
+    for dict in dicts {
+        builder.push(dict.word_map().stream());
+    }
+    let mut union = builder.union();
+    // The probability of the current word in each dictionary, if it's there.
+    let mut probs = vec![None::<Prob>; dicts.len()];
+    while let Some((word_bytes, values)) = union.next() {
+        let word = std::str::from_utf8(word_bytes).map_err(Error::invalid_utf8)?;
+        probs.iter_mut().for_each(|prob| *prob = None);
+        for value in values {
+            let dict = &dicts[value.index];
+            let prob = dict.decode_prob(value.value)?;
+            if dict.accepts(word, prob) {
+                probs[value.index] = Some(prob);
+            }
+        }
+
+        let keep = match op {
+            SetOperation::Union => probs.iter().any(Option::is_some),
+            SetOperation::Intersection => probs.iter().all(Option::is_some),
+            SetOperation::Difference => {
+                probs[0].is_some() && probs[1..].iter().all(Option::is_none)
+            }
+        };
+        if !keep {
+            continue;
+        }
+        let mut present = probs.iter().flatten().map(|prob| prob.to_probability());
+        let p = match combine {
+            Combine::First => present.next(),
+            Combine::Min => present.reduce(f64::min),
+            Combine::Max => present.reduce(f64::max),
+            Combine::Mean => Some(present.sum::<f64>() / dicts.len() as f64),
+        }
+        .expect("kept words are in at least one dictionary");
+        f(word, p)?;
+    }
+    Ok(())
+}
@@ -0,0 +1,342 @@
+//! Solving drop quotes (also called quotefalls).
+//!
+//! A drop quote is a grid containing a quotation, reading left to right and
+//! top to bottom, with black squares between words. Words may wrap from the
+//! end of one row to the start of the next. Above each column is a bank of the
+//! letters which belong in that column, in no particular order. We fill in one
+//! word at a time, always choosing the word with the fewest candidates which
+//! still fit the remaining letters in each column.
+
+use fst::Automaton;
+use log::{debug, trace};
+use std::fmt;
+
+use crate::dictionary::{Dictionary, SearchOptions};
+use crate::error::{Error, Result};
+use crate::probability::Prob;
+
+/// A drop quote puzzle.
+#[derive(Clone, Debug)]
+pub struct DropQuote {
+    width: usize,
+    /// Whether each cell is white, in row-major order.
+    open: Vec<bool>,
+    /// How many of each byte are in each column's bank.
+    banks: Vec<[u16; 256]>,
+    /// The cells of each word in the quote, in order.
+    words: Vec<Vec<usize>>,
+}
+
+impl DropQuote {
+    /// Create a puzzle from a grid, with one row per line, using `.` for white
+    /// cells and `#` for black squares, and the letters in each column's bank.
+    pub fn new(grid: &str, banks: &[String]) -> Result<DropQuote> {
+        let rows = grid
+            .lines()
+            .map(str::trim_end)
+            .filter(|l| !l.is_empty())
+            .collect::<Vec<_>>();
+        let width = rows.first().map_or(0, |row| row.chars().count());
+        if width == 0 {
+            return Err(Error::InvalidPuzzle("grid is empty".to_owned()));
+        }
+        let mut open = vec![];
+        for (i, row) in rows.iter().enumerate() {
+            if row.chars().count() != width {
+                return Err(Error::InvalidPuzzle(format!(
+                    "grid row {} has {} cells, but expected {}",
+                    i + 1,
+                    row.chars().count(),
+                    width,
+                )));
+            }
+            for c in row.chars() {
+                match c {
+                    '.' => open.push(true),
+                    '#' => open.push(false),
+                    c => {
+                        return Err(Error::InvalidPuzzle(format!(
+                            "unexpected {:?} in grid",
+                            c
+                        )))
+                    }
+                }
+            }
+        }
+
+        if banks.len() != width {
+            return Err(Error::InvalidPuzzle(format!(
+                "grid has {} columns, but there are {} letter banks",
+                width,
+                banks.len(),
+            )));
+        }
+        let mut bank_counts = vec![[0u16; 256]; width];
+        for (col, bank) in banks.iter().enumerate() {
+            let mut len = 0;
+            // Allow "-" for columns without any letters.
+            for b in bank.to_ascii_lowercase().bytes().filter(|&b| b != b'-') {
+                if !b.is_ascii_lowercase() {
+                    return Err(Error::InvalidPuzzle(format!(
+                        "{:?} is not a letter",
+                        b as char
+                    )));
+                }
+                bank_counts[col][b as usize] += 1;
+                len += 1;
+            }
+            let cells = (0..rows.len())
+                .filter(|row| open[row * width + col])
+                .count();
+            if len != cells {
+                return Err(Error::InvalidPuzzle(format!(
+                    "column {} has {} white cells, but {} letters",
+                    col + 1,
+                    cells,
+                    len,
+                )));
+            }
+        }
+
+        // Split the quote into words. A word only ends at a black square or
+        // the end of the grid, so words may wrap between rows.
+        let mut words = vec![];
+        let mut word = vec![];
+        for (idx, &is_open) in open.iter().enumerate() {
+            if is_open {
+                word.push(idx);
+            } else if !word.is_empty() {
+                words.push(std::mem::take(&mut word));
+            }
+        }
+        if !word.is_empty() {
+            words.push(word);
+        }
+
+        Ok(DropQuote {
+            width,
+            open,
+            banks: bank_counts,
+            words,
+        })
+    }
+
+    /// Fill in the quote using dictionary words, trying more probable words
+    /// first. Returns `None` if there's no solution.
+    pub fn solve(
+        &self,
+        dict: &Dictionary,
+        opts: &DropQuoteOptions,
+    ) -> Result<Option<Solution>> {
+        let mut solver = Solver {
+            puzzle: self,
+            dict,
+            opts,
+            banks: self.banks.clone(),
+            assigned: vec![None; self.words.len()],
+        };
+        if !solver.search()? {
+            return Ok(None);
+        }
+        let words = solver
+            .assigned
+            .into_iter()
+            .map(|a| a.expect("every word should be assigned"))
+            .collect::<Vec<_>>();
+        let mut letters = vec![None; self.open.len()];
+        for ((_, word), cells) in words.iter().zip(&self.words) {
+            for (&idx, c) in cells.iter().zip(word.chars()) {
+                letters[idx] = Some(c);
+            }
+        }
+        Ok(Some(Solution {
+            prob: words
+                .iter()
+                .fold(Prob::always(), |acc, &(prob, _)| acc * prob),
+            words: words.into_iter().map(|(_, word)| word).collect(),
+            width: self.width,
+            letters,
+        }))
+    }
+
+    /// The column of cell `idx`.
+    fn column(&self, idx: usize) -> usize {
+        idx % self.width
+    }
+}
+
+/// Options for `DropQuote::solve`.
+#[derive(Clone, Debug)]
+pub struct DropQuoteOptions {
+    /// The maximum number of candidate words to consider for each slot. We
+    /// keep the most probable candidates.
+    pub max_candidates: usize,
+}
+
+impl Default for DropQuoteOptions {
+    fn default() -> Self {
+        DropQuoteOptions {
+            max_candidates: 1_000,
+        }
+    }
+}
+
+/// A solved drop quote.
+#[derive(Clone, Debug)]
+pub struct Solution {
+    /// The combined probability of every word.
+    pub prob: Prob,
+    /// The words of the quote, in order.
+    pub words: Vec<String>,
+    width: usize,
+    /// The letter in each cell, or `None` for black squares.
+    letters: Vec<Option<char>>,
+}
+
+impl fmt::Display for Solution {
+    /// Show the filled-in grid.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for row in self.letters.chunks(self.width) {
+            for cell in row {
+                write!(f, "{}", cell.unwrap_or('#'))?;
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
+
+/// Backtracking search state for `DropQuote::solve`.
+struct Solver<'a> {
+    puzzle: &'a DropQuote,
+    dict: &'a Dictionary,
+    opts: &'a DropQuoteOptions,
+    /// The letters still available in each column.
+    banks: Vec<[u16; 256]>,
+    /// The word chosen for each slot so far.
+    assigned: Vec<Option<(Prob, String)>>,
+}
+
+impl Solver<'_> {
+    /// The most probable words which fit in `slot` using the remaining
+    /// letters.
+    fn candidates(&self, slot: usize) -> Result<Vec<(Prob, String)>> {
+        let columns = self.puzzle.words[slot]
+            .iter()
+            .map(|&idx| self.puzzle.column(idx))
+            .collect::<Vec<_>>();
+        let automaton = FitsBanks {
+            columns: &columns,
+            banks: &self.banks,
+        };
+        let search_opts = SearchOptions {
+            limit: Some(self.opts.max_candidates),
+            most_probable: true,
+        };
+        Ok(self
+            .dict
+            .find_matches_by(automaton, &search_opts)?
+            .into_vec())
+    }
+
+    /// Take the letters of `word` from the banks for `slot`. Returns false
+    /// (and takes nothing) if there aren't enough letters, which can happen if
+    /// a word uses the same column more than once.
+    fn take(&mut self, slot: usize, word: &str) -> bool {
+        let cells = &self.puzzle.words[slot];
+        for (i, (&idx, b)) in cells.iter().zip(word.bytes()).enumerate() {
+            let count = &mut self.banks[self.puzzle.column(idx)][b as usize];
+            if *count == 0 {
+                self.put_back(&cells[..i], &word[..i]);
+                return false;
+            }
+            *count -= 1;
+        }
+        true
+    }
+
+    /// Return the letters of `word` to the banks for `cells`.
+    fn put_back(&mut self, cells: &[usize], word: &str) {
+        for (&idx, b) in cells.iter().zip(word.bytes()) {
+            self.banks[self.puzzle.column(idx)][b as usize] += 1;
+        }
+    }
+
+    /// Fill in all remaining slots. Returns true on success, leaving the
+    /// words in `self.assigned`.
+    fn search(&mut self) -> Result<bool> {
+        // Choose the most constrained slot.
+        let mut best: Option<(usize, Vec<(Prob, String)>)> = None;
+        for slot in 0..self.assigned.len() {
+            if self.assigned[slot].is_some() {
+                continue;
+            }
+            let candidates = self.candidates(slot)?;
+            if candidates.is_empty() {
+                return Ok(false);
+            }
+            if best
+                .as_ref()
+                .is_none_or(|(_, b)| candidates.len() < b.len())
+            {
+                best = Some((slot, candidates));
+            }
+        }
+        let (slot, candidates) = match best {
+            Some(best) => best,
+            None => return Ok(true),
+        };
+        debug!("slot {} has {} candidates", slot, candidates.len());
+
+        for (prob, word) in candidates {
+            if !self.take(slot, &word) {
+                continue;
+            }
+            trace!("trying {} ({})", word, prob);
+            self.assigned[slot] = Some((prob, word));
+            if self.search()? {
+                return Ok(true);
+            }
+            let (_, word) = self.assigned[slot].take().expect("slot was assigned");
+            let cells = self.puzzle.words[slot].clone();
+            self.put_back(&cells, &word);
+        }
+        Ok(false)
+    }
+}
+
+/// Matches words whose letters are all available in the banks for their
+/// columns.
+struct FitsBanks<'a> {
+    /// The column of each letter in the word.
+    columns: &'a [usize],
+    /// The letters available in each column.
+    banks: &'a [[u16; 256]],
+}
+
+impl Automaton for FitsBanks<'_> {
+    /// The number of bytes matched so far, or `None` if we can't match.
+    type State = Option<usize>;
+
+    fn start(&self) -> Self::State {
+        Some(0)
+    }
+
+    fn is_match(&self, state: &Self::State) -> bool {
+        *state == Some(self.columns.len())
+    }
+
+    fn can_match(&self, state: &Self::State) -> bool {
+        state.is_some()
+    }
+
+    fn accept(&self, state: &Self::State, byte: u8) -> Self::State {
+        let len = (*state)?;
+        let col = *self.columns.get(len)?;
+        if self.banks[col][byte as usize] > 0 {
+            Some(len + 1)
+        } else {
+            None
+        }
+    }
+}
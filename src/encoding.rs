@@ -0,0 +1,497 @@
+//! Decoding text which spells each letter with a group of symbols.
+//!
+//! A `Code`, like Morse code or the digits on a phone keypad, may be written
+//! without any breaks between letters or words. We break the encoded text
+//! into words using the same lattice as `segment`, but instead of matching
+//! each span against a pattern, we search the dictionary for words whose
+//! encoding is exactly that span.
+//!
+//! A `TokenTable`, like Braille or the NATO alphabet, spells each letter with
+//! a separate token. We look up each token, and then break the letters into
+//! words, treating any tokens we don't recognize as unknown letters.
+
+use fst::Automaton;
+use log::debug;
+use std::{collections::HashMap, fs, path::Path};
+
+use crate::dictionary::{Dictionary, SearchOptions};
+use crate::error::{Error, Result};
+use crate::segment::{break_into_words, Breaking, Lattice, SegmentOptions};
+
+/// A way of spelling each letter with a group of symbols.
+#[derive(Clone, Copy, Debug)]
+pub struct Code {
+    /// The name of this code, for error messages.
+    pub name: &'static str,
+    /// The symbols for each letter.
+    table: &'static [(char, &'static str)],
+}
+
+/// International Morse code, using "." and "-".
+pub const MORSE: Code = Code {
+    name: "Morse code",
+    table: &[
+        ('a', ".-"),
+        ('b', "-..."),
+        ('c', "-.-."),
+        ('d', "-.."),
+        ('e', "."),
+        ('f', "..-."),
+        ('g', "--."),
+        ('h', "...."),
+        ('i', ".."),
+        ('j', ".---"),
+        ('k', "-.-"),
+        ('l', ".-.."),
+        ('m', "--"),
+        ('n', "-."),
+        ('o', "---"),
+        ('p', ".--."),
+        ('q', "--.-"),
+        ('r', ".-."),
+        ('s', "..."),
+        ('t', "-"),
+        ('u', "..-"),
+        ('v', "...-"),
+        ('w', ".--"),
+        ('x', "-..-"),
+        ('y', "-.--"),
+        ('z', "--.."),
+    ],
+};
+
+/// The digits on a phone keypad, as used for T9 predictive text.
+pub const T9: Code = Code {
+    name: "phone keypad digits",
+    table: &[
+        ('a', "2"),
+        ('b', "2"),
+        ('c', "2"),
+        ('d', "3"),
+        ('e', "3"),
+        ('f', "3"),
+        ('g', "4"),
+        ('h', "4"),
+        ('i', "4"),
+        ('j', "5"),
+        ('k', "5"),
+        ('l', "5"),
+        ('m', "6"),
+        ('n', "6"),
+        ('o', "6"),
+        ('p', "7"),
+        ('q', "7"),
+        ('r', "7"),
+        ('s', "7"),
+        ('t', "8"),
+        ('u', "8"),
+        ('v', "8"),
+        ('w', "9"),
+        ('x', "9"),
+        ('y', "9"),
+        ('z', "9"),
+    ],
+};
+
+impl Code {
+    /// The symbols for `letter`, if this code can spell it.
+    fn symbols(&self, letter: u8) -> Option<&'static str> {
+        self.table
+            .iter()
+            .find(|(c, _)| *c == char::from(letter))
+            .map(|(_, symbols)| *symbols)
+    }
+
+    /// Spell `word` in this code, or return `None` if we can't spell some
+    /// letter.
+    pub fn encode(&self, word: &str) -> Option<String> {
+        word.bytes().map(|b| self.symbols(b)).collect()
+    }
+
+    /// Remove whitespace from `encoded`, and make sure every other character
+    /// is one of our symbols.
+    fn normalize(&self, encoded: &str) -> Result<String> {
+        let mut normalized = String::with_capacity(encoded.len());
+        for c in encoded.chars().filter(|c| !c.is_whitespace()) {
+            // People often type Morse dashes as underscores.
+            let c = if c == '_' { '-' } else { c };
+            if !self.table.iter().any(|(_, symbols)| symbols.contains(c)) {
+                return Err(Error::InvalidPuzzle(format!(
+                    "{:?} is not used in {}",
+                    c, self.name
+                )));
+            }
+            normalized.push(c);
+        }
+        Ok(normalized)
+    }
+}
+
+/// Find the most probable ways to read `encoded`, which spells some words in
+/// `code` without any breaks between letters or words. Whitespace in
+/// `encoded` is ignored. Returns each reading, most probable first.
+pub fn decode(
+    dict: &Dictionary,
+    code: &Code,
+    encoded: &str,
+    opts: &SegmentOptions,
+) -> Result<Vec<Breaking>> {
+    let encoded = code.normalize(encoded)?;
+    let search_opts = SearchOptions {
+        limit: Some(opts.limit),
+        most_probable: true,
+    };
+    let lattice = Lattice::build_with(&encoded, opts, |span| {
+        let automaton = Encodes {
+            code,
+            encoded: span.as_bytes(),
+        };
+        Ok(dict.find_matches_by(automaton, &search_opts)?.into_vec())
+    })?;
+    debug!("found {} possible words", lattice.spans.len());
+    Ok(lattice
+        .k_best(opts)
+        .into_iter()
+        .map(|(prob, path)| lattice.breaking(prob, &path))
+        .collect())
+}
+
+/// A table of tokens which each stand for a letter, such as Braille cells or
+/// NATO code words.
+#[derive(Clone, Debug)]
+pub struct TokenTable {
+    /// The name of this table, for error messages.
+    pub name: String,
+    /// The letter for each normalized token.
+    letters: HashMap<String, char>,
+    /// Converts tokens to the form used in `letters`.
+    normalize: fn(&str) -> String,
+}
+
+/// The names of our built-in token tables.
+pub const TOKEN_TABLES: &[&str] = &["braille", "semaphore", "nato", "a1z26"];
+
+impl TokenTable {
+    /// Create a table from `(token, letter)` pairs, where each token has
+    /// already been normalized.
+    fn new(
+        name: &str,
+        pairs: &[(&str, char)],
+        normalize: fn(&str) -> String,
+    ) -> TokenTable {
+        TokenTable {
+            name: name.to_owned(),
+            letters: pairs
+                .iter()
+                .map(|&(token, letter)| (token.to_owned(), letter))
+                .collect(),
+            normalize,
+        }
+    }
+
+    /// Look up one of the tables in `TOKEN_TABLES`.
+    pub fn named(name: &str) -> Option<TokenTable> {
+        match name {
+            "braille" => Some(TokenTable::braille()),
+            "semaphore" => Some(TokenTable::semaphore()),
+            "nato" => Some(TokenTable::nato()),
+            "a1z26" => Some(TokenTable::a1z26()),
+            _ => None,
+        }
+    }
+
+    /// Braille cells, written either as Unicode Braille characters like "⠓",
+    /// or as the numbers of their raised dots like "125".
+    pub fn braille() -> TokenTable {
+        TokenTable::new(
+            "Braille",
+            &[
+                ("1", 'a'),
+                ("12", 'b'),
+                ("14", 'c'),
+                ("145", 'd'),
+                ("15", 'e'),
+                ("124", 'f'),
+                ("1245", 'g'),
+                ("125", 'h'),
+                ("24", 'i'),
+                ("245", 'j'),
+                ("13", 'k'),
+                ("123", 'l'),
+                ("134", 'm'),
+                ("1345", 'n'),
+                ("135", 'o'),
+                ("1234", 'p'),
+                ("12345", 'q'),
+                ("1235", 'r'),
+                ("234", 's'),
+                ("2345", 't'),
+                ("136", 'u'),
+                ("1236", 'v'),
+                ("2456", 'w'),
+                ("1346", 'x'),
+                ("13456", 'y'),
+                ("1356", 'z'),
+            ],
+            normalize_braille,
+        )
+    }
+
+    /// Flag semaphore, written as the directions of the two flags in either
+    /// order, like "S-SW" or "sw+s".
+    pub fn semaphore() -> TokenTable {
+        TokenTable::new(
+            "semaphore",
+            &[
+                ("S-SW", 'a'),
+                ("S-W", 'b'),
+                ("S-NW", 'c'),
+                ("N-S", 'd'),
+                ("NE-S", 'e'),
+                ("E-S", 'f'),
+                ("S-SE", 'g'),
+                ("SW-W", 'h'),
+                ("NW-SW", 'i'),
+                ("E-N", 'j'),
+                ("N-SW", 'k'),
+                ("NE-SW", 'l'),
+                ("E-SW", 'm'),
+                ("SE-SW", 'n'),
+                ("NW-W", 'o'),
+                ("N-W", 'p'),
+                ("NE-W", 'q'),
+                ("E-W", 'r'),
+                ("SE-W", 's'),
+                ("N-NW", 't'),
+                ("NE-NW", 'u'),
+                ("N-SE", 'v'),
+                ("E-NE", 'w'),
+                ("NE-SE", 'x'),
+                ("E-NW", 'y'),
+                ("E-SE", 'z'),
+            ],
+            normalize_semaphore,
+        )
+    }
+
+    /// The NATO phonetic alphabet, like "hotel echo lima lima oscar".
+    pub fn nato() -> TokenTable {
+        TokenTable::new(
+            "NATO alphabet",
+            &[
+                ("alfa", 'a'),
+                ("alpha", 'a'),
+                ("bravo", 'b'),
+                ("charlie", 'c'),
+                ("delta", 'd'),
+                ("echo", 'e'),
+                ("foxtrot", 'f'),
+                ("golf", 'g'),
+                ("hotel", 'h'),
+                ("india", 'i'),
+                ("juliet", 'j'),
+                ("juliett", 'j'),
+                ("kilo", 'k'),
+                ("lima", 'l'),
+                ("mike", 'm'),
+                ("november", 'n'),
+                ("oscar", 'o'),
+                ("papa", 'p'),
+                ("quebec", 'q'),
+                ("romeo", 'r'),
+                ("sierra", 's'),
+                ("tango", 't'),
+                ("uniform", 'u'),
+                ("victor", 'v'),
+                ("whiskey", 'w'),
+                ("whisky", 'w'),
+                ("xray", 'x'),
+                ("yankee", 'y'),
+                ("zulu", 'z'),
+            ],
+            normalize_word,
+        )
+    }
+
+    /// Numbers from 1 for "a" to 26 for "z".
+    pub fn a1z26() -> TokenTable {
+        let numbers = (1..=26).map(|n| n.to_string()).collect::<Vec<_>>();
+        let pairs = numbers
+            .iter()
+            .zip('a'..='z')
+            .map(|(n, letter)| (n.as_str(), letter))
+            .collect::<Vec<_>>();
+        TokenTable::new("A1Z26", &pairs, normalize_number)
+    }
+
+    /// Load a table from a file with one "token letter" pair per line. Blank
+    /// lines and lines starting with "#" are ignored. Tokens are compared
+    /// ignoring case.
+    pub fn from_file(path: &Path) -> Result<TokenTable> {
+        let contents = fs::read_to_string(path).map_err(Error::io(path))?;
+        let mut letters = HashMap::new();
+        for (i, line) in contents.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let invalid = || Error::InvalidWordList {
+                path: path.to_owned(),
+                line: i + 1,
+                message: format!("expected \"token letter\", found {:?}", line),
+            };
+            let mut fields = line.split_whitespace();
+            let (token, letter) = match (fields.next(), fields.next(), fields.next()) {
+                (Some(token), Some(letter), None) => (token, letter),
+                _ => return Err(invalid()),
+            };
+            let mut letter_chars = letter.chars();
+            match (letter_chars.next(), letter_chars.next()) {
+                (Some(c), None) => {
+                    letters.insert(token.to_lowercase(), c.to_ascii_lowercase());
+                }
+                _ => return Err(invalid()),
+            }
+        }
+        Ok(TokenTable {
+            name: path.display().to_string(),
+            letters,
+            normalize: str::to_lowercase,
+        })
+    }
+
+    /// The letter for `token`, if we know it.
+    pub fn letter(&self, token: &str) -> Option<char> {
+        self.letters.get(&(self.normalize)(token)).copied()
+    }
+
+    /// Look up each whitespace-separated token in `encoded`, using "." for
+    /// tokens we don't recognize. Runs of Unicode Braille characters are
+    /// split into one token per character, and "/" tokens, which often
+    /// separate words, are ignored.
+    pub fn letters_of(&self, encoded: &str) -> String {
+        let mut letters = String::new();
+        for token in encoded.split_whitespace().filter(|&t| t != "/") {
+            if token.chars().all(is_braille) {
+                for c in token.chars() {
+                    letters
+                        .push(self.letter(c.encode_utf8(&mut [0; 4])).unwrap_or('.'));
+                }
+            } else {
+                letters.push(self.letter(token).unwrap_or('.'));
+            }
+        }
+        letters
+    }
+}
+
+/// Is `c` a Unicode Braille cell?
+fn is_braille(c: char) -> bool {
+    ('\u{2800}'..='\u{28FF}').contains(&c)
+}
+
+/// Convert a Unicode Braille cell to its dot numbers, and sort dot numbers.
+fn normalize_braille(token: &str) -> String {
+    let mut chars = token.chars();
+    if let (Some(c), None) = (chars.next(), chars.next()) {
+        if is_braille(c) {
+            let bits = c as u32 - 0x2800;
+            return (0..8)
+                .filter(|bit| bits & (1 << bit) != 0)
+                .map(|bit| char::from(b'1' + bit as u8))
+                .collect();
+        }
+    }
+    let mut dots = token
+        .chars()
+        .filter(char::is_ascii_digit)
+        .collect::<Vec<_>>();
+    dots.sort_unstable();
+    dots.dedup();
+    dots.into_iter().collect()
+}
+
+/// Uppercase both semaphore directions, and put them in alphabetical order.
+fn normalize_semaphore(token: &str) -> String {
+    let mut directions = token
+        .split(|c: char| !c.is_ascii_alphabetic())
+        .filter(|d| !d.is_empty())
+        .map(|d| d.to_ascii_uppercase())
+        .collect::<Vec<_>>();
+    directions.sort();
+    directions.join("-")
+}
+
+/// Lowercase a word and remove anything but letters, so that "X-ray" becomes
+/// "xray".
+fn normalize_word(token: &str) -> String {
+    token
+        .chars()
+        .filter(|c| c.is_alphanumeric() || !c.is_ascii())
+        .flat_map(char::to_lowercase)
+        .collect()
+}
+
+/// Remove leading zeros from a number.
+fn normalize_number(token: &str) -> String {
+    let trimmed = token.trim_start_matches('0');
+    if trimmed.is_empty() && !token.is_empty() {
+        "0".to_owned()
+    } else {
+        trimmed.to_owned()
+    }
+}
+
+/// Find the most probable ways to read `encoded`, a series of tokens from
+/// `table` separated by whitespace, as dictionary words. Returns each
+/// reading, most probable first.
+pub fn decode_tokens(
+    dict: &Dictionary,
+    table: &TokenTable,
+    encoded: &str,
+    opts: &SegmentOptions,
+) -> Result<Vec<Breaking>> {
+    let letters = table.letters_of(encoded);
+    if letters.is_empty() {
+        return Err(Error::InvalidPuzzle(format!(
+            "no {} tokens to decode",
+            table.name
+        )));
+    }
+    debug!("decoded {} as {:?}", table.name, letters);
+    break_into_words(dict, &letters, opts)
+}
+
+/// Matches words which are spelled exactly as `encoded` in `code`.
+struct Encodes<'a> {
+    code: &'a Code,
+    encoded: &'a [u8],
+}
+
+impl Automaton for Encodes<'_> {
+    /// The number of bytes of `encoded` matched so far, or `None` if we can't
+    /// match.
+    type State = Option<usize>;
+
+    fn start(&self) -> Self::State {
+        Some(0)
+    }
+
+    fn is_match(&self, state: &Self::State) -> bool {
+        *state == Some(self.encoded.len())
+    }
+
+    fn can_match(&self, state: &Self::State) -> bool {
+        state.is_some()
+    }
+
+    fn accept(&self, state: &Self::State, byte: u8) -> Self::State {
+        let pos = (*state)?;
+        let symbols = self.code.symbols(byte)?;
+        if self.encoded[pos..].starts_with(symbols.as_bytes()) {
+            Some(pos + symbols.len())
+        } else {
+            None
+        }
+    }
+}
@@ -0,0 +1,200 @@
+//! Encrypting dictionaries at rest, for word lists which are licensed on the
+//! condition that they aren't redistributed in readable form.
+//!
+//! Each file of an encrypted dictionary, except its metadata, is sealed with
+//! ChaCha20-Poly1305 using a key derived from a secret with PBKDF2. The
+//! metadata records the salt and iteration count we used, so that the same
+//! secret gives the same key when loading. We can't memory-map an encrypted
+//! file and search it in place, so loading decrypts each file into memory
+//! once. Unencrypted dictionaries are still memory-mapped as usual.
+//!
+//! Anyone who can run a program which loads the dictionary can read the
+//! words, so this protects the files, not the words themselves.
+
+use ring::{
+    aead::{Aad, LessSafeKey, Nonce, UnboundKey, CHACHA20_POLY1305, NONCE_LEN},
+    pbkdf2,
+    rand::{SecureRandom, SystemRandom},
+};
+use serde::{Deserialize, Serialize};
+use std::{fmt, fs, num::NonZeroU32, path::Path};
+
+use crate::error::{Error, Result};
+
+/// The bytes at the start of every encrypted file.
+const MAGIC: &[u8] = b"WPCRYPT1";
+
+/// The number of PBKDF2 iterations we use for new dictionaries.
+const DEFAULT_ITERATIONS: u32 = 100_000;
+
+/// The length of our PBKDF2 salts, in bytes.
+const SALT_LEN: usize = 16;
+
+/// A secret used to encrypt or decrypt a dictionary. We never show it in
+/// debug output.
+#[derive(Clone)]
+pub struct DictionaryKey {
+    secret: Vec<u8>,
+}
+
+impl DictionaryKey {
+    /// Use `secret`, which may be a passphrase or random bytes, as a key.
+    pub fn new(secret: impl Into<Vec<u8>>) -> Result<DictionaryKey> {
+        let secret = secret.into();
+        if secret.is_empty() {
+            return Err(Error::InvalidOption("dictionary key is empty".to_owned()));
+        }
+        Ok(DictionaryKey { secret })
+    }
+
+    /// Read a key from `path`. A trailing newline is ignored, so that keys
+    /// written with `echo` work.
+    pub fn from_file(path: &Path) -> Result<DictionaryKey> {
+        let mut secret = fs::read(path).map_err(Error::io(path))?;
+        while let Some(b'\n' | b'\r') = secret.last() {
+            secret.pop();
+        }
+        DictionaryKey::new(secret)
+    }
+}
+
+impl fmt::Debug for DictionaryKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("DictionaryKey(..)")
+    }
+}
+
+/// How a dictionary was encrypted, as recorded in its metadata.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Encryption {
+    /// The number of PBKDF2-HMAC-SHA256 iterations used to derive the key.
+    pub iterations: u32,
+    /// The PBKDF2 salt, in hexadecimal.
+    pub salt: String,
+}
+
+impl Encryption {
+    /// Choose parameters for encrypting a new dictionary, with a random salt.
+    pub(crate) fn generate() -> Result<Encryption> {
+        let mut salt = [0; SALT_LEN];
+        fill_random(&mut salt)?;
+        Ok(Encryption {
+            iterations: DEFAULT_ITERATIONS,
+            salt: salt.iter().map(|b| format!("{:02x}", b)).collect(),
+        })
+    }
+
+    /// Decode our salt.
+    fn salt_bytes(&self) -> Result<Vec<u8>> {
+        let invalid = || {
+            Error::CorruptDictionary(format!(
+                "invalid encryption salt {:?} in metadata",
+                self.salt
+            ))
+        };
+        if !self.salt.len().is_multiple_of(2) {
+            return Err(invalid());
+        }
+        (0..self.salt.len())
+            .step_by(2)
+            .map(|i| {
+                self.salt
+                    .get(i..i + 2)
+                    .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+                    .ok_or_else(invalid)
+            })
+            .collect()
+    }
+}
+
+/// Encrypts and decrypts the files of one dictionary.
+pub(crate) struct Cipher {
+    key: LessSafeKey,
+}
+
+impl Cipher {
+    /// Derive the key for a dictionary encrypted as described by
+    /// `encryption`.
+    pub(crate) fn new(key: &DictionaryKey, encryption: &Encryption) -> Result<Cipher> {
+        let iterations = NonZeroU32::new(encryption.iterations).ok_or_else(|| {
+            Error::CorruptDictionary("encryption has zero iterations".to_owned())
+        })?;
+        let mut derived = [0; 32];
+        pbkdf2::derive(
+            pbkdf2::PBKDF2_HMAC_SHA256,
+            iterations,
+            &encryption.salt_bytes()?,
+            &key.secret,
+            &mut derived,
+        );
+        let key = UnboundKey::new(&CHACHA20_POLY1305, &derived)
+            .expect("derived key should have the right length");
+        Ok(Cipher {
+            key: LessSafeKey::new(key),
+        })
+    }
+
+    /// Encrypt `plaintext`, the index with `extension`. The extension is
+    /// authenticated along with the contents, so that one index can't be
+    /// swapped for another.
+    pub(crate) fn seal(
+        &self,
+        extension: &str,
+        mut plaintext: Vec<u8>,
+    ) -> Result<Vec<u8>> {
+        let mut nonce = [0; NONCE_LEN];
+        fill_random(&mut nonce)?;
+        self.key
+            .seal_in_place_append_tag(
+                Nonce::assume_unique_for_key(nonce),
+                Aad::from(extension.as_bytes()),
+                &mut plaintext,
+            )
+            .map_err(|_| Error::Encryption("could not encrypt index".to_owned()))?;
+        let mut sealed = Vec::with_capacity(MAGIC.len() + NONCE_LEN + plaintext.len());
+        sealed.extend_from_slice(MAGIC);
+        sealed.extend_from_slice(&nonce);
+        sealed.extend_from_slice(&plaintext);
+        Ok(sealed)
+    }
+
+    /// Decrypt `sealed`, the contents of `path`, which is the index with
+    /// `extension`.
+    pub(crate) fn open(
+        &self,
+        path: &Path,
+        extension: &str,
+        sealed: &[u8],
+    ) -> Result<Vec<u8>> {
+        let wrong_key = || Error::WrongKey {
+            path: path.to_owned(),
+        };
+        let rest = sealed.strip_prefix(MAGIC).ok_or_else(wrong_key)?;
+        if rest.len() < NONCE_LEN {
+            return Err(wrong_key());
+        }
+        let (nonce, ciphertext) = rest.split_at(NONCE_LEN);
+        let nonce =
+            Nonce::try_assume_unique_for_key(nonce).map_err(|_| wrong_key())?;
+        let mut buffer = ciphertext.to_vec();
+        let len = self
+            .key
+            .open_in_place(nonce, Aad::from(extension.as_bytes()), &mut buffer)
+            .map_err(|_| wrong_key())?
+            .len();
+        buffer.truncate(len);
+        Ok(buffer)
+    }
+}
+
+/// Is `bytes` the contents of an encrypted file?
+pub(crate) fn is_encrypted(bytes: &[u8]) -> bool {
+    bytes.starts_with(MAGIC)
+}
+
+/// Fill `buf` with random bytes from the operating system.
+fn fill_random(buf: &mut [u8]) -> Result<()> {
+    SystemRandom::new().fill(buf).map_err(|_| {
+        Error::Encryption("could not get random bytes from the system".to_owned())
+    })
+}
@@ -0,0 +1,91 @@
+//! Crossword-style enumerations like "(5,3)", which give the length of each
+//! word in a multi-word answer.
+
+use std::{fmt, str::FromStr};
+
+use crate::error::{Error, Result};
+use crate::predicate::Predicate;
+
+/// The length of each word in an answer.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Enumeration {
+    lengths: Vec<usize>,
+}
+
+impl Enumeration {
+    /// Create an enumeration from the length of each word.
+    pub fn new(lengths: Vec<usize>) -> Result<Enumeration> {
+        if lengths.is_empty() || lengths.contains(&0) {
+            return Err(Error::InvalidOption(format!(
+                "enumeration must list one or more positive lengths, not {:?}",
+                lengths
+            )));
+        }
+        Ok(Enumeration { lengths })
+    }
+
+    /// The length of each word.
+    pub fn lengths(&self) -> &[usize] {
+        &self.lengths
+    }
+
+    /// The total number of letters in the answer.
+    pub fn letter_count(&self) -> usize {
+        self.lengths.iter().sum()
+    }
+
+    /// The positions between letters where one word ends and the next
+    /// begins, including the start and end of the answer.
+    pub fn boundaries(&self) -> Vec<usize> {
+        let mut boundaries = vec![0];
+        for len in &self.lengths {
+            boundaries.push(boundaries.last().expect("never empty") + len);
+        }
+        boundaries
+    }
+
+    /// Does `text`, a word or a phrase with its words separated by spaces,
+    /// have the shape given by `self.lengths()[start..end]`?
+    pub fn fits_part(&self, start: usize, end: usize, text: &str) -> bool {
+        let lengths = text.split(' ').map(|word| word.chars().count());
+        lengths.eq(self.lengths[start..end].iter().copied())
+    }
+
+    /// Do `words`, some of which may be phrases, have exactly this shape?
+    pub fn fits_words(&self, words: &[String]) -> bool {
+        self.fits_part(0, self.lengths.len(), &words.join(" "))
+    }
+}
+
+impl FromStr for Enumeration {
+    type Err = Error;
+
+    /// Parse an enumeration like "5,3", "(5,3)", "5-3" or "5 3".
+    fn from_str(s: &str) -> Result<Enumeration> {
+        let inner = s.trim().trim_start_matches('(').trim_end_matches(')');
+        let lengths = inner
+            .split(|c: char| c == ',' || c == '-' || c.is_whitespace())
+            .filter(|len| !len.is_empty())
+            .map(|len| {
+                len.parse::<usize>().map_err(|_| {
+                    Error::InvalidOption(format!("cannot parse enumeration {:?}", s))
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+        Enumeration::new(lengths)
+    }
+}
+
+impl fmt::Display for Enumeration {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let lengths = self.lengths.iter().map(|len| len.to_string());
+        write!(f, "({})", lengths.collect::<Vec<_>>().join(","))
+    }
+}
+
+/// Accepts words and phrases with exactly this shape.
+impl Predicate for Enumeration {
+    fn accepts(&self, word: &str) -> bool {
+        self.fits_part(0, self.lengths.len(), word)
+    }
+}
@@ -0,0 +1,160 @@
+//! Errors returned by this library.
+
+use std::{
+    io,
+    path::{Path, PathBuf},
+    str::Utf8Error,
+};
+
+use crate::diagnostics::{LineDiagnostic, PatternDiagnostic};
+
+/// A `Result` which fails with our `Error` by default.
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+/// The ways in which building or searching a dictionary can fail.
+#[derive(Debug, thiserror::Error)]
+#[non_exhaustive]
+pub enum Error {
+    /// A search pattern was not a valid regex.
+    #[error("invalid regex {pattern:?}")]
+    InvalidRegex {
+        pattern: String,
+        #[source]
+        source: Box<dyn std::error::Error + Send + Sync>,
+    },
+
+    /// A search pattern had a syntax error, which we can point to.
+    #[error("{0}")]
+    PatternSyntax(Box<PatternDiagnostic>),
+
+    /// We could not build an automaton for a fuzzy search.
+    #[error("could not search for words near {word:?}")]
+    FuzzySearch {
+        word: String,
+        #[source]
+        source: fst::automaton::LevenshteinError,
+    },
+
+    /// A dictionary or one of its indices is damaged, or was not built by
+    /// `mkdict`.
+    #[error("corrupt dictionary: {0}")]
+    CorruptDictionary(String),
+
+    /// A dictionary lacks an optional index, and needs to be rebuilt with
+    /// `mkdict` to support an operation.
+    #[error("dictionary has no {index} index; rebuild it with `mkdict{flag}`")]
+    MissingIndex {
+        index: &'static str,
+        /// The `mkdict` flag needed to build the index, including a leading
+        /// space, or the empty string.
+        flag: &'static str,
+    },
+
+    /// We could not read or write a file.
+    #[error("I/O error on {}", path.display())]
+    Io {
+        path: PathBuf,
+        #[source]
+        source: io::Error,
+    },
+
+    /// We could not download a file.
+    #[error("could not download {url}")]
+    Download {
+        url: String,
+        #[source]
+        source: Box<ureq::Error>,
+    },
+
+    /// We refused to replace an existing dictionary.
+    #[error("{} already exists; pass --force to replace it", path.display())]
+    DictionaryExists { path: PathBuf },
+
+    /// We could not build an index.
+    #[error("could not build index {}", path.display())]
+    Index {
+        path: PathBuf,
+        #[source]
+        source: fst::Error,
+    },
+
+    /// A line in an input file (word counts, clues, etc.) was invalid.
+    #[error("{}:{line}: {message}", path.display())]
+    InvalidWordList {
+        path: PathBuf,
+        /// The 1-based line number.
+        line: usize,
+        message: String,
+    },
+
+    /// A line in an input file couldn't be parsed, and we can point to the
+    /// problem.
+    #[error("{0}")]
+    InvalidLine(Box<LineDiagnostic>),
+
+    /// A checkpoint file couldn't be used to resume a search.
+    #[error("cannot resume from {}: {message}", path.display())]
+    InvalidCheckpoint { path: PathBuf, message: String },
+
+    /// We don't know how to pronounce a word.
+    #[error("no known pronunciation for {0:?}")]
+    NoPronunciation(String),
+
+    /// A negative log probability was NaN or negative, probably because a
+    /// dictionary is corrupt.
+    #[error("invalid negative log probability {0}")]
+    InvalidProbability(f64),
+
+    /// A dictionary is encrypted, and we weren't given its key.
+    #[error("{} is encrypted; pass its key with --key-file", path.display())]
+    KeyRequired { path: PathBuf },
+
+    /// We couldn't decrypt part of a dictionary, because we were given the
+    /// wrong key or the file is damaged.
+    #[error(
+        "could not decrypt {}; the key is wrong or the file is damaged",
+        path.display()
+    )]
+    WrongKey { path: PathBuf },
+
+    /// We couldn't encrypt a dictionary.
+    #[error("{0}")]
+    Encryption(String),
+
+    /// An option had an invalid value.
+    #[error("{0}")]
+    InvalidOption(String),
+
+    /// A puzzle description, such as a grid or a Wordle board, was invalid.
+    #[error("{0}")]
+    InvalidPuzzle(String),
+}
+
+impl Error {
+    /// Build a function which wraps an `io::Error` involving `path`.
+    pub(crate) fn io(path: &Path) -> impl FnOnce(io::Error) -> Error + '_ {
+        move |source| Error::Io {
+            path: path.to_owned(),
+            source,
+        }
+    }
+
+    /// Build a function which wraps an `fst::Error` involving `path`.
+    pub(crate) fn index(path: &Path) -> impl FnOnce(fst::Error) -> Error + '_ {
+        move |source| match source {
+            fst::Error::Io(source) => Error::Io {
+                path: path.to_owned(),
+                source,
+            },
+            source => Error::Index {
+                path: path.to_owned(),
+                source,
+            },
+        }
+    }
+
+    /// Report a dictionary entry which isn't valid UTF-8.
+    pub(crate) fn invalid_utf8(_err: Utf8Error) -> Error {
+        Error::CorruptDictionary("dictionary contains invalid UTF-8".to_owned())
+    }
+}
@@ -0,0 +1,220 @@
+//! Sorting more records than fit in memory, by sorting them in shards which
+//! do fit, writing each shard to disk, and merging the shards.
+//!
+//! Records are byte strings, sorted bytewise. Callers which need to sort by
+//! a key and carry a value along should put the value after the key, with a
+//! separator which sorts before anything in the key, like `\0`.
+
+use std::{
+    cmp::Reverse,
+    collections::BinaryHeap,
+    fs::{self, File},
+    io::{self, BufReader, BufWriter, Read, Write},
+    iter, mem,
+    path::PathBuf,
+    vec,
+};
+
+use crate::error::{Error, Result};
+
+/// The smallest shard we'll write, however little memory we're given, so
+/// that we don't write millions of tiny files.
+const MIN_SHARD_BYTES: usize = 1 << 20;
+
+/// The most shards we merge at once. With more shards than this, we merge
+/// them in several passes, so that we don't run out of file handles.
+const MAX_MERGE_SHARDS: usize = 64;
+
+/// Sorts records, keeping at most about `max_memory` bytes of them in memory
+/// at once.
+pub(crate) struct ExternalSorter {
+    /// The prefix for our shard files.
+    prefix: PathBuf,
+    /// How many bytes of records to hold before writing a shard.
+    max_memory: usize,
+    /// The records we haven't written yet.
+    buffer: Vec<Vec<u8>>,
+    /// Roughly how much memory `buffer` uses.
+    buffered_bytes: usize,
+    /// The shards we've written, each of them sorted.
+    shards: Vec<PathBuf>,
+    /// How many shard files we've created, for naming the next one.
+    shard_count: usize,
+}
+
+impl ExternalSorter {
+    /// Create a sorter which writes its shards to files named after `prefix`.
+    pub(crate) fn new(prefix: PathBuf, max_memory: usize) -> ExternalSorter {
+        ExternalSorter {
+            prefix,
+            max_memory: max_memory.max(MIN_SHARD_BYTES),
+            buffer: vec![],
+            buffered_bytes: 0,
+            shards: vec![],
+            shard_count: 0,
+        }
+    }
+
+    /// Add a record.
+    pub(crate) fn push(&mut self, record: Vec<u8>) -> Result<()> {
+        self.buffered_bytes += record.len() + mem::size_of::<Vec<u8>>();
+        self.buffer.push(record);
+        if self.buffered_bytes >= self.max_memory {
+            self.write_shard()?;
+        }
+        Ok(())
+    }
+
+    /// Sort and write the records in memory to a new shard.
+    fn write_shard(&mut self) -> Result<()> {
+        self.buffer.sort_unstable();
+        let records = mem::take(&mut self.buffer).into_iter().map(Ok);
+        self.buffered_bytes = 0;
+        self.write_new_shard(records)
+    }
+
+    /// Write `records`, which must be sorted, to a new shard.
+    fn write_new_shard<I>(&mut self, records: I) -> Result<()>
+    where
+        I: Iterator<Item = Result<Vec<u8>>>,
+    {
+        let mut path = self.prefix.clone().into_os_string();
+        path.push(format!(".shard{}", self.shard_count));
+        let path = PathBuf::from(path);
+        self.shard_count += 1;
+        // Record the shard first, so that we remove it even if writing fails.
+        self.shards.push(path.clone());
+
+        let file = File::create(&path).map_err(Error::io(&path))?;
+        let mut wtr = BufWriter::new(file);
+        for record in records {
+            write_record(&mut wtr, &record?).map_err(Error::io(&path))?;
+        }
+        wtr.flush().map_err(Error::io(&path))
+    }
+
+    /// Finish adding records, and return them all in sorted order. If
+    /// everything fit in memory, we never touch the disk.
+    pub(crate) fn finish(mut self) -> Result<Sorted> {
+        if self.shards.is_empty() {
+            let mut records = mem::take(&mut self.buffer);
+            records.sort_unstable();
+            return Ok(Sorted::InMemory(records.into_iter()));
+        }
+        if !self.buffer.is_empty() {
+            self.write_shard()?;
+        }
+        // Merge the oldest shards into a new one at the back of the queue
+        // until few enough are left to merge at once.
+        while self.shards.len() > MAX_MERGE_SHARDS {
+            let group = self.shards.drain(..MAX_MERGE_SHARDS).collect();
+            let mut merge = Merge::open(group)?;
+            self.write_new_shard(iter::from_fn(|| merge.next().transpose()))?;
+        }
+        Ok(Sorted::Merged(Merge::open(mem::take(&mut self.shards))?))
+    }
+}
+
+impl Drop for ExternalSorter {
+    fn drop(&mut self) {
+        remove_shards(&self.shards);
+    }
+}
+
+/// Records from an `ExternalSorter`, in sorted order.
+pub(crate) enum Sorted {
+    /// Every record fit in memory.
+    InMemory(vec::IntoIter<Vec<u8>>),
+    /// We're merging shards from disk.
+    Merged(Merge),
+}
+
+impl Iterator for Sorted {
+    type Item = Result<Vec<u8>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self {
+            Sorted::InMemory(records) => records.next().map(Ok),
+            Sorted::Merged(merge) => merge.next().transpose(),
+        }
+    }
+}
+
+/// A merge of sorted shards on disk.
+pub(crate) struct Merge {
+    /// The shard files, which we remove when we're dropped.
+    shards: Vec<PathBuf>,
+    /// A reader for each shard.
+    readers: Vec<BufReader<File>>,
+    /// The next record from each shard which has any left, and the index of
+    /// the shard, smallest first.
+    heads: BinaryHeap<Reverse<(Vec<u8>, usize)>>,
+}
+
+impl Merge {
+    /// Start merging `shards`, which we remove when we're dropped, even if
+    /// we fail to open them.
+    fn open(shards: Vec<PathBuf>) -> Result<Merge> {
+        let mut merge = Merge {
+            readers: Vec::with_capacity(shards.len()),
+            heads: BinaryHeap::with_capacity(shards.len()),
+            shards,
+        };
+        for (idx, path) in merge.shards.iter().enumerate() {
+            let file = File::open(path).map_err(Error::io(path))?;
+            let mut rdr = BufReader::new(file);
+            if let Some(record) = read_record(&mut rdr).map_err(Error::io(path))? {
+                merge.heads.push(Reverse((record, idx)));
+            }
+            merge.readers.push(rdr);
+        }
+        Ok(merge)
+    }
+
+    /// The next record, if any.
+    fn next(&mut self) -> Result<Option<Vec<u8>>> {
+        let Reverse((record, idx)) = match self.heads.pop() {
+            Some(head) => head,
+            None => return Ok(None),
+        };
+        let path = &self.shards[idx];
+        if let Some(next) =
+            read_record(&mut self.readers[idx]).map_err(Error::io(path))?
+        {
+            self.heads.push(Reverse((next, idx)));
+        }
+        Ok(Some(record))
+    }
+}
+
+impl Drop for Merge {
+    fn drop(&mut self) {
+        remove_shards(&self.shards);
+    }
+}
+
+/// Remove shard files. This is only cleanup, so ignore errors.
+fn remove_shards(shards: &[PathBuf]) {
+    for path in shards {
+        let _ = fs::remove_file(path);
+    }
+}
+
+/// Write `record` with its length in front.
+fn write_record<W: Write>(wtr: &mut W, record: &[u8]) -> io::Result<()> {
+    wtr.write_all(&(record.len() as u64).to_le_bytes())?;
+    wtr.write_all(record)
+}
+
+/// Read a record written by `write_record`, or `None` at the end of the file.
+fn read_record<R: Read>(rdr: &mut R) -> io::Result<Option<Vec<u8>>> {
+    let mut len = [0; 8];
+    match rdr.read_exact(&mut len) {
+        Ok(()) => {}
+        Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+        Err(err) => return Err(err),
+    }
+    let mut record = vec![0; u64::from_le_bytes(len) as usize];
+    rdr.read_exact(&mut record)?;
+    Ok(Some(record))
+}
@@ -0,0 +1,120 @@
+//! Extracting a message from a list of puzzle answers, as in puzzle hunts,
+//! by taking one letter from each answer.
+//!
+//! We try several common schemes, and use the segmentation engine to judge
+//! which of the resulting strings looks most like a sequence of words.
+
+use std::{cmp::Reverse, fmt};
+
+use crate::dictionary::Dictionary;
+use crate::error::{Error, Result};
+use crate::segment::{best_breaking, Breaking, SegmentOptions};
+
+/// A way to take one letter from each answer.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Scheme {
+    /// The first letter of each answer.
+    FirstLetters,
+    /// The last letter of each answer.
+    LastLetters,
+    /// The first letter of the first answer, the second letter of the second
+    /// answer, and so on.
+    Diagonal,
+    /// The letter at a given 1-based position in each answer, with one
+    /// position for each answer.
+    Indexed(Vec<usize>),
+}
+
+impl Scheme {
+    /// Take one letter from each of `answers`, or return `None` if some
+    /// answer doesn't have the letter we need. Each answer should only
+    /// contain letters.
+    pub fn extract(&self, answers: &[String]) -> Option<String> {
+        let nth = |answer: &str, i: usize| answer.chars().nth(i);
+        answers
+            .iter()
+            .enumerate()
+            .map(|(i, answer)| match self {
+                Scheme::FirstLetters => answer.chars().next(),
+                Scheme::LastLetters => answer.chars().next_back(),
+                Scheme::Diagonal => nth(answer, i),
+                Scheme::Indexed(indices) => indices
+                    .get(i)
+                    .and_then(|&idx| idx.checked_sub(1))
+                    .and_then(|idx| nth(answer, idx)),
+            })
+            .collect()
+    }
+}
+
+impl fmt::Display for Scheme {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Scheme::FirstLetters => write!(f, "first letters"),
+            Scheme::LastLetters => write!(f, "last letters"),
+            Scheme::Diagonal => write!(f, "diagonal"),
+            Scheme::Indexed(_) => write!(f, "indexed"),
+        }
+    }
+}
+
+/// The result of applying one scheme.
+#[derive(Clone, Debug)]
+pub struct Extraction {
+    /// The scheme we applied.
+    pub scheme: Scheme,
+    /// The letters we extracted.
+    pub letters: String,
+    /// The most probable way to break `letters` into words, if there is one.
+    pub breaking: Option<Breaking>,
+}
+
+/// Apply each of `schemes` to `answers`, and rank the results by how
+/// plausibly they break into words, most plausible first. Extractions which
+/// don't break into words come last, and schemes which don't apply to every
+/// answer are left out.
+pub fn rank_extractions(
+    dict: &Dictionary,
+    answers: &[String],
+    schemes: &[Scheme],
+) -> Result<Vec<Extraction>> {
+    if answers.is_empty() {
+        return Err(Error::InvalidPuzzle(
+            "no answers to extract from".to_owned(),
+        ));
+    }
+    let answers = answers
+        .iter()
+        .map(|answer| {
+            answer
+                .chars()
+                .filter(|c| c.is_alphabetic())
+                .flat_map(char::to_lowercase)
+                .collect::<String>()
+        })
+        .collect::<Vec<_>>();
+    let mut extractions = vec![];
+    for scheme in schemes {
+        if let Scheme::Indexed(indices) = scheme {
+            if indices.len() != answers.len() {
+                return Err(Error::InvalidPuzzle(format!(
+                    "expected {} indices, one for each answer, found {}",
+                    answers.len(),
+                    indices.len()
+                )));
+            }
+        }
+        let letters = match scheme.extract(&answers) {
+            Some(letters) => letters,
+            None => continue,
+        };
+        let breaking = best_breaking(dict, &letters, &SegmentOptions::default())?;
+        extractions.push(Extraction {
+            scheme: scheme.clone(),
+            letters,
+            breaking,
+        });
+    }
+    extractions.sort_by_key(|e| Reverse(e.breaking.as_ref().map(|b| b.total)));
+    Ok(extractions)
+}
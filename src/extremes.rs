@@ -0,0 +1,51 @@
+//! Finding the longest (or shortest) words which pass some tests.
+
+use std::cmp::Reverse;
+
+use crate::dictionary::Dictionary;
+use crate::error::Result;
+use crate::predicate::Predicates;
+use crate::probability::Prob;
+
+/// Options for `find_extremes`.
+#[derive(Clone, Debug)]
+pub struct ExtremeOptions {
+    /// Look for the shortest words instead of the longest.
+    pub shortest: bool,
+    /// The maximum number of words to return.
+    pub limit: usize,
+}
+
+impl Default for ExtremeOptions {
+    fn default() -> Self {
+        ExtremeOptions {
+            shortest: false,
+            limit: 20,
+        }
+    }
+}
+
+/// Find the longest words in `dict` which pass every test in `predicates`,
+/// or the shortest if `opts.shortest` is set. Words of the same length are
+/// sorted from most to least probable.
+pub fn find_extremes(
+    dict: &Dictionary,
+    predicates: &Predicates,
+    opts: &ExtremeOptions,
+) -> Result<Vec<(Prob, String)>> {
+    let mut words = vec![];
+    for entry in dict.words() {
+        let (word, prob) = entry?;
+        if predicates.accepts(&word) {
+            words.push((prob, word));
+        }
+    }
+    let len = |word: &str| word.chars().count();
+    if opts.shortest {
+        words.sort_by_key(|(prob, word)| (len(word), Reverse(*prob)));
+    } else {
+        words.sort_by_key(|(prob, word)| (Reverse(len(word)), Reverse(*prob)));
+    }
+    words.truncate(opts.limit);
+    Ok(words)
+}
@@ -0,0 +1,1039 @@
+//! Filling crossword grids with dictionary words.
+
+use log::{debug, info, trace};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashSet,
+    convert::TryFrom,
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use crate::checkpoint::{Checkpoint, CheckpointOptions};
+use crate::dictionary::{phrase_letters, Dictionary, SearchOptions};
+use crate::error::{Error, Result};
+use crate::grid::{Cell, Grid, Slot};
+use crate::metadata::content_hash;
+use crate::probability::Prob;
+use crate::random::Rng;
+use crate::search_tree::{NodeId, Outcome, SearchTree, DEFAULT_MAX_NODES};
+
+/// Options for `fill`.
+#[derive(Clone, Debug)]
+pub struct FillOptions {
+    /// The maximum number of candidate words to consider for each slot. We
+    /// keep the most probable candidates.
+    pub max_candidates: usize,
+    /// Allow the same answer to appear more than once. Crosswords normally
+    /// forbid this.
+    pub allow_duplicates: bool,
+    /// Forbid answers which contain another answer, like "stone" and
+    /// "milestone". This also forbids repeating an answer.
+    pub no_substrings: bool,
+    /// How many times to give up and start again with a shuffled search
+    /// order, when a search gets stuck exploring a hopeless part of the grid.
+    pub restarts: usize,
+    /// How many times the first attempt may backtrack before we restart. This
+    /// doubles with each restart, and the last attempt has no limit, so that
+    /// we always find a fill if there is one.
+    pub backtrack_limit: u64,
+    /// The seed for shuffling the search order when restarting.
+    pub seed: u64,
+    /// How many searches to run in parallel, each on its own thread and
+    /// with its own search order. The first uses the same order as a single
+    /// search.
+    pub threads: usize,
+    /// If set, keep looking for more probable fills until this much time has
+    /// passed, and return the most probable fill we found. Otherwise, return
+    /// the first fill any search finds.
+    pub time_budget: Option<Duration>,
+    /// Stop searching once this is set, returning the best fill found so
+    /// far, if any.
+    pub cancel: Option<Arc<AtomicBool>>,
+    /// Save the state of the search here every so often, and resume from it
+    /// if it has already been saved. This requires a single thread and no
+    /// time budget.
+    pub checkpoint: Option<CheckpointOptions>,
+    /// Write the tree of words the search tried here, as a Graphviz graph.
+    /// With several threads, this only shows the first thread's search.
+    pub trace_graph: Option<PathBuf>,
+}
+
+impl Default for FillOptions {
+    fn default() -> Self {
+        FillOptions {
+            max_candidates: 10_000,
+            allow_duplicates: false,
+            no_substrings: false,
+            restarts: 0,
+            backtrack_limit: 1_000,
+            seed: 0,
+            threads: 1,
+            time_budget: None,
+            cancel: None,
+            checkpoint: None,
+            trace_graph: None,
+        }
+    }
+}
+
+/// Fill every open cell in `grid` so that every slot contains a dictionary
+/// word, trying more probable words first. Returns `None` if no fill exists
+/// or we were cancelled before finding one, and an error if `grid` fails
+/// `Grid::validate`.
+pub fn fill(
+    dict: &Dictionary,
+    grid: &Grid,
+    opts: &FillOptions,
+) -> Result<Option<Grid>> {
+    grid.validate()?;
+    if opts.threads == 0 {
+        return Err(Error::InvalidOption(
+            "fill needs at least one thread".to_owned(),
+        ));
+    }
+    if opts.checkpoint.is_some() && (opts.threads > 1 || opts.time_budget.is_some()) {
+        return Err(Error::InvalidOption(
+            "checkpoints only work with a single thread and no time budget".to_owned(),
+        ));
+    }
+    let slots = grid.slots();
+    let search_opts = SearchOptions {
+        limit: Some(opts.max_candidates),
+        most_probable: true,
+    };
+    let mut candidates = vec![];
+    for slot in &slots {
+        candidates.push(slot_candidates(dict, grid, slot, &search_opts)?);
+    }
+
+    let checkpoint = opts.checkpoint.as_ref().map(|checkpoint_opts| {
+        // Identify the search by its candidates, which depend on the
+        // dictionary, its filters and our options.
+        let mut words = String::new();
+        for slot_candidates in &candidates {
+            for (_, word) in slot_candidates {
+                words.extend(word);
+                words.push(' ');
+            }
+            words.push('\n');
+        }
+        let mut search = format!(
+            "fill {} using candidates {} (allow_duplicates={}, no_substrings={}",
+            grid.to_string().trim_end().replace('\n', "/"),
+            content_hash(words.as_bytes()),
+            opts.allow_duplicates,
+            opts.no_substrings,
+        );
+        // The seed doesn't matter unless we restart.
+        if opts.restarts > 0 {
+            search.push_str(&format!(
+                ", restarts={}, backtrack_limit={}, seed={}",
+                opts.restarts, opts.backtrack_limit, opts.seed
+            ));
+        }
+        search.push(')');
+        Checkpoint::new(checkpoint_opts, search)
+    });
+    let resume = match &checkpoint {
+        Some(checkpoint) => checkpoint.load::<FillState>()?,
+        None => None,
+    };
+    if let Some(state) = &resume {
+        debug!(
+            "resuming attempt {} at depth {}",
+            state.attempt,
+            state.path.len()
+        );
+    }
+
+    let crossings = crossings(grid, &slots);
+    let portfolio = Portfolio::new(opts);
+    let ctx = FillContext {
+        slots: &slots,
+        crossings: &crossings,
+        candidates: &candidates,
+        cells: grid.cells(),
+        opts,
+        portfolio: &portfolio,
+    };
+    let tree = opts
+        .trace_graph
+        .as_ref()
+        .map(|_| SearchTree::new("fill", DEFAULT_MAX_NODES));
+    // The first search runs on this thread, and is the only one which can
+    // use a checkpoint or record its tree.
+    let mut first = Worker {
+        index: 0,
+        resume,
+        checkpoint,
+        tree,
+        stats: FillStats::default(),
+    };
+    let mut stats = FillStats::default();
+    thread::scope(|scope| -> Result<()> {
+        let others = (1..opts.threads)
+            .map(|index| {
+                let ctx = &ctx;
+                scope.spawn(move || {
+                    let mut worker = Worker::new(index);
+                    let result = worker.run(ctx);
+                    if result.is_err() {
+                        ctx.portfolio.stop();
+                    }
+                    result.map(|()| worker.stats)
+                })
+            })
+            .collect::<Vec<_>>();
+        let result = first.run(&ctx);
+        if result.is_err() {
+            portfolio.stop();
+        }
+        for other in others {
+            let other_stats = other
+                .join()
+                .unwrap_or_else(|panic| std::panic::resume_unwind(panic))?;
+            stats.add(&other_stats);
+        }
+        result
+    })?;
+    stats.add(&first.stats);
+
+    let finished = !portfolio.was_interrupted();
+    let found = portfolio.into_best();
+    info!(
+        found = found.is_some(), tried = stats.tried, pruned = stats.pruned,
+        backjumps = stats.backjumps, restarts = stats.restarts;
+        "fill tried {} candidates, pruned {} branches, backjumped {} times \
+         and restarted {} times",
+        stats.tried,
+        stats.pruned,
+        stats.backjumps,
+        stats.restarts
+    );
+    if let (Some(tree), Some(path)) = (&first.tree, &opts.trace_graph) {
+        tree.write(path)?;
+    }
+    // Keep the checkpoint if we were cancelled, so that we can resume.
+    if let Some(checkpoint) = &first.checkpoint {
+        if finished {
+            checkpoint.finish()?;
+        }
+    }
+    Ok(found.map(|(_, cells)| grid.with_cells(cells)))
+}
+
+/// The words and phrases from `dict` which fit in `slot`, most probable
+/// first, as the letters to place in its cells. Phrases fill slots without
+/// their spaces, like crossword answers, and we only keep the most probable
+/// entry with each spelling.
+pub(crate) fn slot_candidates(
+    dict: &Dictionary,
+    grid: &Grid,
+    slot: &Slot,
+    search_opts: &SearchOptions,
+) -> Result<Vec<(Prob, Vec<char>)>> {
+    let pattern = grid.slot_pattern(slot);
+    let mut seen = HashSet::new();
+    let words = dict
+        .find_matches_with(&pattern, search_opts)?
+        .into_vec()
+        .into_iter()
+        .map(|(prob, word)| (prob, phrase_letters(&word).chars().collect::<Vec<_>>()))
+        .filter(|(_, word)| {
+            word.len() == slot.cells.len() && seen.insert(word.clone())
+        })
+        .collect::<Vec<_>>();
+    debug!("slot {:?} has {} candidates", pattern, words.len());
+    Ok(words)
+}
+
+/// Options for `score_fill`.
+#[derive(Clone, Debug)]
+pub struct ScoreFillOptions {
+    /// Count words less probable than this as obscure.
+    pub obscure_below: Prob,
+    /// Report pairs of entries which share a run of at least this many
+    /// letters, like "stone" and "milestone".
+    pub min_shared_letters: usize,
+    /// The penalty, in nats, for each entry which isn't in the dictionary
+    /// and each pair of entries which share letters.
+    pub penalty: f64,
+}
+
+impl Default for ScoreFillOptions {
+    fn default() -> Self {
+        ScoreFillOptions {
+            obscure_below: Prob::from_probability(1e-6),
+            min_shared_letters: 4,
+            penalty: 10.0,
+        }
+    }
+}
+
+/// Two entries in a fill which share a run of letters.
+#[derive(Clone, Debug)]
+pub struct SharedLetters {
+    /// The letters they share.
+    pub letters: String,
+    /// The two entries.
+    pub entries: (String, String),
+}
+
+/// How good a completed fill is, as computed by `score_fill`.
+#[derive(Clone, Debug)]
+pub struct FillScore {
+    /// Each entry in the grid, across and then down, with its probability,
+    /// or `None` if it isn't in the dictionary.
+    pub entries: Vec<(String, Option<Prob>)>,
+    /// The product of the probabilities of the entries in the dictionary.
+    pub total: Prob,
+    /// The entries which are in the dictionary, but less probable than
+    /// `ScoreFillOptions::obscure_below`.
+    pub obscure: Vec<(String, Prob)>,
+    /// The entries which aren't in the dictionary.
+    pub unknown: Vec<String>,
+    /// The pairs of entries which share too many letters.
+    pub shared: Vec<SharedLetters>,
+    /// The penalty for each unknown entry and shared pair, in nats.
+    pub penalty: f64,
+}
+
+impl FillScore {
+    /// The negative log probability of the fill plus our penalties. Lower
+    /// is better.
+    pub fn score(&self) -> f64 {
+        let penalties = (self.unknown.len() + self.shared.len()) as f64;
+        self.total.to_f64() + self.penalty * penalties
+    }
+
+    /// The mean negative log probability of the entries in the dictionary.
+    pub fn mean(&self) -> f64 {
+        let known = self.entries.len() - self.unknown.len();
+        self.total.to_f64() / known.max(1) as f64
+    }
+}
+
+/// Score a completed fill of `grid` using the probabilities in `dict`, so
+/// that fills can be compared.
+pub fn score_fill(
+    dict: &Dictionary,
+    grid: &Grid,
+    opts: &ScoreFillOptions,
+) -> Result<FillScore> {
+    let mut entries = vec![];
+    for slot in grid.slots() {
+        let word = grid.slot_word(&slot).ok_or_else(|| {
+            Error::InvalidPuzzle(format!(
+                "grid isn't completely filled: {:?} has open cells",
+                grid.slot_pattern(&slot)
+            ))
+        })?;
+        let prob = dict.probability_of(&word);
+        entries.push((word, prob));
+    }
+
+    let mut total = Prob::always();
+    let mut obscure = vec![];
+    let mut unknown = vec![];
+    for (word, prob) in &entries {
+        match prob {
+            Some(prob) => {
+                total = total * *prob;
+                if *prob < opts.obscure_below {
+                    obscure.push((word.clone(), *prob));
+                }
+            }
+            None => unknown.push(word.clone()),
+        }
+    }
+
+    let mut shared = vec![];
+    for (i, (first, _)) in entries.iter().enumerate() {
+        for (second, _) in &entries[i + 1..] {
+            let letters = longest_common_substring(first, second);
+            if letters.chars().count() >= opts.min_shared_letters.max(1) {
+                shared.push(SharedLetters {
+                    letters,
+                    entries: (first.clone(), second.clone()),
+                });
+            }
+        }
+    }
+
+    Ok(FillScore {
+        entries,
+        total,
+        obscure,
+        unknown,
+        shared,
+        penalty: opts.penalty,
+    })
+}
+
+/// The longest run of letters appearing in both `a` and `b`, or the first
+/// one in `a` if there's a tie.
+fn longest_common_substring(a: &str, b: &str) -> String {
+    let a = a.chars().collect::<Vec<_>>();
+    let b = b.chars().collect::<Vec<_>>();
+    // `lengths[j]` is the length of the common run ending at the current
+    // letter of `a` and letter `j - 1` of `b`.
+    let mut lengths = vec![0; b.len() + 1];
+    let (mut best_len, mut best_end) = (0, 0);
+    for (i, ca) in a.iter().enumerate() {
+        for j in (1..=b.len()).rev() {
+            lengths[j] = if *ca == b[j - 1] {
+                lengths[j - 1] + 1
+            } else {
+                0
+            };
+            if lengths[j] > best_len {
+                best_len = lengths[j];
+                best_end = i + 1;
+            }
+        }
+    }
+    a[best_end - best_len..best_end].iter().collect()
+}
+
+/// The saved state of `fill`.
+#[derive(Deserialize, Serialize)]
+struct FillState {
+    /// Which attempt we were on, counting restarts.
+    #[serde(default)]
+    attempt: usize,
+    /// The slot we chose at each depth of the search, and the index of the
+    /// candidate we were trying in it.
+    path: Vec<(usize, usize)>,
+}
+
+/// How far candidates may move from their place in order of probability
+/// when we shuffle them for a restart.
+const RESTART_SHUFFLE_WINDOW: f64 = 4.0;
+
+/// The order in which one attempt at a fill tries slots and candidates.
+struct SearchOrder {
+    /// The candidate words for each slot, in the order to try them.
+    candidates: Vec<Vec<(Prob, Vec<char>)>>,
+    /// How to break ties between equally constrained slots: lower ranks
+    /// first.
+    slot_rank: Vec<usize>,
+}
+
+impl SearchOrder {
+    /// Try slots in grid order and candidates most probable first, unless
+    /// we have a `shuffle` seed. Then break ties between slots at random,
+    /// and shuffle candidates a few places, so that we explore a different
+    /// part of the search.
+    fn new(
+        slots: &[Slot],
+        candidates: &[Vec<(Prob, Vec<char>)>],
+        shuffle: Option<u64>,
+    ) -> SearchOrder {
+        let mut slot_rank = (0..slots.len()).collect::<Vec<_>>();
+        let mut candidates = candidates.to_vec();
+        if let Some(seed) = shuffle {
+            let mut rng = Rng::new(seed);
+            rng.shuffle(&mut slot_rank);
+            for slot_candidates in &mut candidates {
+                let mut keyed = slot_candidates
+                    .drain(..)
+                    .enumerate()
+                    .map(|(idx, candidate)| {
+                        let key = idx as f64 + RESTART_SHUFFLE_WINDOW * rng.next_f64();
+                        (key, candidate)
+                    })
+                    .collect::<Vec<_>>();
+                keyed.sort_by(|(a, _), (b, _)| a.total_cmp(b));
+                slot_candidates.extend(keyed.into_iter().map(|(_, c)| c));
+            }
+        }
+        SearchOrder {
+            candidates,
+            slot_rank,
+        }
+    }
+}
+
+/// For each slot, the slots which cross it, and the cells where they cross.
+fn crossings(grid: &Grid, slots: &[Slot]) -> Vec<Vec<(usize, usize)>> {
+    let mut slots_by_cell = vec![vec![]; grid.cells().len()];
+    for (i, slot) in slots.iter().enumerate() {
+        for &idx in &slot.cells {
+            slots_by_cell[idx].push(i);
+        }
+    }
+    slots
+        .iter()
+        .enumerate()
+        .map(|(i, slot)| {
+            slot.cells
+                .iter()
+                .flat_map(|&idx| {
+                    slots_by_cell[idx]
+                        .iter()
+                        .filter(move |&&j| j != i)
+                        .map(move |&j| (idx, j))
+                })
+                .collect()
+        })
+        .collect()
+}
+
+/// Counts of what a fill did.
+#[derive(Debug, Default)]
+struct FillStats {
+    /// How many candidates we've put in the grid.
+    tried: u64,
+    /// How many candidates we've taken out again because they left another
+    /// slot with nothing that fits.
+    pruned: u64,
+    /// How many times we've skipped over slots when backtracking, because
+    /// changing them couldn't fix a conflict.
+    backjumps: u64,
+    /// How many attempts we've given up on.
+    restarts: u64,
+}
+
+impl FillStats {
+    /// Add the counts from `other`.
+    fn add(&mut self, other: &FillStats) {
+        self.tried += other.tried;
+        self.pruned += other.pruned;
+        self.backjumps += other.backjumps;
+        self.restarts += other.restarts;
+    }
+}
+
+/// What every search in a fill shares.
+struct FillContext<'a> {
+    slots: &'a [Slot],
+    /// The slots crossing each slot, and the cells where they cross.
+    crossings: &'a [Vec<(usize, usize)>],
+    /// The candidate words for each slot, most probable first.
+    candidates: &'a [Vec<(Prob, Vec<char>)>],
+    /// The cells of the grid we're filling.
+    cells: &'a [Cell],
+    opts: &'a FillOptions,
+    portfolio: &'a Portfolio,
+}
+
+/// The best fill found by any of our searches, and when they should stop.
+struct Portfolio {
+    /// Set when every search should stop.
+    stop: AtomicBool,
+    /// Set by our caller to cancel the fill.
+    cancel: Option<Arc<AtomicBool>>,
+    /// When to stop looking for better fills, if we have a time budget.
+    deadline: Option<Instant>,
+    /// The bits of the score of `best`, or `NO_SCORE`, so that searches can
+    /// check it without locking.
+    best_score: AtomicU64,
+    /// The most probable fill found so far, and its score.
+    best: Mutex<Option<(Prob, Vec<Cell>)>>,
+}
+
+/// The value of `Portfolio::best_score` before we find a fill.
+const NO_SCORE: u64 = u64::MAX;
+
+impl Portfolio {
+    fn new(opts: &FillOptions) -> Portfolio {
+        Portfolio {
+            stop: AtomicBool::new(false),
+            cancel: opts.cancel.clone(),
+            deadline: opts.time_budget.map(|budget| Instant::now() + budget),
+            best_score: AtomicU64::new(NO_SCORE),
+            best: Mutex::new(None),
+        }
+    }
+
+    /// Are we looking for the best fill, rather than the first?
+    fn wants_best(&self) -> bool {
+        self.deadline.is_some()
+    }
+
+    /// Tell every search to stop.
+    fn stop(&self) {
+        self.stop.store(true, Ordering::Relaxed);
+    }
+
+    /// Should every search stop now?
+    fn should_stop(&self) -> bool {
+        self.stop.load(Ordering::Relaxed) || self.was_interrupted()
+    }
+
+    /// Were we cancelled or did we run out of time, so that we may not have
+    /// finished?
+    fn was_interrupted(&self) -> bool {
+        self.cancel
+            .as_ref()
+            .is_some_and(|cancel| cancel.load(Ordering::Relaxed))
+            || self
+                .deadline
+                .is_some_and(|deadline| Instant::now() >= deadline)
+    }
+
+    /// The score of the best fill so far, if any.
+    fn best_score(&self) -> Option<Prob> {
+        match self.best_score.load(Ordering::Relaxed) {
+            NO_SCORE => None,
+            bits => Some(Prob::from_bits(bits)),
+        }
+    }
+
+    /// Record a fill with `score`, keeping it if it's the best so far.
+    fn offer(&self, score: Prob, cells: Vec<Cell>) {
+        let mut best = self.best.lock().expect("lock should not be poisoned");
+        if best
+            .as_ref()
+            .is_none_or(|(best_score, _)| score > *best_score)
+        {
+            self.best_score.store(score.to_bits(), Ordering::Relaxed);
+            *best = Some((score, cells));
+        }
+    }
+
+    /// The best fill found, and its score.
+    fn into_best(self) -> Option<(Prob, Vec<Cell>)> {
+        self.best.into_inner().expect("lock should not be poisoned")
+    }
+}
+
+/// One of the searches in a fill, which runs attempts until one finds a fill
+/// or proves there isn't one. If we're looking for the best fill, it then
+/// starts again with a new order, looking for a better one.
+struct Worker {
+    /// Which search we are. Search 0 starts with the unshuffled order.
+    index: usize,
+    /// Where to resume, from a checkpoint.
+    resume: Option<FillState>,
+    /// Where to save our state, if anywhere.
+    checkpoint: Option<Checkpoint>,
+    /// The words we've tried, if we're recording them.
+    tree: Option<SearchTree>,
+    stats: FillStats,
+}
+
+impl Worker {
+    fn new(index: usize) -> Worker {
+        Worker {
+            index,
+            resume: None,
+            checkpoint: None,
+            tree: None,
+            stats: FillStats::default(),
+        }
+    }
+
+    /// Search until we're done or told to stop.
+    fn run(&mut self, ctx: &FillContext<'_>) -> Result<()> {
+        let opts = ctx.opts;
+        let (mut first_attempt, mut resume_path) = match self.resume.take() {
+            Some(state) => (state.attempt, state.path),
+            None => (0, vec![]),
+        };
+        let mut round = 0;
+        loop {
+            for attempt in first_attempt..=opts.restarts {
+                if ctx.portfolio.should_stop() {
+                    return Ok(());
+                }
+                // Every attempt but the last gives up after a number of
+                // backtracks, which doubles each time. The last has no
+                // limit, so that we still find a fill if there is one.
+                let backtrack_limit = if attempt < opts.restarts {
+                    let doublings = u32::try_from(attempt).unwrap_or(u32::MAX).min(32);
+                    Some(opts.backtrack_limit.saturating_mul(1 << doublings))
+                } else {
+                    None
+                };
+                let shuffle = if (self.index, round, attempt) == (0, 0, 0) {
+                    None
+                } else {
+                    let variant = ((self.index as u64) << 48)
+                        ^ ((round as u64) << 24)
+                        ^ attempt as u64;
+                    Some(opts.seed.wrapping_add(variant))
+                };
+                let order = SearchOrder::new(ctx.slots, ctx.candidates, shuffle);
+                let node = match (&mut self.tree, attempt + round) {
+                    (Some(tree), restart) if restart > 0 => tree.add(
+                        NodeId::default(),
+                        format!("round {}, attempt {}", round, attempt),
+                        None,
+                    ),
+                    _ => NodeId::default(),
+                };
+                let mut filler = Filler {
+                    slots: ctx.slots,
+                    crossings: ctx.crossings,
+                    candidates: &order.candidates,
+                    slot_rank: &order.slot_rank,
+                    portfolio: ctx.portfolio,
+                    cells: ctx.cells.to_vec(),
+                    score: Prob::always(),
+                    allow_duplicates: opts.allow_duplicates,
+                    no_substrings: opts.no_substrings,
+                    used: vec![],
+                    assigned: vec![false; ctx.slots.len()],
+                    path: vec![],
+                    resume: std::mem::take(&mut resume_path),
+                    attempt,
+                    checkpoint: self.checkpoint.take(),
+                    backtrack_limit,
+                    backtracks: 0,
+                    stats: &mut self.stats,
+                    tree: self.tree.take(),
+                    node,
+                };
+                let status = filler.search()?;
+                self.checkpoint = filler.checkpoint.take();
+                self.tree = filler.tree.take();
+                match status {
+                    Status::Found => {
+                        let (score, cells) = (filler.score, filler.cells);
+                        debug!("search {} found a fill scoring {}", self.index, score);
+                        ctx.portfolio.offer(score, cells);
+                        if !ctx.portfolio.wants_best() {
+                            ctx.portfolio.stop();
+                            return Ok(());
+                        }
+                        break;
+                    }
+                    // We searched everything without a limit, so there's no
+                    // fill, or no fill better than the best we've found.
+                    Status::Conflict(_) => {
+                        ctx.portfolio.stop();
+                        return Ok(());
+                    }
+                    Status::OutOfBacktracks => {
+                        debug!(
+                            "search {} giving up on attempt {} and restarting",
+                            self.index, attempt
+                        );
+                        self.stats.restarts += 1;
+                    }
+                    Status::Stopped => return Ok(()),
+                }
+            }
+            first_attempt = 0;
+            round += 1;
+        }
+    }
+}
+
+/// How a branch of the search ended.
+enum Status {
+    /// We filled the grid.
+    Found,
+    /// There's no fill below this branch. This holds the assigned slots
+    /// which caused the failure: until one of them changes, no fill exists.
+    Conflict(Vec<bool>),
+    /// We ran out of backtracks for this attempt.
+    OutOfBacktracks,
+    /// Another search finished, or we were cancelled or ran out of time.
+    Stopped,
+}
+
+/// Backtracking search state for `fill`, for one attempt.
+///
+/// We choose the most constrained slot first, and check after each word
+/// that every other slot still has a candidate. When a branch fails, we
+/// work out which earlier choices caused it, and backtrack straight past any
+/// choices which didn't, since trying other words for them would fail the
+/// same way.
+struct Filler<'a> {
+    slots: &'a [Slot],
+    /// The slots crossing each slot, and the cells where they cross.
+    crossings: &'a [Vec<(usize, usize)>],
+    /// The candidate words for each slot, in the order to try them.
+    candidates: &'a [Vec<(Prob, Vec<char>)>],
+    /// How to break ties between equally constrained slots.
+    slot_rank: &'a [usize],
+    /// Shared with the other searches in this fill.
+    portfolio: &'a Portfolio,
+    /// The current contents of the grid.
+    cells: Vec<Cell>,
+    /// The product of the probabilities of the words we've chosen.
+    score: Prob,
+    /// Copied from `FillOptions`.
+    allow_duplicates: bool,
+    /// Copied from `FillOptions`.
+    no_substrings: bool,
+    /// The slots we've put words in so far, and those words, which later
+    /// answers may not repeat.
+    used: Vec<(usize, &'a [char])>,
+    /// Which slots we've already chosen words for.
+    assigned: Vec<bool>,
+    /// The slot and candidate index we're trying at each depth.
+    path: Vec<(usize, usize)>,
+    /// The path to skip ahead to, when resuming from a checkpoint. We clear
+    /// each entry once we've moved past it.
+    resume: Vec<(usize, usize)>,
+    /// Which attempt this is, for checkpoints.
+    attempt: usize,
+    /// Where to save our state, if anywhere.
+    checkpoint: Option<Checkpoint>,
+    /// Give up once `backtracks` reaches this, if set.
+    backtrack_limit: Option<u64>,
+    /// How many times we've backtracked in this attempt.
+    backtracks: u64,
+    /// Counts of what we've done, shared with other attempts.
+    stats: &'a mut FillStats,
+    /// The words we've tried, if we're recording them.
+    tree: Option<SearchTree>,
+    /// The node in `tree` for the last word we chose.
+    node: NodeId,
+}
+
+impl<'a> Filler<'a> {
+    /// Can we put `word` in `slot` without conflicting with existing letters
+    /// or repeating an answer?
+    fn fits(&self, slot: &Slot, word: &[char]) -> bool {
+        self.letters_fit(slot, word) && self.conflicting_answer(word).is_none()
+    }
+
+    /// Does `word` match the letters already in `slot`?
+    fn letters_fit(&self, slot: &Slot, word: &[char]) -> bool {
+        slot.cells
+            .iter()
+            .zip(word)
+            .all(|(&idx, &c)| match self.cells[idx] {
+                Cell::Letter(existing) => existing == c,
+                _ => true,
+            })
+    }
+
+    /// The slot of an answer we've already used which rules out `word`, if
+    /// there is one.
+    fn conflicting_answer(&self, word: &[char]) -> Option<usize> {
+        self.used
+            .iter()
+            .find(|&&(_, used)| {
+                if self.no_substrings {
+                    contains(used, word) || contains(word, used)
+                } else {
+                    !self.allow_duplicates && used == word
+                }
+            })
+            .map(|&(slot, _)| slot)
+    }
+
+    /// How many candidates still fit in slot `i`?
+    fn count_fits(&self, i: usize) -> usize {
+        self.candidates[i]
+            .iter()
+            .filter(|(_, word)| self.fits(&self.slots[i], word))
+            .count()
+    }
+
+    /// The assigned slots which rule out any of the candidates for slot `i`:
+    /// those which cross it where it has letters, and those whose answers
+    /// conflict with its candidates.
+    fn conflicts(&self, i: usize) -> Vec<bool> {
+        let mut conflicts = vec![false; self.slots.len()];
+        for &(idx, j) in &self.crossings[i] {
+            if self.assigned[j] && matches!(self.cells[idx], Cell::Letter(_)) {
+                conflicts[j] = true;
+            }
+        }
+        if !self.used.is_empty() {
+            for (_, word) in &self.candidates[i] {
+                if self.letters_fit(&self.slots[i], word) {
+                    if let Some(j) = self.conflicting_answer(word) {
+                        conflicts[j] = true;
+                    }
+                }
+            }
+        }
+        conflicts
+    }
+
+    /// Assign words to all remaining slots. On success, leaves the fill in
+    /// `self.cells`.
+    fn search(&mut self) -> Result<Status> {
+        if let Some(checkpoint) = &mut self.checkpoint {
+            let (attempt, path) = (self.attempt, &self.path);
+            checkpoint.save_if_due(|| FillState {
+                attempt,
+                path: path.clone(),
+            })?;
+        }
+
+        // Choose the most constrained slot, breaking ties by rank.
+        let next = (0..self.slots.len())
+            .filter(|&i| !self.assigned[i])
+            .map(|i| (self.count_fits(i), self.slot_rank[i], i))
+            .min();
+        let (fit_count, _, i) = match next {
+            Some(next) => next,
+            None => return Ok(Status::Found),
+        };
+        if fit_count == 0 {
+            return Ok(Status::Conflict(self.conflicts(i)));
+        }
+
+        // When resuming, skip the candidates we'd already tried.
+        let depth = self.path.len();
+        let mut start = 0;
+        if let Some(&(resume_slot, resume_idx)) = self.resume.get(depth) {
+            if resume_slot != i {
+                return Err(Error::InvalidPuzzle(
+                    "checkpoint was saved by a different search".to_owned(),
+                ));
+            }
+            start = resume_idx;
+        }
+
+        // The slots responsible if we fail here, starting with those which
+        // ruled out candidates before we began. We don't know why any
+        // candidates we skipped when resuming failed, so we blame everything.
+        let mut conflict = self.conflicts(i);
+        if start > 0 {
+            conflict.copy_from_slice(&self.assigned);
+        }
+
+        self.assigned[i] = true;
+        let slot = &self.slots[i];
+        let candidates: &'a [(Prob, Vec<char>)] = &self.candidates[i];
+        for (idx, (prob, word)) in candidates.iter().enumerate().skip(start) {
+            // Only the first candidate we try can lead to the resumed branch.
+            if idx > start {
+                self.resume.truncate(depth);
+            }
+            if !self.fits(slot, word) {
+                continue;
+            }
+            if self.portfolio.should_stop() {
+                return Ok(Status::Stopped);
+            }
+            if let Some(limit) = self.backtrack_limit {
+                if self.backtracks >= limit {
+                    return Ok(Status::OutOfBacktracks);
+                }
+            }
+            // When looking for the best fill, skip words which would make
+            // this one no better than the best we have. That depends on
+            // every word we've chosen.
+            let score = self.score * *prob;
+            let beaten = self.portfolio.wants_best()
+                && self
+                    .portfolio
+                    .best_score()
+                    .is_some_and(|best| score <= best);
+            if beaten {
+                for (blame, &assigned) in conflict.iter_mut().zip(&self.assigned) {
+                    *blame |= assigned;
+                }
+                continue;
+            }
+            let text = word.iter().collect::<String>();
+            trace!(
+                event = "try", depth = depth, slot = i, word = text.as_str(),
+                nats = prob.to_f64();
+                "trying {} ({})", text, prob
+            );
+            self.stats.tried += 1;
+            let parent = self.node;
+            let node = match &mut self.tree {
+                Some(tree) => {
+                    tree.add(parent, format!("slot {}: {}", i, text), Some(*prob))
+                }
+                None => parent,
+            };
+            let previous = slot
+                .cells
+                .iter()
+                .map(|&idx| self.cells[idx])
+                .collect::<Vec<_>>();
+            for (&idx, &c) in slot.cells.iter().zip(word) {
+                self.cells[idx] = Cell::Letter(c);
+            }
+            self.used.push((i, word));
+            let previous_score = self.score;
+            self.score = score;
+
+            // Check that every other slot still has at least one candidate
+            // before recursing.
+            let blocked = (0..self.slots.len())
+                .filter(|&j| !self.assigned[j])
+                .find(|&j| self.count_fits(j) == 0);
+            self.path.push((i, idx));
+            let status = match blocked {
+                Some(j) => {
+                    trace!(
+                        event = "prune", depth = depth, slot = i,
+                        word = text.as_str(), blocked_slot = j;
+                        "pruned {}, which leaves nothing for slot {}", text, j
+                    );
+                    self.stats.pruned += 1;
+                    if let Some(tree) = &mut self.tree {
+                        tree.set_outcome(
+                            node,
+                            Outcome::Pruned(format!("slot {} is empty", j)),
+                        );
+                    }
+                    Status::Conflict(self.conflicts(j))
+                }
+                None => {
+                    self.node = node;
+                    let status = self.search()?;
+                    self.node = parent;
+                    status
+                }
+            };
+            self.path.pop();
+            let child = match status {
+                Status::Found => {
+                    if let Some(tree) = &mut self.tree {
+                        tree.set_outcome(node, Outcome::Solution);
+                    }
+                    return Ok(Status::Found);
+                }
+                Status::OutOfBacktracks => return Ok(Status::OutOfBacktracks),
+                Status::Stopped => return Ok(Status::Stopped),
+                Status::Conflict(child) => child,
+            };
+
+            self.score = previous_score;
+            self.used.pop();
+            for (&idx, cell) in slot.cells.iter().zip(previous) {
+                self.cells[idx] = cell;
+            }
+            self.backtracks += 1;
+            if !child[i] {
+                // Nothing we put here could fix the conflict, so pass it back
+                // to an earlier slot which can.
+                trace!(
+                    event = "backjump", depth = depth, slot = i;
+                    "backjumping past slot {}", i
+                );
+                self.stats.backjumps += 1;
+                self.assigned[i] = false;
+                self.resume.truncate(depth);
+                return Ok(Status::Conflict(child));
+            }
+            for (blame, child_blame) in conflict.iter_mut().zip(child) {
+                *blame |= child_blame;
+            }
+        }
+        conflict[i] = false;
+        self.assigned[i] = false;
+        self.resume.truncate(depth);
+        Ok(Status::Conflict(conflict))
+    }
+}
+
+/// Does `haystack` contain `needle` as a run of letters? Every word contains
+/// itself.
+fn contains(haystack: &[char], needle: &[char]) -> bool {
+    needle.len() <= haystack.len()
+        && haystack
+            .windows(needle.len())
+            .any(|window| window == needle)
+}
@@ -0,0 +1,100 @@
+//! Filters which restrict which dictionary words we consider.
+
+use serde::Deserialize;
+use std::{ops::RangeInclusive, str::FromStr};
+
+use crate::dictionary::Dictionary;
+use crate::error::Error;
+use crate::predicate::Predicates;
+use crate::probability::Prob;
+
+/// Restrictions on which words a `Dictionary` will return. The default filter
+/// accepts every word.
+#[derive(Clone, Debug, Default)]
+pub struct WordFilter {
+    /// Only accept words with this many syllables.
+    pub syllables: Option<usize>,
+    /// Only accept words with this frequency rank or better.
+    pub max_rank: Option<u64>,
+    /// Only accept words in this frequency band.
+    pub band: Option<Band>,
+    /// Only accept words which pass all these tests.
+    pub predicates: Predicates,
+    /// Reject words which the dictionary marks as proper nouns.
+    pub exclude_proper_nouns: bool,
+}
+
+impl WordFilter {
+    /// Does this filter accept every word?
+    pub fn accepts_everything(&self) -> bool {
+        self.syllables.is_none()
+            && self.max_rank.is_none()
+            && self.band.is_none()
+            && self.predicates.is_empty()
+            && !self.exclude_proper_nouns
+    }
+
+    /// Should `dict` return `word`?
+    pub fn accepts(&self, dict: &Dictionary, word: &str, prob: Prob) -> bool {
+        if !self.predicates.accepts(word) {
+            return false;
+        }
+        if self.exclude_proper_nouns && dict.is_proper_noun(word) {
+            return false;
+        }
+        if self.max_rank.is_some() || self.band.is_some() {
+            let rank = dict.rank_given_prob(word, prob).rank;
+            if self.max_rank.is_some_and(|max_rank| rank > max_rank) {
+                return false;
+            }
+            if self.band.is_some_and(|band| !band.ranks().contains(&rank)) {
+                return false;
+            }
+        }
+        if let Some(syllables) = self.syllables {
+            if dict.syllables_of(word) != syllables {
+                return false;
+            }
+        }
+        true
+    }
+}
+
+/// A range of word frequencies.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Band {
+    /// The 20,000 most frequent words, suitable for everyday puzzles.
+    Common,
+    /// The next 80,000 words.
+    Medium,
+    /// Everything else.
+    Rare,
+}
+
+impl Band {
+    /// The frequency ranks in this band.
+    pub fn ranks(self) -> RangeInclusive<u64> {
+        match self {
+            Band::Common => 1..=20_000,
+            Band::Medium => 20_001..=100_000,
+            Band::Rare => 100_001..=u64::MAX,
+        }
+    }
+}
+
+impl FromStr for Band {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "common" => Ok(Band::Common),
+            "medium" => Ok(Band::Medium),
+            "rare" => Ok(Band::Rare),
+            _ => Err(Error::InvalidOption(format!(
+                "unknown band {:?}, expected common, medium or rare",
+                s
+            ))),
+        }
+    }
+}
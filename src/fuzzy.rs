@@ -0,0 +1,200 @@
+//! Finding words which are spelled almost like a given word.
+
+use once_cell::sync::Lazy;
+use std::{cmp::Ordering, collections::HashMap, fmt};
+
+use crate::probability::{Dist, Prob};
+
+/// A dictionary word near the word we searched for.
+#[derive(Clone, Debug, PartialEq)]
+pub struct FuzzyMatch {
+    /// The probability of the word.
+    pub prob: Prob,
+    /// The number of single-letter insertions, deletions and substitutions
+    /// needed to turn the search word into this word.
+    pub distance: usize,
+    /// The word itself.
+    pub word: String,
+}
+
+impl FuzzyMatch {
+    /// Order matches from most to least probable, breaking ties by distance.
+    pub fn cmp_by_probability(&self, other: &FuzzyMatch) -> Ordering {
+        other
+            .prob
+            .cmp(&self.prob)
+            .then_with(|| self.distance.cmp(&other.distance))
+            .then_with(|| self.word.cmp(&other.word))
+    }
+}
+
+impl fmt::Display for FuzzyMatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:6.2} {} (distance {})",
+            self.prob, self.word, self.distance
+        )
+    }
+}
+
+/// The Levenshtein distance between `a` and `b`, counting characters.
+pub fn edit_distance(a: &str, b: &str) -> usize {
+    let b = b.chars().collect::<Vec<_>>();
+    let mut prev_row = (0..=b.len()).collect::<Vec<_>>();
+    let mut row = vec![0; b.len() + 1];
+    for (i, ca) in a.chars().enumerate() {
+        row[0] = i + 1;
+        for (j, &cb) in b.iter().enumerate() {
+            let substitution = prev_row[j] + usize::from(ca != cb);
+            row[j + 1] = substitution.min(prev_row[j + 1] + 1).min(row[j] + 1);
+        }
+        std::mem::swap(&mut prev_row, &mut row);
+    }
+    prev_row[b.len()]
+}
+
+/// How likely someone is to make each kind of typing mistake. Probabilities
+/// are for a single character, given what the typist intended to type.
+pub trait ErrorModel {
+    /// The probability of typing `typed` when `intended` was meant. This is
+    /// only called when the two characters differ.
+    fn substitution(&self, typed: char, intended: char) -> Prob;
+    /// The probability of typing a stray `typed`.
+    fn insertion(&self, typed: char) -> Prob;
+    /// The probability of leaving out `intended`.
+    fn deletion(&self, intended: char) -> Prob;
+    /// The probability of typing a character correctly.
+    fn correct(&self) -> Prob;
+}
+
+/// A typing error model for QWERTY keyboards, where hitting a neighboring key
+/// is much more likely than any other substitution.
+#[derive(Clone, Debug)]
+pub struct KeyboardModel {
+    /// Hitting a key next to the intended one.
+    pub adjacent: Prob,
+    /// Hitting any other wrong key.
+    pub distant: Prob,
+    /// Typing an extra character.
+    pub insertion: Prob,
+    /// Leaving out a character.
+    pub deletion: Prob,
+}
+
+impl Default for KeyboardModel {
+    fn default() -> Self {
+        KeyboardModel {
+            adjacent: Prob::from_probability(0.02),
+            distant: Prob::from_probability(0.001),
+            insertion: Prob::from_probability(0.005),
+            deletion: Prob::from_probability(0.005),
+        }
+    }
+}
+
+/// The position of each letter on a QWERTY keyboard, as (row, column). Each
+/// row is shifted right by half a key relative to the row above.
+static KEY_POSITIONS: Lazy<HashMap<char, (f64, f64)>> = Lazy::new(|| {
+    let rows = ["qwertyuiop", "asdfghjkl", "zxcvbnm"];
+    let mut positions = HashMap::new();
+    for (row, keys) in rows.iter().enumerate() {
+        for (col, key) in keys.chars().enumerate() {
+            positions.insert(key, (row as f64, col as f64 + row as f64 * 0.5));
+        }
+    }
+    positions
+});
+
+/// Are `a` and `b` next to each other on a QWERTY keyboard?
+fn keys_adjacent(a: char, b: char) -> bool {
+    match (KEY_POSITIONS.get(&a), KEY_POSITIONS.get(&b)) {
+        (Some(&(r1, c1)), Some(&(r2, c2))) => {
+            (r1 - r2).abs() <= 1.0 && (c1 - c2).abs() <= 1.0
+        }
+        _ => false,
+    }
+}
+
+impl ErrorModel for KeyboardModel {
+    fn substitution(&self, typed: char, intended: char) -> Prob {
+        if keys_adjacent(typed, intended) {
+            self.adjacent
+        } else {
+            self.distant
+        }
+    }
+
+    fn insertion(&self, _typed: char) -> Prob {
+        self.insertion
+    }
+
+    fn deletion(&self, _intended: char) -> Prob {
+        self.deletion
+    }
+
+    fn correct(&self) -> Prob {
+        Prob::from_probability(
+            1.0 - self.adjacent.to_probability()
+                - self.insertion.to_probability()
+                - self.deletion.to_probability(),
+        )
+    }
+}
+
+/// The more probable of `a` and `b`.
+fn more_probable(a: Prob, b: Prob) -> Prob {
+    if a >= b {
+        a
+    } else {
+        b
+    }
+}
+
+/// The probability of typing `typed` when trying to type `intended`, using
+/// the most likely sequence of mistakes.
+pub fn typo_probability(model: &impl ErrorModel, typed: &str, intended: &str) -> Prob {
+    let intended = intended.chars().collect::<Vec<_>>();
+    let mut prev_row = vec![Prob::always()];
+    for &c in &intended {
+        let last = prev_row[prev_row.len() - 1];
+        prev_row.push(last * model.deletion(c));
+    }
+    let mut row = prev_row.clone();
+    for t in typed.chars() {
+        row[0] = prev_row[0] * model.insertion(t);
+        for (j, &c) in intended.iter().enumerate() {
+            let step = if t == c {
+                model.correct()
+            } else {
+                model.substitution(t, c)
+            };
+            row[j + 1] = more_probable(
+                prev_row[j] * step,
+                more_probable(
+                    prev_row[j + 1] * model.insertion(t),
+                    row[j] * model.deletion(c),
+                ),
+            );
+        }
+        std::mem::swap(&mut prev_row, &mut row);
+    }
+    prev_row[intended.len()]
+}
+
+/// Rank `matches` for `typed` by how likely each word is to have been typed
+/// as `typed`, combining the word's probability with `model`.
+pub fn rank_typos(
+    model: &impl ErrorModel,
+    typed: &str,
+    matches: Vec<FuzzyMatch>,
+) -> Dist<String> {
+    let mut dist = Dist::from_vec(
+        matches
+            .into_iter()
+            .map(|m| (m.prob * typo_probability(model, typed, &m.word), m.word))
+            .collect(),
+    );
+    dist.sort_by_probability();
+    dist
+}
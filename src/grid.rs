@@ -0,0 +1,348 @@
+//! Crossword grids, and reading and writing them.
+//!
+//! Our text format has one line per row. `#` is a black square, `.` is an
+//! open white square, and a letter is a white square which is filled in:
+//!
+//! ```text
+//! cat#
+//! a..#
+//! ##..
+//! ```
+//!
+//! Every row must have the same number of cells, and blank lines are
+//! ignored. Letters are stored in lowercase, so writing a grid and reading it
+//! back gives the same grid. Grids can also be read from Across Lite `.puz`
+//! and ipuz files with `Grid::read`, and whole puzzles, with their clues, can
+//! be read and written using `crate::puzzle`.
+
+use std::{fmt, fs, path::Path, str::FromStr};
+
+use crate::error::{Error, Result};
+use crate::puzzle::{Puzzle, PuzzleGrid};
+
+/// A cell in a crossword grid.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Cell {
+    /// A black square.
+    Block,
+    /// An empty white square.
+    Open,
+    /// A white square containing a letter.
+    Letter(char),
+}
+
+impl Cell {
+    /// A white square containing `letter`, which we store in lowercase, as
+    /// the dictionary does.
+    pub fn letter(letter: char) -> Cell {
+        Cell::Letter(
+            letter
+                .to_lowercase()
+                .next()
+                .expect("letter should have a lowercase"),
+        )
+    }
+}
+
+/// A rectangular crossword grid.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Grid {
+    width: usize,
+    height: usize,
+    cells: Vec<Cell>,
+}
+
+impl Grid {
+    /// Create a grid `width` cells wide from `cells`, listed row by row.
+    pub fn new(width: usize, cells: Vec<Cell>) -> Result<Grid> {
+        if width == 0 || cells.is_empty() {
+            return Err(Error::InvalidPuzzle("grid is empty".to_owned()));
+        }
+        if !cells.len().is_multiple_of(width) {
+            return Err(Error::InvalidPuzzle(format!(
+                "{} cells don't make rows of {}",
+                cells.len(),
+                width
+            )));
+        }
+        Ok(Grid {
+            width,
+            height: cells.len() / width,
+            cells,
+        })
+    }
+
+    /// Read `which` grid of the puzzle at `path`, in any format supported by
+    /// `Puzzle::read`. Text files have only one grid, which we use for both.
+    pub fn read(path: &Path, which: PuzzleGrid) -> Result<Grid> {
+        Ok(Puzzle::read(path)?.into_grid(which))
+    }
+
+    /// Write the grid to `path` in our text format.
+    pub fn write(&self, path: &Path) -> Result<()> {
+        fs::write(path, self.to_string()).map_err(Error::io(path))
+    }
+
+    /// The number of cells in each row.
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    /// The number of rows.
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    /// Every cell, row by row.
+    pub fn cells(&self) -> &[Cell] {
+        &self.cells
+    }
+
+    /// This grid, with every letter removed.
+    pub fn blank(&self) -> Grid {
+        let cells = self
+            .cells
+            .iter()
+            .map(|&cell| match cell {
+                Cell::Letter(_) => Cell::Open,
+                cell => cell,
+            })
+            .collect();
+        self.with_cells(cells)
+    }
+
+    /// Do this grid and `other` have the same size and black squares, so
+    /// that they have the same slots?
+    pub fn same_shape(&self, other: &Grid) -> bool {
+        self.width == other.width
+            && self.cells.len() == other.cells.len()
+            && self
+                .cells
+                .iter()
+                .zip(&other.cells)
+                .all(|(&a, &b)| (a == Cell::Block) == (b == Cell::Block))
+    }
+
+    /// This grid, with `cells` in place of our cells.
+    pub(crate) fn with_cells(&self, cells: Vec<Cell>) -> Grid {
+        assert_eq!(cells.len(), self.cells.len(), "grid size should not change");
+        Grid {
+            cells,
+            ..self.clone()
+        }
+    }
+
+    /// The clue number of each cell, using the usual numbering: every cell
+    /// which starts a slot gets the next number, row by row.
+    pub fn numbers(&self) -> Vec<Option<usize>> {
+        let is_white = |row: usize, col: usize| {
+            row < self.height
+                && col < self.width
+                && self.cells[row * self.width + col] != Cell::Block
+        };
+        let mut next = 1;
+        let mut numbers = vec![None; self.cells.len()];
+        for row in 0..self.height {
+            for col in 0..self.width {
+                let starts_across =
+                    (col == 0 || !is_white(row, col - 1)) && is_white(row, col + 1);
+                let starts_down =
+                    (row == 0 || !is_white(row - 1, col)) && is_white(row + 1, col);
+                if is_white(row, col) && (starts_across || starts_down) {
+                    numbers[row * self.width + col] = Some(next);
+                    next += 1;
+                }
+            }
+        }
+        numbers
+    }
+
+    /// Find every across and down slot at least two cells long, across
+    /// slots first.
+    pub fn slots(&self) -> Vec<Slot> {
+        let numbers = self.numbers();
+        let mut slots = vec![];
+        for direction in [Direction::Across, Direction::Down] {
+            let (lines, line_len) = match direction {
+                Direction::Across => (self.height, self.width),
+                Direction::Down => (self.width, self.height),
+            };
+            for line in 0..lines {
+                let mut run = vec![];
+                for pos in 0..=line_len {
+                    let idx = match direction {
+                        Direction::Across => line * self.width + pos,
+                        Direction::Down => pos * self.width + line,
+                    };
+                    if pos < line_len && self.cells[idx] != Cell::Block {
+                        run.push(idx);
+                    } else {
+                        if run.len() >= 2 {
+                            slots.push(Slot {
+                                number: numbers[run[0]]
+                                    .expect("slot should start on a numbered cell"),
+                                direction,
+                                cells: run.clone(),
+                            });
+                        }
+                        run.clear();
+                    }
+                }
+            }
+        }
+        slots
+    }
+
+    /// Check that the grid can be filled: it needs at least one slot, and
+    /// every open cell must be in a slot, or nothing would ever fill it.
+    pub fn validate(&self) -> Result<()> {
+        let slots = self.slots();
+        if slots.is_empty() {
+            return Err(Error::InvalidPuzzle(
+                "grid has no slots of two or more cells".to_owned(),
+            ));
+        }
+        let mut in_slot = vec![false; self.cells.len()];
+        for slot in &slots {
+            for &idx in &slot.cells {
+                in_slot[idx] = true;
+            }
+        }
+        let stranded = self
+            .cells
+            .iter()
+            .zip(&in_slot)
+            .position(|(&cell, &in_slot)| cell == Cell::Open && !in_slot);
+        match stranded {
+            Some(idx) => Err(Error::InvalidPuzzle(format!(
+                "open cell at row {}, column {} isn't part of any slot",
+                idx / self.width + 1,
+                idx % self.width + 1
+            ))),
+            None => Ok(()),
+        }
+    }
+
+    /// The word in `slot`, or `None` if any of its cells are open.
+    pub fn slot_word(&self, slot: &Slot) -> Option<String> {
+        slot.cells
+            .iter()
+            .map(|&idx| match self.cells[idx] {
+                Cell::Letter(c) => Some(c),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// The letters currently in `slot`, as a regex where open cells are `.`.
+    pub(crate) fn slot_pattern(&self, slot: &Slot) -> String {
+        slot.cells
+            .iter()
+            .map(|&idx| match self.cells[idx] {
+                Cell::Letter(c) => c,
+                _ => '.',
+            })
+            .collect()
+    }
+}
+
+/// Parse our text format.
+impl FromStr for Grid {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let mut width = None;
+        let mut cells = vec![];
+        let mut height = 0;
+        for line in s.lines().map(str::trim_end).filter(|l| !l.is_empty()) {
+            let row = line
+                .chars()
+                .map(|c| match c {
+                    '#' => Ok(Cell::Block),
+                    '.' => Ok(Cell::Open),
+                    c if c.is_alphabetic() => Ok(Cell::letter(c)),
+                    c => Err(Error::InvalidPuzzle(format!(
+                        "unexpected {:?} in grid",
+                        c
+                    ))),
+                })
+                .collect::<Result<Vec<_>>>()?;
+            match width {
+                None => width = Some(row.len()),
+                Some(w) if w != row.len() => {
+                    return Err(Error::InvalidPuzzle(format!(
+                        "grid row {} has {} cells, but expected {}",
+                        height + 1,
+                        row.len(),
+                        w,
+                    )))
+                }
+                Some(_) => {}
+            }
+            cells.extend(row);
+            height += 1;
+        }
+        let width =
+            width.ok_or_else(|| Error::InvalidPuzzle("grid is empty".to_owned()))?;
+        Ok(Grid {
+            width,
+            height,
+            cells,
+        })
+    }
+}
+
+/// Write our text format.
+impl fmt::Display for Grid {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for row in self.cells.chunks(self.width) {
+            for cell in row {
+                match cell {
+                    Cell::Block => write!(f, "#")?,
+                    Cell::Open => write!(f, ".")?,
+                    Cell::Letter(c) => write!(f, "{}", c)?,
+                }
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
+
+/// The direction of a slot.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Direction {
+    Across,
+    Down,
+}
+
+impl fmt::Display for Direction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Direction::Across => write!(f, "Across"),
+            Direction::Down => write!(f, "Down"),
+        }
+    }
+}
+
+/// A run of white cells which must contain a word.
+#[derive(Clone, Debug)]
+pub struct Slot {
+    /// The clue number of the first cell.
+    pub number: usize,
+    /// Which way the slot runs.
+    pub direction: Direction,
+    /// The indices of the cells in this slot.
+    pub cells: Vec<usize>,
+}
+
+impl Slot {
+    /// A short name for this slot, like "12A" or "3D".
+    pub fn label(&self) -> String {
+        let letter = match self.direction {
+            Direction::Across => 'A',
+            Direction::Down => 'D',
+        };
+        format!("{}{}", self.number, letter)
+    }
+}
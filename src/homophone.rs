@@ -0,0 +1,62 @@
+//! Finding homophones: words which are pronounced identically but spelled
+//! differently, like "their" and "there".
+
+use std::collections::BTreeSet;
+
+use crate::dictionary::Dictionary;
+use crate::error::{Error, Result};
+use crate::probability::{Dist, Prob};
+
+/// Find words pronounced exactly like some pronunciation of `word`, which
+/// should be lowercase.
+pub fn find_homophones(dict: &Dictionary, word: &str) -> Result<Dist<String>> {
+    let pronunciations = dict.pronunciations_of(word)?;
+    if pronunciations.is_empty() {
+        return Err(Error::NoPronunciation(word.to_owned()));
+    }
+    let mut words = BTreeSet::new();
+    for phones in pronunciations {
+        words.extend(dict.words_pronounced(&phones)?);
+    }
+    words.remove(word);
+    let mut homophones = Dist::from_vec(
+        words
+            .into_iter()
+            .filter_map(|w| dict.probability_of(&w).map(|p| (p, w)))
+            .collect(),
+    );
+    homophones.sort_by_probability();
+    Ok(homophones)
+}
+
+/// Find every pair of homophones in `dict`. Each event is a pair of words
+/// separated by a space, with the combined probability of both words. A pair
+/// with more than one shared pronunciation only appears once.
+pub fn find_homophone_pairs(dict: &Dictionary) -> Result<Dist<String>> {
+    let mut pairs = BTreeSet::new();
+    for (_, words) in dict.homophone_groups()? {
+        let words = words
+            .into_iter()
+            .filter_map(|w| dict.probability_of(&w).map(|p| (p, w)))
+            .collect::<Vec<(Prob, String)>>();
+        for (i, (prob1, word1)) in words.iter().enumerate() {
+            for (prob2, word2) in &words[i + 1..] {
+                // Put the more probable word first.
+                let (first, second) = if prob2 > prob1 {
+                    (word2, word1)
+                } else {
+                    (word1, word2)
+                };
+                pairs.insert((first.clone(), second.clone(), *prob1 * *prob2));
+            }
+        }
+    }
+    let mut pairs = Dist::from_vec(
+        pairs
+            .into_iter()
+            .map(|(first, second, prob)| (prob, format!("{} {}", first, second)))
+            .collect(),
+    );
+    pairs.sort_by_probability();
+    Ok(pairs)
+}
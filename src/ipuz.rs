@@ -0,0 +1,268 @@
+//! Reading and writing ipuz crossword files.
+//!
+//! ipuz is a JSON format described at <http://ipuz.org/>. A crossword gives
+//! its `dimensions`, a `puzzle` grid of clue numbers and black squares, and
+//! optionally a `solution`, the player's progress as `saved`, and `clues`
+//! for each direction. We support the common subset of the format used for
+//! American-style crosswords: we don't support rebus cells holding more than
+//! one letter, and ignore styles and other extensions.
+
+use serde_json::{json, Map, Value};
+use std::convert::TryFrom;
+
+use crate::error::{Error, Result};
+use crate::grid::{Cell, Direction, Grid};
+use crate::puzzle::{Clue, Puzzle};
+
+/// The kind we write, and the prefix of the kinds we read.
+const CROSSWORD_KIND: &str = "http://ipuz.org/crossword";
+
+/// Read a puzzle from `json`, the contents of an ipuz file.
+pub fn read_ipuz(json: &str) -> Result<Puzzle> {
+    let root = serde_json::from_str::<Value>(json)
+        .map_err(|err| invalid(format!("invalid JSON: {}", err)))?;
+    let is_crossword = root["kind"].as_array().is_some_and(|kinds| {
+        kinds
+            .iter()
+            .any(|kind| kind.as_str().is_some_and(|k| k.starts_with(CROSSWORD_KIND)))
+    });
+    if !is_crossword {
+        return Err(invalid("not a crossword".to_owned()));
+    }
+    let width = dimension(&root, "width")?;
+    let height = dimension(&root, "height")?;
+    let block = root["block"].as_str().unwrap_or("#");
+    let empty = root.get("empty").cloned().unwrap_or_else(|| json!(0));
+
+    // The puzzle grid tells us where the black squares are, and may give
+    // some letters to the player.
+    let mut blocks = vec![];
+    let mut given = vec![];
+    for value in rows(&root, "puzzle", width, height)? {
+        let (label, letter) = match value {
+            Value::Object(cell) => (&cell["cell"], cell.get("value")),
+            label => (label, None),
+        };
+        blocks.push(label.is_null() || label.as_str() == Some(block));
+        given.push(match letter {
+            Some(letter) => parse_letter(letter, block, &empty)?,
+            None => Cell::Open,
+        });
+    }
+    let layout = |cells: Vec<Cell>| {
+        let cells = cells
+            .into_iter()
+            .zip(&blocks)
+            .map(|(cell, &is_block)| if is_block { Cell::Block } else { cell })
+            .collect();
+        Grid::new(width, cells)
+    };
+
+    let solution = match root.get("solution") {
+        Some(_) => rows(&root, "solution", width, height)?
+            .map(|value| parse_letter(value, block, &empty))
+            .collect::<Result<Vec<_>>>()?,
+        None => given.clone(),
+    };
+    let progress = match root.get("saved") {
+        Some(_) => rows(&root, "saved", width, height)?
+            .map(|value| parse_letter(value, block, &empty))
+            .collect::<Result<Vec<_>>>()?,
+        None => given,
+    };
+    let mut puzzle = Puzzle::new(layout(solution)?);
+    puzzle.progress = layout(progress)?;
+    puzzle.title = string_field(&root, "title");
+    puzzle.author = string_field(&root, "author");
+    puzzle.copyright = string_field(&root, "copyright");
+    puzzle.notes = string_field(&root, "notes");
+
+    if let Some(clues) = root["clues"].as_object() {
+        for (key, list) in clues {
+            // Directions may have a label after a colon, like "Across:Clues".
+            let direction = match key.split(':').next() {
+                Some("Across") => Direction::Across,
+                Some("Down") => Direction::Down,
+                _ => {
+                    return Err(invalid(format!(
+                        "unsupported clue direction {:?}",
+                        key
+                    )))
+                }
+            };
+            let list = list
+                .as_array()
+                .ok_or_else(|| invalid(format!("{:?} clues aren't a list", key)))?;
+            for clue in list {
+                puzzle.clues.push(parse_clue(clue, direction)?);
+            }
+        }
+        puzzle
+            .clues
+            .sort_by_key(|clue| (clue.number, clue.direction));
+    }
+    Ok(puzzle)
+}
+
+/// Write `puzzle` as an ipuz file. Slots without clues get empty clues.
+pub fn write_ipuz(puzzle: &Puzzle) -> Result<String> {
+    let width = puzzle.solution.width();
+    let to_rows = |cells: Vec<Value>| {
+        cells
+            .chunks(width)
+            .map(|row| Value::Array(row.to_vec()))
+            .collect::<Vec<_>>()
+    };
+    let numbers = puzzle
+        .solution
+        .numbers()
+        .into_iter()
+        .zip(puzzle.solution.cells())
+        .map(|(number, &cell)| match (cell, number) {
+            (Cell::Block, _) => json!("#"),
+            (_, Some(number)) => json!(number),
+            (_, None) => json!(0),
+        })
+        .collect();
+    let letters = |grid: &Grid| {
+        grid.cells()
+            .iter()
+            .map(|&cell| match cell {
+                Cell::Block => json!("#"),
+                Cell::Open => Value::Null,
+                Cell::Letter(c) => json!(c.to_uppercase().to_string()),
+            })
+            .collect()
+    };
+
+    let mut clues = Map::new();
+    for slot in puzzle.slots_in_clue_order() {
+        let clue = json!([slot.number, puzzle.clue(&slot).unwrap_or_default()]);
+        clues
+            .entry(slot.direction.to_string())
+            .or_insert_with(|| json!([]))
+            .as_array_mut()
+            .expect("clues should be a list")
+            .push(clue);
+    }
+
+    let mut root = json!({
+        "version": "http://ipuz.org/v2",
+        "kind": [format!("{}#1", CROSSWORD_KIND)],
+        "dimensions": { "width": width, "height": puzzle.solution.height() },
+        "block": "#",
+        "empty": 0,
+        "puzzle": to_rows(numbers),
+        "solution": to_rows(letters(&puzzle.solution)),
+        "clues": clues,
+    });
+    let fields = [
+        ("title", &puzzle.title),
+        ("author", &puzzle.author),
+        ("copyright", &puzzle.copyright),
+        ("notes", &puzzle.notes),
+    ];
+    for (name, value) in fields.iter() {
+        if !value.is_empty() {
+            root[name] = json!(value);
+        }
+    }
+    if puzzle
+        .progress
+        .cells()
+        .iter()
+        .any(|cell| matches!(cell, Cell::Letter(_)))
+    {
+        root["saved"] = json!(to_rows(letters(&puzzle.progress)));
+    }
+    Ok(serde_json::to_string_pretty(&root).expect("ipuz should serialize") + "\n")
+}
+
+/// Report an invalid ipuz file.
+fn invalid(message: String) -> Error {
+    Error::InvalidPuzzle(format!("ipuz: {}", message))
+}
+
+/// Read one of the grid's dimensions.
+fn dimension(root: &Value, name: &str) -> Result<usize> {
+    root["dimensions"][name]
+        .as_u64()
+        .and_then(|n| usize::try_from(n).ok())
+        .filter(|&n| n > 0)
+        .ok_or_else(|| invalid(format!("missing or invalid {}", name)))
+}
+
+/// Read the optional string field `name`.
+fn string_field(root: &Value, name: &str) -> String {
+    root[name].as_str().unwrap_or_default().to_owned()
+}
+
+/// Every cell of the grid `name`, row by row, checking it's the right size.
+fn rows<'a>(
+    root: &'a Value,
+    name: &str,
+    width: usize,
+    height: usize,
+) -> Result<impl Iterator<Item = &'a Value>> {
+    let rows = root[name]
+        .as_array()
+        .filter(|rows| rows.len() == height)
+        .ok_or_else(|| invalid(format!("{} should have {} rows", name, height)))?;
+    for row in rows {
+        if row.as_array().map(Vec::len) != Some(width) {
+            return Err(invalid(format!(
+                "{} rows should have {} cells",
+                name, width
+            )));
+        }
+    }
+    Ok(rows
+        .iter()
+        .flat_map(|row| row.as_array().into_iter().flatten()))
+}
+
+/// Parse a cell of a solution or saved grid.
+fn parse_letter(value: &Value, block: &str, empty: &Value) -> Result<Cell> {
+    let value = match value {
+        Value::Object(cell) => &cell["value"],
+        value => value,
+    };
+    if value.is_null() || value == empty || value.as_str() == Some("") {
+        return Ok(Cell::Open);
+    }
+    let text = value
+        .as_str()
+        .ok_or_else(|| invalid(format!("unexpected {} in grid", value)))?;
+    if text == block {
+        return Ok(Cell::Block);
+    }
+    let mut chars = text.chars();
+    match (chars.next(), chars.next()) {
+        (Some(c), None) if c.is_alphabetic() => Ok(Cell::letter(c)),
+        _ => Err(invalid(format!("unsupported cell {:?}", text))),
+    }
+}
+
+/// Parse a clue, which is either `[number, clue]` or an object with
+/// `number` and `clue` fields.
+fn parse_clue(value: &Value, direction: Direction) -> Result<Clue> {
+    let (number, text) = match value {
+        Value::Array(parts) if parts.len() == 2 => (&parts[0], &parts[1]),
+        Value::Object(clue) => (&clue["number"], &clue["clue"]),
+        _ => return Err(invalid(format!("unsupported clue {}", value))),
+    };
+    let number = match number {
+        Value::Number(n) => n.as_u64().and_then(|n| usize::try_from(n).ok()),
+        Value::String(s) => s.parse().ok(),
+        _ => None,
+    }
+    .ok_or_else(|| invalid(format!("clue has invalid number {}", number)))?;
+    let text = text
+        .as_str()
+        .ok_or_else(|| invalid(format!("clue {} has no text", number)))?;
+    Ok(Clue {
+        number,
+        direction,
+        text: text.to_owned(),
+    })
+}
@@ -0,0 +1,41 @@
+//! Heuristics for spotting tokens which probably aren't real words, such as
+//! numbers, markup and runs of repeated letters, which often turn up in word
+//! lists built from web corpora.
+
+/// Explain why `word` looks like junk, or return `None` if it looks like a
+/// real word or phrase.
+pub fn junk_reason(word: &str) -> Option<&'static str> {
+    if word.chars().any(|c| c.is_ascii_digit()) {
+        return Some("contains digits");
+    }
+    if word
+        .chars()
+        .any(|c| !(c.is_alphabetic() || c == ' ' || c == '\'' || c == '-'))
+    {
+        return Some("contains symbols");
+    }
+    let chars = word.chars().filter(|&c| c != ' ').collect::<Vec<_>>();
+    if chars.len() == 1 && !matches!(chars[0], 'a' | 'i' | 'A' | 'I') {
+        return Some("single letter");
+    }
+    // We count "y" as a vowel, for words like "rhythms" and "nymphs". Other
+    // alphabets have their own vowels, so we only check ASCII words.
+    let is_vowel = |c: char| "aeiouy".contains(c.to_ascii_lowercase());
+    if chars.len() > 5
+        && chars
+            .iter()
+            .all(|c| c.is_ascii_alphabetic() && !is_vowel(*c))
+    {
+        return Some("no vowels");
+    }
+    // Look for a short unit repeated three or more times, like "zzz" or
+    // "hahaha".
+    for unit in 1..=chars.len() / 3 {
+        if chars.len() % unit == 0
+            && chars.chunks(unit).all(|chunk| chunk == &chars[..unit])
+        {
+            return Some("repeated letters");
+        }
+    }
+    None
+}
@@ -0,0 +1,162 @@
+//! Solving Letter Boxed puzzles.
+//!
+//! A Letter Boxed puzzle has letters arranged around the sides of a square.
+//! Words must be at least 3 letters long, consecutive letters must come from
+//! different sides, and each word must start with the last letter of the
+//! previous word. The goal is to use every letter in as few words as possible.
+
+use fst::Automaton;
+use std::collections::HashMap;
+
+use crate::dictionary::{Dictionary, SearchOptions};
+use crate::error::{Error, Result};
+use crate::probability::{Dist, Prob};
+
+/// The minimum length of a Letter Boxed word.
+const MIN_WORD_LEN: usize = 3;
+
+/// A Letter Boxed puzzle.
+#[derive(Clone, Debug)]
+pub struct LetterBox {
+    /// The side of the box for each byte, if it appears on the box at all.
+    sides: [Option<u8>; 256],
+    /// A bitmask with one bit for each letter on the box.
+    all_letters: u32,
+    /// The bit used by each letter in `all_letters`.
+    letter_bits: HashMap<u8, u32>,
+}
+
+impl LetterBox {
+    /// Create a new puzzle from the letters on each side of the box.
+    pub fn new(sides: &[String]) -> Result<LetterBox> {
+        if sides.len() < 2 {
+            return Err(Error::InvalidPuzzle(
+                "a letter box needs at least two sides".to_owned(),
+            ));
+        }
+        let mut box_sides = [None; 256];
+        let mut letter_bits = HashMap::new();
+        for (side_idx, side) in sides.iter().enumerate() {
+            for b in side.to_ascii_lowercase().bytes() {
+                if !b.is_ascii_lowercase() {
+                    return Err(Error::InvalidPuzzle(format!(
+                        "{:?} is not a letter",
+                        b as char
+                    )));
+                }
+                if box_sides[b as usize].is_some() {
+                    return Err(Error::InvalidPuzzle(format!(
+                        "{:?} appears more than once",
+                        b as char
+                    )));
+                }
+                box_sides[b as usize] = Some(side_idx as u8);
+                let bit = 1 << letter_bits.len();
+                letter_bits.insert(b, bit);
+            }
+        }
+        if letter_bits.len() > 32 {
+            return Err(Error::InvalidPuzzle(
+                "a letter box can have at most 32 letters".to_owned(),
+            ));
+        }
+        let all_letters = letter_bits.values().fold(0, |acc, bit| acc | bit);
+        Ok(LetterBox {
+            sides: box_sides,
+            all_letters,
+            letter_bits,
+        })
+    }
+
+    /// A bitmask of the letters used by `word`.
+    fn letters_in(&self, word: &str) -> u32 {
+        word.bytes()
+            .filter_map(|b| self.letter_bits.get(&b))
+            .fold(0, |acc, bit| acc | bit)
+    }
+
+    /// Find one- and two-word solutions, each sorted by probability.
+    pub fn solve(&self, dict: &Dictionary) -> Result<Solutions> {
+        let words = dict
+            .find_matches_by(self, &SearchOptions::default())?
+            .into_vec()
+            .into_iter()
+            .filter(|(_, w)| w.len() >= MIN_WORD_LEN)
+            .map(|(prob, word)| {
+                let letters = self.letters_in(&word);
+                (prob, word, letters)
+            })
+            .collect::<Vec<_>>();
+
+        let mut by_first_letter = HashMap::<u8, Vec<&(Prob, String, u32)>>::new();
+        for entry in &words {
+            by_first_letter
+                .entry(entry.1.as_bytes()[0])
+                .or_default()
+                .push(entry);
+        }
+
+        let mut one_word = vec![];
+        let mut two_words = vec![];
+        for (prob, word, letters) in &words {
+            if *letters == self.all_letters {
+                one_word.push((*prob, word.clone()));
+            }
+            let last = *word.as_bytes().last().expect("words are not empty");
+            for (prob2, word2, letters2) in
+                by_first_letter.get(&last).into_iter().flatten()
+            {
+                if letters | letters2 == self.all_letters {
+                    two_words.push((*prob * *prob2, format!("{} {}", word, word2)));
+                }
+            }
+        }
+
+        let mut one_word = Dist::from_vec(one_word);
+        one_word.sort_by_probability();
+        let mut two_words = Dist::from_vec(two_words);
+        two_words.sort_by_probability();
+        Ok(Solutions {
+            one_word,
+            two_words,
+        })
+    }
+}
+
+/// Solutions to a Letter Boxed puzzle.
+pub struct Solutions {
+    /// Single words using every letter.
+    pub one_word: Dist<String>,
+    /// Pairs of words using every letter.
+    pub two_words: Dist<String>,
+}
+
+/// Walk the dictionary, only following letters on the box which are on a
+/// different side from the previous letter.
+impl Automaton for &LetterBox {
+    /// `None` if we've rejected the input, or `Some(side)` of the previous
+    /// letter, where `side` is `None` at the start of a word.
+    type State = Option<Option<u8>>;
+
+    fn start(&self) -> Self::State {
+        Some(None)
+    }
+
+    fn is_match(&self, state: &Self::State) -> bool {
+        matches!(state, Some(Some(_)))
+    }
+
+    fn can_match(&self, state: &Self::State) -> bool {
+        state.is_some()
+    }
+
+    fn accept(&self, state: &Self::State, byte: u8) -> Self::State {
+        let prev_side = (*state)?;
+        let side = self.sides[byte as usize]?;
+        if prev_side == Some(side) {
+            None
+        } else {
+            Some(Some(side))
+        }
+    }
+}
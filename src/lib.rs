@@ -0,0 +1,55 @@
+//! Tools for solving word puzzles, built around a compact dictionary of
+//! English words and their probabilities.
+
+pub mod acrostic;
+pub mod anagram;
+pub mod background;
+pub mod chain;
+pub mod checkpoint;
+pub mod clue;
+pub mod compound;
+pub mod cursor;
+pub mod diagnostics;
+pub mod dictionary;
+pub mod dictops;
+pub mod dropquote;
+pub mod encoding;
+pub mod encryption;
+pub mod enumeration;
+pub mod error;
+mod external_sort;
+pub mod extract;
+pub mod extremes;
+pub mod fill;
+pub mod filter;
+pub mod fuzzy;
+pub mod grid;
+pub mod homophone;
+pub mod ipuz;
+pub mod junk;
+pub mod letterboxed;
+pub mod metadata;
+pub mod model;
+pub mod morphology;
+pub mod pangram;
+pub mod pattern;
+pub mod predicate;
+pub mod probability;
+pub mod pronunciation;
+pub mod puz;
+pub mod puzzle;
+pub mod random;
+pub mod rhyme;
+pub mod scorer;
+pub mod scrabble;
+pub mod search_tree;
+pub mod segment;
+mod shard;
+pub mod shift;
+pub mod stats;
+pub mod storage;
+pub mod syllable;
+pub mod wordfit;
+pub mod wordle;
+
+pub use crate::error::{Error, Result};
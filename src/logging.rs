@@ -0,0 +1,124 @@
+//! Setting up logging from `-v` flags and `--log-format`.
+//!
+//! The solvers log search events, like the candidates they try and the
+//! branches they prune, with key-value fields attached. In text mode we
+//! append the fields to the message, and in JSON mode we write each event as
+//! one JSON object per line, so a slow solve can be analyzed afterwards with
+//! tools like `jq`.
+
+use anyhow::{format_err, Error, Result};
+use env_logger::{fmt::Formatter, Builder, Env};
+use log::{
+    kv::{self, Key, Value, VisitSource},
+    Level, Record,
+};
+use serde_json::{Map, Number};
+use std::{io::Write, str::FromStr};
+
+/// How to write log messages.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LogFormat {
+    /// Human-readable lines, with fields as `key=value`.
+    Text,
+    /// One JSON object per line.
+    Json,
+}
+
+/// Parse `text` or `json`.
+impl FromStr for LogFormat {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "text" => Ok(LogFormat::Text),
+            "json" => Ok(LogFormat::Json),
+            _ => Err(format_err!("expected \"text\" or \"json\", found {:?}", s)),
+        }
+    }
+}
+
+/// Start logging to standard error. `verbosity` is the number of `-v` flags:
+/// none shows warnings, one adds progress information, two adds debugging
+/// details and three or more adds every step of each search. Setting
+/// `RUST_LOG` overrides `verbosity`.
+pub fn init(verbosity: u64, format: LogFormat) {
+    let level = match verbosity {
+        0 => Level::Warn,
+        1 => Level::Info,
+        2 => Level::Debug,
+        _ => Level::Trace,
+    };
+    // Only raise the level of our own messages, not our dependencies'.
+    let filter = format!(
+        "warn,{}={}",
+        env!("CARGO_CRATE_NAME"),
+        level.to_string().to_lowercase()
+    );
+    let mut builder = Builder::from_env(Env::default().default_filter_or(filter));
+    match format {
+        LogFormat::Text => builder.format(write_text),
+        LogFormat::Json => builder.format(write_json),
+    };
+    builder.init();
+}
+
+/// Write `record` as `[LEVEL target] message key=value...`.
+fn write_text(buf: &mut Formatter, record: &Record<'_>) -> std::io::Result<()> {
+    write!(
+        buf,
+        "[{} {}] {}",
+        record.level(),
+        record.target(),
+        record.args()
+    )?;
+    let mut fields = Fields::default();
+    // Our visitor never fails.
+    let _ = record.key_values().visit(&mut fields);
+    for (key, value) in fields.0 {
+        match value {
+            serde_json::Value::String(s) => write!(buf, " {}={:?}", key, s)?,
+            value => write!(buf, " {}={}", key, value)?,
+        }
+    }
+    writeln!(buf)
+}
+
+/// Write `record` as a JSON object on one line, with `level`, `target` and
+/// `message` keys followed by the record's own fields.
+fn write_json(buf: &mut Formatter, record: &Record<'_>) -> std::io::Result<()> {
+    let mut object = Map::new();
+    object.insert("level".to_owned(), record.level().as_str().into());
+    object.insert("target".to_owned(), record.target().into());
+    object.insert("message".to_owned(), record.args().to_string().into());
+    let mut fields = Fields::default();
+    let _ = record.key_values().visit(&mut fields);
+    object.extend(fields.0);
+    serde_json::to_writer(&mut *buf, &object)?;
+    writeln!(buf)
+}
+
+/// The key-value fields of a log record, converted to JSON values.
+#[derive(Default)]
+struct Fields(Vec<(String, serde_json::Value)>);
+
+impl<'kvs> VisitSource<'kvs> for Fields {
+    fn visit_pair(
+        &mut self,
+        key: Key<'kvs>,
+        value: Value<'kvs>,
+    ) -> Result<(), kv::Error> {
+        let value = if let Some(n) = value.to_u64() {
+            n.into()
+        } else if let Some(n) = value.to_i64() {
+            n.into()
+        } else if let Some(b) = value.to_bool() {
+            b.into()
+        } else if let Some(n) = value.to_f64().and_then(Number::from_f64) {
+            serde_json::Value::Number(n)
+        } else {
+            value.to_string().into()
+        };
+        self.0.push((key.as_str().to_owned(), value));
+        Ok(())
+    }
+}
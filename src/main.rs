@@ -1,18 +1,107 @@
-use anyhow::Result;
+use anyhow::{format_err, Context, Result};
 use itertools::Itertools;
-use log::{debug, trace};
-use std::{collections::BTreeSet, iter::Iterator, path::PathBuf};
-use structopt::StructOpt;
+use log::{debug, info, trace, warn};
+use serde::Serialize;
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt::Write,
+    fs,
+    io::{self, BufRead},
+    iter::Iterator,
+    mem,
+    path::{Path, PathBuf},
+    process,
+    str::FromStr,
+    time::Duration,
+};
+use structopt::{clap::Shell, StructOpt};
 
-mod dictionary;
-mod probability;
+use letter_permutations::{
+    acrostic::{Acrostic, AcrosticOptions},
+    anagram::{
+        blank_letters, find_anagram_phrases, find_anagrams, find_bank_words,
+        find_buildable_words, find_rack_words, find_transposals, PhraseOptions,
+        TransposalOptions,
+    },
+    chain::{find_chains, ChainOptions},
+    checkpoint::CheckpointOptions,
+    compound::{find_compounds, CompoundOptions},
+    dictionary::{
+        read_word_list, BuildOptions, Captures, Dictionary, OnDuplicate, SearchOptions,
+    },
+    dictops::{write_combined, Combine, SetOperation},
+    dropquote::{DropQuote, DropQuoteOptions},
+    encoding::{decode, decode_tokens, Code, TokenTable, MORSE, T9, TOKEN_TABLES},
+    encryption::DictionaryKey,
+    enumeration::Enumeration,
+    error::Error,
+    extract::{rank_extractions, Scheme},
+    extremes::{find_extremes, ExtremeOptions},
+    fill::{fill, score_fill, FillOptions, ScoreFillOptions},
+    filter::{Band, WordFilter},
+    fuzzy::{rank_typos, KeyboardModel},
+    homophone::{find_homophone_pairs, find_homophones},
+    letterboxed::LetterBox,
+    model::{LanguageModel, SmoothedModel, Smoothing},
+    pangram::{find_pangrams, PangramOptions},
+    pattern::CharClasses,
+    predicate::{
+        AlternatesVowels, DoubleLetters, FromBank, Isogram, Matches,
+        MaxDistinctLetters, NotBlocked, Predicates,
+    },
+    probability::{check_temperature, Dist, Prob, ProbFormat},
+    puzzle::{Puzzle, PuzzleGrid},
+    random::Rng,
+    rhyme::{find_rhymes, RhymeMode},
+    scorer::{BoostList, LengthNormalizer, Scorers, ShortWordPenalty},
+    scrabble::{self, generate_moves, MoveOptions},
+    search_tree::{NodeId, Outcome, SearchTree, DEFAULT_MAX_NODES},
+    segment::{break_into_words, Breaking, Lattice, SegmentOptions, Span},
+    shift::{find_shift_pairs, ShiftOptions},
+    stats::{check_quality, dictionary_stats, QualityOptions},
+    wordfit::{generate_bank, BankOptions, WordFit, WordFitOptions},
+    wordle::{analyze, Board, WordleOptions},
+};
 
-use crate::dictionary::Dictionary;
-use crate::probability::{Dist, Prob};
+/// The name of our executable, for completion scripts.
+const BIN_NAME: &str = "letter-permutations";
+
+mod cache;
+mod config;
+mod logging;
+mod registry;
+mod render;
+
+use crate::cache::QueryCache;
+use crate::config::{Config, Profile, EMBEDDED_NAME};
+use crate::logging::LogFormat;
+use crate::registry::{fetch, Registry};
+use crate::render::{occurrences, ColorWhen, Layout, Renderer};
 
 /// Command-line options.
 #[derive(Debug, StructOpt)]
 struct Opt {
+    /// When to use color: "auto", "always" or "never".
+    #[structopt(long, global = true, default_value = "auto")]
+    color: ColorWhen,
+    /// How to show probabilities: "nats" (negative natural log), "bits",
+    /// "log10", "percent" or "odds".
+    #[structopt(long, global = true, default_value = "nats")]
+    prob_format: ProbFormat,
+    /// Log more details of what we're doing: -v for progress, -vv for
+    /// debugging details and -vvv for every step of each search. Setting
+    /// RUST_LOG overrides this.
+    #[structopt(
+        short = "v",
+        long = "verbose",
+        global = true,
+        parse(from_occurrences)
+    )]
+    verbosity: u64,
+    /// How to write log messages: "text", or "json" for one JSON object per
+    /// line, with fields like the candidates each search tried.
+    #[structopt(long, global = true, default_value = "text")]
+    log_format: LogFormat,
     /// Subcommands.
     #[structopt(subcommand)]
     cmd: Command,
@@ -25,118 +114,2974 @@ enum Command {
     #[structopt(name = "mkdict")]
     MakeDictionary(MakeDictionaryOpt),
 
+    /// Combine dictionaries into a new dictionary, keeping the words in any
+    /// of them ("union"), in all of them ("intersection"), or in the first
+    /// but none of the others ("difference").
+    #[structopt(name = "dictops")]
+    DictOps(DictOpsOpt),
+
+    /// Download or copy a word list, build it into a dictionary in the
+    /// standard data directory, and make it the default.
+    InstallDict(InstallDictOpt),
+
     /// Look up words matching a regular expression.
     Search(SearchOpt),
 
+    /// Find words containing a substring. This is much faster with a
+    /// dictionary built with `--substrings` or `--with-gaddag`.
+    Contains(ContainsOpt),
+
+    /// Look up the probability of specific words.
+    Lookup(LookupOpt),
+
+    /// Check whether each of a list of words is in the dictionary, suggesting
+    /// similar words for any which aren't.
+    Check(CheckOpt),
+
     /// Permute letters or word fragments.
     Permute(PermuteOpt),
+
+    /// Break a string into dictionary words, such as "whatdoesthissay".
+    Segment(SegmentOpt),
+
+    /// Extract a message from puzzle answers by taking one letter from each,
+    /// trying several schemes and ranking them by how much the result looks
+    /// like words.
+    Extract(ExtractOpt),
+
+    /// Decode Morse code with no breaks between letters or words, like
+    /// ".... . .-.. .-.. ---".
+    Morse(EncodedOpt),
+
+    /// Decode the digits on a phone keypad, like "4663", into words.
+    T9(EncodedOpt),
+
+    /// Find pairs of words where shifting every letter of one through the
+    /// alphabet by the same amount gives the other, like "hal" and "ibm".
+    #[structopt(name = "shiftpairs")]
+    ShiftPairs(ShiftPairsOpt),
+
+    /// Decode letters written as separate tokens, such as Braille cells,
+    /// semaphore positions, NATO code words or numbers, and break them into
+    /// words.
+    Decode(DecodeOpt),
+
+    /// List words made of two or more other words.
+    Compounds(CompoundsOpt),
+
+    /// List sets of words which are anagrams of each other.
+    Transposals(TransposalsOpt),
+
+    /// Find words spelled almost like a word, such as a garbled answer.
+    Fuzzy(FuzzyOpt),
+
+    /// Find words which rhyme with a word.
+    Rhymes(RhymesOpt),
+
+    /// Find words pronounced like a word but spelled differently, or list
+    /// every pair of such words. Requires a dictionary built with
+    /// `--pronunciations`.
+    Homophones(HomophonesOpt),
+
+    /// Find chains of words from one word to another, where each word starts
+    /// with the last few letters of the word before it.
+    Chain(ChainOpt),
+
+    /// Find the longest (or shortest) words which pass some tests.
+    Extremes(ExtremesOpt),
+
+    /// Find small sets of words which use every letter in a set, such as the
+    /// whole alphabet.
+    Pangrams(PangramsOpt),
+
+    /// Find single words or phrases which are anagrams of some letters. This
+    /// and the rack and bank commands are much faster with a dictionary built
+    /// with `--with-anagram-index`.
+    Anagram(AnagramOpt),
+
+    /// Find words spelled with some of the tiles in a rack, as in Scrabble.
+    Rack(RackOpt),
+
+    /// Find the highest-scoring Scrabble moves for a rack on a board.
+    Scrabble(ScrabbleOpt),
+
+    /// List the words of every length which can be built from the letters of
+    /// a longer word, like "how many words can you make from CELEBRATION?"
+    Builder(BuilderOpt),
+
+    /// Find words spelled only with letters from a bank, reusing letters as
+    /// often as needed, as in Spelling Bee.
+    Bank(BankOpt),
+
+    /// Fill a crossword grid with words.
+    Fill(FillOpt),
+
+    /// Score a completed crossword fill, to compare fills from different
+    /// sources.
+    ScoreFill(ScoreFillOpt),
+
+    /// Solve a fit-the-words (or kriss-kross) puzzle, placing every word
+    /// from a bank in an empty grid.
+    #[structopt(name = "wordfit")]
+    WordFit(WordFitOpt),
+
+    /// Choose a bank of words for a fit-the-words (or kriss-kross) puzzle,
+    /// which fits into the grid in exactly one way.
+    #[structopt(name = "krisskross")]
+    KrissKross(KrissKrossOpt),
+
+    /// Show crossword answers matching a pattern, with historical clues. The
+    /// dictionary must have been built with `--clues`.
+    Clues(CluesOpt),
+
+    /// Solve an acrostic, given the letters of the quote and the first letter
+    /// of each answer.
+    Acrostic(AcrosticOpt),
+
+    /// Solve a drop quote (or quotefall) puzzle, where the letters of each
+    /// column of a quote are given in scrambled order.
+    #[structopt(name = "dropquote")]
+    DropQuote(DropQuoteOpt),
+
+    /// Solve a Letter Boxed puzzle.
+    #[structopt(name = "letterboxed")]
+    LetterBoxed(LetterBoxedOpt),
+
+    /// Suggest guesses for Wordle, or multi-board variants like Quordle.
+    Wordle(WordleOpt),
+
+    /// Export rank-frequency data, word lengths and letter frequencies from a
+    /// dictionary as CSV or JSON, for plotting.
+    ExportStats(ExportStatsOpt),
+
+    /// Check how closely a dictionary's probabilities follow Zipf's law, look
+    /// for junk entries, and measure how many common words it contains.
+    #[structopt(name = "dictcheck")]
+    DictCheck(DictCheckOpt),
+
+    /// List the dictionaries defined in the config file or installed with
+    /// `install-dict`.
+    Dicts(DictsOpt),
+
+    /// Manage the cache of search and permute results.
+    Cache(CacheOpt),
+
+    /// Print a shell completion script.
+    Completions(CompletionsOpt),
+}
+
+/// Which dictionary to use.
+#[derive(Clone, Debug, Default, StructOpt)]
+struct DictOpt {
+    /// The dictionary to use: either the name of a dictionary in the config
+    /// file, a path, or an HTTP(S) URL. Defaults to the config file's
+    /// default dictionary.
+    #[structopt(long, short = "d")]
+    dict: Option<String>,
+    /// Don't check the dictionary against the checksums recorded when it was
+    /// built. This saves a moment when loading very large dictionaries.
+    #[structopt(long)]
+    no_verify: bool,
+    /// A file containing the key for a dictionary built with
+    /// `--encrypt-key-file`.
+    #[structopt(long)]
+    key_file: Option<PathBuf>,
+}
+
+impl DictOpt {
+    /// Look up our dictionary in the user's config file.
+    fn profile(&self) -> Result<Profile> {
+        let mut profile = Config::load_default()?
+            .profile(self.dict.as_deref(), &Registry::open_default()?)?;
+        profile.no_verify |= self.no_verify;
+        if let Some(key_file) = &self.key_file {
+            profile.key_file = Some(key_file.clone());
+        }
+        Ok(profile)
+    }
+}
+
+/// Options controlling which words we consider and how probable we think they
+/// are, shared by every command which searches a dictionary.
+#[derive(Clone, Debug, StructOpt)]
+struct FilterOpt {
+    /// Only consider words with this frequency rank or better.
+    #[structopt(long)]
+    max_rank: Option<u64>,
+    /// Only consider words in this frequency band: "common" (the top 20,000
+    /// words), "medium" (the next 80,000) or "rare" (everything else).
+    #[structopt(long)]
+    band: Option<Band>,
+    /// Only consider words with this many syllables. This uses
+    /// pronunciations if the dictionary has them, and guesses otherwise.
+    #[structopt(long)]
+    syllables: Option<usize>,
+    /// Rescale word probabilities by raising them to the power
+    /// 1/temperature. Values above 1 favor rarer words, which helps when
+    /// common words crowd out good multi-word answers. Must be positive and
+    /// finite.
+    #[structopt(long, parse(try_from_str = parse_temperature))]
+    temperature: Option<f64>,
+    /// Only consider words which don't repeat any letters.
+    #[structopt(long)]
+    isogram: bool,
+    /// Only consider words which use at most this many different letters.
+    #[structopt(long)]
+    max_distinct_letters: Option<usize>,
+    /// Only consider words with the same letter twice in a row, like "book".
+    #[structopt(long)]
+    double_letters: bool,
+    /// Consider words which are usually capitalized, like names. Only
+    /// dictionaries built with `mkdict --proper-nouns` know which words
+    /// these are.
+    #[structopt(long)]
+    allow_proper_nouns: bool,
+    /// Make the words in this file, one per line, more probable, such as the
+    /// theme words of a puzzle.
+    #[structopt(long)]
+    boost_file: Option<PathBuf>,
+    /// How many times more probable to make the words in --boost-file.
+    #[structopt(long, default_value = "100")]
+    boost_factor: f64,
+    /// Never return the words in this file, such as offensive words. This is
+    /// either a word list with one word per line, or a dictionary built by
+    /// `mkdict` ending in ".fst". Phrases containing blocked words are also
+    /// left out. May be given more than once.
+    #[structopt(long, number_of_values = 1)]
+    blocklist: Vec<PathBuf>,
+    /// Score each word by its probability among words with the same number
+    /// of letters, instead of among all words. This compares answers for a
+    /// slot of known length better, since short words are far more common.
+    #[structopt(long)]
+    normalize_within_length: bool,
+}
+
+impl FilterOpt {
+    /// Fill in any options we don't have from the defaults in `profile`.
+    fn with_defaults(&self, profile: &Profile) -> FilterOpt {
+        FilterOpt {
+            max_rank: self.max_rank.or(profile.max_rank),
+            band: self.band.or(profile.band),
+            syllables: self.syllables.or(profile.syllables),
+            temperature: self.temperature.or(profile.temperature),
+            allow_proper_nouns: self.allow_proper_nouns || profile.allow_proper_nouns,
+            normalize_within_length: self.normalize_within_length
+                || profile.normalize_within_length,
+            ..self.clone()
+        }
+    }
+
+    /// The tests which words must pass.
+    fn predicates(&self) -> Result<Predicates> {
+        let mut predicates = Predicates::new();
+        for path in &self.blocklist {
+            predicates.push(NotBlocked::load(path)?);
+        }
+        if self.isogram {
+            predicates.push(Isogram);
+        }
+        if let Some(max) = self.max_distinct_letters {
+            predicates.push(MaxDistinctLetters(max));
+        }
+        if self.double_letters {
+            predicates.push(DoubleLetters);
+        }
+        Ok(predicates)
+    }
+
+    /// The filter described by our options.
+    fn word_filter(&self) -> Result<WordFilter> {
+        Ok(WordFilter {
+            syllables: self.syllables,
+            max_rank: self.max_rank,
+            band: self.band,
+            predicates: self.predicates()?,
+            exclude_proper_nouns: !self.allow_proper_nouns,
+        })
+    }
+
+    /// Our options, leaving out the paths of files whose contents matter
+    /// instead, for identifying cached results. Include the dictionary's
+    /// filter and scorers in the query as well to cover those contents.
+    fn without_paths(&self) -> FilterOpt {
+        FilterOpt {
+            boost_file: None,
+            blocklist: vec![],
+            ..self.clone()
+        }
+    }
+
+    /// Load the dictionary chosen by `dict`, and apply our filter to it.
+    fn load_dictionary(&self, dict: &DictOpt) -> Result<Dictionary> {
+        let profile = dict.profile()?;
+        self.with_defaults(&profile).load_profile(&profile)
+    }
+
+    /// Load the dictionary described by `profile`, and apply our filter to it
+    /// without looking at the defaults in `profile`.
+    fn load_profile(&self, profile: &Profile) -> Result<Dictionary> {
+        let mut dict = profile.load()?;
+        dict.set_filter(self.word_filter()?);
+        if let Some(temperature) = self.temperature {
+            dict.set_temperature(temperature)?;
+        }
+        let mut scorers = Scorers::new();
+        if self.normalize_within_length {
+            scorers.push(LengthNormalizer::for_dictionary(&dict)?);
+        }
+        if let Some(boost_file) = &self.boost_file {
+            scorers.push(BoostList::from_file(boost_file, self.boost_factor)?);
+        }
+        dict.set_scorers(scorers);
+        Ok(dict)
+    }
+}
+
+/// Options for saving and resuming long searches.
+#[derive(Clone, Debug, StructOpt)]
+struct CheckpointOpt {
+    /// Save the state of the search to this file every so often. If the
+    /// search is interrupted, run the same command again to resume it. The
+    /// file is removed once the search finishes.
+    #[structopt(long)]
+    checkpoint: Option<PathBuf>,
+    /// How often to save the state of the search, in seconds.
+    #[structopt(long, default_value = "60")]
+    checkpoint_interval: u64,
+}
+
+impl CheckpointOpt {
+    /// The checkpoint options to pass to a search.
+    fn options(&self) -> Option<CheckpointOptions> {
+        self.checkpoint.as_ref().map(|path| CheckpointOptions {
+            path: path.clone(),
+            interval: Duration::from_secs(self.checkpoint_interval),
+        })
+    }
+}
+
+/// Limits on how many results a search keeps, so that searches of huge inputs
+/// degrade gracefully instead of running out of memory.
+#[derive(Clone, Debug, StructOpt)]
+struct ResultLimitOpt {
+    /// Keep only this many of the most probable results while searching.
+    #[structopt(long)]
+    max_results: Option<usize>,
+    /// Roughly how much memory to use for results, like "500M" or "2G". We
+    /// keep fewer results if they wouldn't fit.
+    #[structopt(long)]
+    max_memory: Option<ByteSize>,
+}
+
+impl ResultLimitOpt {
+    /// The number of results to keep, if each one uses about `result_size`
+    /// bytes while searching.
+    fn max_results(&self, result_size: usize) -> Option<usize> {
+        let by_memory = self.max_memory.map(|ByteSize(bytes)| {
+            (bytes / result_size.max(1) as u64).min(usize::MAX as u64) as usize
+        });
+        match (self.max_results, by_memory) {
+            (Some(a), Some(b)) => Some(a.min(b)),
+            (a, b) => a.or(b),
+        }
+    }
+}
+
+/// Limits on repeated words when breaking text into words, to rule out
+/// degenerate breakings like "a a a a".
+#[derive(Clone, Debug, StructOpt)]
+struct RepeatOpt {
+    /// Don't allow the same word twice in a row.
+    #[structopt(long)]
+    no_repeat_adjacent: bool,
+    /// The maximum number of times each word may appear in a result.
+    #[structopt(long)]
+    max_repeats_per_word: Option<usize>,
+}
+
+impl RepeatOpt {
+    /// Apply these limits to `opts`.
+    fn apply(&self, opts: SegmentOptions) -> SegmentOptions {
+        SegmentOptions {
+            no_repeat_adjacent: self.no_repeat_adjacent,
+            max_repeats_per_word: self.max_repeats_per_word,
+            ..opts
+        }
+    }
+}
+
+/// Adjustments to the scores of words when ranking breakings.
+#[derive(Clone, Debug, StructOpt)]
+struct ScoreOpt {
+    /// Penalize words with fewer than this many letters.
+    #[structopt(long)]
+    short_word_length: Option<usize>,
+    /// Multiply the probability of words shorter than --short-word-length
+    /// by this, from 0 to 1.
+    #[structopt(long, default_value = "0.1")]
+    short_word_penalty: f64,
+    /// Allow words which aren't in the dictionary, estimating their
+    /// probability with "add-k:K" (such as "add-k:0.5") or "good-turing"
+    /// smoothing. Longer unknown words are less probable.
+    #[structopt(long)]
+    smoothing: Option<Smoothing>,
+}
+
+impl ScoreOpt {
+    /// Apply these adjustments to `opts`, for breaking text into words from
+    /// `dict`.
+    fn apply(
+        &self,
+        dict: &Dictionary,
+        opts: SegmentOptions,
+    ) -> Result<SegmentOptions> {
+        let mut scorers = Scorers::new();
+        if let Some(min_length) = self.short_word_length {
+            if !(0.0..=1.0).contains(&self.short_word_penalty) {
+                return Err(format_err!(
+                    "--short-word-penalty must be between 0 and 1, found {}",
+                    self.short_word_penalty
+                ));
+            }
+            scorers.push(ShortWordPenalty::new(min_length, self.short_word_penalty));
+        }
+        let unknown_word = self
+            .smoothing
+            .map(|smoothing| SmoothedModel::new(dict, smoothing))
+            .transpose()?
+            .map(|model| model.unknown_probability());
+        Ok(SegmentOptions {
+            scorers,
+            unknown_word,
+            ..opts
+        })
+    }
+}
+
+/// Parse `--temperature`, which must be positive.
+fn parse_temperature(s: &str) -> Result<f64> {
+    let temperature = s.parse::<f64>()?;
+    check_temperature(temperature)?;
+    Ok(temperature)
+}
+
+/// A number of bytes, with an optional "K", "M" or "G" suffix.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct ByteSize(u64);
+
+impl FromStr for ByteSize {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let s = s.trim();
+        let (digits, multiplier) = match s.char_indices().last() {
+            Some((i, c)) if c.is_ascii_alphabetic() => {
+                let multiplier = match c.to_ascii_uppercase() {
+                    'K' => 1 << 10,
+                    'M' => 1 << 20,
+                    'G' => 1 << 30,
+                    _ => return Err(format_err!("unknown size suffix in {:?}", s)),
+                };
+                (&s[..i], multiplier)
+            }
+            _ => (s, 1),
+        };
+        let count = digits
+            .parse::<u64>()
+            .with_context(|| format!("invalid size {:?}", s))?;
+        count
+            .checked_mul(multiplier)
+            .map(ByteSize)
+            .ok_or_else(|| format_err!("size {:?} is too large", s))
+    }
 }
 
 #[derive(Debug, StructOpt)]
 struct MakeDictionaryOpt {
-    /// A list of "\s*count\s+word" pairs, one per line.
+    /// A list of "\s*count\s+word" pairs, one per line. The word may be a
+    /// phrase like "new york", which search patterns match without spaces.
     in_words_path: PathBuf,
     /// The output dictionary.
     out_dict_path: PathBuf,
+    /// Replace the output dictionary if it already exists.
+    #[structopt(long)]
+    force: bool,
+    #[structopt(flatten)]
+    build: BuildOpt,
+}
+
+#[derive(Debug, StructOpt)]
+struct InstallDictOpt {
+    /// The name to install the dictionary as.
+    name: String,
+    /// A path or HTTP(S) URL of a list of "\s*count\s+word" pairs, one per
+    /// line.
+    source: String,
+    /// Don't make this the default dictionary, unless there isn't one yet.
+    #[structopt(long)]
+    keep_default: bool,
+    #[structopt(flatten)]
+    build: BuildOpt,
+}
+
+#[derive(Debug, StructOpt)]
+struct DictOpsOpt {
+    #[structopt(flatten)]
+    filter: FilterOpt,
+    /// Which words to keep: "union", "intersection" or "difference".
+    operation: SetOperation,
+    /// The dictionaries to combine, as names in the config file or paths.
+    #[structopt(required = true)]
+    inputs: Vec<String>,
+    /// The output dictionary.
+    #[structopt(long, short = "o")]
+    output: PathBuf,
+    /// How to combine the probabilities of words in more than one
+    /// dictionary: "first", "min", "max" or "mean".
+    #[structopt(long, default_value = "max")]
+    combine: Combine,
+    /// Replace the output dictionary if it already exists.
+    #[structopt(long)]
+    force: bool,
+    #[structopt(flatten)]
+    build: BuildOpt,
 }
 
+/// Options controlling which indices we build, shared by `mkdict`,
+/// `dictops` and `install-dict`.
 #[derive(Debug, StructOpt)]
+struct BuildOpt {
+    /// A pronouncing dictionary in CMU format, used to find rhymes and
+    /// homophones.
+    #[structopt(long = "pronunciations")]
+    pronunciations_path: Option<PathBuf>,
+    /// A file of "answer<TAB>clue" lines, used by the clues command.
+    #[structopt(long = "clues")]
+    clues_path: Option<PathBuf>,
+    /// Build a substring index, used to speed up the contains command.
+    #[structopt(long)]
+    substrings: bool,
+    /// Build an anagram index, used to speed up the anagram, rack and bank
+    /// commands.
+    #[structopt(long = "with-anagram-index")]
+    anagrams: bool,
+    /// Build a GADDAG index, used to speed up the scrabble command, and the
+    /// contains command when there's no substring index.
+    #[structopt(long = "with-gaddag")]
+    gaddag: bool,
+    /// Store each probability in this many bits (1 to 32) instead of 64,
+    /// making the dictionary smaller but less precise.
+    #[structopt(long = "quantize-prob")]
+    quantize_bits: Option<u32>,
+    /// A file of words to leave out, such as stopwords, one per line.
+    #[structopt(long = "exclude-file")]
+    exclude_path: Option<PathBuf>,
+    /// Leave out words which entirely match this regex.
+    #[structopt(long)]
+    exclude_regex: Option<String>,
+    /// Treat the word list as case-sensitive: add together the counts of
+    /// words which differ only in case, and mark words which are usually
+    /// capitalized as proper nouns.
+    #[structopt(long)]
+    proper_nouns: bool,
+    /// The fraction of the time a word must be capitalized to count as a
+    /// proper noun.
+    #[structopt(long, default_value = "0.9")]
+    proper_noun_threshold: f64,
+    /// Add the counts of inflected forms like "cats", "walked" and
+    /// "stopping" to their base forms, guessing base forms from spelling.
+    #[structopt(long)]
+    fold_inflections: bool,
+    /// With `--fold-inflections`, keep inflected forms in the dictionary with
+    /// their own counts, as well as adding them to their base forms.
+    #[structopt(long, requires = "fold-inflections")]
+    keep_inflected_forms: bool,
+    /// Leave out tokens which look like junk from web corpora: tokens with
+    /// digits or symbols, single letters other than "a" and "i", words over
+    /// five letters with no vowels, and repeated letters like "zzz".
+    #[structopt(long)]
+    exclude_junk: bool,
+    /// Skip lines of the word list which can't be parsed, instead of
+    /// failing, and summarize them once the dictionary is built.
+    #[structopt(long)]
+    skip_invalid: bool,
+    /// What to do with more than one entry for the same word, such as "Cat"
+    /// and "cat": "error", "sum" their counts, keep the "max" count, or
+    /// keep the "first" entry. With `--proper-nouns`, only repeated
+    /// spellings count as duplicates.
+    #[structopt(long, default_value = "error")]
+    on_duplicate: OnDuplicate,
+    /// Keep memory use to about this much, such as "2G", by sorting the
+    /// word list on disk. This is slower, but can build dictionaries from
+    /// word lists much larger than memory.
+    #[structopt(long, conflicts_with = "fold-inflections")]
+    max_memory: Option<ByteSize>,
+    /// The word list is already sorted by lowercase word in byte order (for
+    /// a lowercase list, as by `LC_ALL=C sort -k2`). This lets us read it as
+    /// a stream, using little memory, even without `--max-memory`.
+    #[structopt(long, conflicts_with = "fold-inflections")]
+    sorted: bool,
+    /// Split the dictionary and each index into files of about this size,
+    /// such as "1G", for word lists too large for a single file. Queries
+    /// look in every shard.
+    #[structopt(long)]
+    shard_size: Option<ByteSize>,
+    /// Encrypt the dictionary with the key in this file, such as a
+    /// passphrase, so that it can only be loaded with `--key-file`. Useful
+    /// for word lists whose license forbids sharing them in readable form.
+    #[structopt(long)]
+    encrypt_key_file: Option<PathBuf>,
+}
+
+impl BuildOpt {
+    /// Build a dictionary, and print a report of what we built. If
+    /// `overwrite` is false, fail if the dictionary already exists.
+    fn build(
+        &self,
+        in_words_path: &Path,
+        out_dict_path: &Path,
+        overwrite: bool,
+    ) -> Result<()> {
+        let build_opts = BuildOptions {
+            pronunciations_path: self.pronunciations_path.clone(),
+            clues_path: self.clues_path.clone(),
+            substrings: self.substrings,
+            anagrams: self.anagrams,
+            gaddag: self.gaddag,
+            quantize_bits: self.quantize_bits,
+            exclude_path: self.exclude_path.clone(),
+            exclude_regex: self.exclude_regex.clone(),
+            exclude_junk: self.exclude_junk,
+            proper_noun_threshold: self
+                .proper_nouns
+                .then_some(self.proper_noun_threshold),
+            fold_inflections: self.fold_inflections,
+            keep_inflected_forms: self.keep_inflected_forms,
+            on_duplicate: self.on_duplicate,
+            skip_invalid: self.skip_invalid,
+            overwrite,
+            max_memory: self.max_memory.map(|size| size.0),
+            sorted: self.sorted,
+            shard_size: self.shard_size.map(|size| size.0),
+            encryption_key: self
+                .encrypt_key_file
+                .as_deref()
+                .map(DictionaryKey::from_file)
+                .transpose()?,
+        };
+        let report = Dictionary::build(in_words_path, out_dict_path, &build_opts)?;
+        let mut total_size = 0;
+        for (path, size) in &report.files {
+            println!("{:>12} {}", size, path.display());
+            total_size += size;
+        }
+        println!("{:>12} total", total_size);
+        if report.excluded > 0 {
+            println!("Excluded {} words", report.excluded);
+        }
+        let skipped = &report.skipped;
+        if skipped.count > 0 {
+            println!("Skipped {} invalid lines:", skipped.count);
+            for diagnostic in &skipped.examples {
+                println!("{}", diagnostic);
+            }
+            let unshown = skipped.count - skipped.examples.len() as u64;
+            if unshown > 0 {
+                println!("...and {} more", unshown);
+            }
+        }
+        if report.merged > 0 {
+            println!("Merged {} duplicate entries", report.merged);
+        }
+        if report.folded > 0 {
+            println!(
+                "Folded {} inflected forms into their base forms",
+                report.folded
+            );
+        }
+        if let Some(quantization) = &report.quantization {
+            let max_error = quantization.max_error();
+            println!(
+                "Probabilities use {} bits, and are within a factor of {:.6} \
+                 (±{:.6} in log space)",
+                quantization.bits,
+                max_error.exp(),
+                max_error,
+            );
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone, Debug, StructOpt)]
 struct SearchOpt {
-    /// The dictionary to search.
-    dict_path: PathBuf,
+    #[structopt(flatten)]
+    dict: DictOpt,
+    #[structopt(flatten)]
+    filter: FilterOpt,
     /// A regex describing the word (automatically anchored on both ends).
     regex: String,
+    /// Show the frequency rank of each word.
+    #[structopt(long)]
+    rank: bool,
+    /// Stop after finding this many matches.
+    #[structopt(long)]
+    limit: Option<usize>,
+    /// With `--limit`, show the most probable matches instead of the first
+    /// matches in alphabetical order.
+    #[structopt(long, requires = "limit")]
+    most_probable: bool,
+    /// Show the text matched by each capture group in the regex.
+    #[structopt(long)]
+    captures: bool,
+    /// Only show words and phrases with this crossword-style enumeration,
+    /// such as "5,3" for a five-letter word followed by a three-letter word.
+    #[structopt(long = "enum")]
+    enumeration: Option<Enumeration>,
+    /// Extra character classes for the regex, one per line, such as
+    /// "h aeiouy" to make "\h" match a vowel or "y". "\v" (vowel) and "\c"
+    /// (consonant) are always available.
+    #[structopt(long)]
+    classes_file: Option<PathBuf>,
+    /// Don't read or write cached results.
+    #[structopt(long)]
+    no_cache: bool,
+}
+
+#[derive(Debug, StructOpt)]
+struct ContainsOpt {
+    #[structopt(flatten)]
+    dict: DictOpt,
+    #[structopt(flatten)]
+    filter: FilterOpt,
+    /// The letters which must appear together somewhere in each word.
+    substring: String,
+    /// The maximum number of matches to show.
+    #[structopt(long)]
+    limit: Option<usize>,
+}
+
+#[derive(Debug, StructOpt)]
+struct LookupOpt {
+    #[structopt(flatten)]
+    dict: DictOpt,
+    /// The words to look up.
+    words: Vec<String>,
+    /// Estimate probabilities for unknown words, using "add-k:K" (such as
+    /// "add-k:0.5") or "good-turing" smoothing.
+    #[structopt(long)]
+    smoothing: Option<Smoothing>,
+}
+
+#[derive(Debug, StructOpt)]
+struct CheckOpt {
+    #[structopt(flatten)]
+    dict: DictOpt,
+    #[structopt(flatten)]
+    filter: FilterOpt,
+    /// The words to check. By default, we read one word per line from
+    /// standard input.
+    words: Vec<String>,
+    /// The maximum number of edits between a missing word and a suggestion.
+    #[structopt(long, default_value = "2")]
+    max_distance: u32,
+    /// The maximum number of suggestions to show for each missing word.
+    #[structopt(long, default_value = "3")]
+    suggestions: usize,
 }
 
 #[derive(Debug, StructOpt)]
 struct PermuteOpt {
-    /// The dictionary to search.
-    dict_path: PathBuf,
+    #[structopt(flatten)]
+    dict: DictOpt,
+    #[structopt(flatten)]
+    filter: FilterOpt,
     /// Letters or fragments to permute. You can use "." as a placeholder for
     /// unknown letters.
     fragments: Vec<String>,
+    /// Use exactly this many of the fragments, ignoring the rest.
+    #[structopt(long, conflicts_with = "allow-unused")]
+    choose: Option<usize>,
+    /// Allow results which use only some of the fragments, for puzzles with
+    /// decoy fragments.
+    #[structopt(long)]
+    allow_unused: bool,
+    /// Treat results which use the same words in a different order as
+    /// duplicates, and only show the most probable order.
+    #[structopt(long)]
+    dedup_unordered: bool,
+    /// Only break words between fragments, so that each word is made of
+    /// one or more whole fragments. This is much faster.
+    #[structopt(long)]
+    fragments_are_words: bool,
+    /// Show the probability of each word in each result, and the order of
+    /// fragments which produced it.
+    #[structopt(long)]
+    explain: bool,
+    /// Print the results as JSON, including the probability of each word and
+    /// the order of fragments which produced it.
+    #[structopt(long, conflicts_with = "explain")]
+    json: bool,
+    /// Only show breakings with this crossword-style enumeration, such as
+    /// "5,3" for a five-letter word followed by a three-letter word.
+    #[structopt(long = "enum")]
+    enumeration: Option<Enumeration>,
+    /// Don't read or write cached results.
+    #[structopt(long)]
+    no_cache: bool,
+    /// Write the tree of fragment orders we tried to this file, as a
+    /// Graphviz graph, showing which orders didn't make words. This skips
+    /// the cache.
+    #[structopt(long)]
+    trace_graph: Option<PathBuf>,
+    #[structopt(flatten)]
+    repeats: RepeatOpt,
+    #[structopt(flatten)]
+    scores: ScoreOpt,
+    #[structopt(flatten)]
+    limits: ResultLimitOpt,
+}
+
+#[derive(Debug, StructOpt)]
+struct SegmentOpt {
+    #[structopt(flatten)]
+    dict: DictOpt,
+    #[structopt(flatten)]
+    filter: FilterOpt,
+    /// The text to break into words. You can use "." as a placeholder for
+    /// unknown letters.
+    text: String,
+    /// The minimum length of each word.
+    #[structopt(long, default_value = "1")]
+    min_word_len: usize,
+    /// The maximum number of words.
+    #[structopt(long)]
+    max_words: Option<usize>,
+    /// Only show breakings with this crossword-style enumeration, such as
+    /// "5,3" for a five-letter word followed by a three-letter word.
+    #[structopt(long = "enum")]
+    enumeration: Option<Enumeration>,
+    /// The number of segmentations to show.
+    #[structopt(long, default_value = "20")]
+    limit: usize,
+    /// Show the probability of each word in each segmentation.
+    #[structopt(long, conflicts_with = "lattice")]
+    explain: bool,
+    /// Print the lattice of every possible word, plus the best
+    /// segmentations, as "json" or "dot" (for Graphviz).
+    #[structopt(long, possible_values = &["json", "dot"])]
+    lattice: Option<String>,
+    /// Write the lattice of every possible word to this file, as a Graphviz
+    /// graph, showing which words can't be part of any segmentation.
+    #[structopt(long)]
+    trace_graph: Option<PathBuf>,
+    #[structopt(flatten)]
+    repeats: RepeatOpt,
+    #[structopt(flatten)]
+    scores: ScoreOpt,
+    #[structopt(flatten)]
+    limits: ResultLimitOpt,
+}
+
+#[derive(Debug, StructOpt)]
+struct ExtractOpt {
+    #[structopt(flatten)]
+    dict: DictOpt,
+    #[structopt(flatten)]
+    filter: FilterOpt,
+    /// The answers, in order.
+    #[structopt(required = true)]
+    answers: Vec<String>,
+    /// Also take the letter at each of these 1-based positions, one for each
+    /// answer, such as "3,1,4".
+    #[structopt(long, use_delimiter = true)]
+    indices: Vec<usize>,
+}
+
+#[derive(Debug, StructOpt)]
+struct EncodedOpt {
+    #[structopt(flatten)]
+    dict: DictOpt,
+    #[structopt(flatten)]
+    filter: FilterOpt,
+    /// The encoded text. Whitespace is ignored.
+    encoded: String,
+    /// The maximum number of words.
+    #[structopt(long)]
+    max_words: Option<usize>,
+    /// The number of readings to show.
+    #[structopt(long, default_value = "20")]
+    limit: usize,
+    #[structopt(flatten)]
+    repeats: RepeatOpt,
+}
+
+#[derive(Debug, StructOpt)]
+struct ShiftPairsOpt {
+    #[structopt(flatten)]
+    dict: DictOpt,
+    #[structopt(flatten)]
+    filter: FilterOpt,
+    /// Only show pairs related by this shift, from 1 to 25.
+    #[structopt(long)]
+    shift: Option<u8>,
+    /// Only consider words at least this long.
+    #[structopt(long, default_value = "3")]
+    min_length: usize,
+    /// The maximum number of pairs to show for each shift.
+    #[structopt(long)]
+    limit: Option<usize>,
+}
+
+#[derive(Debug, StructOpt)]
+struct DecodeOpt {
+    #[structopt(flatten)]
+    dict: DictOpt,
+    #[structopt(flatten)]
+    filter: FilterOpt,
+    /// The table of tokens: "braille" (like "125" or "⠓"), "semaphore" (like
+    /// "S-SW"), "nato" (like "hotel"), "a1z26" (like "8"), or the path of a
+    /// file with one "token letter" pair per line.
+    #[structopt(long, short = "t")]
+    table: String,
+    /// The tokens to decode. Tokens we don't recognize are treated as unknown
+    /// letters.
+    #[structopt(required = true)]
+    tokens: Vec<String>,
+    /// The maximum number of words.
+    #[structopt(long)]
+    max_words: Option<usize>,
+    /// The number of readings to show.
+    #[structopt(long, default_value = "20")]
+    limit: usize,
+    #[structopt(flatten)]
+    repeats: RepeatOpt,
+}
+
+#[derive(Debug, StructOpt)]
+struct CompoundsOpt {
+    #[structopt(flatten)]
+    dict: DictOpt,
+    #[structopt(flatten)]
+    filter: FilterOpt,
+    /// The minimum length of each part.
+    #[structopt(long, default_value = "3")]
+    min_part_len: usize,
+    /// The maximum number of parts.
+    #[structopt(long, default_value = "2")]
+    max_parts: usize,
+}
+
+#[derive(Debug, StructOpt)]
+struct TransposalsOpt {
+    #[structopt(flatten)]
+    dict: DictOpt,
+    #[structopt(flatten)]
+    filter: FilterOpt,
+    /// Only consider words of exactly this length.
+    #[structopt(long)]
+    length: Option<usize>,
+    /// Only consider words at least this long.
+    #[structopt(long)]
+    min_length: Option<usize>,
+    /// Only consider words with at least this probability (between 0.0 and
+    /// 1.0).
+    #[structopt(long)]
+    min_prob: Option<f64>,
+}
+
+#[derive(Debug, StructOpt)]
+struct FuzzyOpt {
+    #[structopt(flatten)]
+    dict: DictOpt,
+    #[structopt(flatten)]
+    filter: FilterOpt,
+    /// The word to search near.
+    word: String,
+    /// The maximum number of insertions, deletions and substitutions.
+    #[structopt(long, default_value = "1")]
+    max_distance: u32,
+    /// The maximum number of matches to show.
+    #[structopt(long)]
+    limit: Option<usize>,
+    /// Treat the word as a typo, and rank matches by how likely they are to
+    /// be mistyped that way on a QWERTY keyboard.
+    #[structopt(long)]
+    typos: bool,
+}
+
+#[derive(Debug, StructOpt)]
+struct RhymesOpt {
+    #[structopt(flatten)]
+    dict: DictOpt,
+    #[structopt(flatten)]
+    filter: FilterOpt,
+    /// The word to rhyme with.
+    word: String,
+    /// Compare pronunciations instead of spellings. Requires a dictionary
+    /// built with `--pronunciations`.
+    #[structopt(long, conflicts_with = "suffix-len")]
+    phonetic: bool,
+    /// The number of letters at the end of each word which must match. By
+    /// default, we guess based on the vowels in the word.
+    #[structopt(long)]
+    suffix_len: Option<usize>,
+}
+
+#[derive(Debug, StructOpt)]
+struct HomophonesOpt {
+    #[structopt(flatten)]
+    dict: DictOpt,
+    #[structopt(flatten)]
+    filter: FilterOpt,
+    /// The word to find homophones of. By default, we list every pair of
+    /// homophones in the dictionary.
+    word: Option<String>,
+    /// The maximum number of words or pairs to show.
+    #[structopt(long)]
+    limit: Option<usize>,
+}
+
+#[derive(Debug, StructOpt)]
+struct ChainOpt {
+    #[structopt(flatten)]
+    dict: DictOpt,
+    #[structopt(flatten)]
+    filter: FilterOpt,
+    /// The first word in the chain.
+    from: String,
+    /// The last word in the chain.
+    to: String,
+    /// The number of letters shared by neighboring words.
+    #[structopt(long, short = "k", default_value = "2")]
+    overlap: usize,
+    /// The maximum number of words in a chain, including the first and last.
+    #[structopt(long, default_value = "6")]
+    max_words: usize,
+    /// The maximum number of words to consider after each word.
+    #[structopt(long, default_value = "100")]
+    max_candidates: usize,
+    /// The maximum number of chains to show.
+    #[structopt(long, default_value = "10")]
+    limit: usize,
+}
+
+#[derive(Debug, StructOpt)]
+struct ExtremesOpt {
+    #[structopt(flatten)]
+    dict: DictOpt,
+    #[structopt(flatten)]
+    filter: FilterOpt,
+    /// Only consider words matching this regex (automatically anchored on both
+    /// ends).
+    #[structopt(long)]
+    pattern: Option<String>,
+    /// Only consider words which can be spelled with these letters, using
+    /// each letter at most once.
+    #[structopt(long)]
+    bank: Option<String>,
+    /// Only consider words which alternate between vowels and consonants.
+    #[structopt(long)]
+    alternating: bool,
+    /// Show the shortest words instead of the longest.
+    #[structopt(long)]
+    shortest: bool,
+    /// The maximum number of words to show.
+    #[structopt(long, default_value = "20")]
+    limit: usize,
+}
+
+#[derive(Debug, StructOpt)]
+struct PangramsOpt {
+    #[structopt(flatten)]
+    dict: DictOpt,
+    #[structopt(flatten)]
+    filter: FilterOpt,
+    /// The letters to use.
+    #[structopt(default_value = "abcdefghijklmnopqrstuvwxyz")]
+    letters: String,
+    /// Only use words spelled entirely with these letters, as in Spelling
+    /// Bee.
+    #[structopt(long)]
+    only: bool,
+    /// The maximum number of words in a set.
+    #[structopt(long, default_value = "4")]
+    max_words: usize,
+    /// The maximum number of words to try for each letter at each step.
+    #[structopt(long, default_value = "20")]
+    max_candidates: usize,
+    /// The maximum number of sets to show.
+    #[structopt(long, default_value = "10")]
+    limit: usize,
+}
+
+#[derive(Debug, StructOpt)]
+struct AnagramOpt {
+    #[structopt(flatten)]
+    dict: DictOpt,
+    #[structopt(flatten)]
+    filter: FilterOpt,
+    /// The letters to rearrange. Use "." or "?" for blanks which can stand
+    /// for any letter.
+    letters: String,
+    /// Find phrases of up to this many words, not counting `--include`
+    /// words.
+    #[structopt(long, default_value = "1")]
+    words: usize,
+    /// A word which every phrase must contain, such as a theme word. May be
+    /// given more than once.
+    #[structopt(long = "include")]
+    include: Vec<String>,
+    /// A file of words which phrases may not contain, one per line.
+    #[structopt(long = "exclude-words")]
+    exclude_path: Option<PathBuf>,
+    /// Only use words at least this long in phrases.
+    #[structopt(long, default_value = "2")]
+    min_length: usize,
+    /// The maximum number of phrases to show.
+    #[structopt(long, default_value = "100")]
+    limit: usize,
+    #[structopt(flatten)]
+    checkpoint: CheckpointOpt,
+}
+
+#[derive(Debug, StructOpt)]
+struct RackOpt {
+    #[structopt(flatten)]
+    dict: DictOpt,
+    #[structopt(flatten)]
+    filter: FilterOpt,
+    /// The tiles in the rack. Each tile may be used once. Use "." or "?" for
+    /// blanks which can stand for any letter.
+    tiles: String,
+    /// Only show words at least this long.
+    #[structopt(long, default_value = "2")]
+    min_length: usize,
+    /// The maximum number of words to show.
+    #[structopt(long)]
+    limit: Option<usize>,
+}
+
+#[derive(Debug, StructOpt)]
+struct ScrabbleOpt {
+    #[structopt(flatten)]
+    dict: DictOpt,
+    #[structopt(flatten)]
+    filter: FilterOpt,
+    /// A board with one row per line, using "." for empty squares, lowercase
+    /// letters for tiles, and uppercase letters for blanks. 15×15 boards use
+    /// the standard premium squares.
+    board_path: PathBuf,
+    /// The tiles in the rack. Use "." or "?" for blanks.
+    tiles: String,
+    /// The maximum number of moves to show.
+    #[structopt(long, default_value = "20")]
+    limit: usize,
+}
+
+#[derive(Debug, StructOpt)]
+struct BuilderOpt {
+    #[structopt(flatten)]
+    dict: DictOpt,
+    #[structopt(flatten)]
+    filter: FilterOpt,
+    /// The word whose letters we build words from.
+    seed: String,
+    /// Only show words at least this long.
+    #[structopt(long, default_value = "3")]
+    min_length: usize,
+    /// The maximum number of words to show of each length.
+    #[structopt(long)]
+    limit: Option<usize>,
+}
+
+#[derive(Debug, StructOpt)]
+struct BankOpt {
+    #[structopt(flatten)]
+    dict: DictOpt,
+    #[structopt(flatten)]
+    filter: FilterOpt,
+    /// The letters in the bank. Each letter may be used any number of times.
+    letters: String,
+    /// Only show words at least this long.
+    #[structopt(long, default_value = "4")]
+    min_length: usize,
+    /// The maximum number of words to show.
+    #[structopt(long)]
+    limit: Option<usize>,
+}
+
+#[derive(Debug, StructOpt)]
+struct FillOpt {
+    #[structopt(flatten)]
+    dict: DictOpt,
+    #[structopt(flatten)]
+    filter: FilterOpt,
+    /// A grid with one row per line, using "." for open cells, "#" for blocks
+    /// and letters for cells which are already filled in, or an Across Lite
+    /// .puz or .ipuz file, whose filled-in letters are kept.
+    grid_path: PathBuf,
+    /// Write the filled grid to this file, as well as showing it. Files
+    /// ending in .puz or .ipuz get the clues from the input, if any, and are
+    /// ready to be solved.
+    #[structopt(short = "o", long)]
+    output: Option<PathBuf>,
+    /// The maximum number of candidate words to consider for each slot.
+    #[structopt(long, default_value = "10000")]
+    max_candidates: usize,
+    /// Allow the same answer to appear more than once in the grid.
+    #[structopt(long)]
+    allow_duplicates: bool,
+    /// Don't allow any answer to contain another, like "stone" and
+    /// "milestone".
+    #[structopt(long)]
+    no_substrings: bool,
+    /// Give up and start again with a shuffled search order up to this many
+    /// times, which helps with large grids where the search gets stuck.
+    #[structopt(long, default_value = "0")]
+    restarts: usize,
+    /// How many times the search may backtrack before its first restart.
+    /// This doubles with each restart.
+    #[structopt(long, default_value = "1000")]
+    backtrack_limit: u64,
+    /// The seed for shuffling the search order on restarts, to reproduce an
+    /// earlier run. Chosen at random and logged by default.
+    #[structopt(long)]
+    seed: Option<u64>,
+    /// Run this many searches in parallel, each trying words in a different
+    /// order, and use the first fill any of them finds.
+    #[structopt(long, default_value = "1")]
+    threads: usize,
+    /// Keep looking for more probable fills for this many seconds, and show
+    /// the best one found.
+    #[structopt(long)]
+    time_budget: Option<u64>,
+    #[structopt(flatten)]
+    checkpoint: CheckpointOpt,
+    /// Write the tree of words the search tried to this file, as a Graphviz
+    /// graph, showing which words were pruned because they left another slot
+    /// with nothing that fits.
+    #[structopt(long)]
+    trace_graph: Option<PathBuf>,
+}
+
+#[derive(Debug, StructOpt)]
+struct WordFitOpt {
+    /// A grid with one row per line, using "." for open cells, "#" for blocks
+    /// and letters for cells which are given, or an Across Lite .puz or
+    /// .ipuz file.
+    grid_path: PathBuf,
+    /// The words to place, one per line, with one word for each slot.
+    bank_path: PathBuf,
+    /// Write the solution to this file, as well as showing it.
+    #[structopt(short = "o", long)]
+    output: Option<PathBuf>,
+    /// Show up to this many solutions. The default is enough to tell whether
+    /// the solution is unique.
+    #[structopt(long, default_value = "2")]
+    max_solutions: usize,
+}
+
+#[derive(Debug, StructOpt)]
+struct KrissKrossOpt {
+    #[structopt(flatten)]
+    dict: DictOpt,
+    #[structopt(flatten)]
+    filter: FilterOpt,
+    /// A grid with one row per line, using "." for open cells, "#" for blocks
+    /// and letters for cells which are given, or an Across Lite .puz or
+    /// .ipuz file.
+    grid_path: PathBuf,
+    /// Write the solution to this file, as well as showing it.
+    #[structopt(short = "o", long)]
+    output: Option<PathBuf>,
+    /// The maximum number of candidate words to consider for each slot.
+    #[structopt(long, default_value = "1000")]
+    max_candidates: usize,
+    /// How many random banks to try before giving up.
+    #[structopt(long, default_value = "100")]
+    attempts: usize,
+    /// The seed for choosing words, to reproduce an earlier run. Chosen at
+    /// random and logged by default.
+    #[structopt(long)]
+    seed: Option<u64>,
+}
+
+#[derive(Debug, StructOpt)]
+struct ScoreFillOpt {
+    #[structopt(flatten)]
+    dict: DictOpt,
+    #[structopt(flatten)]
+    filter: FilterOpt,
+    /// A completed grid with one row per line, using "#" for blocks and
+    /// letters for every other cell, or a .puz or .ipuz file, whose solution
+    /// is scored.
+    grid_path: PathBuf,
+    /// Count entries less probable than this, such as "1e-6", as obscure.
+    #[structopt(long, default_value = "1e-6")]
+    obscure_below: f64,
+    /// Flag pairs of entries which share a run of at least this many
+    /// letters, like "stone" and "milestone".
+    #[structopt(long, default_value = "4")]
+    min_shared_letters: usize,
+    /// The penalty, in nats, for each entry which isn't in the dictionary
+    /// and each pair of entries which share letters.
+    #[structopt(long, default_value = "10")]
+    penalty: f64,
+}
+
+#[derive(Debug, StructOpt)]
+struct CluesOpt {
+    #[structopt(flatten)]
+    dict: DictOpt,
+    #[structopt(flatten)]
+    filter: FilterOpt,
+    /// A regex describing the answer (automatically anchored on both ends).
+    regex: String,
+    /// The maximum number of clues to show for each answer.
+    #[structopt(long, default_value = "5")]
+    max_clues: usize,
+    /// Only show answers which have clues.
+    #[structopt(long)]
+    only_clued: bool,
+}
+
+#[derive(Debug, StructOpt)]
+struct AcrosticOpt {
+    #[structopt(flatten)]
+    dict: DictOpt,
+    #[structopt(flatten)]
+    filter: FilterOpt,
+    /// A file with one line per answer, containing the position in the quote
+    /// of each letter of that answer, counting from 1.
+    numbering_path: PathBuf,
+    /// The first letter of each answer, in order.
+    initials: String,
+    /// The letters of the quote, in any order.
+    letters: String,
+    /// The maximum number of candidate words to consider for each answer.
+    #[structopt(long, default_value = "1000")]
+    max_candidates: usize,
+    /// The maximum number of solutions to show.
+    #[structopt(long, default_value = "10")]
+    limit: usize,
+}
+
+#[derive(Debug, StructOpt)]
+struct DropQuoteOpt {
+    #[structopt(flatten)]
+    dict: DictOpt,
+    #[structopt(flatten)]
+    filter: FilterOpt,
+    /// A grid with one row per line, using "." for open cells and "#" for
+    /// blocks. Words wrap from the end of one row to the start of the next.
+    grid_path: PathBuf,
+    /// The letters in each column, from left to right. Use "-" for a column
+    /// without any letters.
+    #[structopt(required = true)]
+    banks: Vec<String>,
+    /// The maximum number of candidate words to consider for each slot.
+    #[structopt(long, default_value = "1000")]
+    max_candidates: usize,
+}
+
+#[derive(Debug, StructOpt)]
+struct LetterBoxedOpt {
+    #[structopt(flatten)]
+    dict: DictOpt,
+    #[structopt(flatten)]
+    filter: FilterOpt,
+    /// The letters on each side of the box, such as "abc def ghi jkl".
+    sides: Vec<String>,
+    /// The maximum number of two-word solutions to show.
+    #[structopt(long, default_value = "20")]
+    limit: usize,
+}
+
+#[derive(Debug, StructOpt)]
+struct WordleOpt {
+    #[structopt(flatten)]
+    dict: DictOpt,
+    #[structopt(flatten)]
+    filter: FilterOpt,
+    /// The guesses played on a board so far, as "guess:marks" pairs separated
+    /// by commas, where marks are "g" for green, "y" for yellow and "." for
+    /// gray. Pass once per board for Quordle and friends.
+    #[structopt(long = "board")]
+    boards: Vec<Board>,
+    /// The number of boards, if no guesses have been played yet.
+    #[structopt(long, default_value = "1")]
+    board_count: usize,
+    /// The length of the target words.
+    #[structopt(long, default_value = "5")]
+    length: usize,
+    /// How many of the most common words to consider as guesses, in addition
+    /// to the remaining candidates.
+    #[structopt(long, default_value = "2000")]
+    guess_pool: usize,
+    /// The number of candidates and suggestions to show.
+    #[structopt(long, default_value = "10")]
+    limit: usize,
+}
+
+#[derive(Debug, StructOpt)]
+struct ExportStatsOpt {
+    #[structopt(flatten)]
+    dict: DictOpt,
+    #[structopt(flatten)]
+    filter: FilterOpt,
+    /// The output format.
+    #[structopt(long, default_value = "csv", possible_values = &["csv", "json"])]
+    format: String,
+    /// The table to export. CSV output has one table, which defaults to
+    /// "rank-frequency". JSON output includes every table by default.
+    #[structopt(long, possible_values = &["rank-frequency", "lengths", "letters"])]
+    table: Option<String>,
+    /// Only include this many of the most probable words in the
+    /// rank-frequency table.
+    #[structopt(long)]
+    top: Option<usize>,
+}
+
+#[derive(Debug, StructOpt)]
+struct DictCheckOpt {
+    #[structopt(flatten)]
+    dict: DictOpt,
+    #[structopt(flatten)]
+    filter: FilterOpt,
+    /// A list of common words, one per line, which the dictionary should
+    /// contain. Blank lines and lines starting with "#" are ignored.
+    #[structopt(long = "reference")]
+    reference_path: Option<PathBuf>,
+    /// The maximum number of junk words and missing words to list.
+    #[structopt(long, default_value = "20")]
+    examples: usize,
+    /// Print the report as JSON.
+    #[structopt(long)]
+    json: bool,
+}
+
+#[derive(Debug, StructOpt)]
+struct DictsOpt {
+    /// Only print the name of each dictionary, one per line.
+    #[structopt(long)]
+    names: bool,
+}
+
+#[derive(Debug, StructOpt)]
+struct CompletionsOpt {
+    /// The shell to generate completions for.
+    #[structopt(possible_values = &Shell::variants(), case_insensitive = true)]
+    shell: Shell,
+}
+
+#[derive(Debug, StructOpt)]
+enum CacheOpt {
+    /// Remove all cached results.
+    Clear,
 }
 
 fn main() -> Result<()> {
-    env_logger::init();
     let opt = Opt::from_args();
+    logging::init(opt.verbosity, opt.log_format);
     debug!("options: {:?}", opt);
+    let out = &mut Renderer::new(opt.color, opt.prob_format);
 
     match &opt.cmd {
         Command::MakeDictionary(mkdict_opt) => make_dictionary_cmd(mkdict_opt),
-        Command::Search(search_opt) => search_cmd(search_opt),
-        Command::Permute(permute_opt) => permute_cmd(permute_opt),
+        Command::DictOps(dictops_opt) => dictops_cmd(dictops_opt),
+        Command::InstallDict(install_opt) => install_dict_cmd(install_opt),
+        Command::Search(search_opt) => search_cmd(search_opt, out),
+        Command::Contains(contains_opt) => contains_cmd(contains_opt, out),
+        Command::Lookup(lookup_opt) => lookup_cmd(lookup_opt, out),
+        Command::Check(check_opt) => check_cmd(check_opt, out),
+        Command::Permute(permute_opt) => permute_cmd(permute_opt, out),
+        Command::Segment(segment_opt) => segment_cmd(segment_opt, out),
+        Command::Extract(extract_opt) => extract_cmd(extract_opt, out),
+        Command::Morse(morse_opt) => decode_cmd(morse_opt, &MORSE, out),
+        Command::T9(t9_opt) => decode_cmd(t9_opt, &T9, out),
+        Command::ShiftPairs(shiftpairs_opt) => shiftpairs_cmd(shiftpairs_opt, out),
+        Command::Decode(decode_opt) => decode_tokens_cmd(decode_opt, out),
+        Command::Compounds(compounds_opt) => compounds_cmd(compounds_opt, out),
+        Command::Transposals(transposals_opt) => transposals_cmd(transposals_opt, out),
+        Command::Fuzzy(fuzzy_opt) => fuzzy_cmd(fuzzy_opt, out),
+        Command::Rhymes(rhymes_opt) => rhymes_cmd(rhymes_opt, out),
+        Command::Homophones(homophones_opt) => homophones_cmd(homophones_opt, out),
+        Command::Chain(chain_opt) => chain_cmd(chain_opt, out),
+        Command::Extremes(extremes_opt) => extremes_cmd(extremes_opt, out),
+        Command::Pangrams(pangrams_opt) => pangrams_cmd(pangrams_opt, out),
+        Command::Anagram(anagram_opt) => anagram_cmd(anagram_opt, out),
+        Command::Rack(rack_opt) => rack_cmd(rack_opt, out),
+        Command::Scrabble(scrabble_opt) => scrabble_cmd(scrabble_opt, out),
+        Command::Builder(builder_opt) => builder_cmd(builder_opt, out),
+        Command::Bank(bank_opt) => bank_cmd(bank_opt, out),
+        Command::Fill(fill_opt) => fill_cmd(fill_opt, out),
+        Command::ScoreFill(score_fill_opt) => score_fill_cmd(score_fill_opt, out),
+        Command::WordFit(wordfit_opt) => wordfit_cmd(wordfit_opt, out),
+        Command::KrissKross(krisskross_opt) => krisskross_cmd(krisskross_opt, out),
+        Command::Clues(clues_opt) => clues_cmd(clues_opt, out),
+        Command::Acrostic(acrostic_opt) => acrostic_cmd(acrostic_opt, out),
+        Command::DropQuote(dropquote_opt) => dropquote_cmd(dropquote_opt, out),
+        Command::LetterBoxed(letterboxed_opt) => letterboxed_cmd(letterboxed_opt, out),
+        Command::Wordle(wordle_opt) => wordle_cmd(wordle_opt, out),
+        Command::ExportStats(export_opt) => export_stats_cmd(export_opt, out),
+        Command::DictCheck(dictcheck_opt) => dictcheck_cmd(dictcheck_opt, out),
+        Command::Dicts(dicts_opt) => dicts_cmd(dicts_opt),
+        Command::Cache(cache_opt) => cache_cmd(cache_opt),
+        Command::Completions(completions_opt) => completions_cmd(completions_opt),
     }
 }
 
 fn make_dictionary_cmd(opt: &MakeDictionaryOpt) -> Result<()> {
-    Dictionary::build(&opt.in_words_path, &opt.out_dict_path)?;
-    Ok(())
-}
-
-fn search_cmd(opt: &SearchOpt) -> Result<()> {
-    let dict = Dictionary::load(&opt.dict_path)?;
-    let matches = dict.find_matches(&opt.regex)?;
-    print!("{}", matches);
-    Ok(())
+    opt.build
+        .build(&opt.in_words_path, &opt.out_dict_path, opt.force)
 }
 
-fn permute_cmd(opt: &PermuteOpt) -> Result<()> {
-    let dict = Dictionary::load(&opt.dict_path)?;
-    let mut matches = vec![];
-    let mut seen_candidates = BTreeSet::new();
-    for permutation in opt
-        .fragments
+fn dictops_cmd(opt: &DictOpsOpt) -> Result<()> {
+    let config = Config::load_default()?;
+    let registry = Registry::open_default()?;
+    let dicts = opt
+        .inputs
         .iter()
-        .map(|s| &s[..])
-        .permutations(opt.fragments.len())
-    {
-        let candidate = permutation.concat();
-        if seen_candidates.contains(&candidate) {
-            continue;
+        .map(|name| {
+            let profile = config.profile(Some(name), &registry)?;
+            opt.filter.with_defaults(&profile).load_profile(&profile)
+        })
+        .collect::<Result<Vec<_>>>()?;
+    // Check this before doing all the work of combining the dictionaries.
+    if !opt.force && opt.output.exists() {
+        return Err(Error::DictionaryExists {
+            path: opt.output.clone(),
         }
-        trace!("candidate: {}", candidate);
-        seen_candidates.insert(candidate.clone());
-        let mut so_far = vec![];
-        break_into_words(&dict, &mut so_far, &candidate, &mut matches)?;
+        .into());
     }
-    let mut matches = Dist::from_vec(matches);
-    matches.sort_by_probability();
-    print!("{}", matches);
-    Ok(())
+
+    // Write the combined word list next to the output, and build it the same
+    // way as any other word list.
+    let file_name = opt
+        .output
+        .file_name()
+        .ok_or_else(|| format_err!("{} is not a file name", opt.output.display()))?;
+    let words_path = opt.output.with_file_name(format!(
+        ".{}.words.tmp{}",
+        file_name.to_string_lossy(),
+        process::id()
+    ));
+    let result = write_combined(&dicts, opt.operation, opt.combine, &words_path)
+        .map_err(anyhow::Error::from)
+        .and_then(|count| {
+            opt.build.build(&words_path, &opt.output, opt.force)?;
+            println!("Combined {} words", count);
+            Ok(())
+        });
+    let _ = fs::remove_file(&words_path);
+    result
 }
 
-fn break_into_words(
-    dict: &Dictionary,
-    so_far: &mut Vec<(Prob, String)>,
-    remaining_pattern: &str,
-    matches: &mut Vec<(Prob, String)>,
-) -> Result<()> {
-    if remaining_pattern.len() == 0 {
-        let mut prob = Prob::always();
-        let mut words = String::new();
-        for (p, w) in so_far {
-            prob = prob * *p;
-            if words.len() > 0 {
-                words.push(' ');
-            }
-            words.push_str(w);
-        }
-        debug!("Found {} {}", prob, words);
-        matches.push((prob, words));
+fn install_dict_cmd(opt: &InstallDictOpt) -> Result<()> {
+    let mut registry = Registry::open_default()?;
+    let wordlist_path = registry.wordlist_path(&opt.name)?;
+    let dict_path = registry.dict_path(&opt.name)?;
+    fetch(&opt.source, &wordlist_path)?;
+    if let Some(dir) = dict_path.parent() {
+        fs::create_dir_all(dir)
+            .with_context(|| format!("could not create {}", dir.display()))?;
+    }
+    // Installing a dictionary again replaces it.
+    opt.build.build(&wordlist_path, &dict_path, true)?;
+    registry.add(&opt.name, &opt.source, !opt.keep_default)?;
+    if registry.default_name() == Some(opt.name.as_str()) {
+        println!("Installed {} as the default dictionary", opt.name);
     } else {
-        for i in (1..=remaining_pattern.len()).rev() {
-            let word_pat = &remaining_pattern[..i];
-            let rest = &remaining_pattern[i..];
+        println!("Installed {}", opt.name);
+    }
+    Ok(())
+}
+
+fn search_cmd(opt: &SearchOpt, out: &mut Renderer) -> Result<()> {
+    let profile = opt.dict.profile()?;
+    let filter = opt.filter.with_defaults(&profile);
+    let mut dict = filter.load_profile(&profile)?;
+    if let Some(enumeration) = &opt.enumeration {
+        let mut word_filter = filter.word_filter()?;
+        word_filter.predicates.push(enumeration.clone());
+        dict.set_filter(word_filter);
+    }
+    let classes = match &opt.classes_file {
+        Some(classes_file) => CharClasses::with_file(classes_file)?,
+        None => CharClasses::default(),
+    };
+    dict.set_char_classes(classes.clone());
+    let search_opts = SearchOptions {
+        limit: opt.limit,
+        most_probable: opt.most_probable,
+    };
+    // Everything but the file paths affects our output.
+    let query = format!(
+        "{:?} {:?} {:?} {:?}",
+        SearchOpt {
+            dict: DictOpt::default(),
+            filter: filter.without_paths(),
+            classes_file: None,
+            no_cache: false,
+            ..opt.clone()
+        },
+        classes,
+        dict.filter(),
+        dict.scorers(),
+    );
+    print_cached(out, &dict, &query, opt.no_cache, Layout::Annotated, || {
+        if !opt.rank && !opt.captures {
+            return Ok(dict
+                .find_matches_with(&opt.regex, &search_opts)?
+                .to_string());
+        }
+        let matches = if opt.captures {
+            dict.find_captures_with(&opt.regex, &search_opts)?
+        } else {
+            dict.find_matches_with(&opt.regex, &search_opts)?
+                .into_vec()
+                .into_iter()
+                .map(|(prob, word)| Captures {
+                    prob,
+                    word,
+                    groups: vec![],
+                })
+                .collect()
+        };
+        let mut text = String::new();
+        for m in matches {
+            write!(text, "{:6.2} {}", m.prob, m.word)?;
+            for group in &m.groups {
+                write!(text, " {}", group.as_deref().unwrap_or("-"))?;
+            }
+            if opt.rank {
+                if let Some(rank) = dict.rank_of(&m.word)? {
+                    write!(text, " ({})", rank)?;
+                }
+            }
+            writeln!(text)?;
+        }
+        Ok(text)
+    })
+}
+
+fn contains_cmd(opt: &ContainsOpt, out: &mut Renderer) -> Result<()> {
+    let dict = opt.filter.load_dictionary(&opt.dict)?;
+    let substring = opt.substring.to_ascii_lowercase();
+    let mut matches = dict.find_containing(&substring)?;
+    if let Some(limit) = opt.limit {
+        matches.truncate(limit);
+    }
+    out.dist_highlighting(&matches, |word| occurrences(word, &substring))
+}
 
-            let word_matches = dict.find_matches(&word_pat)?;
-            for (p, w) in &word_matches {
-                so_far.push((p, w.to_owned()));
-                trace!("Trying {:?}", so_far);
-                break_into_words(dict, so_far, rest, matches)?;
-                so_far.pop();
+fn lookup_cmd(opt: &LookupOpt, out: &mut Renderer) -> Result<()> {
+    let profile = opt.dict.profile()?;
+    let dict = profile.load()?;
+    let model = opt
+        .smoothing
+        .map(|smoothing| SmoothedModel::new(&dict, smoothing))
+        .transpose()?;
+    let mut text = String::new();
+    for word in &opt.words {
+        let word = word.to_ascii_lowercase();
+        let prob = match &model {
+            Some(model) => model.word_probability(&word),
+            None => dict.probability_of(&word),
+        };
+        match (prob, dict.rank_of(&word)?) {
+            (Some(prob), Some(rank)) => {
+                writeln!(text, "{:6.2} {} ({})", prob, word, rank)?
             }
+            (Some(prob), None) => {
+                writeln!(text, "{:6.2} {} (not in dictionary)", prob, word)?
+            }
+            (None, _) => writeln!(text, "{:>6} {} (not in dictionary)", "-", word)?,
         }
     }
-    Ok(())
+    out.text(&text, Layout::Annotated)
+}
+
+fn check_cmd(opt: &CheckOpt, out: &mut Renderer) -> Result<()> {
+    let dict = opt.filter.load_dictionary(&opt.dict)?;
+    let words = if opt.words.is_empty() {
+        io::stdin()
+            .lock()
+            .lines()
+            .collect::<io::Result<Vec<_>>>()
+            .context("could not read words from standard input")?
+    } else {
+        opt.words.clone()
+    };
+    let mut text = String::new();
+    for word in &words {
+        // Normalize spacing, so that phrases match their dictionary entries.
+        let word = word.split_whitespace().join(" ").to_lowercase();
+        if word.is_empty() {
+            continue;
+        }
+        if let Some(prob) = dict.probability_of(&word) {
+            match dict.rank_of(&word)? {
+                Some(rank) => writeln!(text, "{:6.2} {} ({})", prob, word, rank)?,
+                None => writeln!(text, "{:6.2} {}", prob, word)?,
+            }
+            continue;
+        }
+        let mut near = dict.find_within_distance(&word, opt.max_distance)?;
+        near.sort_by(|m1, m2| {
+            m1.distance
+                .cmp(&m2.distance)
+                .then_with(|| m1.cmp_by_probability(m2))
+        });
+        if near.is_empty() {
+            writeln!(text, "{:>6} {} (not in dictionary)", "-", word)?;
+        } else {
+            let suggestions = near
+                .iter()
+                .take(opt.suggestions)
+                .map(|m| m.word.as_str())
+                .join(", ");
+            writeln!(
+                text,
+                "{:>6} {} (not in dictionary; did you mean {}?)",
+                "-", word, suggestions
+            )?;
+        }
+    }
+    out.text(&text, Layout::Annotated)
+}
+
+fn permute_cmd(opt: &PermuteOpt, out: &mut Renderer) -> Result<()> {
+    let profile = opt.dict.profile()?;
+    let filter = opt.filter.with_defaults(&profile);
+    let dict = filter.load_profile(&profile)?;
+    let query = format!(
+        "permute {:?} {:?} {:?} {:?} {:?} {:?} {:?} \
+         {:?} {:?} {:?} {:?} {:?} {:?} {:?}",
+        opt.fragments,
+        opt.choose,
+        opt.allow_unused,
+        opt.dedup_unordered,
+        opt.fragments_are_words,
+        opt.enumeration,
+        opt.repeats,
+        opt.scores,
+        opt.explain,
+        opt.json,
+        opt.limits,
+        filter.without_paths(),
+        dict.filter(),
+        dict.scorers(),
+    );
+    // A cached result wouldn't write the trace graph.
+    let no_cache = opt.no_cache || opt.trace_graph.is_some();
+    print_cached(out, &dict, &query, no_cache, Layout::Words, || {
+        permute(&dict, opt)
+    })
+}
+
+/// A result of `permute`, and the order of fragments which produced it.
+#[derive(Serialize)]
+struct Permutation {
+    #[serde(flatten)]
+    breaking: Breaking,
+    fragments: Vec<String>,
+}
+
+/// Find every way to permute `opt.fragments` into a sequence of words.
+fn permute(dict: &Dictionary, opt: &PermuteOpt) -> Result<String> {
+    let mut segment_opts = opt.scores.apply(
+        dict,
+        opt.repeats.apply(SegmentOptions {
+            enumeration: opt.enumeration.clone(),
+            ..SegmentOptions::default()
+        }),
+    )?;
+    // Decide how many fragments each result may use.
+    let count = opt.fragments.len();
+    let sizes = match opt.choose {
+        Some(k) if k == 0 || k > count => {
+            return Err(format_err!("cannot choose {} of {} fragments", k, count));
+        }
+        Some(k) => k..=k,
+        None if opt.allow_unused => 1..=count,
+        None => count..=count,
+    };
+    let fragments = opt.fragments.iter().map(|s| &s[..]).collect::<Vec<_>>();
+    let max_results = opt.limits.max_results(permutation_size(&opt.fragments));
+    // Different fragment orders can produce the same words when fragments
+    // contain placeholders, so we keep the most probable result for each
+    // set of words before choosing the top results.
+    let mut distinct = BTreeMap::<String, (Prob, Permutation)>::new();
+    let mut tried = 0u64;
+    let mut tree = opt
+        .trace_graph
+        .as_ref()
+        .map(|_| SearchTree::new("permute", DEFAULT_MAX_NODES));
+    // The tree nodes for the fragments of the last permutation we tried, so
+    // that permutations which start the same way share nodes.
+    let mut branch = Vec::<(String, NodeId)>::new();
+    let mut try_permutation = |permutation: &[&str]| -> Result<()> {
+        let candidate = permutation.concat();
+        trace!(
+            event = "try", candidate = candidate.as_str();
+            "candidate: {}", candidate
+        );
+        tried += 1;
+        if opt.fragments_are_words {
+            let ends = permutation.iter().scan(0, |end, fragment| {
+                *end += fragment.chars().count();
+                Some(*end)
+            });
+            segment_opts.breaks = Some(ends.collect());
+        }
+        let breakings = break_into_words(dict, &candidate, &segment_opts)?;
+        if let Some(tree) = &mut tree {
+            let shared = branch
+                .iter()
+                .zip(permutation)
+                .take_while(|((seen, _), fragment)| seen == *fragment)
+                .count();
+            branch.truncate(shared);
+            for &fragment in &permutation[shared..] {
+                let parent =
+                    branch.last().map_or(NodeId::default(), |&(_, node)| node);
+                let node = tree.add(parent, format!("{:?}", fragment), None);
+                branch.push((fragment.to_owned(), node));
+            }
+            let parent = branch.last().map_or(NodeId::default(), |&(_, node)| node);
+            if breakings.is_empty() {
+                let node = tree.add(parent, candidate.clone(), None);
+                tree.set_outcome(node, Outcome::Pruned("no words".to_owned()));
+            }
+            for breaking in &breakings {
+                let node =
+                    tree.add(parent, breaking.to_string(), Some(breaking.total));
+                tree.set_outcome(node, Outcome::Solution);
+            }
+        }
+        for breaking in breakings {
+            let key = if opt.dedup_unordered {
+                breaking.words().sorted().join(" ")
+            } else {
+                breaking.to_string()
+            };
+            if distinct
+                .get(&key)
+                .is_some_and(|&(best, _)| best >= breaking.total)
+            {
+                continue;
+            }
+            let fragments = permutation.iter().map(|f| f.to_string()).collect();
+            let prob = breaking.total;
+            distinct.insert(
+                key,
+                (
+                    prob,
+                    Permutation {
+                        breaking,
+                        fragments,
+                    },
+                ),
+            );
+        }
+        Ok(())
+    };
+    for k in sizes {
+        for_each_arrangement(&fragments, k, &mut try_permutation)?;
+    }
+    if let (Some(tree), Some(path)) = (&tree, &opt.trace_graph) {
+        tree.write(path)?;
+    }
+    let found = distinct.len();
+    let mut matches = Dist::top_k(max_results.unwrap_or(usize::MAX));
+    for (prob, permutation) in distinct.into_values() {
+        matches.push(prob, permutation);
+    }
+    info!(
+        tried = tried, found = found;
+        "tried {} permutations and found {} results",
+        tried,
+        found
+    );
+    let matches = matches.into_dist();
+    if matches.len() < found {
+        warn!(
+            "only kept the {} most probable of {} results",
+            matches.len(),
+            found
+        );
+    }
+    let matches = matches.into_vec();
+    if opt.json {
+        let permutations = matches.iter().map(|(_, p)| p).collect::<Vec<_>>();
+        return Ok(serde_json::to_string_pretty(&permutations)? + "\n");
+    }
+    let mut text = String::new();
+    for (prob, permutation) in &matches {
+        writeln!(text, "{:6.2} {}", prob, permutation.breaking)?;
+        if opt.explain {
+            explain_words(&mut text, &permutation.breaking)?;
+            let fragments = permutation
+                .fragments
+                .iter()
+                .map(|f| format!("{:?}", f))
+                .join(" + ");
+            writeln!(text, "{:6} from {}", "", fragments)?;
+        }
+    }
+    Ok(text)
+}
+
+/// A generous estimate of the memory used by each result of permuting
+/// `fragments`, allowing for `TopK` holding twice as many results as it keeps.
+fn permutation_size(fragments: &[String]) -> usize {
+    let letters = fragments.iter().map(|f| f.len()).sum::<usize>();
+    let words = letters / 2 + 1;
+    let result = mem::size_of::<(Prob, Permutation)>()
+        + mem::size_of_val(fragments)
+        + words * mem::size_of::<(Prob, String)>()
+        + 2 * letters;
+    2 * result
+}
+
+/// Call `visit` with every distinct ordered selection of `k` items from
+/// `items`. Equal items are interchangeable, so repeated fragments never
+/// produce the same arrangement twice.
+fn for_each_arrangement<'a, F>(
+    items: &[&'a str],
+    k: usize,
+    visit: &mut F,
+) -> Result<()>
+where
+    F: FnMut(&[&'a str]) -> Result<()>,
+{
+    /// Extend `chosen` with every arrangement of the items left in `counts`.
+    fn extend<'a, F>(
+        counts: &mut [(&'a str, usize)],
+        k: usize,
+        chosen: &mut Vec<&'a str>,
+        visit: &mut F,
+    ) -> Result<()>
+    where
+        F: FnMut(&[&'a str]) -> Result<()>,
+    {
+        if chosen.len() == k {
+            return visit(chosen);
+        }
+        for i in 0..counts.len() {
+            if counts[i].1 == 0 {
+                continue;
+            }
+            counts[i].1 -= 1;
+            chosen.push(counts[i].0);
+            extend(counts, k, chosen, visit)?;
+            chosen.pop();
+            counts[i].1 += 1;
+        }
+        Ok(())
+    }
+
+    // Count each distinct item, keeping them in their original order.
+    let mut counts = Vec::<(&str, usize)>::new();
+    for &item in items {
+        match counts.iter_mut().find(|(seen, _)| *seen == item) {
+            Some((_, count)) => *count += 1,
+            None => counts.push((item, 1)),
+        }
+    }
+    extend(&mut counts, k, &mut Vec::with_capacity(k), visit)
+}
+
+/// Write the probability of each word in a result, indented to line up with
+/// the result's words.
+fn explain_words(text: &mut String, breaking: &Breaking) -> Result<()> {
+    let scores = breaking
+        .words
+        .iter()
+        .map(|(prob, word)| format!("{} ({:.2})", word, prob))
+        .join(" + ");
+    writeln!(text, "{:6} = {}", "", scores)?;
+    Ok(())
+}
+
+/// Print the output of `run` as results laid out like `layout`, using a cached
+/// copy if we've already run `query` against this dictionary. Problems with
+/// the cache are only warnings.
+fn print_cached(
+    out: &mut Renderer,
+    dict: &Dictionary,
+    query: &str,
+    no_cache: bool,
+    layout: Layout,
+    run: impl FnOnce() -> Result<String>,
+) -> Result<()> {
+    let cache = if no_cache {
+        None
+    } else {
+        QueryCache::open_default()
+            .map_err(|err| warn!("not using cache: {:#}", err))
+            .ok()
+    };
+    let content_hash = dict.content_hash();
+    if let Some(cache) = &cache {
+        match cache.get(&content_hash, query) {
+            Ok(Some(text)) => {
+                debug!("using cached result for {}", query);
+                return out.text(&text, layout);
+            }
+            Ok(None) => {}
+            Err(err) => warn!("could not read cache: {:#}", err),
+        }
+    }
+    let text = run()?;
+    if let Some(cache) = &cache {
+        if let Err(err) = cache.put(&content_hash, query, &text) {
+            warn!("could not write cache: {:#}", err);
+        }
+    }
+    out.text(&text, layout)
+}
+
+fn segment_cmd(opt: &SegmentOpt, out: &mut Renderer) -> Result<()> {
+    let dict = opt.filter.load_dictionary(&opt.dict)?;
+    let text = opt
+        .text
+        .chars()
+        .filter(|c| !c.is_whitespace())
+        .flat_map(char::to_lowercase)
+        .collect::<String>();
+    let mut limit = opt.limit;
+    let result_size = segmentation_size(text.chars().count(), opt.max_words);
+    if let Some(max) = opt.limits.max_results(result_size) {
+        if max < limit {
+            warn!("only keeping the {} most probable segmentations", max);
+            limit = max;
+        }
+    }
+    let segment_opts = opt.scores.apply(
+        &dict,
+        opt.repeats.apply(SegmentOptions {
+            min_word_len: opt.min_word_len,
+            max_words: opt.max_words,
+            limit,
+            enumeration: opt.enumeration.clone(),
+            ..SegmentOptions::default()
+        }),
+    )?;
+    let lattice = Lattice::build(&dict, &text, &segment_opts)?;
+    let best = lattice.k_best(&segment_opts);
+    if let Some(path) = &opt.trace_graph {
+        fs::write(path, lattice.to_dot(&best))
+            .with_context(|| format!("could not write {}", path.display()))?;
+    }
+    match opt.lattice.as_deref() {
+        Some("json") => out.line(&lattice.to_json(&best))?,
+        Some("dot") => out.line(lattice.to_dot(&best).trim_end())?,
+        _ if opt.explain => {
+            let mut text = String::new();
+            for (prob, path) in &best {
+                writeln!(text, "{:6.2} {}", prob, lattice.words(path).join(" "))?;
+                explain_words(&mut text, &lattice.breaking(*prob, path))?;
+            }
+            out.text(&text, Layout::Words)?;
+        }
+        _ => {
+            let segmentations = Dist::from_vec(
+                best.iter()
+                    .map(|(prob, path)| (*prob, lattice.words(path).join(" ")))
+                    .collect(),
+            );
+            out.dist(&segmentations)?;
+        }
+    }
+    Ok(())
+}
+
+/// A generous estimate of the memory needed for each segmentation we keep
+/// when segmenting `len` characters. `Lattice::k_best` keeps that many
+/// partial paths for each position and word count, and the lattice keeps that
+/// many words for each span.
+fn segmentation_size(len: usize, max_words: Option<usize>) -> usize {
+    let word_counts = max_words.unwrap_or(len).min(len) + 1;
+    let path = mem::size_of::<(Prob, Vec<usize>)>() + len * mem::size_of::<usize>();
+    let span_words = (len + 1) * len / 2 * (mem::size_of::<Span>() + len);
+    2 * ((len + 1) * word_counts * path + span_words)
+}
+
+fn extract_cmd(opt: &ExtractOpt, out: &mut Renderer) -> Result<()> {
+    let dict = opt.filter.load_dictionary(&opt.dict)?;
+    let mut schemes =
+        vec![Scheme::FirstLetters, Scheme::LastLetters, Scheme::Diagonal];
+    if !opt.indices.is_empty() {
+        schemes.push(Scheme::Indexed(opt.indices.clone()));
+    }
+    let extractions = rank_extractions(&dict, &opt.answers, &schemes)?;
+    let best = extractions
+        .iter()
+        .filter_map(|e| e.breaking.as_ref().map(|b| b.total))
+        .max();
+    for extraction in &extractions {
+        match (&extraction.breaking, best) {
+            (Some(breaking), Some(best)) => {
+                let text = format!(
+                    "{} ({}: {})",
+                    extraction.letters, extraction.scheme, breaking
+                );
+                out.result(breaking.total, best, &text, Layout::Annotated, &[])?;
+            }
+            _ => out.line(&format!(
+                "{:>6} {} ({}: no words found)",
+                "-", extraction.letters, extraction.scheme
+            ))?,
+        }
+    }
+    Ok(())
+}
+
+fn decode_cmd(opt: &EncodedOpt, code: &Code, out: &mut Renderer) -> Result<()> {
+    let dict = opt.filter.load_dictionary(&opt.dict)?;
+    let segment_opts = opt.repeats.apply(SegmentOptions {
+        max_words: opt.max_words,
+        limit: opt.limit,
+        ..SegmentOptions::default()
+    });
+    let readings = decode(&dict, code, &opt.encoded, &segment_opts)?;
+    print_readings(out, readings)
+}
+
+fn shiftpairs_cmd(opt: &ShiftPairsOpt, out: &mut Renderer) -> Result<()> {
+    let dict = opt.filter.load_dictionary(&opt.dict)?;
+    let shift_opts = ShiftOptions {
+        shift: opt.shift,
+        min_length: opt.min_length,
+    };
+    for (i, (shift, mut pairs)) in find_shift_pairs(&dict, &shift_opts)?
+        .into_iter()
+        .enumerate()
+    {
+        if i > 0 {
+            out.line("")?;
+        }
+        out.line(&format!("Shift {} ({} pairs):", shift, pairs.len()))?;
+        if let Some(limit) = opt.limit {
+            pairs.truncate(limit);
+        }
+        out.dist(&pairs)?;
+    }
+    Ok(())
+}
+
+fn decode_tokens_cmd(opt: &DecodeOpt, out: &mut Renderer) -> Result<()> {
+    let table = match TokenTable::named(&opt.table) {
+        Some(table) => table,
+        None if Path::new(&opt.table).exists() => {
+            TokenTable::from_file(Path::new(&opt.table))?
+        }
+        None => {
+            return Err(format_err!(
+                "unknown table {:?}; expected {} or a file",
+                opt.table,
+                TOKEN_TABLES.join(", ")
+            ))
+        }
+    };
+    let dict = opt.filter.load_dictionary(&opt.dict)?;
+    let segment_opts = opt.repeats.apply(SegmentOptions {
+        max_words: opt.max_words,
+        limit: opt.limit,
+        ..SegmentOptions::default()
+    });
+    let readings = decode_tokens(&dict, &table, &opt.tokens.join(" "), &segment_opts)?;
+    print_readings(out, readings)
+}
+
+/// Print each reading of some encoded text.
+fn print_readings(out: &mut Renderer, readings: Vec<Breaking>) -> Result<()> {
+    out.dist(&Dist::from_vec(
+        readings
+            .into_iter()
+            .map(|reading| (reading.total, reading.to_string()))
+            .collect(),
+    ))
+}
+
+fn compounds_cmd(opt: &CompoundsOpt, out: &mut Renderer) -> Result<()> {
+    let dict = opt.filter.load_dictionary(&opt.dict)?;
+    let compound_opts = CompoundOptions {
+        min_part_len: opt.min_part_len,
+        max_parts: opt.max_parts,
+    };
+    let mut text = String::new();
+    for compound in find_compounds(&dict, &compound_opts)? {
+        let breakings = compound
+            .breakings
+            .iter()
+            .map(|parts| parts.join("+"))
+            .collect::<Vec<_>>();
+        writeln!(
+            text,
+            "{:6.2} {} ({})",
+            compound.prob,
+            compound.word,
+            breakings.join(", ")
+        )?;
+    }
+    out.text(&text, Layout::Annotated)
+}
+
+fn transposals_cmd(opt: &TransposalsOpt, out: &mut Renderer) -> Result<()> {
+    let dict = opt.filter.load_dictionary(&opt.dict)?;
+    let transposal_opts = TransposalOptions {
+        length: opt.length,
+        min_length: opt.min_length,
+        min_prob: opt.min_prob.map(Prob::from_probability),
+    };
+    out.dist(&find_transposals(&dict, &transposal_opts)?)
+}
+
+fn fuzzy_cmd(opt: &FuzzyOpt, out: &mut Renderer) -> Result<()> {
+    let dict = opt.filter.load_dictionary(&opt.dict)?;
+    let word = opt.word.to_ascii_lowercase();
+    let matches = dict.find_within_distance(&word, opt.max_distance)?;
+    let limit = opt.limit.unwrap_or(usize::MAX);
+    if opt.typos {
+        let mut ranked = rank_typos(&KeyboardModel::default(), &word, matches);
+        ranked.truncate(limit);
+        out.dist(&ranked)
+    } else {
+        let mut text = String::new();
+        for m in matches.iter().take(limit) {
+            writeln!(text, "{}", m)?;
+        }
+        out.text(&text, Layout::Annotated)
+    }
+}
+
+fn rhymes_cmd(opt: &RhymesOpt, out: &mut Renderer) -> Result<()> {
+    let dict = opt.filter.load_dictionary(&opt.dict)?;
+    let mode = if opt.phonetic {
+        RhymeMode::Sound
+    } else {
+        RhymeMode::Spelling {
+            suffix_len: opt.suffix_len,
+        }
+    };
+    out.dist(&find_rhymes(&dict, &opt.word.to_ascii_lowercase(), mode)?)
+}
+
+fn homophones_cmd(opt: &HomophonesOpt, out: &mut Renderer) -> Result<()> {
+    let dict = opt.filter.load_dictionary(&opt.dict)?;
+    let mut homophones = match &opt.word {
+        Some(word) => find_homophones(&dict, &word.to_ascii_lowercase())?,
+        None => find_homophone_pairs(&dict)?,
+    };
+    if let Some(limit) = opt.limit {
+        homophones.truncate(limit);
+    }
+    out.dist(&homophones)
+}
+
+fn chain_cmd(opt: &ChainOpt, out: &mut Renderer) -> Result<()> {
+    let dict = opt.filter.load_dictionary(&opt.dict)?;
+    let chain_opts = ChainOptions {
+        overlap: opt.overlap,
+        max_words: opt.max_words,
+        max_candidates: opt.max_candidates,
+        limit: opt.limit,
+    };
+    let chains = find_chains(
+        &dict,
+        &opt.from.to_lowercase(),
+        &opt.to.to_lowercase(),
+        &chain_opts,
+    )?;
+    if chains.is_empty() {
+        return Err(format_err!("could not find a chain"));
+    }
+    out.dist(&chains)
+}
+
+fn extremes_cmd(opt: &ExtremesOpt, out: &mut Renderer) -> Result<()> {
+    let dict = opt.filter.load_dictionary(&opt.dict)?;
+    let mut predicates = Predicates::new();
+    if let Some(pattern) = &opt.pattern {
+        predicates.push(Matches::new(pattern)?);
+    }
+    if let Some(bank) = &opt.bank {
+        predicates.push(FromBank::new(bank));
+    }
+    if opt.alternating {
+        predicates.push(AlternatesVowels);
+    }
+    let extreme_opts = ExtremeOptions {
+        shortest: opt.shortest,
+        limit: opt.limit,
+    };
+    let words = find_extremes(&dict, &predicates, &extreme_opts)?;
+    let best = words.iter().map(|(prob, _)| *prob).max();
+    for (prob, word) in &words {
+        let best = best.expect("we have at least one word");
+        let text = format!("{} (length {})", word, word.chars().count());
+        out.result(*prob, best, &text, Layout::Annotated, &[])?;
+    }
+    Ok(())
+}
+
+fn pangrams_cmd(opt: &PangramsOpt, out: &mut Renderer) -> Result<()> {
+    let dict = opt.filter.load_dictionary(&opt.dict)?;
+    let pangram_opts = PangramOptions {
+        only_target: opt.only,
+        max_words: opt.max_words,
+        max_candidates: opt.max_candidates,
+        limit: opt.limit,
+    };
+    let pangrams = find_pangrams(&dict, &opt.letters, &pangram_opts)?;
+    if pangrams.is_empty() {
+        return Err(format_err!(
+            "could not find a set of at most {} words",
+            opt.max_words
+        ));
+    }
+    out.dist(&pangrams)
+}
+
+fn anagram_cmd(opt: &AnagramOpt, out: &mut Renderer) -> Result<()> {
+    let dict = opt.filter.load_dictionary(&opt.dict)?;
+    if opt.words <= 1 && opt.include.is_empty() && opt.exclude_path.is_none() {
+        return print_with_blanks(
+            out,
+            &opt.letters,
+            &find_anagrams(&dict, &opt.letters)?,
+        );
+    }
+    let phrase_opts = PhraseOptions {
+        max_words: opt.words,
+        min_length: opt.min_length,
+        include: opt.include.clone(),
+        exclude: match &opt.exclude_path {
+            Some(path) => read_word_list(path)?.into_iter().collect(),
+            None => BTreeSet::new(),
+        },
+        limit: opt.limit,
+        checkpoint: opt.checkpoint.options(),
+        ..PhraseOptions::default()
+    };
+    out.dist(&find_anagram_phrases(&dict, &opt.letters, &phrase_opts)?)
+}
+
+fn rack_cmd(opt: &RackOpt, out: &mut Renderer) -> Result<()> {
+    let dict = opt.filter.load_dictionary(&opt.dict)?;
+    let mut words = find_rack_words(&dict, &opt.tiles, opt.min_length)?;
+    if let Some(limit) = opt.limit {
+        words.truncate(limit);
+    }
+    print_with_blanks(out, &opt.tiles, &words)
+}
+
+/// Print `words`, which were spelled using `tiles`, showing which letter each
+/// blank tile stood for.
+fn print_with_blanks(
+    out: &mut Renderer,
+    tiles: &str,
+    words: &Dist<String>,
+) -> Result<()> {
+    let mut best = None;
+    for (prob, word) in words {
+        let best = *best.get_or_insert(prob);
+        let blanks = blank_letters(tiles, word);
+        if blanks.is_empty() {
+            out.result(prob, best, word, Layout::Annotated, &[])?;
+            continue;
+        }
+        let text = format!(
+            "{} (blanks: {})",
+            word,
+            blanks.iter().map(|(_, c)| c).join(" ")
+        );
+        let highlights = blanks
+            .iter()
+            .map(|&(offset, c)| offset..offset + c.len_utf8())
+            .collect::<Vec<_>>();
+        out.result(prob, best, &text, Layout::Annotated, &highlights)?;
+    }
+    Ok(())
+}
+
+fn scrabble_cmd(opt: &ScrabbleOpt, out: &mut Renderer) -> Result<()> {
+    let dict = opt.filter.load_dictionary(&opt.dict)?;
+    let board = fs::read_to_string(&opt.board_path)
+        .with_context(|| format!("could not read {}", opt.board_path.display()))?
+        .parse::<scrabble::Board>()?;
+    let move_opts = MoveOptions {
+        limit: Some(opt.limit),
+    };
+    let moves = generate_moves(&dict, &board, &opt.tiles, &move_opts)?;
+    if moves.is_empty() {
+        return Err(format_err!("no legal moves for {:?}", opt.tiles));
+    }
+    for m in &moves {
+        let mut text =
+            format!("{:4} {} {} {}", m.score, m.word, m.position(), m.direction);
+        if !m.blanks.is_empty() {
+            let blanks = m.blanks.iter().map(|&i| &m.word[i..i + 1]).join(" ");
+            write!(text, " (blanks: {})", blanks)?;
+        }
+        if !m.cross_words.is_empty() {
+            write!(text, " (also {})", m.cross_words.join(", "))?;
+        }
+        out.line(&text)?;
+    }
+    Ok(())
+}
+
+fn builder_cmd(opt: &BuilderOpt, out: &mut Renderer) -> Result<()> {
+    let dict = opt.filter.load_dictionary(&opt.dict)?;
+    let groups = find_buildable_words(&dict, &opt.seed, opt.min_length)?;
+    let total = groups.iter().map(|(_, words)| words.len()).sum::<usize>();
+    out.line(&format!("{} words from {:?}", total, opt.seed))?;
+    for (len, mut words) in groups {
+        out.line("")?;
+        out.line(&format!("{} letters ({} words):", len, words.len()))?;
+        if let Some(limit) = opt.limit {
+            words.truncate(limit);
+        }
+        out.dist(&words)?;
+    }
+    Ok(())
+}
+
+fn bank_cmd(opt: &BankOpt, out: &mut Renderer) -> Result<()> {
+    let dict = opt.filter.load_dictionary(&opt.dict)?;
+    let mut words = find_bank_words(&dict, &opt.letters, opt.min_length)?;
+    if let Some(limit) = opt.limit {
+        words.truncate(limit);
+    }
+    out.dist(&words)
+}
+
+/// Read the crossword puzzle at `path`.
+fn read_puzzle(path: &Path) -> Result<Puzzle> {
+    Puzzle::read(path)
+        .with_context(|| format!("could not read puzzle from {}", path.display()))
+}
+
+fn fill_cmd(opt: &FillOpt, out: &mut Renderer) -> Result<()> {
+    let dict = opt.filter.load_dictionary(&opt.dict)?;
+    let puzzle = read_puzzle(&opt.grid_path)?;
+    let grid = puzzle.grid(PuzzleGrid::Progress);
+    let seed = opt.seed.unwrap_or_else(Rng::random_seed);
+    if opt.restarts > 0 || opt.threads > 1 || opt.time_budget.is_some() {
+        info!("fill is using --seed {}", seed);
+    }
+    let fill_opts = FillOptions {
+        max_candidates: opt.max_candidates,
+        allow_duplicates: opt.allow_duplicates,
+        no_substrings: opt.no_substrings,
+        restarts: opt.restarts,
+        backtrack_limit: opt.backtrack_limit,
+        seed,
+        threads: opt.threads,
+        time_budget: opt.time_budget.map(Duration::from_secs),
+        cancel: None,
+        checkpoint: opt.checkpoint.options(),
+        trace_graph: opt.trace_graph.clone(),
+    };
+    match fill(&dict, grid, &fill_opts)? {
+        Some(filled) => {
+            for row in filled.to_string().lines() {
+                out.line(row)?;
+            }
+            if let Some(output) = &opt.output {
+                puzzle.with_solution(filled)?.write(output)?;
+            }
+        }
+        None => return Err(format_err!("could not find a way to fill the grid")),
+    }
+    Ok(())
+}
+
+fn wordfit_cmd(opt: &WordFitOpt, out: &mut Renderer) -> Result<()> {
+    let puzzle = read_puzzle(&opt.grid_path)?;
+    let bank = read_word_list(&opt.bank_path)?;
+    let wordfit = WordFit::new(puzzle.grid(PuzzleGrid::Progress), &bank)?;
+    let wordfit_opts = WordFitOptions {
+        max_solutions: opt.max_solutions,
+    };
+    let solutions = wordfit.solve(&wordfit_opts);
+    for (i, solution) in solutions.iter().enumerate() {
+        if i > 0 {
+            out.line("")?;
+        }
+        for row in solution.to_string().lines() {
+            out.line(row)?;
+        }
+    }
+    match solutions.len() {
+        0 => return Err(format_err!("could not fit the words into the grid")),
+        1 => {}
+        n if n < opt.max_solutions => warn!("the puzzle has {} solutions", n),
+        n => warn!("the puzzle has at least {} solutions", n),
+    }
+    if let (Some(output), Some(solution)) = (&opt.output, solutions.first()) {
+        puzzle.with_solution(solution.clone())?.write(output)?;
+    }
+    Ok(())
+}
+
+fn krisskross_cmd(opt: &KrissKrossOpt, out: &mut Renderer) -> Result<()> {
+    let dict = opt.filter.load_dictionary(&opt.dict)?;
+    let puzzle = read_puzzle(&opt.grid_path)?;
+    let seed = opt.seed.unwrap_or_else(Rng::random_seed);
+    info!("krisskross is using --seed {}", seed);
+    let bank_opts = BankOptions {
+        max_candidates: opt.max_candidates,
+        attempts: opt.attempts,
+        seed,
+        ..BankOptions::default()
+    };
+    let bank = generate_bank(&dict, puzzle.grid(PuzzleGrid::Progress), &bank_opts)?
+        .ok_or_else(|| {
+            format_err!("could not find a bank which fits in only one way")
+        })?;
+    for word in &bank.words {
+        out.line(word)?;
+    }
+    out.line("")?;
+    for row in bank.solution.to_string().lines() {
+        out.line(row)?;
+    }
+    if let Some(output) = &opt.output {
+        puzzle.with_solution(bank.solution)?.write(output)?;
+    }
+    Ok(())
+}
+
+fn score_fill_cmd(opt: &ScoreFillOpt, out: &mut Renderer) -> Result<()> {
+    let dict = opt.filter.load_dictionary(&opt.dict)?;
+    let puzzle = read_puzzle(&opt.grid_path)?;
+    let grid = puzzle.grid(PuzzleGrid::Solution);
+    if !(opt.obscure_below > 0.0 && opt.obscure_below <= 1.0) {
+        return Err(format_err!(
+            "--obscure-below must be a probability between 0 and 1, not {}",
+            opt.obscure_below
+        ));
+    }
+    let score_opts = ScoreFillOptions {
+        obscure_below: Prob::from_probability(opt.obscure_below),
+        min_shared_letters: opt.min_shared_letters,
+        penalty: opt.penalty,
+    };
+    let score = score_fill(&dict, grid, &score_opts)?;
+    // `score_fill` returns the entries in the same order as `Grid::slots`.
+    let best = score.entries.iter().filter_map(|(_, prob)| *prob).max();
+    for ((word, prob), slot) in score.entries.iter().zip(grid.slots()) {
+        let text = match puzzle.clue(&slot) {
+            Some(clue) if !clue.is_empty() => {
+                format!("{:>4} {}  {}", slot.label(), word, clue)
+            }
+            _ => format!("{:>4} {}", slot.label(), word),
+        };
+        match (prob, best) {
+            (Some(prob), Some(best)) => {
+                out.result(*prob, best, &text, Layout::Plain, &[])?
+            }
+            _ => out.line(&format!("{:>6} {}", "?", text))?,
+        }
+    }
+    out.line("")?;
+    out.line(&format!(
+        "{} entries, {:.2} nats in total, {:.2} per entry",
+        score.entries.len(),
+        score.total,
+        score.mean()
+    ))?;
+    if !score.unknown.is_empty() {
+        out.line(&format!(
+            "{} not in the dictionary: {}",
+            score.unknown.len(),
+            score.unknown.join(", ")
+        ))?;
+    }
+    if !score.obscure.is_empty() {
+        let obscure = score
+            .obscure
+            .iter()
+            .map(|(word, _)| word.as_str())
+            .collect::<Vec<_>>();
+        out.line(&format!(
+            "{} obscure: {}",
+            obscure.len(),
+            obscure.join(", ")
+        ))?;
+    }
+    for shared in &score.shared {
+        out.line(&format!(
+            "{} and {} share {:?}",
+            shared.entries.0, shared.entries.1, shared.letters
+        ))?;
+    }
+    out.line(&format!("Score: {:.2} (lower is better)", score.score()))?;
+    Ok(())
+}
+
+fn clues_cmd(opt: &CluesOpt, out: &mut Renderer) -> Result<()> {
+    let dict = opt.filter.load_dictionary(&opt.dict)?;
+    let mut best = None;
+    for (prob, answer) in &dict.find_matches(&opt.regex)? {
+        let clues = dict.clues_for(answer)?;
+        if opt.only_clued && clues.is_empty() {
+            continue;
+        }
+        let best = *best.get_or_insert(prob);
+        out.result(prob, best, answer, Layout::Words, &[])?;
+        for clue in clues.iter().take(opt.max_clues) {
+            out.line(&format!("{:6} {}", "", clue))?;
+        }
+    }
+    Ok(())
+}
+
+fn acrostic_cmd(opt: &AcrosticOpt, out: &mut Renderer) -> Result<()> {
+    let dict = opt.filter.load_dictionary(&opt.dict)?;
+    let numbering = fs::read_to_string(&opt.numbering_path)
+        .with_context(|| format!("could not read {}", opt.numbering_path.display()))?;
+    let puzzle = Acrostic::new(&numbering, &opt.initials, &opt.letters)?;
+    let acrostic_opts = AcrosticOptions {
+        max_candidates: opt.max_candidates,
+        limit: opt.limit,
+        ..AcrosticOptions::default()
+    };
+    let solutions = puzzle.solve(&dict, &acrostic_opts)?;
+    if solutions.is_empty() {
+        return Err(format_err!("could not solve the acrostic"));
+    }
+    let best = solutions[0].prob;
+    for solution in &solutions {
+        let quote = solution.quote.join(" ");
+        out.result(solution.prob, best, &quote, Layout::Words, &[])?;
+        out.line(&format!("       {}", solution.answers.join(" ")))?;
+    }
+    Ok(())
+}
+
+fn dropquote_cmd(opt: &DropQuoteOpt, out: &mut Renderer) -> Result<()> {
+    let dict = opt.filter.load_dictionary(&opt.dict)?;
+    let grid = fs::read_to_string(&opt.grid_path)
+        .with_context(|| format!("could not read {}", opt.grid_path.display()))?;
+    let puzzle = DropQuote::new(&grid, &opt.banks)?;
+    let dropquote_opts = DropQuoteOptions {
+        max_candidates: opt.max_candidates,
+    };
+    match puzzle.solve(&dict, &dropquote_opts)? {
+        Some(solution) => {
+            let quote = solution.words.join(" ");
+            out.result(solution.prob, solution.prob, &quote, Layout::Words, &[])?;
+            for row in solution.to_string().lines() {
+                out.line(row)?;
+            }
+            Ok(())
+        }
+        None => Err(format_err!("could not solve the drop quote")),
+    }
+}
+
+fn letterboxed_cmd(opt: &LetterBoxedOpt, out: &mut Renderer) -> Result<()> {
+    let dict = opt.filter.load_dictionary(&opt.dict)?;
+    let mut solutions = LetterBox::new(&opt.sides)?.solve(&dict)?;
+    solutions.two_words.truncate(opt.limit);
+    out.dist(&solutions.one_word)?;
+    out.dist(&solutions.two_words)
+}
+
+fn wordle_cmd(opt: &WordleOpt, out: &mut Renderer) -> Result<()> {
+    let dict = opt.filter.load_dictionary(&opt.dict)?;
+    let mut boards = opt.boards.clone();
+    if boards.is_empty() {
+        boards.resize(opt.board_count, Board::default());
+    }
+    let wordle_opts = WordleOptions {
+        word_len: opt.length,
+        guess_pool: opt.guess_pool,
+    };
+    let mut analysis = analyze(&dict, &boards, &wordle_opts)?;
+    for (i, candidates) in analysis.candidates.iter_mut().enumerate() {
+        if boards[i].is_solved() {
+            out.line(&format!("Board {}: solved", i + 1))?;
+            continue;
+        }
+        out.line(&format!("Board {}: {} candidates", i + 1, candidates.len()))?;
+        candidates.truncate(opt.limit);
+        out.dist(candidates)?;
+    }
+    out.line("Suggested guesses (expected bits of information):")?;
+    for (bits, guess) in analysis.suggestions.iter().take(opt.limit) {
+        out.line(&format!("{:6.2} {}", bits, guess))?;
+    }
+    Ok(())
+}
+
+fn export_stats_cmd(opt: &ExportStatsOpt, out: &mut Renderer) -> Result<()> {
+    let dict = opt.filter.load_dictionary(&opt.dict)?;
+    let stats = dictionary_stats(&dict, opt.top)?;
+    if opt.format == "json" {
+        let json = match opt.table.as_deref() {
+            None => serde_json::to_string_pretty(&stats)?,
+            Some("rank-frequency") => {
+                serde_json::to_string_pretty(&stats.rank_frequency)?
+            }
+            Some("lengths") => serde_json::to_string_pretty(&stats.lengths)?,
+            Some(_) => serde_json::to_string_pretty(&stats.letters)?,
+        };
+        out.line(&json)?;
+        return Ok(());
+    }
+    match opt.table.as_deref().unwrap_or("rank-frequency") {
+        "rank-frequency" => {
+            out.line("rank,word,probability")?;
+            for row in &stats.rank_frequency {
+                out.line(&format!(
+                    "{},{},{}",
+                    row.rank,
+                    csv_field(&row.word),
+                    row.probability
+                ))?;
+            }
+        }
+        "lengths" => {
+            out.line("length,words,probability")?;
+            for row in &stats.lengths {
+                out.line(&format!(
+                    "{},{},{}",
+                    row.length, row.words, row.probability
+                ))?;
+            }
+        }
+        _ => {
+            out.line("letter,count,probability")?;
+            for row in &stats.letters {
+                let letter = csv_field(&row.letter.to_string());
+                out.line(&format!("{},{},{}", letter, row.count, row.probability))?;
+            }
+        }
+    }
+    Ok(())
+}
+
+fn dictcheck_cmd(opt: &DictCheckOpt, out: &mut Renderer) -> Result<()> {
+    let dict = opt.filter.load_dictionary(&opt.dict)?;
+    let reference = match &opt.reference_path {
+        Some(path) => Some(read_word_list(path)?),
+        None => None,
+    };
+    let quality_opts = QualityOptions {
+        examples: opt.examples,
+        ..QualityOptions::default()
+    };
+    let report = check_quality(&dict, reference.as_deref(), &quality_opts)?;
+    if opt.json {
+        out.line(&serde_json::to_string_pretty(&report)?)?;
+        return Ok(());
+    }
+
+    out.line(&format!("{} words", report.word_count))?;
+    match &report.zipf {
+        Some(zipf) => {
+            out.line(&format!(
+                "Zipf fit: probability = {:.4} / rank^{:.3} (R² = {:.3})",
+                zipf.constant, zipf.exponent, zipf.r_squared
+            ))?;
+            if !(0.7..=1.3).contains(&zipf.exponent) {
+                out.line(
+                    "  warning: natural language usually has an exponent near 1",
+                )?;
+            }
+        }
+        None => out.line("Zipf fit: too few words")?,
+    }
+
+    if report.flat_regions.is_empty() {
+        out.line("No suspiciously flat regions")?;
+    } else {
+        out.line("Suspiciously flat regions:")?;
+        for region in &report.flat_regions {
+            out.line(&format!(
+                "  {} words from rank {} share probability {:.3e} \
+                 (expected about {:.1})",
+                region.words,
+                region.first_rank,
+                region.probability,
+                region.expected_words,
+            ))?;
+        }
+    }
+
+    out.line(&format!(
+        "{} junk-looking words, with {:.2}% of the probability",
+        report.junk_count,
+        report.junk_probability * 100.0
+    ))?;
+    if report.junk_probability > 0.01 {
+        out.line("  warning: more than 1% of the probability is in junk words")?;
+    }
+    for junk in &report.junk {
+        out.line(&format!(
+            "  {:.3e} {} ({})",
+            junk.probability, junk.word, junk.reason
+        ))?;
+    }
+
+    if let Some(coverage) = &report.coverage {
+        out.line(&format!(
+            "Found {} of {} reference words ({:.1}%)",
+            coverage.found,
+            coverage.reference_words,
+            coverage.fraction() * 100.0
+        ))?;
+        if !coverage.missing.is_empty() {
+            out.line(&format!("  missing: {}", coverage.missing.join(", ")))?;
+        }
+    }
+    Ok(())
+}
+
+/// Quote `field` for CSV output if it contains a comma, quote or newline.
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_owned()
+    }
+}
+
+fn dicts_cmd(opt: &DictsOpt) -> Result<()> {
+    let config = Config::load_default()?;
+    let registry = Registry::open_default()?;
+    let embedded = cfg!(feature = "embedded-dictionary").then_some(EMBEDDED_NAME);
+    if opt.names {
+        let names = config
+            .dictionaries
+            .keys()
+            .chain(registry.dictionaries().map(|(name, _)| name))
+            .map(String::as_str)
+            .chain(embedded)
+            .collect::<BTreeSet<_>>();
+        for name in names {
+            println!("{}", name);
+        }
+        return Ok(());
+    }
+    let default = config
+        .default
+        .as_deref()
+        .or_else(|| registry.default_name())
+        .or(embedded);
+    let marker = |name: &str| if default == Some(name) { "*" } else { " " };
+    if config.dictionaries.is_empty()
+        && registry.dictionaries().next().is_none()
+        && embedded.is_none()
+    {
+        println!(
+            "No dictionaries are defined in {} or installed in {}",
+            Config::default_path()?.display(),
+            registry.dir().display(),
+        );
+    }
+    for (name, profile) in &config.dictionaries {
+        println!(
+            "{} {} ({}) {}",
+            marker(name),
+            name,
+            profile.language.as_deref().unwrap_or("unknown language"),
+            profile.path.display(),
+        );
+    }
+    for (name, installed) in registry.dictionaries() {
+        if config.dictionaries.contains_key(name) {
+            continue;
+        }
+        println!(
+            "{} {} (installed from {}) {}",
+            marker(name),
+            name,
+            installed.source,
+            installed.path.display(),
+        );
+    }
+    if let Some(name) = embedded {
+        if !config.dictionaries.contains_key(name) && registry.get(name).is_none() {
+            println!("{} {} (built in)", marker(name), name);
+        }
+    }
+    Ok(())
+}
+
+fn cache_cmd(opt: &CacheOpt) -> Result<()> {
+    let cache = QueryCache::open_default()?;
+    match opt {
+        CacheOpt::Clear => {
+            let removed = cache.clear()?;
+            println!(
+                "Removed {} cached results from {}",
+                removed,
+                cache.dir().display()
+            );
+        }
+    }
+    Ok(())
+}
+
+fn completions_cmd(opt: &CompletionsOpt) -> Result<()> {
+    let mut script = vec![];
+    Opt::clap().gen_completions_to(BIN_NAME, opt.shell, &mut script);
+    let script = String::from_utf8(script)?;
+    print!("{}", complete_dict_names(opt.shell, &script));
+    Ok(())
+}
+
+/// Patch a completion script generated by clap so that `--dict` completes the
+/// names printed by `dicts --names`, as well as file names. clap can only
+/// generate static completions, so we need to do this ourselves.
+fn complete_dict_names(shell: Shell, script: &str) -> String {
+    let names = format!("{} dicts --names 2>/dev/null", BIN_NAME);
+    let bash_files = r#"$(compgen -f "${cur}")"#;
+    let bash_names_and_files = format!(
+        r#"$(compgen -W "$({})" -- "${{cur}}") {}"#,
+        names, bash_files
+    );
+    let mut out = String::new();
+    let mut prev_line = "";
+    for line in script.lines() {
+        match shell {
+            Shell::Bash if matches!(prev_line.trim(), "--dict)" | "-d)") => {
+                out.push_str(&line.replace(bash_files, &bash_names_and_files));
+            }
+            Shell::Zsh
+                if line.starts_with("'--dict=[") || line.starts_with("'-d+[") =>
+            {
+                out.push_str(
+                    &line.replace("]' \\", "]:dict:_letter_permutations_dicts' \\"),
+                );
+            }
+            Shell::Zsh if line == format!("_{} \"$@\"", BIN_NAME) => {
+                out.push_str("_letter_permutations_dicts() {\n");
+                out.push_str("    local -a dicts\n");
+                out.push_str(&format!("    dicts=(${{(f)\"$({})\"}})\n", names));
+                out.push_str(
+                    "    _alternative 'dicts:dictionary:compadd -a dicts' \
+                     'files:file:_files'\n",
+                );
+                out.push_str("}\n\n");
+                out.push_str(line);
+            }
+            Shell::Fish if line.contains(" -s d -l dict ") => {
+                out.push_str(&format!("{} -a '({})'", line, names));
+            }
+            _ => out.push_str(line),
+        }
+        out.push('\n');
+        prev_line = line;
+    }
+    out
 }
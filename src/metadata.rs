@@ -0,0 +1,91 @@
+//! Metadata describing a dictionary, stored next to it as JSON.
+
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, fs, path::Path};
+use xxhash_rust::xxh3::{xxh3_64, Xxh3};
+
+use crate::encryption::Encryption;
+use crate::error::{Error, Result};
+use crate::probability::Quantization;
+
+/// The current version of our dictionary format.
+pub const FORMAT_VERSION: u32 = 1;
+
+/// Information about a dictionary which we record when building it.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Metadata {
+    /// The version of the dictionary format.
+    pub format_version: u32,
+    /// The number of distinct words.
+    pub word_count: u64,
+    /// The number of entries which are phrases of more than one word.
+    #[serde(default)]
+    pub phrase_count: u64,
+    /// The number of words marked as proper nouns.
+    #[serde(default)]
+    pub proper_noun_count: u64,
+    /// The sum of the counts of all words.
+    pub total_count: u64,
+    /// A hash of the main dictionary file, which identifies its contents and
+    /// lets us check that it hasn't been damaged.
+    pub content_hash: String,
+    /// The same kind of hash for each index file, by extension.
+    #[serde(default)]
+    pub checksums: BTreeMap<String, String>,
+    /// How we quantized probabilities, or `None` if we stored them at full
+    /// precision.
+    #[serde(default)]
+    pub quantization: Option<Quantization>,
+    /// How the dictionary's files were encrypted, or `None` if they weren't.
+    /// The hashes above are of the files before encryption.
+    #[serde(default)]
+    pub encryption: Option<Encryption>,
+    /// The total probability of the words with each number of letters, as a
+    /// negative log probability, for comparing words of the same length.
+    #[serde(default)]
+    pub length_totals: BTreeMap<usize, f64>,
+    /// The number of shards of each index split into more than one, by
+    /// extension, where the words themselves have an empty extension.
+    #[serde(default)]
+    pub shards: BTreeMap<String, usize>,
+}
+
+impl Metadata {
+    /// Read metadata from `path`, if it exists.
+    pub fn read(path: &Path) -> Result<Option<Metadata>> {
+        if !path.exists() {
+            return Ok(None);
+        }
+        let json = fs::read(path).map_err(Error::io(path))?;
+        Ok(Some(Metadata::parse(path, &json)?))
+    }
+
+    /// Parse metadata from `json`, the contents of `path`.
+    pub fn parse(path: &Path, json: &[u8]) -> Result<Metadata> {
+        serde_json::from_slice(json).map_err(|err| {
+            Error::CorruptDictionary(format!("{}: {}", path.display(), err))
+        })
+    }
+
+    /// Write metadata to `path`.
+    pub fn write(&self, path: &Path) -> Result<()> {
+        let json =
+            serde_json::to_string_pretty(self).expect("metadata should serialize");
+        fs::write(path, json + "\n").map_err(Error::io(path))
+    }
+}
+
+/// Hash the contents of a dictionary file.
+pub fn content_hash(bytes: &[u8]) -> String {
+    format!("{:016x}", xxh3_64(bytes))
+}
+
+/// Hash the contents of a dictionary split into `shards`, in order. This is
+/// the same as the hash of a single file holding all of them.
+pub fn content_hash_of<'a>(shards: impl IntoIterator<Item = &'a [u8]>) -> String {
+    let mut hasher = Xxh3::new();
+    for shard in shards {
+        hasher.update(shard);
+    }
+    format!("{:016x}", hasher.digest())
+}
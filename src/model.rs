@@ -0,0 +1,161 @@
+//! Language models, which estimate the probability of a word.
+//!
+//! A `Dictionary` is itself a language model, but it knows nothing about
+//! words it hasn't seen. A `SmoothedModel` sets aside a little probability for
+//! unknown words, so that a scoring function can keep going when it meets a
+//! word which isn't in the dictionary.
+
+use std::{collections::HashMap, str::FromStr};
+
+use crate::dictionary::Dictionary;
+use crate::error::{Error, Result};
+use crate::probability::Prob;
+
+/// Something which can estimate the probability of a word.
+pub trait LanguageModel {
+    /// The probability of `word`, which should be lowercase, or `None` if the
+    /// model considers it impossible.
+    fn word_probability(&self, word: &str) -> Option<Prob>;
+}
+
+impl LanguageModel for Dictionary {
+    fn word_probability(&self, word: &str) -> Option<Prob> {
+        self.probability_of(word)
+    }
+}
+
+/// How to reserve probability for words we haven't seen.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Smoothing {
+    /// Pretend we saw every word `k` more times than we did, including one
+    /// extra unknown word.
+    AddK(f64),
+    /// Estimate how often we see unknown words from how many words we only
+    /// saw once, and discount rarely-seen words to match.
+    GoodTuring,
+}
+
+/// Parse `add-k:0.5` or `good-turing`.
+impl FromStr for Smoothing {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        if s == "good-turing" {
+            return Ok(Smoothing::GoodTuring);
+        }
+        if let Some(k) = s.strip_prefix("add-k:") {
+            if let Ok(k) = k.parse::<f64>() {
+                if k.is_finite() && k > 0.0 {
+                    return Ok(Smoothing::AddK(k));
+                }
+            }
+        }
+        Err(Error::InvalidOption(format!(
+            "expected \"add-k:K\" or \"good-turing\", found {:?}",
+            s
+        )))
+    }
+}
+
+/// Counts above this are reliable enough that Good-Turing leaves them alone.
+const GOOD_TURING_MAX_COUNT: u64 = 5;
+
+/// A language model which gives unknown words a small probability.
+pub struct SmoothedModel<'a> {
+    dict: &'a Dictionary,
+    smoothing: Smoothing,
+    /// The total number of words counted when building the dictionary.
+    total_count: f64,
+    /// The number of distinct words in the dictionary.
+    word_count: f64,
+    /// For Good-Turing, the adjusted count for each small count.
+    adjusted_counts: HashMap<u64, f64>,
+    /// The probability of each unknown word.
+    unknown: Prob,
+}
+
+impl<'a> SmoothedModel<'a> {
+    /// Smooth the probabilities in `dict`. The dictionary must have been
+    /// built by a version of `mkdict` which records word counts.
+    pub fn new(dict: &'a Dictionary, smoothing: Smoothing) -> Result<Self> {
+        let metadata = dict.metadata().ok_or(Error::MissingIndex {
+            index: "metadata",
+            flag: "",
+        })?;
+        let total_count = metadata.total_count as f64;
+        let word_count = metadata.word_count as f64;
+        let mut model = SmoothedModel {
+            dict,
+            smoothing,
+            total_count,
+            word_count,
+            adjusted_counts: HashMap::new(),
+            unknown: Prob::always(),
+        };
+        model.unknown = match smoothing {
+            Smoothing::AddK(k) => {
+                Prob::from_probability(k / (total_count + k * (word_count + 1.0)))
+            }
+            Smoothing::GoodTuring => {
+                // Count how many words we saw once, twice, etc.
+                let mut counts_of_counts = HashMap::<u64, u64>::new();
+                for entry in dict.words() {
+                    let (word, _) = entry?;
+                    let count = model.count_of(&word);
+                    if count <= GOOD_TURING_MAX_COUNT + 1 {
+                        *counts_of_counts.entry(count).or_default() += 1;
+                    }
+                }
+                for count in 1..=GOOD_TURING_MAX_COUNT {
+                    let n = counts_of_counts.get(&count).copied().unwrap_or(0);
+                    let n_next =
+                        counts_of_counts.get(&(count + 1)).copied().unwrap_or(0);
+                    if n > 0 && n_next > 0 {
+                        let adjusted = (count + 1) as f64 * n_next as f64 / n as f64;
+                        model.adjusted_counts.insert(count, adjusted);
+                    }
+                }
+                // Share the probability of seeing a new word among as many
+                // unknown words as we have known words.
+                let once = counts_of_counts.get(&1).copied().unwrap_or(0).max(1);
+                Prob::from_probability(once as f64 / total_count / word_count.max(1.0))
+            }
+        };
+        Ok(model)
+    }
+
+    /// The probability we give each word which isn't in the dictionary.
+    pub fn unknown_probability(&self) -> Prob {
+        self.unknown
+    }
+
+    /// How many times we saw `word` when building the dictionary.
+    fn count_of(&self, word: &str) -> u64 {
+        self.dict.raw_probability_of(word).map_or(0, |prob| {
+            (prob.to_probability() * self.total_count).round() as u64
+        })
+    }
+}
+
+impl LanguageModel for SmoothedModel<'_> {
+    fn word_probability(&self, word: &str) -> Option<Prob> {
+        if self.dict.probability_of(word).is_none() {
+            return Some(self.unknown);
+        }
+        let count = self.count_of(word) as f64;
+        let prob = match self.smoothing {
+            Smoothing::AddK(k) => {
+                (count + k) / (self.total_count + k * (self.word_count + 1.0))
+            }
+            Smoothing::GoodTuring => {
+                let adjusted = self
+                    .adjusted_counts
+                    .get(&(count as u64))
+                    .copied()
+                    .unwrap_or(count);
+                adjusted / self.total_count
+            }
+        };
+        Some(Prob::from_probability(prob))
+    }
+}
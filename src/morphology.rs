@@ -0,0 +1,59 @@
+//! Guessing the base forms of simple English inflections, like "cats" and
+//! "stopped", using spelling rules. This is only a guess, and it's wrong for
+//! words like "news" and "does".
+
+/// Guess the base form of `word` if it looks like a plural or a verb ending
+/// in "-s", "-ed" or "-ing". We only return base forms for which `is_word`
+/// returns true, trying the most likely spellings first.
+pub fn base_form(word: &str, is_word: impl Fn(&str) -> bool) -> Option<String> {
+    candidate_bases(word).into_iter().find(|base| is_word(base))
+}
+
+/// Possible base forms of `word`, most likely first.
+fn candidate_bases(word: &str) -> Vec<String> {
+    if !word.chars().all(|c| c.is_ascii_lowercase()) {
+        return vec![];
+    }
+    let mut bases = vec![];
+    let doubled = |stem: &str| {
+        let bytes = stem.as_bytes();
+        let n = bytes.len();
+        (n >= 2 && bytes[n - 1] == bytes[n - 2] && !is_vowel(bytes[n - 1] as char))
+            .then(|| stem[..n - 1].to_owned())
+    };
+    if let Some(stem) = word.strip_suffix("ies") {
+        bases.push(format!("{}y", stem));
+        bases.push(format!("{}ie", stem));
+    } else if let Some(stem) = word.strip_suffix('s') {
+        if !(stem.ends_with('s') || stem.ends_with('u') || stem.ends_with('i')) {
+            bases.push(stem.to_owned());
+            if let Some(stem) = stem.strip_suffix('e') {
+                bases.push(stem.to_owned());
+            }
+        }
+    } else if word.len() >= 5 {
+        if let Some(stem) = word.strip_suffix("ied") {
+            bases.push(format!("{}y", stem));
+        } else if let Some(stem) = word.strip_suffix("ed") {
+            bases.push(stem.to_owned());
+            bases.push(format!("{}e", stem));
+            bases.extend(doubled(stem));
+        } else if let Some(stem) = word.strip_suffix("ying") {
+            bases.push(format!("{}ie", stem));
+            bases.push(format!("{}y", stem));
+        } else if let Some(stem) = word.strip_suffix("ing") {
+            bases.push(stem.to_owned());
+            bases.push(format!("{}e", stem));
+            bases.extend(doubled(stem));
+        }
+    }
+    // Very short bases and bases without vowels are almost always wrong, as
+    // in "thing" and "string".
+    bases.retain(|base| base.len() >= 3 && base.chars().any(is_vowel));
+    bases
+}
+
+/// Is `c` a vowel? We count "y", for words like "fly".
+fn is_vowel(c: char) -> bool {
+    "aeiouy".contains(c)
+}
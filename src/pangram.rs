@@ -0,0 +1,239 @@
+//! Finding small sets of words which use every letter in a target set, like
+//! pangrams using the whole alphabet, or Spelling Bee pangrams using seven
+//! letters.
+//!
+//! This is a weighted set cover problem, where each word covers its letters
+//! and costs its negative log probability. We look for covers with as few
+//! words as possible, and then for the most probable of those. At each step,
+//! we branch on the uncovered letter which the fewest words contain, trying
+//! the words which cover the most new letters per unit of cost first.
+
+use log::{debug, trace};
+use std::collections::{HashMap, HashSet};
+
+use crate::dictionary::Dictionary;
+use crate::error::{Error, Result};
+use crate::probability::{Dist, Prob};
+
+/// Options for `find_pangrams`.
+#[derive(Clone, Debug)]
+pub struct PangramOptions {
+    /// Only use words spelled entirely with letters from the target set.
+    pub only_target: bool,
+    /// The maximum number of words in a set.
+    pub max_words: usize,
+    /// The maximum number of words to try for each letter at each step.
+    pub max_candidates: usize,
+    /// The maximum number of sets to return.
+    pub limit: usize,
+}
+
+impl Default for PangramOptions {
+    fn default() -> Self {
+        PangramOptions {
+            only_target: false,
+            max_words: 4,
+            max_candidates: 20,
+            limit: 10,
+        }
+    }
+}
+
+/// A word which covers some of our target letters.
+#[derive(Clone, Debug)]
+struct Candidate {
+    prob: Prob,
+    /// The target letters in this word, as a bit set.
+    mask: u64,
+    word: String,
+}
+
+/// Find the most probable sets of as few words as possible which, between
+/// them, use every letter in `letters`. Each event in the result is a set of
+/// words separated by spaces.
+pub fn find_pangrams(
+    dict: &Dictionary,
+    letters: &str,
+    opts: &PangramOptions,
+) -> Result<Dist<String>> {
+    let mut target = vec![];
+    for c in letters.chars().filter(|c| !c.is_whitespace()) {
+        for c in c.to_lowercase() {
+            if !target.contains(&c) {
+                target.push(c);
+            }
+        }
+    }
+    if target.is_empty() || target.len() > 64 {
+        return Err(Error::InvalidPuzzle(format!(
+            "expected between 1 and 64 target letters, found {}",
+            target.len()
+        )));
+    }
+    let bits = target
+        .iter()
+        .enumerate()
+        .map(|(i, &c)| (c, 1u64 << i))
+        .collect::<HashMap<_, _>>();
+
+    // Keep the most probable word for each set of target letters.
+    let mut best_by_mask = HashMap::<u64, (Prob, String)>::new();
+    'words: for entry in dict.words() {
+        let (word, prob) = entry?;
+        let mut mask = 0;
+        for c in word.chars() {
+            match bits.get(&c) {
+                Some(bit) => mask |= bit,
+                None if opts.only_target => continue 'words,
+                None => {}
+            }
+        }
+        if mask == 0 {
+            continue;
+        }
+        let best = best_by_mask
+            .entry(mask)
+            .or_insert_with(|| (prob, word.clone()));
+        if prob > best.0 {
+            *best = (prob, word);
+        }
+    }
+    let candidates = best_by_mask
+        .into_iter()
+        .map(|(mask, (prob, word))| Candidate { prob, mask, word })
+        .collect::<Vec<_>>();
+    debug!("{} candidate letter sets", candidates.len());
+
+    let mut search = Search {
+        candidates: &candidates,
+        full: target.iter().fold(0, |acc, c| acc | bits[c]),
+        most_letters: candidates
+            .iter()
+            .map(|c| c.mask.count_ones())
+            .max()
+            .unwrap_or(0),
+        coverage: (0..64)
+            .map(|i| candidates.iter().filter(|c| c.mask & (1 << i) != 0).count())
+            .collect(),
+        opts,
+        found: vec![],
+    };
+    for max_words in 1..=opts.max_words {
+        debug!("looking for sets of {} words", max_words);
+        search.search(max_words, 0, Prob::always(), &mut vec![]);
+        if !search.found.is_empty() {
+            break;
+        }
+    }
+    Ok(Dist::from_vec(
+        search
+            .found
+            .into_iter()
+            .map(|(prob, chosen)| {
+                let words = chosen.iter().map(|&i| candidates[i].word.as_str());
+                (prob, words.collect::<Vec<_>>().join(" "))
+            })
+            .collect(),
+    ))
+}
+
+/// Depth-first search for covers.
+struct Search<'a> {
+    candidates: &'a [Candidate],
+    /// Every target letter.
+    full: u64,
+    /// The largest number of target letters in any one word.
+    most_letters: u32,
+    /// The number of candidates containing each target letter.
+    coverage: Vec<usize>,
+    opts: &'a PangramOptions,
+    /// The best covers so far, most probable first, with the index of each
+    /// candidate in them.
+    found: Vec<(Prob, Vec<usize>)>,
+}
+
+impl Search<'_> {
+    /// Look for covers using at most `words_left` more words, given that we
+    /// already have the words in `chosen` which cover `covered` with combined
+    /// probability `prob`.
+    fn search(
+        &mut self,
+        words_left: usize,
+        covered: u64,
+        prob: Prob,
+        chosen: &mut Vec<usize>,
+    ) {
+        let uncovered = self.full & !covered;
+        if uncovered == 0 {
+            self.record(prob, chosen);
+            return;
+        }
+        if words_left == 0
+            || uncovered.count_ones() as usize
+                > words_left * self.most_letters as usize
+        {
+            return;
+        }
+        // Probabilities only go down as we add words.
+        if self.found.len() == self.opts.limit
+            && self.found.last().is_some_and(|(worst, _)| prob <= *worst)
+        {
+            return;
+        }
+
+        // Branch on the uncovered letter with the fewest candidates.
+        let letter = (0..64)
+            .map(|i| 1u64 << i)
+            .filter(|bit| uncovered & bit != 0)
+            .min_by_key(|bit| self.coverage[bit.trailing_zeros() as usize])
+            .expect("some letter is uncovered");
+        let mut options = self
+            .candidates
+            .iter()
+            .enumerate()
+            .filter(|(_, c)| c.mask & letter != 0)
+            .filter(|(_, c)| words_left > 1 || c.mask & uncovered == uncovered)
+            .map(|(i, c)| {
+                let new_letters = (c.mask & uncovered).count_ones() as f64;
+                (c.prob.to_f64() / new_letters, i)
+            })
+            .collect::<Vec<_>>();
+        options.sort_by(|(cost1, _), (cost2, _)| cost1.total_cmp(cost2));
+        options.truncate(self.opts.max_candidates);
+
+        for (_, i) in options {
+            let candidate = &self.candidates[i];
+            trace!("trying {}", candidate.word);
+            chosen.push(i);
+            self.search(
+                words_left - 1,
+                covered | candidate.mask,
+                prob * candidate.prob,
+                chosen,
+            );
+            chosen.pop();
+        }
+    }
+
+    /// Remember the cover in `chosen`, if it's one of the best so far.
+    fn record(&mut self, prob: Prob, chosen: &[usize]) {
+        let mut sorted = chosen.to_owned();
+        sorted.sort_unstable();
+        let seen = self
+            .found
+            .iter()
+            .map(|(_, found)| {
+                let mut found = found.clone();
+                found.sort_unstable();
+                found
+            })
+            .collect::<HashSet<_>>();
+        if seen.contains(&sorted) {
+            return;
+        }
+        debug!("found cover with probability {}", prob);
+        let idx = self.found.partition_point(|(p, _)| *p >= prob);
+        self.found.insert(idx, (prob, chosen.to_owned()));
+        self.found.truncate(self.opts.limit);
+    }
+}
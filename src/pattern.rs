@@ -0,0 +1,125 @@
+//! Extensions to the regex syntax used in search patterns.
+
+use std::{collections::BTreeMap, fs, path::Path};
+
+use crate::error::{Error, Result};
+
+/// Letters which already mean something after a `\` in a regex, and which we
+/// therefore can't use as class names.
+const RESERVED_ESCAPES: &str = "aAbBdDfnpPrsStuUvwWxz";
+
+/// Named character classes which can be used in patterns as `\` followed by
+/// the class name. By default, `\v` matches a vowel and `\c` matches a
+/// consonant, where "y" is a consonant.
+#[derive(Clone, Debug)]
+pub struct CharClasses {
+    /// The letters in each class.
+    classes: BTreeMap<char, String>,
+}
+
+impl Default for CharClasses {
+    fn default() -> Self {
+        let mut classes = BTreeMap::new();
+        classes.insert('v', "aeiou".to_owned());
+        classes.insert('c', "bcdfghjklmnpqrstvwxyz".to_owned());
+        CharClasses { classes }
+    }
+}
+
+impl CharClasses {
+    /// Add the classes defined in `path` to the default classes. Each line
+    /// contains a one-letter class name, whitespace, and the letters in the
+    /// class, such as `h aeiouy`. Blank lines and lines starting with `#` are
+    /// ignored.
+    pub fn with_file(path: &Path) -> Result<CharClasses> {
+        let mut classes = CharClasses::default();
+        let contents = fs::read_to_string(path).map_err(Error::io(path))?;
+        for (i, line) in contents.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let invalid = |message: String| Error::InvalidWordList {
+                path: path.to_owned(),
+                line: i + 1,
+                message,
+            };
+            let mut fields = line.split_whitespace();
+            let (name, letters) = match (fields.next(), fields.next(), fields.next()) {
+                (Some(name), Some(letters), None) => (name, letters),
+                _ => {
+                    return Err(invalid(format!(
+                        "expected \"name letters\", found {:?}",
+                        line
+                    )))
+                }
+            };
+            let mut name_chars = name.chars();
+            let name = match (name_chars.next(), name_chars.next()) {
+                (Some(c), None) if c.is_ascii_alphabetic() => c,
+                _ => {
+                    return Err(invalid(format!(
+                        "class name {:?} must be a single letter",
+                        name
+                    )))
+                }
+            };
+            if name != 'v' && RESERVED_ESCAPES.contains(name) {
+                return Err(invalid(format!(
+                    "\\{} already has a meaning in regexes",
+                    name
+                )));
+            }
+            if !letters.chars().all(char::is_alphabetic) {
+                return Err(invalid(format!(
+                    "class {:?} may only contain letters",
+                    letters
+                )));
+            }
+            classes.classes.insert(name, letters.to_lowercase());
+        }
+        Ok(classes)
+    }
+
+    /// Is there a class named `name`?
+    pub fn contains(&self, name: char) -> bool {
+        self.classes.contains_key(&name)
+    }
+
+    /// Replace each class in `regex` with the equivalent regex character
+    /// class.
+    pub fn expand(&self, regex: &str) -> String {
+        let mut expanded = String::with_capacity(regex.len());
+        let mut in_brackets = false;
+        let mut chars = regex.chars();
+        while let Some(c) = chars.next() {
+            match c {
+                '\\' => match chars.next() {
+                    Some(name) => match self.classes.get(&name) {
+                        Some(letters) if in_brackets => expanded.push_str(letters),
+                        Some(letters) => {
+                            expanded.push('[');
+                            expanded.push_str(letters);
+                            expanded.push(']');
+                        }
+                        None => {
+                            expanded.push('\\');
+                            expanded.push(name);
+                        }
+                    },
+                    None => expanded.push('\\'),
+                },
+                '[' => {
+                    in_brackets = true;
+                    expanded.push(c);
+                }
+                ']' => {
+                    in_brackets = false;
+                    expanded.push(c);
+                }
+                _ => expanded.push(c),
+            }
+        }
+        expanded
+    }
+}
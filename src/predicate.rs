@@ -0,0 +1,234 @@
+//! Tests which words must pass, which can be combined and applied to any
+//! stream of dictionary words.
+
+use std::{collections::HashSet, fmt, path::Path, sync::Arc};
+
+use crate::dictionary::{compile_full_regex, read_word_list, Dictionary};
+use crate::error::Result;
+use crate::metadata::content_hash;
+
+/// A test which a word must pass.
+pub trait Predicate: fmt::Debug + Send + Sync {
+    /// Does `word` pass this test?
+    fn accepts(&self, word: &str) -> bool;
+}
+
+/// A set of predicates which a word must all pass. An empty set accepts every
+/// word.
+#[derive(Clone, Debug, Default)]
+pub struct Predicates {
+    predicates: Vec<Arc<dyn Predicate>>,
+}
+
+impl Predicates {
+    /// Create an empty set of predicates.
+    pub fn new() -> Predicates {
+        Predicates::default()
+    }
+
+    /// Require words to pass `predicate` as well.
+    pub fn push(&mut self, predicate: impl Predicate + 'static) {
+        self.predicates.push(Arc::new(predicate));
+    }
+
+    /// Does this set accept every word?
+    pub fn is_empty(&self) -> bool {
+        self.predicates.is_empty()
+    }
+
+    /// Does `word` pass every predicate?
+    pub fn accepts(&self, word: &str) -> bool {
+        self.predicates.iter().all(|p| p.accepts(word))
+    }
+}
+
+/// Accepts words which match a regex, which is automatically anchored on both
+/// ends.
+#[derive(Debug)]
+pub struct Matches {
+    regex: fancy_regex::Regex,
+}
+
+impl Matches {
+    /// Accept words matching `regex`.
+    pub fn new(regex: &str) -> Result<Matches> {
+        Ok(Matches {
+            regex: compile_full_regex(regex)?,
+        })
+    }
+}
+
+impl Predicate for Matches {
+    fn accepts(&self, word: &str) -> bool {
+        self.regex.is_match(word).unwrap_or(false)
+    }
+}
+
+/// Accepts words which can be spelled using the letters in a bank, using each
+/// letter no more often than it appears in the bank.
+#[derive(Debug)]
+pub struct FromBank {
+    counts: [u16; 256],
+}
+
+impl FromBank {
+    /// Accept words spelled with the letters in `bank`. Whitespace is ignored.
+    pub fn new(bank: &str) -> FromBank {
+        let mut counts = [0; 256];
+        for b in bank.to_lowercase().bytes() {
+            if !b.is_ascii_whitespace() {
+                counts[b as usize] += 1;
+            }
+        }
+        FromBank { counts }
+    }
+}
+
+impl Predicate for FromBank {
+    fn accepts(&self, word: &str) -> bool {
+        let mut counts = self.counts;
+        word.bytes().all(|b| {
+            let count = &mut counts[b as usize];
+            *count > 0 && {
+                *count -= 1;
+                true
+            }
+        })
+    }
+}
+
+/// Accepts words which never use the same letter twice.
+#[derive(Debug)]
+pub struct Isogram;
+
+impl Predicate for Isogram {
+    fn accepts(&self, word: &str) -> bool {
+        let mut seen = vec![];
+        word.chars().all(|c| {
+            let new = !seen.contains(&c);
+            seen.push(c);
+            new
+        })
+    }
+}
+
+/// Accepts words which use at most a given number of different letters.
+#[derive(Debug)]
+pub struct MaxDistinctLetters(pub usize);
+
+impl Predicate for MaxDistinctLetters {
+    fn accepts(&self, word: &str) -> bool {
+        let mut seen = vec![];
+        for c in word.chars() {
+            if !seen.contains(&c) {
+                seen.push(c);
+                if seen.len() > self.0 {
+                    return false;
+                }
+            }
+        }
+        true
+    }
+}
+
+/// Accepts words which use the same letter twice in a row somewhere.
+#[derive(Debug)]
+pub struct DoubleLetters;
+
+impl Predicate for DoubleLetters {
+    fn accepts(&self, word: &str) -> bool {
+        word.chars()
+            .zip(word.chars().skip(1))
+            .any(|(c1, c2)| c1 == c2)
+    }
+}
+
+/// Accepts words whose letters alternate between vowels and consonants.
+#[derive(Debug)]
+pub struct AlternatesVowels;
+
+impl Predicate for AlternatesVowels {
+    fn accepts(&self, word: &str) -> bool {
+        let is_vowel = |c: char| "aeiou".contains(c);
+        word.chars()
+            .zip(word.chars().skip(1))
+            .all(|(c1, c2)| is_vowel(c1) != is_vowel(c2))
+    }
+}
+
+/// Rejects words in a blocklist, such as offensive words to leave out of
+/// puzzles for publication. Phrases are rejected if any of their words is
+/// blocked.
+pub struct NotBlocked {
+    blocked: Blocked,
+    /// A hash of the blocklist, so that results cached using one blocklist
+    /// aren't reused with another.
+    hash: String,
+}
+
+/// The words in a blocklist.
+enum Blocked {
+    /// A list of words, read from a text file.
+    Words(HashSet<String>),
+    /// A compiled dictionary. We ignore its filter and probabilities.
+    Dictionary(Dictionary),
+}
+
+impl NotBlocked {
+    /// Reject each of `words`.
+    pub fn new<I>(words: I) -> NotBlocked
+    where
+        I: IntoIterator<Item = String>,
+    {
+        let mut words = words.into_iter().collect::<Vec<_>>();
+        words.sort_unstable();
+        words.dedup();
+        NotBlocked {
+            hash: content_hash(words.join("\n").as_bytes()),
+            blocked: Blocked::Words(words.into_iter().collect()),
+        }
+    }
+
+    /// Reject every word in `dict`.
+    pub fn from_dictionary(dict: Dictionary) -> NotBlocked {
+        NotBlocked {
+            hash: dict.content_hash(),
+            blocked: Blocked::Dictionary(dict),
+        }
+    }
+
+    /// Load a blocklist from `path`, which is either a compiled dictionary
+    /// ending in ".fst", or a word list with one word per line as read by
+    /// `read_word_list`.
+    pub fn load(path: &Path) -> Result<NotBlocked> {
+        if path.extension().is_some_and(|ext| ext == "fst") {
+            Ok(NotBlocked::from_dictionary(Dictionary::load(path)?))
+        } else {
+            Ok(NotBlocked::new(read_word_list(path)?))
+        }
+    }
+
+    /// Is `word` itself in the blocklist?
+    fn is_blocked(&self, word: &str) -> bool {
+        match &self.blocked {
+            Blocked::Words(words) => words.contains(word),
+            Blocked::Dictionary(dict) => dict.raw_probability_of(word).is_some(),
+        }
+    }
+}
+
+/// Show the hash of the blocklist, rather than every word.
+impl fmt::Debug for NotBlocked {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("NotBlocked")
+            .field("hash", &self.hash)
+            .finish()
+    }
+}
+
+impl Predicate for NotBlocked {
+    fn accepts(&self, word: &str) -> bool {
+        !self.is_blocked(word)
+            && (!word.contains(' ') || !word.split(' ').any(|w| self.is_blocked(w)))
+    }
+}
@@ -1,7 +1,18 @@
 //! Routines for working with probability.
 
 use ordered_float::OrderedFloat;
-use std::{fmt, ops::Mul, slice};
+use serde::{Deserialize, Serialize};
+use std::{
+    cmp::Ordering,
+    collections::{hash_map::Entry, BinaryHeap, HashMap, HashSet},
+    fmt,
+    hash::Hash,
+    ops::{Div, Mul},
+    slice,
+    str::FromStr,
+};
+
+use crate::error::{Error, Result};
 
 /// A probability, represented as negative log probability. This makes it
 /// trivial to describe highly improbable events without underflowing a `f64`.
@@ -9,7 +20,11 @@ use std::{fmt, ops::Mul, slice};
 /// This also means that we can't represent a probability of 0, but that's OK,
 /// because in a naive Bayesian world, a probability of 0 means "This is
 /// absolutely impossible and no amount of evidence can convince me otherwise."
-#[derive(Clone, Copy, PartialEq)]
+///
+/// A valid `Prob` is never NaN or negative. The checked constructors enforce
+/// this, and our ordering is total even if something slips through, so that a
+/// corrupt dictionary can't break sorting.
+#[derive(Clone, Copy, Serialize)]
 pub struct Prob(f64);
 
 impl Prob {
@@ -23,15 +38,251 @@ impl Prob {
         Self(-f64::ln(num as f64 / denom as f64))
     }
 
+    /// Construct a probability from an ordinary `f64` between 0.0 and 1.0.
+    pub fn from_probability(p: f64) -> Self {
+        Self(-f64::ln(p))
+    }
+
     /// Convert from a 64-bit number, typically coming from an `fst::Map`.
+    /// This doesn't check the result; see `try_from_bits`.
     pub fn from_bits(bits: u64) -> Self {
         Self(f64::from_bits(bits))
     }
 
+    /// Like `from_bits`, but fail if the result isn't valid.
+    pub fn try_from_bits(bits: u64) -> Result<Self> {
+        Prob::from_f64(f64::from_bits(bits))
+    }
+
+    /// Construct a probability from a raw negative log probability, failing if
+    /// it's NaN or negative.
+    pub fn from_f64(neg_log_prob: f64) -> Result<Self> {
+        let prob = Self(neg_log_prob);
+        if prob.is_valid() {
+            Ok(prob)
+        } else {
+            Err(Error::InvalidProbability(neg_log_prob))
+        }
+    }
+
+    /// Is this a real probability? Negative log probabilities must be zero or
+    /// more, and may be infinite for impossible events.
+    pub fn is_valid(self) -> bool {
+        self.0 >= 0.0
+    }
+
     // Convert to a 64-bit number for storage in an `fst::Map`.
     pub fn to_bits(self) -> u64 {
         self.0.to_bits()
     }
+
+    /// Convert to an ordinary `f64` between 0.0 and 1.0.
+    pub fn to_probability(self) -> f64 {
+        f64::exp(-self.0)
+    }
+
+    /// The raw negative log probability.
+    pub fn to_f64(self) -> f64 {
+        self.0
+    }
+
+    /// Raise this probability to the power `exponent`.
+    pub fn powf(self, exponent: f64) -> Self {
+        Self(self.0 * exponent)
+    }
+
+    /// The sum of `probs`, computed without underflowing.
+    pub fn sum(probs: impl IntoIterator<Item = Prob>) -> Self {
+        // Use the log-sum-exp trick, factoring out the most probable event.
+        let probs = probs.into_iter().map(|p| p.0).collect::<Vec<_>>();
+        let best = probs.iter().cloned().fold(f64::INFINITY, f64::min);
+        if best.is_infinite() {
+            return Self(f64::INFINITY);
+        }
+        let total = probs.iter().map(|&p| f64::exp(best - p)).sum::<f64>();
+        Self(best - total.ln())
+    }
+
+    /// Display this probability using `format`. Like our `Display`
+    /// implementation, this respects the width and precision of the format
+    /// string.
+    pub fn display(self, format: ProbFormat) -> ProbDisplay {
+        ProbDisplay { prob: self, format }
+    }
+}
+
+/// How to show a probability to people.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ProbFormat {
+    /// Negative natural log probability, which is how we store probabilities.
+    #[default]
+    Nats,
+    /// Negative base-2 log probability.
+    Bits,
+    /// Negative base-10 log probability, roughly the number of zeros after the
+    /// decimal point.
+    Log10,
+    /// A percentage, such as "0.03%".
+    Percent,
+    /// Odds, such as "1:3332".
+    Odds,
+}
+
+impl FromStr for ProbFormat {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "nats" => Ok(ProbFormat::Nats),
+            "bits" => Ok(ProbFormat::Bits),
+            "log10" => Ok(ProbFormat::Log10),
+            "percent" => Ok(ProbFormat::Percent),
+            "odds" => Ok(ProbFormat::Odds),
+            _ => Err(Error::InvalidOption(format!(
+                "unknown probability format {:?}, expected nats, bits, log10, \
+                 percent or odds",
+                s
+            ))),
+        }
+    }
+}
+
+/// A probability displayed using a `ProbFormat`.
+pub struct ProbDisplay {
+    prob: Prob,
+    format: ProbFormat,
+}
+
+impl fmt::Display for ProbDisplay {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let nats = self.prob.0;
+        let precision = f.precision().unwrap_or(2);
+        let text = match self.format {
+            ProbFormat::Nats => format!("{:.*}", precision, nats),
+            ProbFormat::Bits => {
+                format!("{:.*}", precision, nats / std::f64::consts::LN_2)
+            }
+            ProbFormat::Log10 => {
+                format!("{:.*}", precision, nats / std::f64::consts::LN_10)
+            }
+            ProbFormat::Percent => {
+                let percent = self.prob.to_probability() * 100.0;
+                // Show tiny percentages in scientific notation, instead of
+                // rounding them to zero.
+                if percent == 0.0 || percent >= 10f64.powi(-(precision as i32)) {
+                    format!("{:.*}%", precision, percent)
+                } else {
+                    format!("{:.*e}%", precision.saturating_sub(1), percent)
+                }
+            }
+            ProbFormat::Odds => {
+                // Show the odds against unlikely events as "1:N", and the odds
+                // in favor of likely events as "N:1".
+                let p = self.prob.to_probability();
+                if p <= 0.5 {
+                    format!("1:{:.0}", (1.0 - p) / p)
+                } else {
+                    format!("{:.0}:1", p / (1.0 - p))
+                }
+            }
+        };
+        match f.width() {
+            Some(width) => write!(f, "{:>width$}", text, width = width),
+            None => f.write_str(&text),
+        }
+    }
+}
+
+/// A scheme for storing probabilities in fewer bits, by dividing the range of
+/// negative log probabilities into equal-sized buckets.
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize, Serialize)]
+pub struct Quantization {
+    /// The number of bits used for each probability.
+    pub bits: u32,
+    /// The smallest finite negative log probability we can represent.
+    pub min: f64,
+    /// The largest finite negative log probability we can represent.
+    pub max: f64,
+    /// Do we reserve the top bucket for a probability of 0, for words with a
+    /// count of 0?
+    #[serde(default)]
+    pub zero_bucket: bool,
+}
+
+impl Quantization {
+    /// Choose buckets covering every probability in `probs`. `bits` must be
+    /// between 1 and 32.
+    pub fn covering(bits: u32, probs: impl IntoIterator<Item = Prob>) -> Self {
+        assert!((1..=32).contains(&bits), "cannot quantize to {} bits", bits);
+        let mut min = f64::INFINITY;
+        let mut max = f64::NEG_INFINITY;
+        let mut zero_bucket = false;
+        for prob in probs {
+            if prob.0.is_finite() {
+                min = min.min(prob.0);
+                max = max.max(prob.0);
+            } else {
+                zero_bucket = true;
+            }
+        }
+        if min > max {
+            min = 0.0;
+            max = 0.0;
+        }
+        Quantization {
+            bits,
+            min,
+            max,
+            zero_bucket,
+        }
+    }
+
+    /// The highest bucket number.
+    fn top_bucket(&self) -> u64 {
+        (1u64 << self.bits) - 1
+    }
+
+    /// The highest bucket number used for finite probabilities.
+    fn top_finite_bucket(&self) -> u64 {
+        self.top_bucket() - u64::from(self.zero_bucket)
+    }
+
+    /// The width of each bucket.
+    fn step(&self) -> f64 {
+        match self.top_finite_bucket() {
+            0 => 0.0,
+            buckets => (self.max - self.min) / buckets as f64,
+        }
+    }
+
+    /// Convert `prob` to a bucket number.
+    pub fn encode(&self, prob: Prob) -> u64 {
+        let step = self.step();
+        if self.zero_bucket && prob.0.is_infinite() {
+            self.top_bucket()
+        } else if step == 0.0 {
+            0
+        } else {
+            let bucket = ((prob.0 - self.min) / step).round().max(0.0) as u64;
+            bucket.min(self.top_finite_bucket())
+        }
+    }
+
+    /// Convert a bucket number back to a probability.
+    pub fn decode(&self, bucket: u64) -> Prob {
+        if self.zero_bucket && bucket == self.top_bucket() {
+            Prob(f64::INFINITY)
+        } else {
+            Prob(self.min + bucket as f64 * self.step())
+        }
+    }
+
+    /// The largest difference between a negative log probability and its
+    /// quantized value. The quantized probability is within a factor of
+    /// `exp(max_error())` of the original.
+    pub fn max_error(&self) -> f64 {
+        self.step() / 2.0
+    }
 }
 
 impl fmt::Debug for Prob {
@@ -49,14 +300,41 @@ impl fmt::Display for Prob {
 impl Mul for Prob {
     type Output = Self;
     fn mul(self, rhs: Self) -> Self {
+        // Multiplying probabilities means adding negative log probabilities.
+        #[allow(clippy::suspicious_arithmetic_impl)]
         Self(self.0 + rhs.0)
     }
 }
 
+impl Div for Prob {
+    type Output = Self;
+    fn div(self, rhs: Self) -> Self {
+        // Dividing probabilities means subtracting negative log probabilities.
+        #[allow(clippy::suspicious_arithmetic_impl)]
+        Self(self.0 - rhs.0)
+    }
+}
+
+impl PartialEq for Prob {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Prob {}
+
 impl PartialOrd for Prob {
-    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Prob {
+    fn cmp(&self, other: &Self) -> Ordering {
         // Flip order of comparison because we use negative log probability.
-        other.0.partial_cmp(&self.0)
+        // `OrderedFloat` sorts NaN above everything else, which makes it less
+        // probable than any real probability.
+        OrderedFloat(other.0).cmp(&OrderedFloat(self.0))
     }
 }
 
@@ -67,15 +345,215 @@ impl PartialOrd for Prob {
 #[derive(Debug)]
 pub struct Dist<T>(Vec<(Prob, T)>);
 
+/// Check that `temperature` can be used to rescale probabilities. Zero,
+/// negative and infinite temperatures, and NaN, would give probabilities
+/// which aren't numbers.
+pub fn check_temperature(temperature: f64) -> Result<()> {
+    if temperature.is_finite() && temperature > 0.0 {
+        Ok(())
+    } else {
+        Err(Error::InvalidOption(format!(
+            "temperature must be a positive finite number, not {}",
+            temperature
+        )))
+    }
+}
+
 impl<T> Dist<T> {
     /// Construct a distribution from a vector of events.
     pub fn from_vec(v: Vec<(Prob, T)>) -> Self {
         Dist(v)
     }
 
+    /// The number of events in this distribution.
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    /// Is this distribution empty?
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Convert a distribution back into a vector of events.
+    pub fn into_vec(self) -> Vec<(Prob, T)> {
+        self.0
+    }
+
     /// Sort a probability distribution in order of descending probability.
     pub fn sort_by_probability(&mut self) {
-        self.0.sort_by_key(|(p, _)| OrderedFloat(p.0));
+        self.0.sort_by(|(p1, _), (p2, _)| p2.cmp(p1));
+    }
+
+    /// Remove every event with the same key as an earlier event, so that the
+    /// first (and normally most probable) event for each key is kept. Unlike
+    /// `Vec::dedup_by_key`, duplicates don't need to be adjacent.
+    pub fn dedup_by_key<K, F>(&mut self, mut key: F)
+    where
+        K: Eq + Hash,
+        F: FnMut(&T) -> K,
+    {
+        let mut seen = HashSet::new();
+        self.0.retain(|(_, value)| seen.insert(key(value)));
+    }
+
+    /// Split this distribution into groups of events with the same key. Groups
+    /// are in order of their first event, and events keep their order within
+    /// each group.
+    pub fn group_by<K, F>(self, mut key: F) -> Vec<(K, Dist<T>)>
+    where
+        K: Eq + Hash + Clone,
+        F: FnMut(&T) -> K,
+    {
+        let mut groups = Vec::<(K, Dist<T>)>::new();
+        let mut indices = HashMap::new();
+        for (prob, value) in self.0 {
+            let k = key(&value);
+            let idx = match indices.entry(k.clone()) {
+                Entry::Occupied(entry) => *entry.get(),
+                Entry::Vacant(entry) => {
+                    groups.push((k, Dist(vec![])));
+                    *entry.insert(groups.len() - 1)
+                }
+            };
+            groups[idx].1 .0.push((prob, value));
+        }
+        groups
+    }
+
+    /// Raise each probability to the power `1 / temperature` and renormalize,
+    /// so that the probabilities sum to 1. Temperatures above 1 flatten the
+    /// distribution, making unlikely events more likely, and temperatures
+    /// below 1 sharpen it. Returns an error unless `temperature` is finite
+    /// and positive, as checked by `check_temperature`.
+    pub fn rescale(&mut self, temperature: f64) -> Result<()> {
+        check_temperature(temperature)?;
+        for (p, _) in &mut self.0 {
+            *p = p.powf(1.0 / temperature);
+        }
+        self.normalize();
+        Ok(())
+    }
+
+    /// Divide each probability by their total, so that they sum to 1. This
+    /// turns probabilities drawn from a larger distribution, like a whole
+    /// dictionary, into probabilities given that one of our events happens.
+    pub fn normalize(&mut self) {
+        let total = Prob::sum(self.0.iter().map(|(p, _)| *p));
+        for (p, _) in &mut self.0 {
+            *p = *p / total;
+        }
+    }
+
+    /// Keep only the first `len` events.
+    pub fn truncate(&mut self, len: usize) {
+        self.0.truncate(len);
+    }
+
+    /// Start collecting the `limit` most probable events from a search.
+    pub fn top_k(limit: usize) -> TopK<T> {
+        TopK {
+            limit,
+            heap: BinaryHeap::new(),
+            pushed: 0,
+        }
+    }
+}
+
+/// Collects the most probable events from a search as they're found, so that
+/// searches which find huge numbers of results only need memory for the
+/// `limit` events they keep, and never need to sort the rest.
+///
+/// Events with equal probabilities are kept in the order they were pushed,
+/// so we keep exactly the same events as sorting every event and then
+/// truncating.
+#[derive(Debug)]
+pub struct TopK<T> {
+    /// The maximum number of events to keep.
+    limit: usize,
+    /// The events we're keeping, with the first one we'd drop on top.
+    heap: BinaryHeap<Ranked<T>>,
+    /// The number of events pushed so far.
+    pushed: usize,
+}
+
+impl<T> TopK<T> {
+    /// Add an event, which we'll drop if `limit` more probable events have
+    /// already been added.
+    pub fn push(&mut self, prob: Prob, value: T) {
+        let event = Ranked {
+            prob,
+            seq: self.pushed,
+            value,
+        };
+        self.pushed += 1;
+        if self.heap.len() < self.limit {
+            self.heap.push(event);
+        } else if let Some(mut worst) = self.heap.peek_mut() {
+            if event < *worst {
+                *worst = event;
+            }
+        }
+    }
+
+    /// Would we keep an event with probability `prob` if it were pushed now?
+    /// This lets callers avoid building events we'd drop.
+    pub fn would_keep(&self, prob: Prob) -> bool {
+        self.heap.len() < self.limit
+            || self.heap.peek().is_some_and(|worst| prob > worst.prob)
+    }
+
+    /// The number of events pushed so far, including any we dropped.
+    pub fn pushed(&self) -> usize {
+        self.pushed
+    }
+
+    /// The events we're keeping, from most to least probable.
+    pub fn sorted(&self) -> Vec<(Prob, &T)> {
+        let mut events = self.heap.iter().collect::<Vec<_>>();
+        events.sort();
+        events.into_iter().map(|e| (e.prob, &e.value)).collect()
+    }
+
+    /// The events we're keeping, sorted from most to least probable.
+    pub fn into_dist(self) -> Dist<T> {
+        Dist(
+            self.heap
+                .into_sorted_vec()
+                .into_iter()
+                .map(|e| (e.prob, e.value))
+                .collect(),
+        )
+    }
+}
+
+/// An event in a `TopK`. These are ordered from best to worst: more probable
+/// events first, and then earlier events first.
+#[derive(Debug)]
+struct Ranked<T> {
+    prob: Prob,
+    /// The number of events pushed before this one.
+    seq: usize,
+    value: T,
+}
+
+impl<T> PartialEq for Ranked<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl<T> Eq for Ranked<T> {}
+
+impl<T> PartialOrd for Ranked<T> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<T> Ord for Ranked<T> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        other.prob.cmp(&self.prob).then(self.seq.cmp(&other.seq))
     }
 }
 
@@ -94,7 +572,7 @@ impl<'a, T> IntoIterator for &'a Dist<T> {
     type IntoIter = DistIter<'a, T>;
 
     fn into_iter(self) -> Self::IntoIter {
-        DistIter((&self.0).into_iter())
+        DistIter(self.0.iter())
     }
 }
 
@@ -0,0 +1,80 @@
+//! Pronunciations, in the format used by the CMU Pronouncing Dictionary.
+//!
+//! Each pronunciation is a space-separated list of ARPAbet phones, where
+//! vowels end in a stress marker: `1` for primary stress, `2` for secondary
+//! stress and `0` for no stress. For example, "table" is `T EY1 B AH0 L`.
+
+use std::{
+    fs::File,
+    io::{BufRead, BufReader},
+    path::Path,
+};
+
+use crate::error::{Error, Result};
+
+/// Read a CMU-style pronouncing dictionary, returning lowercase `(word,
+/// pronunciation)` pairs for each word where `keep(word)` returns true. Lines
+/// starting with `;;;` are comments, and alternate pronunciations are written
+/// as `WORD(2)`.
+pub fn read_pronunciations(
+    path: &Path,
+    keep: impl Fn(&str) -> bool,
+) -> Result<Vec<(String, String)>> {
+    let file = File::open(path).map_err(Error::io(path))?;
+    let mut rdr = BufReader::new(file);
+    let mut pronunciations = vec![];
+    let mut buf = vec![];
+    loop {
+        buf.clear();
+        let read = rdr.read_until(b'\n', &mut buf).map_err(Error::io(path))?;
+        if read == 0 {
+            break;
+        }
+
+        // Older versions of the CMU dictionary are in Latin-1, but the
+        // entries we care about are all ASCII.
+        let line = String::from_utf8_lossy(&buf);
+        if line.starts_with(";;;") {
+            continue;
+        }
+        let mut fields = line.split_whitespace();
+        let word = match fields.next() {
+            Some(word) => word,
+            None => continue,
+        };
+        let word = match word.find('(') {
+            Some(paren) => &word[..paren],
+            None => word,
+        }
+        .to_lowercase();
+        let phones = fields.collect::<Vec<_>>().join(" ");
+        if !phones.is_empty() && keep(&word) {
+            pronunciations.push((word, phones));
+        }
+    }
+    Ok(pronunciations)
+}
+
+/// Is `phone` a vowel? Vowels are the phones with stress markers.
+pub fn is_vowel(phone: &str) -> bool {
+    phone.ends_with(|c: char| c.is_ascii_digit())
+}
+
+/// The part of `phones` which needs to match for two words to rhyme: the last
+/// vowel with primary stress and everything after it. If no vowel has primary
+/// stress, we use the last vowel.
+pub fn rhyme_part(phones: &str) -> String {
+    let phones = phones.split(' ').collect::<Vec<_>>();
+    let start = phones
+        .iter()
+        .rposition(|p| p.ends_with('1'))
+        .or_else(|| phones.iter().rposition(|p| is_vowel(p)))
+        .unwrap_or(0);
+    phones[start..].join(" ")
+}
+
+/// Reverse the order of the phones in `phones`, so that we can look up
+/// pronunciation endings using prefix queries.
+pub fn reverse_phones(phones: &str) -> String {
+    phones.split(' ').rev().collect::<Vec<_>>().join(" ")
+}
@@ -0,0 +1,273 @@
+//! Reading and writing Across Lite `.puz` files.
+//!
+//! A `.puz` file starts with a fixed header, followed by the solution and the
+//! player's progress, each with one byte per cell, row by row. In both, `.`
+//! is a black square, and in the progress, `-` is an empty square. Then come
+//! NUL-terminated strings: the title, author and copyright, one clue for each
+//! slot in clue order, and the notes. Text is in ISO-8859-1.
+//!
+//! The header holds several checksums. We don't check them when reading,
+//! since files in the wild often get them wrong, but we compute them all when
+//! writing, since Across Lite refuses files whose checksums are wrong.
+
+use std::convert::TryFrom;
+
+use crate::error::{Error, Result};
+use crate::grid::{Cell, Grid};
+use crate::puzzle::{Clue, Puzzle};
+
+/// The magic string at `MAGIC_OFFSET` in every `.puz` file.
+const MAGIC: &[u8] = b"ACROSS&DOWN\0";
+
+/// Where we find `MAGIC`.
+const MAGIC_OFFSET: usize = 0x02;
+
+/// Where we find the checksum of the CIB, the part of the header describing
+/// the grid.
+const CIB_CHECKSUM_OFFSET: usize = 0x0E;
+
+/// Where we find the masked checksums, low bytes first.
+const MASKED_CHECKSUMS_OFFSET: usize = 0x10;
+
+/// Where we find the version string.
+const VERSION_OFFSET: usize = 0x18;
+
+/// Where the CIB starts: the width and height of the grid, one byte each,
+/// then the number of clues, a bitmask, and a flag saying the solution is
+/// scrambled, each two bytes.
+const CIB_OFFSET: usize = 0x2C;
+
+/// Where we find the flag saying the solution is scrambled.
+const SCRAMBLED_OFFSET: usize = 0x32;
+
+/// The length of the header, after which the solution starts.
+const HEADER_LEN: usize = 0x34;
+
+/// Read a puzzle from `bytes`, the contents of a `.puz` file.
+pub fn read_puz(bytes: &[u8]) -> Result<Puzzle> {
+    let magic = bytes.get(MAGIC_OFFSET..MAGIC_OFFSET + MAGIC.len());
+    if magic != Some(MAGIC) || bytes.len() < HEADER_LEN {
+        return Err(Error::InvalidPuzzle(
+            "not an Across Lite .puz file".to_owned(),
+        ));
+    }
+    let width = usize::from(bytes[CIB_OFFSET]);
+    let height = usize::from(bytes[CIB_OFFSET + 1]);
+    let clue_count = usize::from(u16::from_le_bytes([
+        bytes[CIB_OFFSET + 2],
+        bytes[CIB_OFFSET + 3],
+    ]));
+    let len = width * height;
+    let grids = &bytes[HEADER_LEN..];
+    if grids.len() < 2 * len {
+        return Err(Error::InvalidPuzzle(format!(
+            ".puz file is too short for a {}×{} grid",
+            width, height
+        )));
+    }
+    if bytes[SCRAMBLED_OFFSET..SCRAMBLED_OFFSET + 2] != [0, 0] {
+        return Err(Error::InvalidPuzzle(
+            ".puz file has a scrambled solution".to_owned(),
+        ));
+    }
+    let solution = Grid::new(width, decode_cells(&grids[..len])?)?;
+    let progress = Grid::new(width, decode_cells(&grids[len..2 * len])?)?;
+
+    let mut strings = grids[2 * len..].split(|&b| b == 0).map(decode_text);
+    let mut next_string = || strings.next().unwrap_or_default();
+    let mut puzzle = Puzzle::new(solution);
+    puzzle.progress = progress;
+    puzzle.title = next_string();
+    puzzle.author = next_string();
+    puzzle.copyright = next_string();
+    let slots = puzzle.slots_in_clue_order();
+    if slots.len() != clue_count {
+        return Err(Error::InvalidPuzzle(format!(
+            ".puz file has {} clues, but its grid has {} slots",
+            clue_count,
+            slots.len()
+        )));
+    }
+    for slot in slots {
+        puzzle.clues.push(Clue {
+            number: slot.number,
+            direction: slot.direction,
+            text: next_string(),
+        });
+    }
+    puzzle.notes = next_string();
+    Ok(puzzle)
+}
+
+/// Write `puzzle` as a `.puz` file. Slots without clues get empty clues.
+pub fn write_puz(puzzle: &Puzzle) -> Result<Vec<u8>> {
+    let size = (
+        u8::try_from(puzzle.solution.width()),
+        u8::try_from(puzzle.solution.height()),
+    );
+    let (width, height) = match size {
+        (Ok(width), Ok(height)) => (width, height),
+        _ => {
+            return Err(Error::InvalidPuzzle(
+                ".puz files can't hold grids more than 255 cells across".to_owned(),
+            ))
+        }
+    };
+    if puzzle.solution.cells().contains(&Cell::Open) {
+        return Err(Error::InvalidPuzzle(
+            ".puz files need a complete solution".to_owned(),
+        ));
+    }
+    let solution = encode_cells(&puzzle.solution)?;
+    let progress = encode_cells(&puzzle.progress)?;
+    let text = Text {
+        title: encode_text(&puzzle.title),
+        author: encode_text(&puzzle.author),
+        copyright: encode_text(&puzzle.copyright),
+        clues: puzzle
+            .slots_in_clue_order()
+            .iter()
+            .map(|slot| encode_text(puzzle.clue(slot).unwrap_or_default()))
+            .collect(),
+        notes: encode_text(&puzzle.notes),
+    };
+    let clue_count = u16::try_from(text.clues.len()).map_err(|_| {
+        Error::InvalidPuzzle(".puz files can't hold this many clues".to_owned())
+    })?;
+
+    let mut cib = [0; HEADER_LEN - CIB_OFFSET];
+    cib[0] = width;
+    cib[1] = height;
+    cib[2..4].copy_from_slice(&clue_count.to_le_bytes());
+    // A bitmask which is always 1 for normal puzzles.
+    cib[4] = 1;
+
+    let cib_sum = checksum(&cib, 0);
+    let mut file_sum = checksum(&solution, cib_sum);
+    file_sum = checksum(&progress, file_sum);
+    file_sum = text.checksum(file_sum);
+    let masked_sums = [
+        cib_sum,
+        checksum(&solution, 0),
+        checksum(&progress, 0),
+        text.checksum(0),
+    ];
+
+    let mut bytes = vec![0; HEADER_LEN];
+    bytes[..2].copy_from_slice(&file_sum.to_le_bytes());
+    bytes[MAGIC_OFFSET..MAGIC_OFFSET + MAGIC.len()].copy_from_slice(MAGIC);
+    bytes[CIB_CHECKSUM_OFFSET..CIB_CHECKSUM_OFFSET + 2]
+        .copy_from_slice(&cib_sum.to_le_bytes());
+    // The low bytes are masked with "ICHE" and the high bytes with "ATED".
+    for (i, sum) in masked_sums.iter().enumerate() {
+        let [low, high] = sum.to_le_bytes();
+        bytes[MASKED_CHECKSUMS_OFFSET + i] = b"ICHE"[i] ^ low;
+        bytes[MASKED_CHECKSUMS_OFFSET + 4 + i] = b"ATED"[i] ^ high;
+    }
+    bytes[VERSION_OFFSET..VERSION_OFFSET + 4].copy_from_slice(b"1.3\0");
+    bytes[CIB_OFFSET..].copy_from_slice(&cib);
+    bytes.extend_from_slice(&solution);
+    bytes.extend_from_slice(&progress);
+    for string in text.strings() {
+        bytes.extend_from_slice(string);
+        bytes.push(0);
+    }
+    Ok(bytes)
+}
+
+/// The strings at the end of a `.puz` file, encoded.
+struct Text {
+    title: Vec<u8>,
+    author: Vec<u8>,
+    copyright: Vec<u8>,
+    clues: Vec<Vec<u8>>,
+    notes: Vec<u8>,
+}
+
+impl Text {
+    /// Every string, in the order they're stored.
+    fn strings(&self) -> impl Iterator<Item = &Vec<u8>> {
+        IntoIterator::into_iter([&self.title, &self.author, &self.copyright])
+            .chain(&self.clues)
+            .chain([&self.notes])
+    }
+
+    /// Continue `sum` over our strings. Empty strings are skipped, and clues
+    /// are checksummed without their NULs.
+    fn checksum(&self, mut sum: u16) -> u16 {
+        for string in [&self.title, &self.author, &self.copyright] {
+            if !string.is_empty() {
+                sum = checksum(string, sum);
+                sum = checksum(&[0], sum);
+            }
+        }
+        for clue in &self.clues {
+            sum = checksum(clue, sum);
+        }
+        if !self.notes.is_empty() {
+            sum = checksum(&self.notes, sum);
+            sum = checksum(&[0], sum);
+        }
+        sum
+    }
+}
+
+/// Decode the cells of a grid.
+fn decode_cells(bytes: &[u8]) -> Result<Vec<Cell>> {
+    bytes
+        .iter()
+        .map(|&byte| match char::from(byte) {
+            '.' => Ok(Cell::Block),
+            '-' => Ok(Cell::Open),
+            // ISO-8859-1 bytes are the first 256 Unicode code points.
+            c if c.is_alphabetic() => Ok(Cell::letter(c)),
+            c => Err(Error::InvalidPuzzle(format!(
+                "unexpected {:?} in .puz grid",
+                c
+            ))),
+        })
+        .collect()
+}
+
+/// Encode the cells of `grid`. Letters are stored in uppercase, so we can
+/// only store letters whose uppercase is a single ISO-8859-1 character:
+/// not "ß", which becomes "SS".
+fn encode_cells(grid: &Grid) -> Result<Vec<u8>> {
+    grid.cells()
+        .iter()
+        .map(|&cell| match cell {
+            Cell::Block => Ok(b'.'),
+            Cell::Open => Ok(b'-'),
+            Cell::Letter(c) => {
+                let mut upper = c.to_uppercase();
+                match (upper.next().map(u8::try_from), upper.next()) {
+                    (Some(Ok(byte)), None) => Ok(byte),
+                    _ => Err(Error::InvalidPuzzle(format!(
+                        ".puz files can't hold the letter {:?}",
+                        c
+                    ))),
+                }
+            }
+        })
+        .collect()
+}
+
+/// Decode an ISO-8859-1 string.
+fn decode_text(bytes: &[u8]) -> String {
+    bytes.iter().map(|&b| char::from(b)).collect()
+}
+
+/// Encode `text` as ISO-8859-1, replacing anything it can't hold with `?`.
+fn encode_text(text: &str) -> Vec<u8> {
+    text.chars()
+        .map(|c| u8::try_from(c).unwrap_or(b'?'))
+        .collect()
+}
+
+/// Continue the Across Lite checksum `sum` over `bytes`.
+fn checksum(bytes: &[u8], mut sum: u16) -> u16 {
+    for &byte in bytes {
+        sum = sum.rotate_right(1).wrapping_add(u16::from(byte));
+    }
+    sum
+}
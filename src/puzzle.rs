@@ -0,0 +1,192 @@
+//! Crossword puzzles: grids with their clues, and the files which hold them.
+//!
+//! We can read and write three formats, chosen by the file's extension:
+//! Across Lite `.puz` files, ipuz JSON files ending in `.ipuz`, and our own
+//! text format for grids, described in `crate::grid`, which has no clues.
+
+use std::{fs, path::Path};
+
+use crate::error::{Error, Result};
+use crate::grid::{Direction, Grid, Slot};
+use crate::{ipuz, puz};
+
+/// Which of a puzzle's grids to use.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PuzzleGrid {
+    /// The answers.
+    Solution,
+    /// The letters the player has filled in so far, which is empty in a
+    /// fresh puzzle.
+    Progress,
+}
+
+/// The kinds of file we can read puzzles from.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PuzzleFormat {
+    /// Our text format, which only holds a grid.
+    Text,
+    /// An Across Lite `.puz` file.
+    Puz,
+    /// An ipuz JSON file.
+    Ipuz,
+}
+
+impl PuzzleFormat {
+    /// Guess the format of `path` from its extension, assuming our text
+    /// format if we don't recognize it.
+    pub fn for_path(path: &Path) -> PuzzleFormat {
+        let ext = path
+            .extension()
+            .map(|ext| ext.to_string_lossy().to_ascii_lowercase());
+        match ext.as_deref() {
+            Some("puz") => PuzzleFormat::Puz,
+            Some("ipuz") => PuzzleFormat::Ipuz,
+            _ => PuzzleFormat::Text,
+        }
+    }
+}
+
+/// The clue for one slot of a puzzle.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Clue {
+    /// The number of the slot.
+    pub number: usize,
+    /// Which way the slot runs.
+    pub direction: Direction,
+    /// The clue itself.
+    pub text: String,
+}
+
+/// A crossword puzzle.
+#[derive(Clone, Debug)]
+pub struct Puzzle {
+    /// The answers. This may have open cells if the puzzle isn't finished.
+    pub solution: Grid,
+    /// The letters filled in so far. This has the same black squares as
+    /// `solution`.
+    pub progress: Grid,
+    pub title: String,
+    pub author: String,
+    pub copyright: String,
+    pub notes: String,
+    /// The clues, ordered by number and then direction. Slots may have no
+    /// clue.
+    pub clues: Vec<Clue>,
+}
+
+impl Puzzle {
+    /// A puzzle with no clues, using `grid` as both its solution and its
+    /// progress.
+    pub fn new(grid: Grid) -> Puzzle {
+        Puzzle {
+            solution: grid.clone(),
+            progress: grid,
+            title: String::new(),
+            author: String::new(),
+            copyright: String::new(),
+            notes: String::new(),
+            clues: vec![],
+        }
+    }
+
+    /// Read the puzzle in the file at `path`, guessing its format from its
+    /// extension.
+    pub fn read(path: &Path) -> Result<Puzzle> {
+        let puzzle = match PuzzleFormat::for_path(path) {
+            PuzzleFormat::Text => Puzzle::new(
+                fs::read_to_string(path).map_err(Error::io(path))?.parse()?,
+            ),
+            PuzzleFormat::Puz => {
+                puz::read_puz(&fs::read(path).map_err(Error::io(path))?)?
+            }
+            PuzzleFormat::Ipuz => {
+                ipuz::read_ipuz(&fs::read_to_string(path).map_err(Error::io(path))?)?
+            }
+        };
+        puzzle.validate()?;
+        Ok(puzzle)
+    }
+
+    /// Write the puzzle to `path`, choosing the format from its extension.
+    /// Our text format only holds the solution.
+    pub fn write(&self, path: &Path) -> Result<()> {
+        self.validate()?;
+        let data = match PuzzleFormat::for_path(path) {
+            PuzzleFormat::Text => self.solution.to_string().into_bytes(),
+            PuzzleFormat::Puz => puz::write_puz(self)?,
+            PuzzleFormat::Ipuz => ipuz::write_ipuz(self)?.into_bytes(),
+        };
+        fs::write(path, data).map_err(Error::io(path))
+    }
+
+    /// One of our grids.
+    pub fn grid(&self, which: PuzzleGrid) -> &Grid {
+        match which {
+            PuzzleGrid::Solution => &self.solution,
+            PuzzleGrid::Progress => &self.progress,
+        }
+    }
+
+    /// Take one of our grids, discarding the rest of the puzzle.
+    pub fn into_grid(self, which: PuzzleGrid) -> Grid {
+        match which {
+            PuzzleGrid::Solution => self.solution,
+            PuzzleGrid::Progress => self.progress,
+        }
+    }
+
+    /// This puzzle with a new solution, such as a completed fill, and no
+    /// progress, ready to give to solvers. The clues are kept, since the
+    /// slots are the same.
+    pub fn with_solution(&self, solution: Grid) -> Result<Puzzle> {
+        if !solution.same_shape(&self.solution) {
+            return Err(Error::InvalidPuzzle(
+                "new solution has different black squares".to_owned(),
+            ));
+        }
+        Ok(Puzzle {
+            progress: solution.blank(),
+            solution,
+            ..self.clone()
+        })
+    }
+
+    /// The clue for `slot`, if it has one.
+    pub fn clue(&self, slot: &Slot) -> Option<&str> {
+        self.clues
+            .iter()
+            .find(|clue| {
+                clue.number == slot.number && clue.direction == slot.direction
+            })
+            .map(|clue| &clue.text[..])
+    }
+
+    /// The slots of our grid in clue order, by number and then direction.
+    pub(crate) fn slots_in_clue_order(&self) -> Vec<Slot> {
+        let mut slots = self.solution.slots();
+        slots.sort_by_key(|slot| (slot.number, slot.direction));
+        slots
+    }
+
+    /// Check that our grids match and that every clue belongs to a slot.
+    fn validate(&self) -> Result<()> {
+        if !self.solution.same_shape(&self.progress) {
+            return Err(Error::InvalidPuzzle(
+                "solution and progress grids have different black squares".to_owned(),
+            ));
+        }
+        let slots = self.solution.slots();
+        for clue in &self.clues {
+            let has_slot = slots.iter().any(|slot| {
+                slot.number == clue.number && slot.direction == clue.direction
+            });
+            if !has_slot {
+                return Err(Error::InvalidPuzzle(format!(
+                    "clue for {} {} doesn't match any slot in the grid",
+                    clue.number, clue.direction
+                )));
+            }
+        }
+        Ok(())
+    }
+}
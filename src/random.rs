@@ -0,0 +1,61 @@
+//! A small, seedable random number generator for randomized search
+//! strategies.
+//!
+//! Searches which make random choices should take an `Rng` (or a seed) as
+//! part of their options, instead of creating their own, so that a run can
+//! be reproduced exactly by passing the same seed.
+
+use std::{
+    collections::hash_map::RandomState,
+    hash::{BuildHasher, Hasher},
+};
+
+/// A SplitMix64 generator. It's fast and good enough for choosing search
+/// orders, but it's not suitable for anything security-related.
+#[derive(Clone, Debug)]
+pub struct Rng {
+    state: u64,
+}
+
+impl Rng {
+    /// Create a generator which always produces the same numbers for the same
+    /// `seed`.
+    pub fn new(seed: u64) -> Rng {
+        Rng { state: seed }
+    }
+
+    /// Pick a seed which is different every time. Log it if you might want to
+    /// reproduce the run.
+    pub fn random_seed() -> u64 {
+        RandomState::new().build_hasher().finish()
+    }
+
+    /// The next random number.
+    pub fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    /// A random number in `0..n`. `n` must not be 0.
+    pub fn below(&mut self, n: usize) -> usize {
+        assert!(n > 0, "cannot choose a number below 0");
+        // Multiply and keep the high bits, which avoids most of the bias of
+        // taking a remainder.
+        ((u128::from(self.next_u64()) * n as u128) >> 64) as usize
+    }
+
+    /// A random number between 0 and 1.
+    pub fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    /// Shuffle `items` into a random order.
+    pub fn shuffle<T>(&mut self, items: &mut [T]) {
+        for i in (1..items.len()).rev() {
+            items.swap(i, self.below(i + 1));
+        }
+    }
+}
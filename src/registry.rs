@@ -0,0 +1,184 @@
+//! Dictionaries installed by `install-dict`, which live in the user's standard
+//! data directory so that they can be used by name.
+
+use anyhow::{format_err, Context, Result};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    fs::{self, File},
+    io::{self, ErrorKind},
+    path::{Path, PathBuf},
+    process,
+};
+
+/// The dictionaries we've installed.
+pub struct Registry {
+    dir: PathBuf,
+    index: Index,
+}
+
+/// The contents of our index file.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+struct Index {
+    /// The installed dictionary to use when none is specified.
+    default: Option<String>,
+    /// Every installed dictionary.
+    #[serde(default)]
+    dictionaries: BTreeMap<String, Installed>,
+}
+
+/// An installed dictionary.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Installed {
+    /// The dictionary file.
+    pub path: PathBuf,
+    /// Where we got the word list from.
+    pub source: String,
+}
+
+impl Registry {
+    /// Open the registry in the user's standard data directory.
+    pub fn open_default() -> Result<Registry> {
+        let dir = dirs::data_dir()
+            .ok_or_else(|| format_err!("could not find a data directory"))?
+            .join("word-puzzler");
+        let index_path = dir.join("dictionaries.toml");
+        let index = match fs::read_to_string(&index_path) {
+            Ok(text) => toml::from_str(&text).with_context(|| {
+                format!("could not parse {}", index_path.display())
+            })?,
+            Err(err) if err.kind() == ErrorKind::NotFound => Index::default(),
+            Err(err) => {
+                return Err(err).with_context(|| {
+                    format!("could not read {}", index_path.display())
+                })
+            }
+        };
+        Ok(Registry { dir, index })
+    }
+
+    /// The directory containing our dictionaries.
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// The name of the default dictionary, if we have one.
+    pub fn default_name(&self) -> Option<&str> {
+        self.index.default.as_deref()
+    }
+
+    /// Look up the installed dictionary called `name`.
+    pub fn get(&self, name: &str) -> Option<&Installed> {
+        self.index.dictionaries.get(name)
+    }
+
+    /// Every installed dictionary, sorted by name.
+    pub fn dictionaries(&self) -> impl Iterator<Item = (&String, &Installed)> {
+        self.index.dictionaries.iter()
+    }
+
+    /// Where we keep the word list for the dictionary called `name`.
+    pub fn wordlist_path(&self, name: &str) -> Result<PathBuf> {
+        check_name(name)?;
+        Ok(self.dir.join("wordlists").join(format!("{}.txt", name)))
+    }
+
+    /// Where we keep the dictionary called `name`.
+    pub fn dict_path(&self, name: &str) -> Result<PathBuf> {
+        check_name(name)?;
+        Ok(self.dir.join("dicts").join(format!("{}.fst", name)))
+    }
+
+    /// Record that we've installed the dictionary called `name` from `source`,
+    /// and optionally make it the default.
+    pub fn add(&mut self, name: &str, source: &str, make_default: bool) -> Result<()> {
+        let installed = Installed {
+            path: self.dict_path(name)?,
+            source: source.to_owned(),
+        };
+        self.index.dictionaries.insert(name.to_owned(), installed);
+        if make_default || self.index.default.is_none() {
+            self.index.default = Some(name.to_owned());
+        }
+        self.save()
+    }
+
+    /// Write our index to disk.
+    fn save(&self) -> Result<()> {
+        let path = self.dir.join("dictionaries.toml");
+        let text = toml::to_string(&self.index)
+            .context("could not serialize dictionary index")?;
+        write_atomically(&path, |tmp_path| {
+            fs::write(tmp_path, &text)
+                .with_context(|| format!("could not write to {}", tmp_path.display()))
+        })
+    }
+}
+
+/// Make sure `name` is safe to use as a file name.
+fn check_name(name: &str) -> Result<()> {
+    let valid = !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+    if valid {
+        Ok(())
+    } else {
+        Err(format_err!(
+            "dictionary names may only use letters, digits, '-' and '_', not {:?}",
+            name
+        ))
+    }
+}
+
+/// Copy the word list at `source`, which may be a path or an HTTP(S) URL, to
+/// `dest`.
+pub fn fetch(source: &str, dest: &Path) -> Result<()> {
+    write_atomically(dest, |tmp_path| {
+        let mut out = File::create(tmp_path)
+            .with_context(|| format!("could not create {}", tmp_path.display()))?;
+        if source.starts_with("http://") || source.starts_with("https://") {
+            let response = ureq::get(source)
+                .call()
+                .with_context(|| format!("could not download {}", source))?;
+            io::copy(&mut response.into_body().into_reader(), &mut out)
+                .with_context(|| format!("could not download {}", source))?;
+        } else {
+            let mut input = File::open(source)
+                .with_context(|| format!("could not open {}", source))?;
+            io::copy(&mut input, &mut out)
+                .with_context(|| format!("could not copy {}", source))?;
+        }
+        Ok(())
+    })
+}
+
+/// Create `path` by having `write` write to a temporary file and then renaming
+/// it, so that nobody ever sees a partial file. The temporary file is named
+/// after `path` and our process, so that concurrent writers of the same or
+/// neighbouring files never share one.
+fn write_atomically(
+    path: &Path,
+    write: impl FnOnce(&Path) -> Result<()>,
+) -> Result<()> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)
+            .with_context(|| format!("could not create {}", dir.display()))?;
+    }
+    let file_name = path
+        .file_name()
+        .ok_or_else(|| format_err!("{} is not a file name", path.display()))?;
+    let tmp_path = path.with_file_name(format!(
+        ".{}.tmp{}",
+        file_name.to_string_lossy(),
+        process::id()
+    ));
+    let written = write(&tmp_path).and_then(|()| {
+        fs::rename(&tmp_path, path)
+            .with_context(|| format!("could not write to {}", path.display()))
+    });
+    if written.is_err() {
+        let _ = fs::remove_file(&tmp_path);
+    }
+    written
+}
@@ -0,0 +1,231 @@
+//! Printing results to the terminal, with optional color.
+//!
+//! Each result line starts with its probability. We color results by how they
+//! compare to the most probable result, highlight the parts of each word which
+//! matched a query, and alternate colors between the words of a multi-word
+//! result so that it's easy to see where each word starts.
+
+use anyhow::{format_err, Error, Result};
+use std::{
+    io::{self, IsTerminal, Write},
+    ops::Range,
+    str::FromStr,
+};
+use termcolor::{Color, ColorChoice, ColorSpec, StandardStream, WriteColor};
+
+use letter_permutations::probability::{Dist, Prob, ProbFormat};
+
+/// Results at least this much less probable than the best result are grayed
+/// out.
+const FADE_BELOW: f64 = 1e-3;
+
+/// Results at least this probable relative to the best result are emphasized.
+const EMPHASIZE_ABOVE: f64 = 0.1;
+
+/// When to use color.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ColorWhen {
+    /// Use color if we're writing to a terminal.
+    Auto,
+    /// Always use color.
+    Always,
+    /// Never use color.
+    Never,
+}
+
+impl FromStr for ColorWhen {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "auto" => Ok(ColorWhen::Auto),
+            "always" => Ok(ColorWhen::Always),
+            "never" => Ok(ColorWhen::Never),
+            _ => Err(format_err!(
+                "expected \"auto\", \"always\" or \"never\", found {:?}",
+                s
+            )),
+        }
+    }
+}
+
+/// How the text after the probability on each result line is laid out.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Layout {
+    /// One or more words, separated by spaces.
+    Words,
+    /// A single word, followed by extra information.
+    Annotated,
+    /// Text we print as it is, such as a row of a table.
+    Plain,
+}
+
+/// Writes results to standard output.
+pub struct Renderer {
+    out: StandardStream,
+    prob_format: ProbFormat,
+}
+
+impl Renderer {
+    /// Create a renderer which uses color according to `when`, and shows
+    /// probabilities using `prob_format`.
+    pub fn new(when: ColorWhen, prob_format: ProbFormat) -> Renderer {
+        let choice = match when {
+            // `ColorChoice::Auto` checks `TERM` and `NO_COLOR`, but not
+            // whether we're writing to a terminal.
+            ColorWhen::Auto if io::stdout().is_terminal() => ColorChoice::Auto,
+            ColorWhen::Auto | ColorWhen::Never => ColorChoice::Never,
+            ColorWhen::Always => ColorChoice::Always,
+        };
+        Renderer {
+            out: StandardStream::stdout(choice),
+            prob_format,
+        }
+    }
+
+    /// Print every event in `dist`.
+    pub fn dist(&mut self, dist: &Dist<String>) -> Result<()> {
+        self.dist_highlighting(dist, |_| vec![])
+    }
+
+    /// Print every event in `dist`, highlighting the byte ranges returned by
+    /// `highlights` for each event.
+    pub fn dist_highlighting<F>(
+        &mut self,
+        dist: &Dist<String>,
+        highlights: F,
+    ) -> Result<()>
+    where
+        F: Fn(&str) -> Vec<Range<usize>>,
+    {
+        let mut best = None;
+        for (prob, text) in dist {
+            let best = *best.get_or_insert(prob);
+            self.result(prob, best, text, Layout::Words, &highlights(text))?;
+        }
+        Ok(())
+    }
+
+    /// Print `text`, which contains results formatted as `{:6.2} rest` lines,
+    /// like those written by `Dist`'s `Display` implementation. Lines which
+    /// don't start with a probability are printed as they are.
+    pub fn text(&mut self, text: &str, layout: Layout) -> Result<()> {
+        let mut best = None;
+        for line in text.lines() {
+            let trimmed = line.trim_start();
+            let parsed = trimmed.split_once(' ').and_then(|(prob, rest)| {
+                let prob = prob.parse::<f64>().ok()?;
+                Some((Prob::from_probability((-prob).exp()), rest))
+            });
+            match parsed {
+                Some((prob, rest)) => {
+                    let best = *best.get_or_insert(prob);
+                    self.result(prob, best, rest, layout, &[])?;
+                }
+                None => self.line(line)?,
+            }
+        }
+        Ok(())
+    }
+
+    /// Print a line which isn't a result.
+    pub fn line(&mut self, line: &str) -> Result<()> {
+        writeln!(self.out, "{}", line)?;
+        Ok(())
+    }
+
+    /// Print a single result with probability `prob`, where `best` is the
+    /// probability of the most probable result, highlighting the byte ranges
+    /// of `text` in `highlights`.
+    pub fn result(
+        &mut self,
+        prob: Prob,
+        best: Prob,
+        text: &str,
+        layout: Layout,
+        highlights: &[Range<usize>],
+    ) -> Result<()> {
+        let relative = (prob / best).to_probability();
+        let faded = relative < FADE_BELOW;
+        let mut base = ColorSpec::new();
+        base.set_dimmed(faded);
+
+        let mut prob_spec = base.clone();
+        if relative >= EMPHASIZE_ABOVE {
+            prob_spec.set_fg(Some(Color::Green)).set_bold(true);
+        }
+        self.out.set_color(&prob_spec)?;
+        write!(self.out, "{:6.2}", prob.display(self.prob_format))?;
+        self.out.reset()?;
+        write!(self.out, " ")?;
+
+        let parts = match layout {
+            Layout::Words => text.split(' ').collect::<Vec<_>>(),
+            Layout::Annotated => text.splitn(2, ' ').collect(),
+            Layout::Plain => vec![text],
+        };
+        let mut offset = 0;
+        for (i, part) in parts.into_iter().enumerate() {
+            if i > 0 {
+                write!(self.out, " ")?;
+            }
+            let mut spec = base.clone();
+            match layout {
+                Layout::Words if i % 2 == 1 => {
+                    spec.set_fg(Some(Color::Cyan));
+                }
+                Layout::Annotated if i > 0 => {
+                    spec.set_dimmed(true);
+                }
+                _ => {}
+            }
+            self.highlighted(part, offset, highlights, &spec)?;
+            offset += part.len() + 1;
+        }
+        writeln!(self.out)?;
+        Ok(())
+    }
+
+    /// Print `part`, which starts at byte `offset` of its result, in `spec`,
+    /// except for any bytes in `highlights`.
+    fn highlighted(
+        &mut self,
+        part: &str,
+        offset: usize,
+        highlights: &[Range<usize>],
+        spec: &ColorSpec,
+    ) -> Result<()> {
+        let mut highlight_spec = spec.clone();
+        highlight_spec
+            .set_fg(Some(Color::Yellow))
+            .set_bold(true)
+            .set_underline(true);
+        let is_highlighted = |idx: usize| highlights.iter().any(|r| r.contains(&idx));
+        let mut start = 0;
+        while start < part.len() {
+            let highlight = is_highlighted(offset + start);
+            let end = part[start..]
+                .char_indices()
+                .map(|(i, _)| start + i)
+                .find(|&i| is_highlighted(offset + i) != highlight)
+                .unwrap_or(part.len());
+            self.out
+                .set_color(if highlight { &highlight_spec } else { spec })?;
+            write!(self.out, "{}", &part[start..end])?;
+            start = end;
+        }
+        self.out.reset()?;
+        Ok(())
+    }
+}
+
+/// The byte ranges of every occurrence of `needle` in `haystack`.
+pub fn occurrences(haystack: &str, needle: &str) -> Vec<Range<usize>> {
+    if needle.is_empty() {
+        return vec![];
+    }
+    haystack
+        .match_indices(needle)
+        .map(|(start, m)| start..start + m.len())
+        .collect()
+}
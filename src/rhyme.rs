@@ -0,0 +1,81 @@
+//! Finding rhymes.
+
+use std::collections::BTreeSet;
+
+use crate::dictionary::Dictionary;
+use crate::error::{Error, Result};
+use crate::probability::Dist;
+use crate::pronunciation::rhyme_part;
+
+/// How to decide whether two words rhyme.
+#[derive(Clone, Copy, Debug)]
+pub enum RhymeMode {
+    /// Words rhyme if they end with the same letters. If `suffix_len` is
+    /// `None`, we guess how many letters need to match.
+    Spelling { suffix_len: Option<usize> },
+    /// Words rhyme if their pronunciations match from the last stressed vowel
+    /// onwards. This requires a pronunciation index.
+    Sound,
+}
+
+/// Find words which rhyme with `word`, which should be lowercase.
+pub fn find_rhymes(
+    dict: &Dictionary,
+    word: &str,
+    mode: RhymeMode,
+) -> Result<Dist<String>> {
+    let mut rhymes = match mode {
+        RhymeMode::Spelling { suffix_len } => {
+            let suffix = match suffix_len {
+                Some(len) => {
+                    let start = word.char_indices().rev().nth(len.saturating_sub(1));
+                    &word[start.map_or(0, |(i, _)| i)..]
+                }
+                None => spelling_rhyme_part(word),
+            };
+            dict.find_by_suffix(suffix)?.into_vec()
+        }
+        RhymeMode::Sound => {
+            let pronunciations = dict.pronunciations_of(word)?;
+            if pronunciations.is_empty() {
+                return Err(Error::NoPronunciation(word.to_owned()));
+            }
+            let mut words = BTreeSet::new();
+            for phones in pronunciations {
+                for (rhyme, _) in
+                    dict.pronunciations_ending_with(&rhyme_part(&phones))?
+                {
+                    words.insert(rhyme);
+                }
+            }
+            words
+                .into_iter()
+                .filter_map(|w| dict.probability_of(&w).map(|p| (p, w)))
+                .collect()
+        }
+    };
+    rhymes.retain(|(_, w)| w != word);
+    let mut rhymes = Dist::from_vec(rhymes);
+    rhymes.sort_by_probability();
+    Ok(rhymes)
+}
+
+/// Guess which letters at the end of `word` another word needs to share to
+/// rhyme with it: the last group of vowels and everything after it. We skip
+/// over a final "e" or "y", so that "table" gives "able" and "happy" gives
+/// "appy".
+pub fn spelling_rhyme_part(word: &str) -> &str {
+    let is_vowel = |c: char| "aeiou".contains(c);
+    let body = word.strip_suffix(|c| c == 'e' || c == 'y').unwrap_or(word);
+    let last_vowel = match body.rfind(is_vowel) {
+        Some(i) => i,
+        None => return word,
+    };
+    let start = body[..last_vowel]
+        .char_indices()
+        .rev()
+        .take_while(|&(_, c)| is_vowel(c))
+        .last()
+        .map_or(last_vowel, |(i, _)| i);
+    &word[start..]
+}
@@ -0,0 +1,185 @@
+//! Adjusting the scores of words when ranking breakings, so that searches
+//! can prefer some words over others beyond what the dictionary says.
+//!
+//! A scorer takes a word and its probability in the dictionary, and returns
+//! the score to use instead. Scores combine like probabilities: a breaking's
+//! score is the product of the scores of its words. Because each word is
+//! scored on its own, the segmentation search can still find the best
+//! breakings efficiently.
+//!
+//! Scorers can be applied to segmentation with `SegmentOptions::scorers`, or
+//! to every word a dictionary returns with `Dictionary::set_scorers`.
+
+use std::{
+    collections::{BTreeMap, HashSet},
+    fmt,
+    path::Path,
+    sync::Arc,
+};
+
+use crate::dictionary::{phrase_letters, read_word_list, Dictionary};
+use crate::error::{Error, Result};
+use crate::metadata::content_hash;
+use crate::probability::Prob;
+
+/// A way to adjust the scores of words.
+pub trait Scorer: fmt::Debug + Send + Sync {
+    /// The score to use for `word`, whose score so far is `prob`.
+    fn score(&self, word: &str, prob: Prob) -> Prob;
+}
+
+/// A sequence of scorers, each applied to the result of the last. An empty
+/// sequence scores every word by its probability.
+#[derive(Clone, Debug, Default)]
+pub struct Scorers {
+    scorers: Vec<Arc<dyn Scorer>>,
+}
+
+impl Scorers {
+    /// Create an empty sequence of scorers.
+    pub fn new() -> Scorers {
+        Scorers::default()
+    }
+
+    /// Apply `scorer` after any scorers we already have.
+    pub fn push(&mut self, scorer: impl Scorer + 'static) {
+        self.scorers.push(Arc::new(scorer));
+    }
+
+    /// Do we leave every score alone?
+    pub fn is_empty(&self) -> bool {
+        self.scorers.is_empty()
+    }
+
+    /// The score to use for `word`, whose probability is `prob`.
+    pub fn score(&self, word: &str, prob: Prob) -> Prob {
+        self.scorers
+            .iter()
+            .fold(prob, |prob, scorer| scorer.score(word, prob))
+    }
+}
+
+/// Makes words from a list, like the theme words of a puzzle, more probable,
+/// so that topical words the dictionary considers rare still rank well.
+pub struct BoostList {
+    words: HashSet<String>,
+    /// How much more probable to make each word.
+    boost: Prob,
+    /// A hash of the list, so that results cached using one list aren't
+    /// reused with another.
+    hash: String,
+}
+
+impl BoostList {
+    /// Multiply the probability of each word in `words` by `factor`, which
+    /// must be at least 1. No word can become more probable than 1.
+    pub fn new<I>(words: I, factor: f64) -> Result<BoostList>
+    where
+        I: IntoIterator<Item = String>,
+    {
+        if !(factor.is_finite() && factor >= 1.0) {
+            return Err(Error::InvalidOption(format!(
+                "boost factor must be at least 1, not {}",
+                factor
+            )));
+        }
+        let mut words = words.into_iter().collect::<Vec<_>>();
+        words.sort_unstable();
+        words.dedup();
+        Ok(BoostList {
+            hash: content_hash(words.join("\n").as_bytes()),
+            words: words.into_iter().collect(),
+            boost: Prob::from_probability(1.0 / factor),
+        })
+    }
+
+    /// Read the words to boost from `path`, one per line, as with
+    /// `read_word_list`.
+    pub fn from_file(path: &Path, factor: f64) -> Result<BoostList> {
+        BoostList::new(read_word_list(path)?, factor)
+    }
+}
+
+/// Show the size and hash of the list, rather than every word.
+impl fmt::Debug for BoostList {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("BoostList")
+            .field("words", &self.words.len())
+            .field("boost", &self.boost)
+            .field("hash", &self.hash)
+            .finish()
+    }
+}
+
+impl Scorer for BoostList {
+    fn score(&self, word: &str, prob: Prob) -> Prob {
+        if self.words.contains(word) {
+            (prob / self.boost).min(Prob::always())
+        } else {
+            prob
+        }
+    }
+}
+
+/// Scores each word by its probability among the words with the same number
+/// of letters, instead of among every word. This compares the candidates for
+/// a slot of known length better than overall frequency, which favors short
+/// words.
+#[derive(Debug)]
+pub struct LengthNormalizer {
+    /// The total probability of the words with each number of letters.
+    totals: BTreeMap<usize, Prob>,
+}
+
+impl LengthNormalizer {
+    /// Divide the probability of each word by the total for its length in
+    /// `totals`. Words whose length isn't in `totals` are left alone.
+    pub fn new(totals: BTreeMap<usize, Prob>) -> LengthNormalizer {
+        LengthNormalizer { totals }
+    }
+
+    /// Normalize using the length totals of `dict`.
+    pub fn for_dictionary(dict: &Dictionary) -> Result<LengthNormalizer> {
+        Ok(LengthNormalizer::new(dict.length_totals()?))
+    }
+}
+
+impl Scorer for LengthNormalizer {
+    fn score(&self, word: &str, prob: Prob) -> Prob {
+        let len = phrase_letters(word).chars().count();
+        match self.totals.get(&len) {
+            Some(&total) => (prob / total).min(Prob::always()),
+            None => prob,
+        }
+    }
+}
+
+/// Penalizes words with fewer than `min_length` letters, which otherwise
+/// tend to fill up breakings with things like "a" and "i".
+#[derive(Debug)]
+pub struct ShortWordPenalty {
+    min_length: usize,
+    penalty: Prob,
+}
+
+impl ShortWordPenalty {
+    /// Multiply the probability of words shorter than `min_length` letters by
+    /// `penalty`, which should be between 0.0 and 1.0.
+    pub fn new(min_length: usize, penalty: f64) -> ShortWordPenalty {
+        ShortWordPenalty {
+            min_length,
+            penalty: Prob::from_probability(penalty),
+        }
+    }
+}
+
+impl Scorer for ShortWordPenalty {
+    fn score(&self, word: &str, prob: Prob) -> Prob {
+        let letters = word.chars().filter(|c| c.is_alphabetic()).count();
+        if letters < self.min_length {
+            prob * self.penalty
+        } else {
+            prob
+        }
+    }
+}
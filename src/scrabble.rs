@@ -0,0 +1,861 @@
+//! Generating and scoring Scrabble moves.
+//!
+//! For each row and column, we compute which letters each empty square can
+//! hold without spelling a non-word in the other direction (its "cross-check
+//! set"). Then, for each square where a word could start, we walk the
+//! dictionary with an automaton which only accepts words that fit the line,
+//! using tiles from the rack for empty squares. Most branches of the
+//! dictionary die after a letter or two, so this is fast.
+
+use fst::Automaton;
+use std::{cmp::Reverse, fmt, str::FromStr};
+
+use crate::anagram::is_blank;
+use crate::dictionary::{Dictionary, SearchOptions};
+use crate::error::{Error, Result};
+use crate::probability::Prob;
+
+/// The number of tiles in a full rack.
+const RACK_SIZE: usize = 7;
+
+/// The bonus for using every tile in a full rack.
+const BINGO_BONUS: u32 = 50;
+
+/// The value of each tile from "a" to "z", in English Scrabble.
+const TILE_VALUES: [u32; 26] = [
+    1, 3, 3, 2, 1, 4, 2, 4, 1, 8, 5, 1, 3, 1, 1, 3, 10, 1, 1, 1, 1, 4, 4, 8, 4, 10,
+];
+
+/// The premium squares on a standard 15×15 board. "d" and "t" are double and
+/// triple letter scores, and "D" and "T" are double and triple word scores.
+const STANDARD_PREMIUMS: &str = "\
+T..d...T...d..T
+.D...t...t...D.
+..D...d.d...D..
+d..D...d...D..d
+....D.....D....
+.t...t...t...t.
+..d...d.d...d..
+T..d...D...d..T
+..d...d.d...d..
+.t...t...t...t.
+....D.....D....
+d..D...d...D..d
+..D...d.d...D..
+.D...t...t...D.
+T..d...T...d..T";
+
+/// A square on the board.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Square {
+    /// An empty square.
+    Empty,
+    /// A square holding a tile, which may be a blank standing for a letter.
+    Tile { letter: u8, blank: bool },
+}
+
+/// A bonus for placing a tile on a square.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Premium {
+    None,
+    DoubleLetter,
+    TripleLetter,
+    DoubleWord,
+    TripleWord,
+}
+
+impl Premium {
+    /// How much this premium multiplies the value of a tile.
+    fn letter_multiplier(self) -> u32 {
+        match self {
+            Premium::DoubleLetter => 2,
+            Premium::TripleLetter => 3,
+            _ => 1,
+        }
+    }
+
+    /// How much this premium multiplies the value of a word.
+    fn word_multiplier(self) -> u32 {
+        match self {
+            Premium::DoubleWord => 2,
+            Premium::TripleWord => 3,
+            _ => 1,
+        }
+    }
+}
+
+/// A Scrabble board, with the tiles played so far.
+///
+/// In text form, each line is a row, `.` is an empty square, lowercase
+/// letters are tiles, and uppercase letters are blanks played as that letter.
+/// 15×15 boards use the standard premium squares, and other sizes have none.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Board {
+    width: usize,
+    height: usize,
+    squares: Vec<Square>,
+    premiums: Vec<Premium>,
+}
+
+impl Board {
+    /// The square at `row` and `col`.
+    pub fn square(&self, row: usize, col: usize) -> Square {
+        self.squares[row * self.width + col]
+    }
+
+    /// Is there no tile on the board yet?
+    pub fn is_empty(&self) -> bool {
+        self.squares.iter().all(|&sq| sq == Square::Empty)
+    }
+
+    /// The index of the square `pos` squares along line `line`.
+    fn index(&self, direction: Direction, line: usize, pos: usize) -> usize {
+        match direction {
+            Direction::Across => line * self.width + pos,
+            Direction::Down => pos * self.width + line,
+        }
+    }
+
+    /// The number of lines in `direction`, and the length of each.
+    fn lines(&self, direction: Direction) -> (usize, usize) {
+        match direction {
+            Direction::Across => (self.height, self.width),
+            Direction::Down => (self.width, self.height),
+        }
+    }
+
+    /// The tile at `idx`, if there is one.
+    fn tile(&self, idx: usize) -> Option<(u8, bool)> {
+        match self.squares[idx] {
+            Square::Tile { letter, blank } => Some((letter, blank)),
+            Square::Empty => None,
+        }
+    }
+
+    /// The tiles before and after the empty square at `pos` on `line`,
+    /// reading in `direction`, which will form a word with whatever we place
+    /// there. Returns `None` if there are no such tiles.
+    fn cross_word(
+        &self,
+        direction: Direction,
+        line: usize,
+        pos: usize,
+    ) -> Option<Cross> {
+        let (_, len) = self.lines(direction);
+        let tile_at = |p: usize| self.tile(self.index(direction, line, p));
+        let mut start = pos;
+        while start > 0 && tile_at(start - 1).is_some() {
+            start -= 1;
+        }
+        let mut end = pos + 1;
+        while end < len && tile_at(end).is_some() {
+            end += 1;
+        }
+        if start == pos && end == pos + 1 {
+            return None;
+        }
+        let mut cross = Cross {
+            allowed: 0,
+            prefix: String::new(),
+            suffix: String::new(),
+            score: 0,
+        };
+        for p in (start..pos).chain(pos + 1..end) {
+            let (letter, blank) = tile_at(p).expect("should be a tile");
+            if p < pos {
+                cross.prefix.push(letter as char);
+            } else {
+                cross.suffix.push(letter as char);
+            }
+            cross.score += tile_value(letter, blank);
+        }
+        Some(cross)
+    }
+}
+
+impl FromStr for Board {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let mut width = None;
+        let mut squares = vec![];
+        let mut height = 0;
+        for line in s.lines().map(str::trim_end).filter(|l| !l.is_empty()) {
+            let row = line
+                .chars()
+                .map(|c| match c {
+                    '.' => Ok(Square::Empty),
+                    'a'..='z' => Ok(Square::Tile {
+                        letter: c as u8,
+                        blank: false,
+                    }),
+                    'A'..='Z' => Ok(Square::Tile {
+                        letter: c.to_ascii_lowercase() as u8,
+                        blank: true,
+                    }),
+                    c => Err(Error::InvalidPuzzle(format!(
+                        "unexpected {:?} on board",
+                        c
+                    ))),
+                })
+                .collect::<Result<Vec<_>>>()?;
+            match width {
+                None => width = Some(row.len()),
+                Some(w) if w != row.len() => {
+                    return Err(Error::InvalidPuzzle(format!(
+                        "board row {} has {} squares, but expected {}",
+                        height + 1,
+                        row.len(),
+                        w,
+                    )))
+                }
+                Some(_) => {}
+            }
+            squares.extend(row);
+            height += 1;
+        }
+        let width =
+            width.ok_or_else(|| Error::InvalidPuzzle("board is empty".to_owned()))?;
+        let premiums = if width == 15 && height == 15 {
+            STANDARD_PREMIUMS
+                .lines()
+                .flat_map(str::chars)
+                .map(|c| match c {
+                    'd' => Premium::DoubleLetter,
+                    't' => Premium::TripleLetter,
+                    'D' => Premium::DoubleWord,
+                    'T' => Premium::TripleWord,
+                    _ => Premium::None,
+                })
+                .collect()
+        } else {
+            vec![Premium::None; width * height]
+        };
+        Ok(Board {
+            width,
+            height,
+            squares,
+            premiums,
+        })
+    }
+}
+
+impl fmt::Display for Board {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for row in self.squares.chunks(self.width) {
+            for square in row {
+                match *square {
+                    Square::Empty => write!(f, ".")?,
+                    Square::Tile { letter, blank } if blank => {
+                        write!(f, "{}", (letter as char).to_ascii_uppercase())?
+                    }
+                    Square::Tile { letter, .. } => write!(f, "{}", letter as char)?,
+                }
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
+
+/// The direction of a move.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Direction {
+    Across,
+    Down,
+}
+
+impl Direction {
+    /// The other direction.
+    fn cross(self) -> Direction {
+        match self {
+            Direction::Across => Direction::Down,
+            Direction::Down => Direction::Across,
+        }
+    }
+}
+
+impl fmt::Display for Direction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Direction::Across => write!(f, "across"),
+            Direction::Down => write!(f, "down"),
+        }
+    }
+}
+
+/// A word formed in the other direction by placing a tile on a square.
+#[derive(Clone, Debug)]
+struct Cross {
+    /// The letters which can be placed on the square, as a bit mask.
+    allowed: u32,
+    /// The tiles before the square.
+    prefix: String,
+    /// The tiles after the square.
+    suffix: String,
+    /// The value of the tiles before and after the square.
+    score: u32,
+}
+
+/// A square on a line we're trying to place a word along.
+#[derive(Clone, Debug)]
+enum LineSquare {
+    /// A tile which is already on the board.
+    Tile { letter: u8, blank: bool },
+    /// An empty square.
+    Empty {
+        /// Is this square next to a tile, or the center square of an empty
+        /// board, so that placing a tile here connects a move to the board?
+        anchor: bool,
+        /// The word we'd form in the other direction, if any.
+        cross: Option<Cross>,
+        premium: Premium,
+    },
+}
+
+impl LineSquare {
+    /// Can `letter` be placed on this square, assuming it's empty?
+    fn allows(&self, letter: u8) -> bool {
+        match self {
+            LineSquare::Empty {
+                cross: Some(cross), ..
+            } => cross.allowed & letter_bit(letter) != 0,
+            _ => true,
+        }
+    }
+}
+
+/// A legal move.
+#[derive(Clone, Debug)]
+pub struct Move {
+    /// The row of the first letter of the word, from 0.
+    pub row: usize,
+    /// The column of the first letter of the word, from 0.
+    pub col: usize,
+    pub direction: Direction,
+    /// The main word formed by the move, including tiles already on the
+    /// board.
+    pub word: String,
+    /// The byte offsets in `word` of the new tiles which are blanks.
+    pub blanks: Vec<usize>,
+    /// The number of tiles placed from the rack.
+    pub tiles_placed: usize,
+    /// The other words formed by the move, in the other direction.
+    pub cross_words: Vec<String>,
+    /// The total score of the move.
+    pub score: u32,
+    /// The probability of the main word, used to break ties.
+    pub prob: Prob,
+}
+
+impl Move {
+    /// The position of the move in standard notation, where columns are
+    /// letters and rows are numbers. Across moves list the row first, as in
+    /// "8H", and down moves list the column first, as in "H8". Boards wider
+    /// than 26 columns continue with "AA", "AB" and so on, like spreadsheets.
+    pub fn position(&self) -> String {
+        let col = column_label(self.col);
+        match self.direction {
+            Direction::Across => format!("{}{}", self.row + 1, col),
+            Direction::Down => format!("{}{}", col, self.row + 1),
+        }
+    }
+}
+
+/// The letters naming column `col`, counting from 0: "A" to "Z", then "AA".
+fn column_label(mut col: usize) -> String {
+    let mut label = vec![];
+    loop {
+        label.push(char::from(b'A' + (col % 26) as u8));
+        if col < 26 {
+            break;
+        }
+        col = col / 26 - 1;
+    }
+    label.iter().rev().collect()
+}
+
+/// Options for `generate_moves`.
+#[derive(Clone, Debug, Default)]
+pub struct MoveOptions {
+    /// The maximum number of moves to return.
+    pub limit: Option<usize>,
+}
+
+/// Find the legal moves using tiles from `rack` on `board`, and score them,
+/// highest score first. The rack may include "." or "?" blanks.
+pub fn generate_moves(
+    dict: &Dictionary,
+    board: &Board,
+    rack: &str,
+    opts: &MoveOptions,
+) -> Result<Vec<Move>> {
+    let mut counts = [0; 26];
+    let mut blanks = 0;
+    for c in rack.chars().filter(|c| !c.is_whitespace()) {
+        match c.to_ascii_lowercase() {
+            c if is_blank(c) => blanks += 1,
+            c @ 'a'..='z' => counts[(c as u8 - b'a') as usize] += 1,
+            c => {
+                return Err(Error::InvalidPuzzle(format!(
+                    "unexpected {:?} in rack",
+                    c
+                )))
+            }
+        }
+    }
+    let rack = Rack { counts, blanks };
+    if rack.len() == 0 {
+        return Err(Error::InvalidPuzzle("rack is empty".to_owned()));
+    }
+
+    let mut moves = vec![];
+    for direction in [Direction::Across, Direction::Down] {
+        let (lines, len) = board.lines(direction);
+        for line in 0..lines {
+            let squares = line_squares(dict, board, direction, line, len);
+            for (start, prob, word) in placements(dict, &squares, rack)? {
+                let m = score_move(
+                    board, direction, line, start, &squares, &rack, &word, prob,
+                );
+                // A single tile which forms words in both directions is found
+                // once in each direction, so only keep the across move.
+                if direction == Direction::Down && m.tiles_placed == 1 {
+                    let placed_at = (start..start + word.len())
+                        .find(|&p| matches!(squares[p], LineSquare::Empty { .. }))
+                        .expect("should have placed a tile");
+                    if let LineSquare::Empty { cross: Some(_), .. } =
+                        squares[placed_at]
+                    {
+                        continue;
+                    }
+                }
+                moves.push(m);
+            }
+        }
+    }
+    // Break ties by position and word, so that the order doesn't depend on
+    // how we found the moves.
+    moves.sort_by(|m1, m2| {
+        let key =
+            |m: &Move| (Reverse(m.score), Reverse(m.prob), m.row, m.col, m.direction);
+        key(m1).cmp(&key(m2)).then_with(|| m1.word.cmp(&m2.word))
+    });
+    if let Some(limit) = opts.limit {
+        moves.truncate(limit);
+    }
+    Ok(moves)
+}
+
+/// Find every word which can be placed along a line, with the position of
+/// its first letter. If the dictionary has a GADDAG, we grow words outwards
+/// from each anchor square. Otherwise, we try starting a word on every
+/// square.
+fn placements(
+    dict: &Dictionary,
+    squares: &[LineSquare],
+    rack: Rack,
+) -> Result<Vec<(usize, Prob, String)>> {
+    let mut placements = vec![];
+    if dict.has_gaddag() {
+        for anchor in 0..squares.len() {
+            if !matches!(squares[anchor], LineSquare::Empty { anchor: true, .. }) {
+                continue;
+            }
+            let automaton = GaddagPlacement {
+                squares,
+                anchor,
+                rack,
+            };
+            for m in dict.search_gaddag(automaton)? {
+                placements.push((anchor - m.anchor, m.prob, m.word));
+            }
+        }
+    } else {
+        for start in 0..squares.len() {
+            if start > 0 && matches!(squares[start - 1], LineSquare::Tile { .. }) {
+                continue;
+            }
+            let automaton = Placement {
+                squares,
+                start,
+                rack,
+            };
+            let words = dict.find_matches_by(automaton, &SearchOptions::default())?;
+            for (prob, word) in words.into_vec() {
+                placements.push((start, prob, word));
+            }
+        }
+    }
+    Ok(placements)
+}
+
+/// Describe each square along a line of the board.
+fn line_squares(
+    dict: &Dictionary,
+    board: &Board,
+    direction: Direction,
+    line: usize,
+    len: usize,
+) -> Vec<LineSquare> {
+    let empty_board = board.is_empty();
+    let (lines, _) = board.lines(direction);
+    (0..len)
+        .map(|pos| {
+            let idx = board.index(direction, line, pos);
+            if let Some((letter, blank)) = board.tile(idx) {
+                return LineSquare::Tile { letter, blank };
+            }
+            // The square's position along the crossing line is our line, and
+            // the crossing line is `pos`.
+            let cross =
+                board
+                    .cross_word(direction.cross(), pos, line)
+                    .map(|mut cross| {
+                        for letter in b'a'..=b'z' {
+                            let word = format!(
+                                "{}{}{}",
+                                cross.prefix, letter as char, cross.suffix
+                            );
+                            if dict.probability_of(&word).is_some() {
+                                cross.allowed |= letter_bit(letter);
+                            }
+                        }
+                        cross
+                    });
+            let is_tile = |line: Option<usize>, pos: Option<usize>| match (line, pos) {
+                (Some(line), Some(pos)) if line < lines && pos < len => {
+                    board.tile(board.index(direction, line, pos)).is_some()
+                }
+                _ => false,
+            };
+            let neighbors = [
+                is_tile(Some(line), pos.checked_sub(1)),
+                is_tile(Some(line), Some(pos + 1)),
+                is_tile(line.checked_sub(1), Some(pos)),
+                is_tile(Some(line + 1), Some(pos)),
+            ];
+            let center = (lines / 2, len / 2) == (line, pos);
+            LineSquare::Empty {
+                anchor: neighbors.contains(&true) || (empty_board && center),
+                cross,
+                premium: board.premiums[idx],
+            }
+        })
+        .collect()
+}
+
+/// The tiles in a rack.
+#[derive(Clone, Copy, Debug)]
+struct Rack {
+    /// The number of tiles of each letter.
+    counts: [usize; 26],
+    /// The number of blanks.
+    blanks: usize,
+}
+
+impl Rack {
+    /// The total number of tiles.
+    fn len(&self) -> usize {
+        self.counts.iter().sum::<usize>() + self.blanks
+    }
+}
+
+/// Matches words which can be placed along a line starting at `start`,
+/// using tiles from `rack`.
+struct Placement<'a> {
+    squares: &'a [LineSquare],
+    start: usize,
+    rack: Rack,
+}
+
+/// How far we've got placing a word.
+#[derive(Clone, Debug)]
+struct PlacementState {
+    /// The next square to fill.
+    pos: usize,
+    /// The tiles we have left.
+    rack: Rack,
+    /// The number of tiles placed so far.
+    placed: usize,
+    /// Does the word connect to tiles already on the board?
+    connected: bool,
+}
+
+impl Automaton for Placement<'_> {
+    type State = Option<PlacementState>;
+
+    fn start(&self) -> Self::State {
+        Some(PlacementState {
+            pos: self.start,
+            rack: self.rack,
+            placed: 0,
+            connected: false,
+        })
+    }
+
+    fn is_match(&self, state: &Self::State) -> bool {
+        match state {
+            Some(state) => {
+                let ends_here = self
+                    .squares
+                    .get(state.pos)
+                    .is_none_or(|sq| matches!(sq, LineSquare::Empty { .. }));
+                ends_here
+                    && state.placed > 0
+                    && state.connected
+                    && state.pos - self.start >= 2
+            }
+            None => false,
+        }
+    }
+
+    fn can_match(&self, state: &Self::State) -> bool {
+        state.is_some()
+    }
+
+    fn accept(&self, state: &Self::State, byte: u8) -> Self::State {
+        let mut state = state.clone()?;
+        if !byte.is_ascii_lowercase() {
+            return None;
+        }
+        let square = self.squares.get(state.pos)?;
+        match square {
+            LineSquare::Tile { letter, .. } => {
+                if *letter != byte {
+                    return None;
+                }
+                state.connected = true;
+            }
+            LineSquare::Empty { anchor, .. } => {
+                if !square.allows(byte) {
+                    return None;
+                }
+                if !state.rack.take(byte) {
+                    return None;
+                }
+                state.placed += 1;
+                state.connected |= *anchor;
+            }
+        }
+        state.pos += 1;
+        Some(state)
+    }
+}
+
+impl Rack {
+    /// Take a tile for `byte` from the rack, using a blank if we have to.
+    /// Returns false if we can't.
+    fn take(&mut self, byte: u8) -> bool {
+        let count = &mut self.counts[(byte - b'a') as usize];
+        if *count > 0 {
+            *count -= 1;
+        } else if self.blanks > 0 {
+            self.blanks -= 1;
+        } else {
+            return false;
+        }
+        true
+    }
+}
+
+/// Matches paths through a GADDAG for words which can be placed along a line
+/// through the empty square `anchor`, using tiles from `rack`. The path first
+/// places letters leftwards from the anchor, then rightwards after the
+/// separator.
+///
+/// To find each move only once, a move may not cover any anchor to the left
+/// of `anchor`. Those moves are found from their leftmost anchor instead.
+struct GaddagPlacement<'a> {
+    squares: &'a [LineSquare],
+    anchor: usize,
+    rack: Rack,
+}
+
+/// How far we've got placing a word through an anchor.
+#[derive(Clone, Debug)]
+struct GaddagState {
+    /// The leftmost square we've filled, or `anchor + 1` if we haven't filled
+    /// any yet.
+    left: usize,
+    /// The rightmost square we've filled.
+    right: usize,
+    /// Have we passed the separator, and started filling squares to the
+    /// right of the anchor?
+    rightwards: bool,
+    /// The tiles we have left.
+    rack: Rack,
+}
+
+impl Automaton for GaddagPlacement<'_> {
+    type State = Option<GaddagState>;
+
+    fn start(&self) -> Self::State {
+        Some(GaddagState {
+            left: self.anchor + 1,
+            right: self.anchor,
+            rightwards: false,
+            rack: self.rack,
+        })
+    }
+
+    fn is_match(&self, state: &Self::State) -> bool {
+        match state {
+            Some(state) => {
+                let ends_here = self
+                    .squares
+                    .get(state.right + 1)
+                    .is_none_or(|sq| matches!(sq, LineSquare::Empty { .. }));
+                state.rightwards && ends_here && state.right + 1 - state.left >= 2
+            }
+            None => false,
+        }
+    }
+
+    fn can_match(&self, state: &Self::State) -> bool {
+        state.is_some()
+    }
+
+    fn accept(&self, state: &Self::State, byte: u8) -> Self::State {
+        let mut state = state.clone()?;
+        if byte == 0 {
+            // The word must not continue to the left.
+            let before = state.left.checked_sub(1).map(|pos| &self.squares[pos]);
+            if state.rightwards
+                || state.left > self.anchor
+                || matches!(before, Some(LineSquare::Tile { .. }))
+            {
+                return None;
+            }
+            state.rightwards = true;
+            return Some(state);
+        }
+        if !byte.is_ascii_lowercase() {
+            return None;
+        }
+        let pos = if state.rightwards {
+            state.right + 1
+        } else {
+            state.left.checked_sub(1)?
+        };
+        match self.squares.get(pos)? {
+            LineSquare::Tile { letter, .. } if *letter == byte => {}
+            LineSquare::Tile { .. } => return None,
+            LineSquare::Empty { anchor, .. } => {
+                let other_anchor_on_left = *anchor && pos < self.anchor;
+                if other_anchor_on_left
+                    || !self.squares[pos].allows(byte)
+                    || !state.rack.take(byte)
+                {
+                    return None;
+                }
+            }
+        }
+        if state.rightwards {
+            state.right = pos;
+        } else {
+            state.left = pos;
+        }
+        Some(state)
+    }
+}
+
+/// Score `word`, placed along `line` starting at `start`.
+#[allow(clippy::too_many_arguments)]
+fn score_move(
+    board: &Board,
+    direction: Direction,
+    line: usize,
+    start: usize,
+    squares: &[LineSquare],
+    rack: &Rack,
+    word: &str,
+    prob: Prob,
+) -> Move {
+    // Find the new tiles, and the main word's multiplier.
+    let mut new_tiles = vec![];
+    let mut word_multiplier = 1;
+    for (i, letter) in word.bytes().enumerate() {
+        if let LineSquare::Empty { cross, premium, .. } = &squares[start + i] {
+            word_multiplier *= premium.word_multiplier();
+            new_tiles.push((i, letter, cross, *premium));
+        }
+    }
+
+    // Blanks score nothing, so when we need blanks for a letter, play the
+    // real tiles where they score the most. A tile's value counts towards the
+    // main word and any cross word.
+    let mut blanks = vec![];
+    for letter in b'a'..=b'z' {
+        let mut uses = new_tiles
+            .iter()
+            .filter(|(_, l, _, _)| *l == letter)
+            .map(|(i, _, cross, premium)| {
+                let cross_multiplier =
+                    cross.as_ref().map_or(0, |_| premium.word_multiplier());
+                let weight =
+                    premium.letter_multiplier() * (word_multiplier + cross_multiplier);
+                (Reverse(weight), *i)
+            })
+            .collect::<Vec<_>>();
+        uses.sort();
+        let real = rack.counts[(letter - b'a') as usize];
+        blanks.extend(uses.iter().skip(real).map(|(_, i)| *i));
+    }
+    blanks.sort_unstable();
+
+    // Add up the main word and the cross words.
+    let mut main_score = 0;
+    let mut cross_score = 0;
+    let mut cross_words = vec![];
+    for (i, letter) in word.bytes().enumerate() {
+        match &squares[start + i] {
+            LineSquare::Tile { letter, blank } => {
+                main_score += tile_value(*letter, *blank)
+            }
+            LineSquare::Empty { cross, premium, .. } => {
+                let value = tile_value(letter, blanks.contains(&i))
+                    * premium.letter_multiplier();
+                main_score += value;
+                if let Some(cross) = cross {
+                    cross_score += (cross.score + value) * premium.word_multiplier();
+                    cross_words.push(format!(
+                        "{}{}{}",
+                        cross.prefix, letter as char, cross.suffix
+                    ));
+                }
+            }
+        }
+    }
+    let mut score = main_score * word_multiplier + cross_score;
+    if new_tiles.len() == RACK_SIZE {
+        score += BINGO_BONUS;
+    }
+
+    let idx = board.index(direction, line, start);
+    Move {
+        row: idx / board.width,
+        col: idx % board.width,
+        direction,
+        word: word.to_owned(),
+        blanks,
+        tiles_placed: new_tiles.len(),
+        cross_words,
+        score,
+        prob,
+    }
+}
+
+/// The value of a tile.
+fn tile_value(letter: u8, blank: bool) -> u32 {
+    if blank {
+        0
+    } else {
+        TILE_VALUES[(letter - b'a') as usize]
+    }
+}
+
+/// The bit representing `letter` in a set of letters.
+fn letter_bit(letter: u8) -> u32 {
+    1 << (letter - b'a')
+}
@@ -0,0 +1,162 @@
+//! Recording the tree explored by a backtracking search, so that it can be
+//! drawn with Graphviz to see where the search spent its time and which
+//! branches it pruned.
+//!
+//! Searches which support this take an `Option<SearchTree>`, add a node for
+//! each choice they try, and mark each node with what became of it.
+
+use std::{
+    fmt::{self, Write as _},
+    fs,
+    path::Path,
+};
+
+use crate::error::{Error, Result};
+use crate::probability::Prob;
+
+/// The most nodes we'll record by default. Graphviz struggles to lay out
+/// much bigger graphs, and searches can try millions of choices.
+pub const DEFAULT_MAX_NODES: usize = 10_000;
+
+/// A node in a `SearchTree`. The default is the root.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct NodeId(usize);
+
+impl NodeId {
+    /// Stands in for nodes we didn't record because the tree was full, and
+    /// for all of their descendants.
+    const DROPPED: NodeId = NodeId(usize::MAX);
+}
+
+/// What became of a choice in a search.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Outcome {
+    /// The search explored everything below this choice.
+    Explored,
+    /// The search gave up on this choice without exploring it, for the
+    /// reason given.
+    Pruned(String),
+    /// This choice is part of a result.
+    Solution,
+}
+
+/// A choice made by a search.
+#[derive(Clone, Debug)]
+struct Node {
+    /// The node this choice was made from, or `None` for the root.
+    parent: Option<NodeId>,
+    /// What was chosen.
+    label: String,
+    /// The probability of the choice, if it has one.
+    prob: Option<Prob>,
+    /// What became of it.
+    outcome: Outcome,
+}
+
+/// The tree of choices explored by a search.
+#[derive(Clone, Debug)]
+pub struct SearchTree {
+    /// Our nodes, with the root first.
+    nodes: Vec<Node>,
+    /// The most nodes we'll record.
+    max_nodes: usize,
+    /// How many nodes we didn't record because we already had `max_nodes`.
+    dropped: u64,
+}
+
+impl SearchTree {
+    /// Create a tree whose root is labeled `label`, which records at most
+    /// `max_nodes` nodes.
+    pub fn new(label: &str, max_nodes: usize) -> SearchTree {
+        SearchTree {
+            nodes: vec![Node {
+                parent: None,
+                label: label.to_owned(),
+                prob: None,
+                outcome: Outcome::Explored,
+            }],
+            max_nodes: max_nodes.max(1),
+            dropped: 0,
+        }
+    }
+
+    /// Record a choice made from `parent`. Until we know better, we assume
+    /// that the search will explore it.
+    pub fn add(
+        &mut self,
+        parent: NodeId,
+        label: String,
+        prob: Option<Prob>,
+    ) -> NodeId {
+        if parent == NodeId::DROPPED || self.nodes.len() >= self.max_nodes {
+            self.dropped += 1;
+            return NodeId::DROPPED;
+        }
+        self.nodes.push(Node {
+            parent: Some(parent),
+            label,
+            prob,
+            outcome: Outcome::Explored,
+        });
+        NodeId(self.nodes.len() - 1)
+    }
+
+    /// Record what became of `node`.
+    pub fn set_outcome(&mut self, node: NodeId, outcome: Outcome) {
+        if let Some(node) = self.nodes.get_mut(node.0) {
+            node.outcome = outcome;
+        }
+    }
+
+    /// Write the tree as a Graphviz graph. Results are drawn in bold green,
+    /// and pruned choices in red with the reason they were pruned.
+    pub fn to_dot(&self) -> String {
+        let mut dot = String::new();
+        self.write_dot(&mut dot)
+            .expect("writing to a String should never fail");
+        dot
+    }
+
+    /// Implementation of `to_dot`.
+    fn write_dot(&self, out: &mut String) -> fmt::Result {
+        writeln!(out, "digraph search {{")?;
+        writeln!(out, "  node [shape=box];")?;
+        for (idx, node) in self.nodes.iter().enumerate() {
+            let mut label = node.label.clone();
+            if let Some(prob) = node.prob {
+                write!(label, "\n({:.2})", prob)?;
+            }
+            let style = match &node.outcome {
+                Outcome::Explored => "",
+                Outcome::Pruned(reason) => {
+                    write!(label, "\npruned: {}", reason)?;
+                    ", color=red, fontcolor=red"
+                }
+                Outcome::Solution => ", color=darkgreen, style=bold",
+            };
+            writeln!(
+                out,
+                "  n{} [label=\"{}\"{}];",
+                idx,
+                label.escape_default(),
+                style
+            )?;
+            if let Some(parent) = node.parent {
+                writeln!(out, "  n{} -> n{};", parent.0, idx)?;
+            }
+        }
+        if self.dropped > 0 {
+            writeln!(
+                out,
+                "  dropped [label=\"{} more choices not shown\", shape=plaintext];",
+                self.dropped
+            )?;
+        }
+        writeln!(out, "}}")
+    }
+
+    /// Write the tree to `path` as a Graphviz graph.
+    pub fn write(&self, path: &Path) -> Result<()> {
+        fs::write(path, self.to_dot()).map_err(Error::io(path))
+    }
+}
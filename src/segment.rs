@@ -0,0 +1,495 @@
+//! Breaking strings into sequences of dictionary words.
+//!
+//! We first build a lattice of every dictionary word which could appear at
+//! each position in the string, and then use dynamic programming over the
+//! positions to find the most probable ways to walk from the start of the
+//! string to the end.
+
+use itertools::Itertools;
+use log::{debug, trace};
+use serde::Serialize;
+use std::fmt::{self, Write};
+
+use crate::dictionary::{phrase_letters, Dictionary, SearchOptions};
+use crate::enumeration::Enumeration;
+use crate::error::{Error, Result};
+use crate::probability::Prob;
+use crate::scorer::Scorers;
+
+/// One way to break a string into words. This displays as the words
+/// separated by spaces.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Breaking {
+    /// Each word, with its probability.
+    pub words: Vec<(Prob, String)>,
+    /// The combined probability of every word.
+    pub total: Prob,
+}
+
+impl Breaking {
+    /// The words, without their probabilities.
+    pub fn words(&self) -> impl Iterator<Item = &str> + '_ {
+        self.words.iter().map(|(_, word)| word.as_str())
+    }
+
+    /// The number of words.
+    pub fn len(&self) -> usize {
+        self.words.len()
+    }
+
+    /// Does this breaking have no words? Only the empty string breaks this
+    /// way.
+    pub fn is_empty(&self) -> bool {
+        self.words.is_empty()
+    }
+
+    /// Take the words, without their probabilities.
+    pub fn into_words(self) -> Vec<String> {
+        self.words.into_iter().map(|(_, word)| word).collect()
+    }
+}
+
+impl fmt::Display for Breaking {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.words().join(" "))
+    }
+}
+
+/// Options for `break_into_words`.
+#[derive(Clone, Debug)]
+pub struct SegmentOptions {
+    /// The minimum length of each word.
+    pub min_word_len: usize,
+    /// The maximum number of words in each breaking.
+    pub max_words: Option<usize>,
+    /// The maximum number of breakings to return.
+    pub limit: usize,
+    /// Only return breakings whose words have this shape, like a crossword
+    /// answer. A phrase in the dictionary may cover several of its words.
+    pub enumeration: Option<Enumeration>,
+    /// Only allow words to start and end at these character positions, such
+    /// as the boundaries between fragments. The start and end of the string
+    /// are always allowed.
+    pub breaks: Option<Vec<usize>>,
+    /// Don't allow the same word twice in a row, as in "a a a".
+    pub no_repeat_adjacent: bool,
+    /// The maximum number of times each word may appear in a breaking.
+    pub max_repeats_per_word: Option<usize>,
+    /// Adjustments to the score of each word. The probabilities of words in
+    /// breakings are these scores.
+    pub scorers: Scorers,
+    /// Allow words which aren't in the dictionary, giving each one this
+    /// probability, such as `SmoothedModel::unknown_probability`. Longer
+    /// unknown words are less probable, by a factor of `UNKNOWN_LETTER` for
+    /// each letter after the first. By default, breakings need every word to
+    /// be in the dictionary.
+    pub unknown_word: Option<Prob>,
+}
+
+/// The probability of each letter after the first in a word which isn't in
+/// the dictionary, as if it were chosen at random. Without this, a single
+/// unknown word covering the whole string would beat any real breaking.
+const UNKNOWN_LETTER: f64 = 1.0 / 26.0;
+
+impl Default for SegmentOptions {
+    fn default() -> Self {
+        SegmentOptions {
+            min_word_len: 1,
+            max_words: None,
+            limit: 100,
+            enumeration: None,
+            breaks: None,
+            no_repeat_adjacent: false,
+            max_repeats_per_word: None,
+            scorers: Scorers::new(),
+            unknown_word: None,
+        }
+    }
+}
+
+impl SegmentOptions {
+    /// May a breaking start with `word` followed by `rest`, given our limits
+    /// on repeated words?
+    fn allows_repeats<'a>(
+        &self,
+        word: &str,
+        rest: impl Iterator<Item = &'a str> + Clone,
+    ) -> bool {
+        if self.no_repeat_adjacent && rest.clone().next() == Some(word) {
+            return false;
+        }
+        match self.max_repeats_per_word {
+            Some(max) => rest.filter(|&other| other == word).count() < max,
+            None => true,
+        }
+    }
+}
+
+/// A word which could appear in part of the string we're breaking.
+#[derive(Clone, Debug, Serialize)]
+pub struct Span {
+    /// The index of the first character covered by this word.
+    pub start: usize,
+    /// The index just past the last character covered by this word.
+    pub end: usize,
+    /// The word.
+    pub word: String,
+    /// The probability of the word, as a negative log probability.
+    pub prob: Prob,
+}
+
+/// Every dictionary word which could appear at each position of a string.
+#[derive(Clone, Debug, Serialize)]
+pub struct Lattice {
+    /// The string we're breaking.
+    pub text: String,
+    /// The number of characters in the string.
+    pub len: usize,
+    /// The possible words, sorted by start position.
+    pub spans: Vec<Span>,
+}
+
+impl Lattice {
+    /// Find every word in `dict` which could appear in `pattern`. `pattern` may
+    /// use `.` for unknown letters. For each span of `pattern`, we keep at most
+    /// `opts.limit` of the most probable words.
+    pub fn build(
+        dict: &Dictionary,
+        pattern: &str,
+        opts: &SegmentOptions,
+    ) -> Result<Lattice> {
+        let search_opts = SearchOptions {
+            limit: Some(opts.limit),
+            most_probable: true,
+        };
+        Lattice::build_with(pattern, opts, |word_pat| {
+            let mut words = words_matching(dict, word_pat, &search_opts)?;
+            if let Some(unknown) = opts.unknown_word {
+                let is_known = words
+                    .iter()
+                    .any(|(_, word)| phrase_letters(word) == word_pat);
+                if !is_known && word_pat.chars().all(|c| c.is_alphabetic()) {
+                    let extra_letters = word_pat.chars().count() - 1;
+                    let letter = Prob::from_probability(UNKNOWN_LETTER);
+                    let prob =
+                        (0..extra_letters).fold(unknown, |prob, _| prob * letter);
+                    words.push((prob, word_pat.to_owned()));
+                }
+            }
+            Ok(words)
+        })
+    }
+
+    /// Like `build`, but call `words_for` to find the words which could
+    /// appear in each span of `text`. This lets us break up text which spells
+    /// words in some other way, like Morse code.
+    pub fn build_with<F>(
+        text: &str,
+        opts: &SegmentOptions,
+        mut words_for: F,
+    ) -> Result<Lattice>
+    where
+        F: FnMut(&str) -> Result<Vec<(Prob, String)>>,
+    {
+        let chars = text.chars().collect::<Vec<_>>();
+
+        // Decide where words may start and end.
+        let mut breaks = match &opts.breaks {
+            Some(breaks) => {
+                let mut breaks = breaks.clone();
+                breaks.extend([0, chars.len()]);
+                breaks.retain(|&pos| pos <= chars.len());
+                breaks.sort_unstable();
+                breaks.dedup();
+                breaks
+            }
+            None => (0..=chars.len()).collect(),
+        };
+        let mut enumerated = None;
+        if let Some(enumeration) = &opts.enumeration {
+            if enumeration.letter_count() != chars.len() {
+                return Err(Error::InvalidPuzzle(format!(
+                    "{:?} has {} letters, but the enumeration {} has {}",
+                    text,
+                    chars.len(),
+                    enumeration,
+                    enumeration.letter_count()
+                )));
+            }
+            let boundaries = enumeration.boundaries();
+            breaks.retain(|pos| boundaries.contains(pos));
+            enumerated = Some((enumeration, boundaries));
+        }
+
+        let mut spans = vec![];
+        for (i, &start) in breaks.iter().enumerate() {
+            for &end in &breaks[i + 1..] {
+                if end - start < opts.min_word_len {
+                    continue;
+                }
+                let span_text = chars[start..end].iter().collect::<String>();
+                for (prob, word) in words_for(&span_text)? {
+                    let prob = opts.scorers.score(&word, prob);
+                    // With an enumeration, each word or phrase needs to cover
+                    // whole enumerated words with the right lengths.
+                    if let Some((enumeration, boundaries)) = &enumerated {
+                        let part = |pos| {
+                            boundaries
+                                .iter()
+                                .position(|&b| b == pos)
+                                .expect("every break is an enumeration boundary")
+                        };
+                        if !enumeration.fits_part(part(start), part(end), &word) {
+                            continue;
+                        }
+                    }
+                    trace!(
+                        event = "candidate", start = start, end = end,
+                        word = word.as_str(), nats = prob.to_f64();
+                        "{}..{} may be {:?}", start, end, word
+                    );
+                    spans.push(Span {
+                        start,
+                        end,
+                        word,
+                        prob,
+                    });
+                }
+            }
+        }
+        debug!(spans = spans.len(); "found {} possible words", spans.len());
+        Ok(Lattice {
+            text: text.to_owned(),
+            len: chars.len(),
+            spans,
+        })
+    }
+
+    /// Find the `opts.limit` most probable paths through the lattice which
+    /// use at most `opts.max_words` words and respect `opts`' limits on
+    /// repeated words, returning the spans used by each.
+    ///
+    /// We check the limits on repeats as we extend each path, so they cost
+    /// little. But since we only keep the best `opts.limit` paths from each
+    /// position, a very repetitive string may produce fewer paths than it
+    /// could.
+    pub fn k_best(&self, opts: &SegmentOptions) -> Vec<(Prob, Vec<usize>)> {
+        let k = opts.limit;
+        let max_words = opts.max_words.unwrap_or(self.len).min(self.len);
+        let check_repeats =
+            opts.no_repeat_adjacent || opts.max_repeats_per_word.is_some();
+
+        // `best[i][n]` holds the `k` best ways to break the string from
+        // position `i` onwards into exactly `n` words, as indices into
+        // `self.spans`.
+        let mut best =
+            vec![vec![Vec::<(Prob, Vec<usize>)>::new(); max_words + 1]; self.len + 1];
+        best[self.len][0].push((Prob::always(), vec![]));
+        for start in (0..self.len).rev() {
+            for (idx, span) in self.spans.iter().enumerate() {
+                if span.start != start {
+                    continue;
+                }
+                for n in 1..=max_words {
+                    let extended = best[span.end][n - 1]
+                        .iter()
+                        .filter(|(_, rest)| {
+                            let rest_words =
+                                rest.iter().map(|&idx| self.spans[idx].word.as_str());
+                            !check_repeats
+                                || opts.allows_repeats(&span.word, rest_words)
+                        })
+                        .map(|(prob, rest)| {
+                            let mut path = Vec::with_capacity(rest.len() + 1);
+                            path.push(idx);
+                            path.extend_from_slice(rest);
+                            (span.prob * *prob, path)
+                        })
+                        .collect::<Vec<_>>();
+                    best[start][n].extend(extended);
+                }
+            }
+            for paths in &mut best[start] {
+                keep_best(paths, k);
+            }
+        }
+
+        let mut paths = best
+            .swap_remove(0)
+            .into_iter()
+            .flatten()
+            .collect::<Vec<_>>();
+        keep_best(&mut paths, k);
+        paths
+    }
+
+    /// Write the lattice and the paths in `best` as JSON.
+    pub fn to_json(&self, best: &[(Prob, Vec<usize>)]) -> String {
+        #[derive(Serialize)]
+        struct Path<'a> {
+            #[serde(flatten)]
+            breaking: Breaking,
+            spans: &'a [usize],
+        }
+
+        #[derive(Serialize)]
+        struct Output<'a> {
+            #[serde(flatten)]
+            lattice: &'a Lattice,
+            best: Vec<Path<'a>>,
+        }
+
+        let output = Output {
+            lattice: self,
+            best: best
+                .iter()
+                .map(|(prob, path)| Path {
+                    breaking: self.breaking(*prob, path),
+                    spans: path,
+                })
+                .collect(),
+        };
+        serde_json::to_string_pretty(&output).expect("lattice should serialize")
+    }
+
+    /// Write the lattice as a Graphviz graph, where nodes are positions in
+    /// the string and edges are words. Edges on the most probable path in
+    /// `best` are drawn in bold, and words which can't be part of any path
+    /// through the lattice are dashed and gray.
+    pub fn to_dot(&self, best: &[(Prob, Vec<usize>)]) -> String {
+        let mut dot = String::new();
+        self.write_dot(best, &mut dot)
+            .expect("writing to a String should never fail");
+        dot
+    }
+
+    /// Implementation of `to_dot`.
+    fn write_dot(&self, best: &[(Prob, Vec<usize>)], out: &mut String) -> fmt::Result {
+        let best_path = best.first().map_or(&[][..], |(_, path)| &path[..]);
+        let dead = self.dead_spans();
+        writeln!(out, "digraph lattice {{")?;
+        writeln!(out, "  rankdir=LR;")?;
+        writeln!(out, "  label={:?};", self.text)?;
+        for pos in 0..=self.len {
+            writeln!(out, "  {} [shape=circle];", pos)?;
+        }
+        for (idx, span) in self.spans.iter().enumerate() {
+            let style = if best_path.contains(&idx) {
+                ", style=bold"
+            } else if dead[idx] {
+                ", style=dashed, color=gray, fontcolor=gray"
+            } else {
+                ""
+            };
+            writeln!(
+                out,
+                "  {} -> {} [label=\"{} ({:.2})\"{}];",
+                span.start,
+                span.end,
+                span.word.escape_default(),
+                span.prob,
+                style,
+            )?;
+        }
+        writeln!(out, "}}")
+    }
+
+    /// Which spans can't be part of any path from the start of the string to
+    /// the end, because nothing leads to their start or nothing leads on from
+    /// their end.
+    fn dead_spans(&self) -> Vec<bool> {
+        let mut from_start = vec![false; self.len + 1];
+        from_start[0] = true;
+        let mut to_end = vec![false; self.len + 1];
+        to_end[self.len] = true;
+        // Spans always go forwards, so one pass in each direction is enough.
+        let mut by_start = self.spans.iter().collect::<Vec<_>>();
+        by_start.sort_by_key(|span| span.start);
+        for span in &by_start {
+            if from_start[span.start] {
+                from_start[span.end] = true;
+            }
+        }
+        for span in by_start.iter().rev() {
+            if to_end[span.end] {
+                to_end[span.start] = true;
+            }
+        }
+        self.spans
+            .iter()
+            .map(|span| !from_start[span.start] || !to_end[span.end])
+            .collect()
+    }
+
+    /// The words used by `path`.
+    pub fn words(&self, path: &[usize]) -> Vec<String> {
+        path.iter()
+            .map(|&idx| self.spans[idx].word.clone())
+            .collect()
+    }
+
+    /// The breaking described by `path`, whose combined probability is
+    /// `total`.
+    pub fn breaking(&self, total: Prob, path: &[usize]) -> Breaking {
+        Breaking {
+            words: path
+                .iter()
+                .map(|&idx| (self.spans[idx].prob, self.spans[idx].word.clone()))
+                .collect(),
+            total,
+        }
+    }
+}
+
+/// Sort `paths` from most to least probable, and keep the first `k`.
+fn keep_best(paths: &mut Vec<(Prob, Vec<usize>)>, k: usize) {
+    paths.sort_by(|(p1, _), (p2, _)| p2.cmp(p1));
+    paths.truncate(k);
+}
+
+/// Find the most probable ways to break `pattern` into dictionary words,
+/// most probable first. `pattern` may use `.` for unknown letters.
+pub fn break_into_words(
+    dict: &Dictionary,
+    pattern: &str,
+    opts: &SegmentOptions,
+) -> Result<Vec<Breaking>> {
+    let lattice = Lattice::build(dict, pattern, opts)?;
+    let breakings = lattice
+        .k_best(opts)
+        .into_iter()
+        .map(|(prob, path)| lattice.breaking(prob, &path))
+        .collect::<Vec<_>>();
+    for breaking in &breakings {
+        debug!("Found {} {}", breaking.total, breaking);
+    }
+    Ok(breakings)
+}
+
+/// Find the single most probable way to break `pattern` into dictionary
+/// words, if there is one.
+pub fn best_breaking(
+    dict: &Dictionary,
+    pattern: &str,
+    opts: &SegmentOptions,
+) -> Result<Option<Breaking>> {
+    let opts = SegmentOptions {
+        limit: 1,
+        ..opts.clone()
+    };
+    Ok(break_into_words(dict, pattern, &opts)?.into_iter().next())
+}
+
+/// Find the most probable words and phrases matching `pattern`. Plain words
+/// are looked up directly, which is much faster than compiling a regex.
+fn words_matching(
+    dict: &Dictionary,
+    pattern: &str,
+    opts: &SearchOptions,
+) -> Result<Vec<(Prob, String)>> {
+    if pattern.chars().all(|c| c.is_alphabetic()) {
+        Ok(dict.find_spelled(pattern)?.into_vec())
+    } else {
+        Ok(dict.find_matches_with(pattern, opts)?.into_vec())
+    }
+}
@@ -0,0 +1,309 @@
+//! Indexes split into several fst files, or shards, so that no single file
+//! grows too large for very big word lists.
+//!
+//! Each shard holds every key starting with some range of first bytes, and
+//! the shards are in key order. So looking up a key only needs one shard,
+//! and streaming the shards one after another visits every key in order.
+//! The first shard of the index with extension "rank" is stored as usual,
+//! next to the dictionary, and the others as "rank1", "rank2" and so on.
+
+use fst::{
+    automaton::{AlwaysMatch, Automaton},
+    map, raw, set, IntoStreamer, Map, Set, Streamer,
+};
+use std::{
+    fs::File,
+    io::BufWriter,
+    path::{Path, PathBuf},
+};
+
+use crate::error::{Error, Result};
+use crate::storage::{sidecar_path, Bytes};
+
+/// The extension of shard number `shard` of the index with `extension`,
+/// where `extension` is empty for the words themselves.
+pub(crate) fn shard_extension(extension: &str, shard: usize) -> String {
+    match shard {
+        0 => extension.to_owned(),
+        _ => format!("{}{}", extension, shard),
+    }
+}
+
+/// An `fst::Map` or `fst::Set` split into shards.
+pub(crate) struct Sharded<T> {
+    /// The shards, in key order.
+    shards: Vec<T>,
+    /// The first byte of the first key in each shard but the first.
+    starts: Vec<u8>,
+}
+
+/// A map split into shards.
+pub(crate) type ShardedMap = Sharded<Map<Bytes>>;
+
+/// A set split into shards.
+pub(crate) type ShardedSet = Sharded<Set<Bytes>>;
+
+impl<T: From<raw::Fst<Bytes>>> Sharded<T> {
+    /// Wrap `shards`, the shards of the index at `path`, checking that
+    /// they're in order.
+    pub(crate) fn new(path: &Path, shards: Vec<raw::Fst<Bytes>>) -> Result<Self> {
+        let mut starts = Vec::with_capacity(shards.len().saturating_sub(1));
+        for shard in shards.iter().skip(1) {
+            let first = shard
+                .stream()
+                .next()
+                .and_then(|(key, _)| key.first().copied());
+            match first {
+                Some(first) if starts.last().is_none_or(|&last| last < first) => {
+                    starts.push(first)
+                }
+                _ => {
+                    return Err(Error::CorruptDictionary(format!(
+                        "the shards of {} are empty or out of order",
+                        path.display()
+                    )))
+                }
+            }
+        }
+        Ok(Sharded {
+            shards: shards.into_iter().map(T::from).collect(),
+            starts,
+        })
+    }
+}
+
+impl<T> Sharded<T> {
+    /// The shards, in key order.
+    pub(crate) fn shards(&self) -> &[T] {
+        &self.shards
+    }
+
+    /// The index of the only shard which may contain keys starting with
+    /// `first`, or the first shard if `first` is `None`, for the empty key.
+    pub(crate) fn shard_for(&self, first: Option<u8>) -> usize {
+        match first {
+            Some(first) => self.starts.partition_point(|&start| start <= first),
+            None => 0,
+        }
+    }
+
+    /// The shard which may contain `key`.
+    fn shard_for_key(&self, key: &[u8]) -> &T {
+        &self.shards[self.shard_for(key.first().copied())]
+    }
+}
+
+impl ShardedMap {
+    /// Look up the value of `key`.
+    pub(crate) fn get<K: AsRef<[u8]>>(&self, key: K) -> Option<u64> {
+        let key = key.as_ref();
+        self.shard_for_key(key).get(key)
+    }
+
+    /// The number of keys in every shard.
+    pub(crate) fn len(&self) -> usize {
+        self.shards.iter().map(Map::len).sum()
+    }
+
+    /// Are all of our shards empty?
+    pub(crate) fn is_empty(&self) -> bool {
+        self.shards.iter().all(Map::is_empty)
+    }
+
+    /// Stream every key and value, in order.
+    pub(crate) fn stream(&self) -> MapStream<'_> {
+        self.search(AlwaysMatch)
+    }
+
+    /// Stream every key accepted by `automaton`, and its value, in order.
+    pub(crate) fn search<'m, A>(&'m self, automaton: A) -> MapStream<'m, A>
+    where
+        A: Automaton + Clone + 'm,
+    {
+        match &self.shards[..] {
+            [shard] => MapStream::One(shard.search(automaton).into_stream()),
+            shards => {
+                let mut union = map::OpBuilder::new();
+                for shard in shards {
+                    union.push(shard.search(automaton.clone()));
+                }
+                MapStream::Many(union.union())
+            }
+        }
+    }
+
+    /// Stream every key starting with `prefix`, and its value, in order.
+    pub(crate) fn prefix(&self, prefix: &[u8]) -> MapStream<'_> {
+        if prefix.is_empty() {
+            return self.stream();
+        }
+        let range = self.shard_for_key(prefix).range().ge(prefix);
+        // The first key after every key starting with `prefix` is `prefix` with
+        // its last byte incremented, once we drop any trailing 0xFF bytes.
+        let mut end = prefix.to_owned();
+        while end.last() == Some(&0xFF) {
+            end.pop();
+        }
+        MapStream::One(match end.last_mut() {
+            Some(last) => {
+                *last += 1;
+                range.lt(end).into_stream()
+            }
+            None => range.into_stream(),
+        })
+    }
+}
+
+impl ShardedSet {
+    /// Does any shard contain `key`?
+    pub(crate) fn contains<K: AsRef<[u8]>>(&self, key: K) -> bool {
+        let key = key.as_ref();
+        self.shard_for_key(key).contains(key)
+    }
+
+    /// Stream every key, in order.
+    pub(crate) fn stream(&self) -> SetStream<'_> {
+        self.search(AlwaysMatch)
+    }
+
+    /// Stream every key accepted by `automaton`, in order.
+    pub(crate) fn search<'s, A>(&'s self, automaton: A) -> SetStream<'s, A>
+    where
+        A: Automaton + Clone + 's,
+    {
+        match &self.shards[..] {
+            [shard] => SetStream::One(shard.search(automaton).into_stream()),
+            shards => {
+                let mut union = set::OpBuilder::new();
+                for shard in shards {
+                    union.push(shard.search(automaton.clone()));
+                }
+                SetStream::Many(union.union())
+            }
+        }
+    }
+}
+
+/// The keys and values of a `ShardedMap`, in order.
+pub(crate) enum MapStream<'m, A: Automaton = AlwaysMatch> {
+    /// The stream of our only shard.
+    One(map::Stream<'m, A>),
+    /// The streams of all our shards. No key is in more than one shard.
+    Many(map::Union<'m>),
+}
+
+impl<'a, 'm, A: Automaton> Streamer<'a> for MapStream<'m, A> {
+    type Item = (&'a [u8], u64);
+
+    fn next(&'a mut self) -> Option<Self::Item> {
+        match self {
+            MapStream::One(stream) => stream.next(),
+            MapStream::Many(union) => {
+                union.next().map(|(key, values)| (key, values[0].value))
+            }
+        }
+    }
+}
+
+/// The keys of a `ShardedSet`, in order.
+pub(crate) enum SetStream<'s, A: Automaton = AlwaysMatch> {
+    /// The stream of our only shard.
+    One(set::Stream<'s, A>),
+    /// The streams of all our shards.
+    Many(set::Union<'s>),
+}
+
+impl<'a, 's, A: Automaton> Streamer<'a> for SetStream<'s, A> {
+    type Item = &'a [u8];
+
+    fn next(&'a mut self) -> Option<Self::Item> {
+        match self {
+            SetStream::One(stream) => stream.next(),
+            SetStream::Many(union) => union.next(),
+        }
+    }
+}
+
+/// Writes an index next to a dictionary, starting a new shard once the
+/// current one is at least `shard_size` bytes and the next key starts with a
+/// different byte.
+pub(crate) struct ShardWriter<'a> {
+    dict_path: &'a Path,
+    extension: &'a str,
+    shard_size: Option<u64>,
+    builder: raw::Builder<BufWriter<File>>,
+    /// The path of every shard we've started.
+    paths: Vec<PathBuf>,
+    /// The first byte of the last key we wrote.
+    last_first: Option<u8>,
+}
+
+impl<'a> ShardWriter<'a> {
+    /// Start writing the index with `extension` next to `dict_path`.
+    pub(crate) fn new(
+        dict_path: &'a Path,
+        extension: &'a str,
+        shard_size: Option<u64>,
+    ) -> Result<Self> {
+        let path = sidecar_path(dict_path, extension);
+        Ok(ShardWriter {
+            dict_path,
+            extension,
+            shard_size,
+            builder: new_builder(&path)?,
+            paths: vec![path],
+            last_first: None,
+        })
+    }
+
+    /// Add `key` with `value`. Keys must be added in order.
+    pub(crate) fn insert(&mut self, key: &[u8], value: u64) -> Result<()> {
+        self.start_key(key)?;
+        self.builder
+            .insert(key, value)
+            .map_err(Error::index(self.path()))
+    }
+
+    /// Add `key`, for an index which is a set. Keys must be added in order.
+    pub(crate) fn add(&mut self, key: &[u8]) -> Result<()> {
+        self.start_key(key)?;
+        self.builder.add(key).map_err(Error::index(self.path()))
+    }
+
+    /// Finish writing, and return the path of every shard.
+    pub(crate) fn finish(self) -> Result<Vec<PathBuf>> {
+        let path = self.path().to_owned();
+        self.builder.finish().map_err(Error::index(&path))?;
+        Ok(self.paths)
+    }
+
+    /// The path of the shard we're writing.
+    fn path(&self) -> &Path {
+        self.paths.last().expect("we always have a shard")
+    }
+
+    /// Start a new shard before `key` if it's time to.
+    fn start_key(&mut self, key: &[u8]) -> Result<()> {
+        let first = key.first().copied();
+        let full = self
+            .shard_size
+            .is_some_and(|size| self.builder.bytes_written() >= size);
+        if full && self.last_first.is_some() && first != self.last_first {
+            let path = sidecar_path(
+                self.dict_path,
+                &shard_extension(self.extension, self.paths.len()),
+            );
+            let builder = std::mem::replace(&mut self.builder, new_builder(&path)?);
+            builder.finish().map_err(Error::index(self.path()))?;
+            self.paths.push(path);
+        }
+        self.last_first = first;
+        Ok(())
+    }
+}
+
+/// Create a new fst builder writing to `path`.
+fn new_builder(path: &Path) -> Result<raw::Builder<BufWriter<File>>> {
+    let file = File::create(path).map_err(Error::io(path))?;
+    raw::Builder::new_type(BufWriter::new(file), 0).map_err(Error::index(path))
+}
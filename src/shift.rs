@@ -0,0 +1,95 @@
+//! Finding pairs of words related by shifting every letter through the
+//! alphabet by the same amount, like "hal" and "ibm".
+
+use std::collections::{BTreeMap, HashMap};
+
+use crate::dictionary::Dictionary;
+use crate::error::{Error, Result};
+use crate::probability::{Dist, Prob};
+
+/// Options for `find_shift_pairs`.
+#[derive(Clone, Debug)]
+pub struct ShiftOptions {
+    /// Only find pairs related by this shift, from 1 to 25.
+    pub shift: Option<u8>,
+    /// Only consider words at least this long.
+    pub min_length: usize,
+}
+
+impl Default for ShiftOptions {
+    fn default() -> Self {
+        ShiftOptions {
+            shift: None,
+            min_length: 3,
+        }
+    }
+}
+
+/// Shift every letter of `word`, which must only contain the letters "a" to
+/// "z", forward through the alphabet by `shift`, wrapping around from "z" to
+/// "a".
+pub fn shift_word(word: &str, shift: u8) -> String {
+    word.bytes()
+        .map(|b| char::from(b'a' + (b - b'a' + shift % 26) % 26))
+        .collect()
+}
+
+/// Find every pair of words where shifting the first by some amount gives the
+/// second. Each pair is returned with the probability of its less probable
+/// word, and the results are grouped by shift. Without `opts.shift`, each
+/// pair appears once, with a shift from 1 to 13.
+pub fn find_shift_pairs(
+    dict: &Dictionary,
+    opts: &ShiftOptions,
+) -> Result<Vec<(u8, Dist<String>)>> {
+    if let Some(shift) = opts.shift {
+        if !(1..=25).contains(&shift) {
+            return Err(Error::InvalidOption(format!(
+                "shift must be between 1 and 25, not {}",
+                shift
+            )));
+        }
+    }
+
+    // Words which are shifts of each other have the same letters once we
+    // shift them to start with "a".
+    let mut groups = HashMap::<String, Vec<(Prob, String)>>::new();
+    for entry in dict.words() {
+        let (word, prob) = entry?;
+        if word.len() < opts.min_length
+            || !word.bytes().all(|b| b.is_ascii_lowercase())
+        {
+            continue;
+        }
+        let key = shift_word(&word, 26 - (word.as_bytes()[0] - b'a'));
+        groups.entry(key).or_default().push((prob, word));
+    }
+
+    let mut by_shift = BTreeMap::<u8, Vec<(Prob, String)>>::new();
+    for group in groups.into_values().filter(|g| g.len() > 1) {
+        for (i, (prob1, word1)) in group.iter().enumerate() {
+            for (prob2, word2) in &group[i + 1..] {
+                let forward = (26 + word2.as_bytes()[0] - word1.as_bytes()[0]) % 26;
+                let (shift, from, to) = match opts.shift {
+                    Some(shift) if shift == forward => (forward, word1, word2),
+                    Some(shift) if shift == 26 - forward => (shift, word2, word1),
+                    Some(_) => continue,
+                    None if forward <= 13 => (forward, word1, word2),
+                    None => (26 - forward, word2, word1),
+                };
+                by_shift
+                    .entry(shift)
+                    .or_default()
+                    .push(((*prob1).min(*prob2), format!("{} {}", from, to)));
+            }
+        }
+    }
+    Ok(by_shift
+        .into_iter()
+        .map(|(shift, pairs)| {
+            let mut pairs = Dist::from_vec(pairs);
+            pairs.sort_by_probability();
+            (shift, pairs)
+        })
+        .collect())
+}
@@ -0,0 +1,368 @@
+//! Summary statistics about a dictionary, such as rank-frequency data and
+//! letter frequencies, for plotting, plus checks for common problems with
+//! word lists built from messy corpora.
+
+use serde::Serialize;
+use std::{cmp::Reverse, collections::BTreeMap};
+
+use crate::dictionary::Dictionary;
+use crate::error::Result;
+use crate::junk::junk_reason;
+
+/// The frequency of one word, and its rank by frequency.
+#[derive(Clone, Debug, Serialize)]
+pub struct RankFrequency {
+    /// The 1-based rank of this word. Words with equal probabilities share
+    /// the same rank.
+    pub rank: u64,
+    /// The word itself.
+    pub word: String,
+    /// The probability of the word, between 0 and 1.
+    pub probability: f64,
+}
+
+/// How many words have a given length.
+#[derive(Clone, Debug, Serialize)]
+pub struct LengthCount {
+    /// The number of letters.
+    pub length: usize,
+    /// The number of different words with this many letters.
+    pub words: u64,
+    /// The combined probability of those words, which is how often we'd
+    /// expect a word in running text to have this length.
+    pub probability: f64,
+}
+
+/// How often a letter is used.
+#[derive(Clone, Debug, Serialize)]
+pub struct LetterCount {
+    /// The letter.
+    pub letter: char,
+    /// The number of times the letter appears across all the different words
+    /// in the dictionary.
+    pub count: u64,
+    /// The fraction of letters in running text which are this letter, based
+    /// on the probability of each word.
+    pub probability: f64,
+}
+
+/// Statistics about every word in a dictionary.
+#[derive(Clone, Debug, Serialize)]
+pub struct DictionaryStats {
+    /// Words from most to least probable.
+    pub rank_frequency: Vec<RankFrequency>,
+    /// Word counts by length, from shortest to longest.
+    pub lengths: Vec<LengthCount>,
+    /// Letter counts, in alphabetical order.
+    pub letters: Vec<LetterCount>,
+}
+
+/// Compute statistics for every word `dict` returns. If `top` is given, only
+/// the `top` most probable words are included in the rank-frequency data,
+/// but the other tables still cover every word.
+pub fn dictionary_stats(
+    dict: &Dictionary,
+    top: Option<usize>,
+) -> Result<DictionaryStats> {
+    let mut words = vec![];
+    let mut lengths = BTreeMap::<usize, (u64, f64)>::new();
+    let mut letters = BTreeMap::<char, (u64, f64)>::new();
+    for entry in dict.words() {
+        let (word, prob) = entry?;
+        let p = prob.to_probability();
+        let length = lengths.entry(word.chars().count()).or_default();
+        length.0 += 1;
+        length.1 += p;
+        for c in word.chars().filter(|c| c.is_alphabetic()) {
+            let letter = letters.entry(c).or_default();
+            letter.0 += 1;
+            letter.1 += p;
+        }
+        words.push((prob, word));
+    }
+
+    words.sort_by_key(|(prob, _)| Reverse(*prob));
+    words.truncate(top.unwrap_or(usize::MAX));
+    let mut rank_frequency = Vec::with_capacity(words.len());
+    let mut prev_prob = None;
+    let mut rank = 0;
+    for (i, (prob, word)) in words.into_iter().enumerate() {
+        if prev_prob != Some(prob) {
+            rank = i as u64 + 1;
+            prev_prob = Some(prob);
+        }
+        rank_frequency.push(RankFrequency {
+            rank,
+            word,
+            probability: prob.to_probability(),
+        });
+    }
+
+    let letter_total = letters.values().map(|(_, p)| p).sum::<f64>();
+    Ok(DictionaryStats {
+        rank_frequency,
+        lengths: lengths
+            .into_iter()
+            .map(|(length, (words, probability))| LengthCount {
+                length,
+                words,
+                probability,
+            })
+            .collect(),
+        letters: letters
+            .into_iter()
+            .map(|(letter, (count, weight))| LetterCount {
+                letter,
+                count,
+                probability: if letter_total > 0.0 {
+                    weight / letter_total
+                } else {
+                    0.0
+                },
+            })
+            .collect(),
+    })
+}
+
+/// A power law `probability = constant / rank^exponent` fitted to the
+/// probabilities in a dictionary. Natural language usually has an exponent
+/// close to 1.
+#[derive(Clone, Debug, Serialize)]
+pub struct ZipfFit {
+    /// How quickly probability falls with rank.
+    pub exponent: f64,
+    /// The fitted probability of the most common word.
+    pub constant: f64,
+    /// The fraction of the variance in log probability explained by the fit,
+    /// from 0 to 1.
+    pub r_squared: f64,
+}
+
+impl ZipfFit {
+    /// Fit a power law to `rank_frequency`, which must be sorted from most to
+    /// least probable. Returns `None` if there are too few words.
+    ///
+    /// Most words in a dictionary are rare, so fitting every word equally
+    /// would mostly fit the tail. Instead, we sample ranks which are spaced
+    /// evenly on a log scale.
+    pub fn fit(rank_frequency: &[RankFrequency]) -> Option<ZipfFit> {
+        let mut points = vec![];
+        let mut i = 0;
+        while i < rank_frequency.len() {
+            let x = ((i + 1) as f64).ln();
+            points.push((x, rank_frequency[i].probability.ln()));
+            i = (i + 1).max((i as f64 * 1.1) as usize);
+        }
+        if points.len() < 3 {
+            return None;
+        }
+        let n = points.len() as f64;
+        let mean_x = points.iter().map(|(x, _)| x).sum::<f64>() / n;
+        let mean_y = points.iter().map(|(_, y)| y).sum::<f64>() / n;
+        let sxx = points
+            .iter()
+            .map(|(x, _)| (x - mean_x).powi(2))
+            .sum::<f64>();
+        let sxy = points
+            .iter()
+            .map(|(x, y)| (x - mean_x) * (y - mean_y))
+            .sum::<f64>();
+        let syy = points
+            .iter()
+            .map(|(_, y)| (y - mean_y).powi(2))
+            .sum::<f64>();
+        let slope = sxy / sxx;
+        let intercept = mean_y - slope * mean_x;
+        Some(ZipfFit {
+            exponent: -slope,
+            constant: intercept.exp(),
+            r_squared: if syy > 0.0 {
+                sxy * sxy / (sxx * syy)
+            } else {
+                1.0
+            },
+        })
+    }
+
+    /// The rank at which this fit predicts a word with `probability`.
+    pub fn rank_of(&self, probability: f64) -> f64 {
+        (self.constant / probability).powf(1.0 / self.exponent)
+    }
+}
+
+/// A run of words which all have the same probability.
+#[derive(Clone, Debug, Serialize)]
+pub struct FlatRegion {
+    /// The rank of the first word in the run.
+    pub first_rank: u64,
+    /// The number of words in the run.
+    pub words: u64,
+    /// About how many words the Zipf fit predicts would share this
+    /// probability.
+    pub expected_words: f64,
+    /// The probability they share.
+    pub probability: f64,
+}
+
+/// A word which doesn't look like a real word.
+#[derive(Clone, Debug, Serialize)]
+pub struct JunkWord {
+    /// The word.
+    pub word: String,
+    /// The probability of the word.
+    pub probability: f64,
+    /// Why it looks like junk.
+    pub reason: &'static str,
+}
+
+/// How many words from a reference list of common words are in a dictionary.
+#[derive(Clone, Debug, Serialize)]
+pub struct Coverage {
+    /// The number of words in the reference list.
+    pub reference_words: usize,
+    /// The number of those words which are in the dictionary.
+    pub found: usize,
+    /// The first few reference words which are missing, in the order they
+    /// were listed.
+    pub missing: Vec<String>,
+}
+
+impl Coverage {
+    /// The fraction of reference words we found, from 0 to 1.
+    pub fn fraction(&self) -> f64 {
+        if self.reference_words == 0 {
+            1.0
+        } else {
+            self.found as f64 / self.reference_words as f64
+        }
+    }
+}
+
+/// Options for `check_quality`.
+#[derive(Clone, Debug)]
+pub struct QualityOptions {
+    /// Report runs of equal probabilities with at least this many words, if
+    /// they're much longer than the Zipf fit predicts.
+    pub min_flat_run: usize,
+    /// How many times longer than predicted a run must be to report it.
+    pub flat_factor: f64,
+    /// The maximum number of junk words and missing reference words to list.
+    pub examples: usize,
+}
+
+impl Default for QualityOptions {
+    fn default() -> Self {
+        QualityOptions {
+            min_flat_run: 20,
+            flat_factor: 10.0,
+            examples: 20,
+        }
+    }
+}
+
+/// A report on how well a dictionary's probabilities match natural language,
+/// and on anything which looks wrong with it.
+#[derive(Clone, Debug, Serialize)]
+pub struct QualityReport {
+    /// The number of words in the dictionary.
+    pub word_count: usize,
+    /// A power law fitted to the probabilities, if there are enough words.
+    pub zipf: Option<ZipfFit>,
+    /// Runs of equal probabilities which are much longer than the fit
+    /// predicts, from most to least probable.
+    pub flat_regions: Vec<FlatRegion>,
+    /// The number of words which look like junk.
+    pub junk_count: usize,
+    /// The combined probability of the words which look like junk.
+    pub junk_probability: f64,
+    /// The most probable words which look like junk.
+    pub junk: Vec<JunkWord>,
+    /// How much of the reference list we found, if one was given.
+    pub coverage: Option<Coverage>,
+}
+
+/// Check `dict` for problems, and compare it against `reference`, a list of
+/// common words, if we have one.
+pub fn check_quality(
+    dict: &Dictionary,
+    reference: Option<&[String]>,
+    opts: &QualityOptions,
+) -> Result<QualityReport> {
+    let stats = dictionary_stats(dict, None)?;
+    let words = &stats.rank_frequency;
+    let zipf = ZipfFit::fit(words);
+
+    // Find runs of equal probabilities. The last run is usually all the words
+    // seen once, which is expected, and has no following run to compare with.
+    let mut runs = vec![];
+    let mut start = 0;
+    for i in 1..=words.len() {
+        if i == words.len() || words[i].probability != words[start].probability {
+            runs.push(start..i);
+            start = i;
+        }
+    }
+    let mut flat_regions = vec![];
+    if let Some(zipf) = &zipf {
+        for (i, run) in runs.iter().enumerate().skip(1) {
+            let next = match runs.get(i + 1) {
+                Some(next) => next,
+                None => break,
+            };
+            let probability = words[run.start].probability;
+            let upper = (words[runs[i - 1].start].probability + probability) / 2.0;
+            let lower = (words[next.start].probability + probability) / 2.0;
+            let expected_words = zipf.rank_of(lower) - zipf.rank_of(upper);
+            let len = run.len();
+            if len >= opts.min_flat_run
+                && len as f64 > opts.flat_factor * expected_words
+            {
+                flat_regions.push(FlatRegion {
+                    first_rank: words[run.start].rank,
+                    words: len as u64,
+                    expected_words,
+                    probability,
+                });
+            }
+        }
+    }
+
+    let mut junk = words
+        .iter()
+        .filter_map(|row| {
+            junk_reason(&row.word).map(|reason| JunkWord {
+                word: row.word.clone(),
+                probability: row.probability,
+                reason,
+            })
+        })
+        .collect::<Vec<_>>();
+    let junk_count = junk.len();
+    let junk_probability = junk
+        .iter()
+        .map(|j| j.probability)
+        .fold(0.0, |sum, p| sum + p);
+    junk.truncate(opts.examples);
+
+    let coverage = reference.map(|reference| {
+        let missing = reference
+            .iter()
+            .filter(|word| dict.probability_of(word).is_none())
+            .collect::<Vec<_>>();
+        Coverage {
+            reference_words: reference.len(),
+            found: reference.len() - missing.len(),
+            missing: missing.into_iter().take(opts.examples).cloned().collect(),
+        }
+    });
+
+    Ok(QualityReport {
+        word_count: words.len(),
+        zipf,
+        flat_regions,
+        junk_count,
+        junk_probability,
+        junk,
+        coverage,
+    })
+}
@@ -0,0 +1,247 @@
+//! Where the bytes of a dictionary come from.
+//!
+//! A dictionary is a main file plus optional index files, each named by an
+//! extension. Normally we memory-map them from disk, but a `Storage` can
+//! supply them from anywhere: memory, data built into the program, or a web
+//! server. This lets dictionaries be used where files can't be mapped, as in
+//! WebAssembly or a server which fetches its dictionary at startup.
+
+use memmap2::Mmap;
+use std::{
+    collections::HashMap,
+    fmt,
+    fs::File,
+    io::Read,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use crate::error::{Error, Result};
+
+/// The contents of one of a dictionary's files.
+pub struct Bytes(Repr);
+
+/// The ways we can hold the contents of a file.
+enum Repr {
+    /// Memory-mapped from disk.
+    Mapped(Mmap),
+    /// Owned by us, as when we decrypted or downloaded the file.
+    Owned(Vec<u8>),
+    /// Built into the program.
+    Static(&'static [u8]),
+    /// Shared with someone else.
+    Shared(Arc<dyn AsRef<[u8]> + Send + Sync>),
+}
+
+impl Bytes {
+    /// Memory-map the file at `path`.
+    pub fn map_file(path: &Path) -> Result<Bytes> {
+        // We need to use `unsafe` because bad things can happen if someone
+        // modifies the file while we're using it.
+        let file = File::open(path).map_err(Error::io(path))?;
+        let mapped = unsafe { Mmap::map(&file) }.map_err(Error::io(path))?;
+        Ok(Bytes(Repr::Mapped(mapped)))
+    }
+
+    /// Use bytes which are also used elsewhere, without copying them.
+    pub fn from_shared(bytes: Arc<dyn AsRef<[u8]> + Send + Sync>) -> Bytes {
+        Bytes(Repr::Shared(bytes))
+    }
+}
+
+impl From<Vec<u8>> for Bytes {
+    fn from(bytes: Vec<u8>) -> Bytes {
+        Bytes(Repr::Owned(bytes))
+    }
+}
+
+impl From<&'static [u8]> for Bytes {
+    fn from(bytes: &'static [u8]) -> Bytes {
+        Bytes(Repr::Static(bytes))
+    }
+}
+
+impl AsRef<[u8]> for Bytes {
+    fn as_ref(&self) -> &[u8] {
+        match &self.0 {
+            Repr::Mapped(mapped) => mapped,
+            Repr::Owned(owned) => owned,
+            Repr::Static(bytes) => bytes,
+            Repr::Shared(shared) => (**shared).as_ref(),
+        }
+    }
+}
+
+/// Show how we hold the bytes and how many there are, rather than the bytes.
+impl fmt::Debug for Bytes {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let kind = match &self.0 {
+            Repr::Mapped(_) => "Mapped",
+            Repr::Owned(_) => "Owned",
+            Repr::Static(_) => "Static",
+            Repr::Shared(_) => "Shared",
+        };
+        write!(f, "Bytes::{}({} bytes)", kind, self.as_ref().len())
+    }
+}
+
+/// A place to load a dictionary's files from.
+pub trait Storage: fmt::Debug + Send + Sync {
+    /// The contents of the index file with `extension`, or of the main
+    /// dictionary file if `extension` is empty. Returns `None` if there is
+    /// no such file.
+    fn read(&self, extension: &str) -> Result<Option<Bytes>>;
+
+    /// What to call the file with `extension` in error messages, such as
+    /// its path or URL.
+    fn name(&self, extension: &str) -> PathBuf;
+}
+
+/// A dictionary on disk, with its index files next to it, which we
+/// memory-map.
+#[derive(Clone, Debug)]
+pub struct FileStorage {
+    dict_path: PathBuf,
+}
+
+impl FileStorage {
+    /// Load the dictionary at `dict_path`.
+    pub fn new(dict_path: impl Into<PathBuf>) -> FileStorage {
+        FileStorage {
+            dict_path: dict_path.into(),
+        }
+    }
+}
+
+impl Storage for FileStorage {
+    fn read(&self, extension: &str) -> Result<Option<Bytes>> {
+        let path = self.name(extension);
+        if path.exists() {
+            Ok(Some(Bytes::map_file(&path)?))
+        } else {
+            Ok(None)
+        }
+    }
+
+    fn name(&self, extension: &str) -> PathBuf {
+        sidecar_path(&self.dict_path, extension)
+    }
+}
+
+/// A dictionary whose files are already in memory, such as data built into
+/// the program with `include_bytes!`.
+#[derive(Clone)]
+pub struct MemoryStorage {
+    /// What to call the dictionary in error messages.
+    name: String,
+    /// The contents of each file, by extension.
+    files: HashMap<String, Arc<dyn AsRef<[u8]> + Send + Sync>>,
+}
+
+impl MemoryStorage {
+    /// Create storage with no files, which we'll call `name` in error
+    /// messages.
+    pub fn new(name: &str) -> MemoryStorage {
+        MemoryStorage {
+            name: name.to_owned(),
+            files: HashMap::new(),
+        }
+    }
+
+    /// Add the file with `extension`, or the main dictionary file if
+    /// `extension` is empty.
+    pub fn insert<B>(&mut self, extension: &str, bytes: B)
+    where
+        B: AsRef<[u8]> + Send + Sync + 'static,
+    {
+        self.files.insert(extension.to_owned(), Arc::new(bytes));
+    }
+}
+
+/// Show which files we have, rather than their contents.
+impl fmt::Debug for MemoryStorage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut extensions = self.files.keys().collect::<Vec<_>>();
+        extensions.sort();
+        f.debug_struct("MemoryStorage")
+            .field("name", &self.name)
+            .field("files", &extensions)
+            .finish()
+    }
+}
+
+impl Storage for MemoryStorage {
+    fn read(&self, extension: &str) -> Result<Option<Bytes>> {
+        Ok(self.files.get(extension).cloned().map(Bytes::from_shared))
+    }
+
+    fn name(&self, extension: &str) -> PathBuf {
+        sidecar_path(Path::new(&self.name), extension)
+    }
+}
+
+/// A dictionary on a web server, with its index files next to it, which we
+/// download in full when loading.
+#[derive(Clone, Debug)]
+pub struct HttpStorage {
+    url: String,
+}
+
+impl HttpStorage {
+    /// Load the dictionary at `url`, which must be an HTTP or HTTPS URL.
+    pub fn new(url: &str) -> Result<HttpStorage> {
+        if !is_url(url) {
+            return Err(Error::InvalidOption(format!(
+                "expected an http:// or https:// URL, found {:?}",
+                url
+            )));
+        }
+        Ok(HttpStorage {
+            url: url.to_owned(),
+        })
+    }
+}
+
+impl Storage for HttpStorage {
+    fn read(&self, extension: &str) -> Result<Option<Bytes>> {
+        let name = self.name(extension);
+        let url = name.to_string_lossy();
+        let response = match ureq::get(&*url).call() {
+            Ok(response) => response,
+            Err(ureq::Error::StatusCode(404)) => return Ok(None),
+            Err(source) => {
+                return Err(Error::Download {
+                    url: url.into_owned(),
+                    source: Box::new(source),
+                })
+            }
+        };
+        let mut bytes = vec![];
+        response
+            .into_body()
+            .into_reader()
+            .read_to_end(&mut bytes)
+            .map_err(Error::io(&name))?;
+        Ok(Some(Bytes::from(bytes)))
+    }
+
+    fn name(&self, extension: &str) -> PathBuf {
+        sidecar_path(Path::new(&self.url), extension)
+    }
+}
+
+/// Does `location` look like an HTTP or HTTPS URL, rather than a path?
+pub fn is_url(location: &str) -> bool {
+    location.starts_with("http://") || location.starts_with("https://")
+}
+
+/// The path of the index with `extension` stored next to the dictionary at
+/// `dict_path`, or `dict_path` itself if `extension` is empty.
+pub(crate) fn sidecar_path(dict_path: &Path, extension: &str) -> PathBuf {
+    let mut path = dict_path.as_os_str().to_owned();
+    if !extension.is_empty() {
+        path.push(".");
+        path.push(extension);
+    }
+    PathBuf::from(path)
+}
@@ -0,0 +1,36 @@
+//! Counting syllables.
+
+use crate::pronunciation::is_vowel;
+
+/// Count the syllables in a pronunciation, which is one per vowel.
+pub fn count_syllables(phones: &str) -> usize {
+    phones.split(' ').filter(|p| is_vowel(p)).count()
+}
+
+/// Estimate the number of syllables in `word` from its spelling, by counting
+/// groups of vowels and allowing for a silent final "e". This is only a
+/// guess, and it's wrong for many words.
+pub fn estimate_syllables(word: &str) -> usize {
+    let is_vowel = |c: char| "aeiouy".contains(c);
+    let chars = word.chars().collect::<Vec<_>>();
+    let mut syllables = 0;
+    let mut prev_was_vowel = false;
+    for &c in &chars {
+        let vowel = is_vowel(c);
+        if vowel && !prev_was_vowel {
+            syllables += 1;
+        }
+        prev_was_vowel = vowel;
+    }
+
+    // A final "e" is usually silent, except in "-le" after a consonant, as in
+    // "table".
+    let n = chars.len();
+    if n > 2 && chars[n - 1] == 'e' && !is_vowel(chars[n - 2]) {
+        let syllabic_le = chars[n - 2] == 'l' && !is_vowel(chars[n - 3]);
+        if !syllabic_le {
+            syllables -= 1;
+        }
+    }
+    syllables.max(1)
+}
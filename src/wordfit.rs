@@ -0,0 +1,403 @@
+//! Solving fit-the-words puzzles (also called kriss-krosses or criss-crosses).
+//!
+//! A fit-the-words puzzle is an empty crossword grid and a bank of words, and
+//! the solver must place every word from the bank in exactly one slot. Unlike
+//! `crate::fill`, there's no dictionary and no scoring: the bank is a finite
+//! multiset, and a solution uses all of it. We fill one slot at a time,
+//! always choosing the slot with the fewest unused words which still fit, and
+//! keep going after the first solution so that we can tell whether a puzzle
+//! has exactly one.
+//!
+//! `generate_bank` goes the other way, for constructors: it fills a grid
+//! with random dictionary words, and keeps the fill as a bank only if the
+//! solver finds exactly one way to fit it back into the grid.
+
+use log::{debug, info, warn};
+use std::collections::{BTreeMap, HashSet};
+
+use crate::dictionary::{phrase_letters, Dictionary, SearchOptions};
+use crate::error::{Error, Result};
+use crate::fill::slot_candidates;
+use crate::grid::{Cell, Grid, Slot};
+use crate::random::Rng;
+
+/// Options for `WordFit::solve`.
+#[derive(Clone, Debug)]
+pub struct WordFitOptions {
+    /// Stop after finding this many solutions. Use 2 to check that a puzzle
+    /// has a unique solution.
+    pub max_solutions: usize,
+}
+
+impl Default for WordFitOptions {
+    fn default() -> Self {
+        WordFitOptions { max_solutions: 2 }
+    }
+}
+
+/// A fit-the-words puzzle.
+#[derive(Clone, Debug)]
+pub struct WordFit {
+    grid: Grid,
+    slots: Vec<Slot>,
+    /// The distinct letters of the words in the bank.
+    words: Vec<Vec<char>>,
+    /// How many times each of `words` appears in the bank.
+    counts: Vec<usize>,
+}
+
+impl WordFit {
+    /// Create a puzzle from `grid`, which may have some letters already
+    /// filled in, and `bank`, which must have one word for each slot. Spaces
+    /// in phrases are ignored.
+    pub fn new(grid: &Grid, bank: &[String]) -> Result<WordFit> {
+        grid.validate()?;
+        let slots = grid.slots();
+        let mut words = BTreeMap::<Vec<char>, usize>::new();
+        for word in bank {
+            let letters = phrase_letters(&word.to_lowercase())
+                .chars()
+                .collect::<Vec<_>>();
+            if letters.is_empty() {
+                return Err(Error::InvalidPuzzle("bank has an empty word".to_owned()));
+            }
+            *words.entry(letters).or_default() += 1;
+        }
+
+        // Each length must have as many words as slots, or no assignment
+        // could use every word exactly once.
+        let mut lengths = BTreeMap::<usize, (usize, usize)>::new();
+        for slot in &slots {
+            lengths.entry(slot.cells.len()).or_default().0 += 1;
+        }
+        for (letters, &count) in &words {
+            lengths.entry(letters.len()).or_default().1 += count;
+        }
+        for (len, (slot_count, word_count)) in lengths {
+            if slot_count != word_count {
+                return Err(Error::InvalidPuzzle(format!(
+                    "bank has {} words of {} letters, but the grid has {} slots \
+                     of that length",
+                    word_count, len, slot_count
+                )));
+            }
+        }
+
+        let (words, counts) = words.into_iter().unzip();
+        Ok(WordFit {
+            grid: grid.clone(),
+            slots,
+            words,
+            counts,
+        })
+    }
+
+    /// Find up to `opts.max_solutions` ways to place every word from the
+    /// bank. Each solution is a different grid: swapping two copies of the
+    /// same word doesn't count as a new solution.
+    pub fn solve(&self, opts: &WordFitOptions) -> Vec<Grid> {
+        let mut solver = Solver {
+            puzzle: self,
+            max_solutions: opts.max_solutions,
+            cells: self.grid.cells().to_vec(),
+            counts: self.counts.clone(),
+            assigned: vec![false; self.slots.len()],
+            solutions: vec![],
+            tried: 0,
+        };
+        if opts.max_solutions > 0 {
+            solver.search();
+        }
+        debug!(
+            "wordfit tried {} words and found {} solutions",
+            solver.tried,
+            solver.solutions.len()
+        );
+        solver.solutions
+    }
+}
+
+/// The state of a search for solutions to a `WordFit`.
+struct Solver<'a> {
+    puzzle: &'a WordFit,
+    max_solutions: usize,
+    /// The cells of the grid as filled so far.
+    cells: Vec<Cell>,
+    /// How many copies of each word are still unused.
+    counts: Vec<usize>,
+    /// Whether each slot has a word.
+    assigned: Vec<bool>,
+    solutions: Vec<Grid>,
+    /// How many times we've placed a word, for logging.
+    tried: u64,
+}
+
+impl<'a> Solver<'a> {
+    /// Does `word` fit in `slot` given the letters placed so far?
+    fn fits(&self, slot: &Slot, word: &[char]) -> bool {
+        word.len() == slot.cells.len()
+            && slot
+                .cells
+                .iter()
+                .zip(word)
+                .all(|(&idx, &c)| match self.cells[idx] {
+                    Cell::Letter(placed) => placed == c,
+                    _ => true,
+                })
+    }
+
+    /// The unused words which fit in `slot`.
+    fn fitting_words(&self, slot: &Slot) -> Vec<usize> {
+        (0..self.puzzle.words.len())
+            .filter(|&w| self.counts[w] > 0 && self.fits(slot, &self.puzzle.words[w]))
+            .collect()
+    }
+
+    /// Fill the remaining slots in every possible way, recording solutions.
+    /// Returns `true` once we have enough solutions to stop.
+    fn search(&mut self) -> bool {
+        // Choose the unassigned slot with the fewest fitting words. If any
+        // slot has none, this branch is hopeless.
+        let mut best: Option<(usize, Vec<usize>)> = None;
+        for (i, slot) in self.puzzle.slots.iter().enumerate() {
+            if self.assigned[i] {
+                continue;
+            }
+            let fitting = self.fitting_words(slot);
+            if fitting.is_empty() {
+                return false;
+            }
+            if best.as_ref().is_none_or(|(_, b)| fitting.len() < b.len()) {
+                best = Some((i, fitting));
+            }
+        }
+        let (i, fitting) = match best {
+            Some(best) => best,
+            None => {
+                self.solutions
+                    .push(self.puzzle.grid.with_cells(self.cells.clone()));
+                return self.solutions.len() >= self.max_solutions;
+            }
+        };
+
+        let slot = &self.puzzle.slots[i];
+        let saved = slot
+            .cells
+            .iter()
+            .map(|&idx| self.cells[idx])
+            .collect::<Vec<_>>();
+        self.assigned[i] = true;
+        for w in fitting {
+            self.tried += 1;
+            self.counts[w] -= 1;
+            for (&idx, &c) in slot.cells.iter().zip(&self.puzzle.words[w]) {
+                self.cells[idx] = Cell::Letter(c);
+            }
+            let done = self.search();
+            self.counts[w] += 1;
+            for (&idx, &cell) in slot.cells.iter().zip(&saved) {
+                self.cells[idx] = cell;
+            }
+            if done {
+                self.assigned[i] = false;
+                return true;
+            }
+        }
+        self.assigned[i] = false;
+        false
+    }
+}
+
+/// Options for `generate_bank`.
+#[derive(Clone, Debug)]
+pub struct BankOptions {
+    /// The maximum number of candidate words to consider for each slot. We
+    /// keep the most probable candidates, so that the bank has familiar words.
+    pub max_candidates: usize,
+    /// How many random fills to try before giving up.
+    pub attempts: usize,
+    /// How many words each fill may try placing before we give up on it.
+    pub max_tries: u64,
+    /// The seed for choosing words at random.
+    pub seed: u64,
+}
+
+impl Default for BankOptions {
+    fn default() -> Self {
+        BankOptions {
+            max_candidates: 1_000,
+            attempts: 100,
+            max_tries: 100_000,
+            seed: 0,
+        }
+    }
+}
+
+/// A bank of words with exactly one fit.
+#[derive(Clone, Debug)]
+pub struct Bank {
+    /// The words, sorted by length and then alphabetically, the way
+    /// kriss-kross puzzles usually list them.
+    pub words: Vec<String>,
+    /// The only way to fit the words into the grid.
+    pub solution: Grid,
+}
+
+/// Choose a bank of distinct dictionary words, one for each slot of `grid`,
+/// which fit into the grid in exactly one way. Letters already in `grid` are
+/// kept, and give solvers a place to start. Returns `None` if none of our
+/// attempts found such a bank, and an error if `grid` fails `Grid::validate`.
+pub fn generate_bank(
+    dict: &Dictionary,
+    grid: &Grid,
+    opts: &BankOptions,
+) -> Result<Option<Bank>> {
+    grid.validate()?;
+    let slots = grid.slots();
+    let search_opts = SearchOptions {
+        limit: Some(opts.max_candidates),
+        most_probable: true,
+    };
+    let mut candidates = vec![];
+    for slot in &slots {
+        let words = slot_candidates(dict, grid, slot, &search_opts)?;
+        candidates.push(words.into_iter().map(|(_, word)| word).collect::<Vec<_>>());
+    }
+
+    let mut rng = Rng::new(opts.seed);
+    let wordfit_opts = WordFitOptions { max_solutions: 2 };
+    for attempt in 0..opts.attempts {
+        for words in &mut candidates {
+            rng.shuffle(words);
+        }
+        let mut filler = RandomFill {
+            slots: &slots,
+            candidates: &candidates,
+            cells: grid.cells().to_vec(),
+            used: HashSet::new(),
+            assigned: vec![false; slots.len()],
+            tries: 0,
+            max_tries: opts.max_tries,
+        };
+        if !filler.search() {
+            if filler.tries > opts.max_tries {
+                debug!("attempt {} gave up looking for a fill", attempt);
+                continue;
+            }
+            // We searched every fill using our candidates, so shuffling them
+            // again won't help. More candidates might.
+            warn!(
+                "there is no way to fill the grid with distinct words from the \
+                 {} most probable candidates for each slot",
+                opts.max_candidates
+            );
+            return Ok(None);
+        }
+        let filled = grid.with_cells(filler.cells);
+        let mut words = slots
+            .iter()
+            .map(|slot| filled.slot_word(slot).expect("slot should be filled"))
+            .collect::<Vec<_>>();
+        let solutions = WordFit::new(grid, &words)?.solve(&wordfit_opts);
+        if solutions.len() == 1 {
+            info!("found a bank with a unique fit on attempt {}", attempt + 1);
+            words.sort_by(|a, b| a.len().cmp(&b.len()).then_with(|| a.cmp(b)));
+            return Ok(Some(Bank {
+                words,
+                solution: filled,
+            }));
+        }
+        debug!(
+            "attempt {} found a bank which fits more than one way",
+            attempt
+        );
+    }
+    Ok(None)
+}
+
+/// The state of a search for a random fill of a grid with distinct words.
+struct RandomFill<'a> {
+    slots: &'a [Slot],
+    /// The candidates for each slot, in the order to try them.
+    candidates: &'a [Vec<Vec<char>>],
+    /// The cells of the grid as filled so far.
+    cells: Vec<Cell>,
+    /// The words placed so far.
+    used: HashSet<&'a [char]>,
+    /// Whether each slot has a word.
+    assigned: Vec<bool>,
+    /// How many times we've placed a word.
+    tries: u64,
+    max_tries: u64,
+}
+
+impl<'a> RandomFill<'a> {
+    /// Does `word` fit in `slot` given the letters placed so far?
+    fn fits(&self, slot: &Slot, word: &[char]) -> bool {
+        !self.used.contains(word)
+            && slot
+                .cells
+                .iter()
+                .zip(word)
+                .all(|(&idx, &c)| match self.cells[idx] {
+                    Cell::Letter(placed) => placed == c,
+                    _ => true,
+                })
+    }
+
+    /// Fill the remaining slots, always choosing the slot with the fewest
+    /// fitting candidates. Returns `false` if there's no fill, or if we ran
+    /// out of tries.
+    fn search(&mut self) -> bool {
+        let mut best: Option<(usize, usize)> = None;
+        for (i, slot) in self.slots.iter().enumerate() {
+            if self.assigned[i] {
+                continue;
+            }
+            let count = self.candidates[i]
+                .iter()
+                .filter(|word| self.fits(slot, word))
+                .count();
+            if count == 0 {
+                return false;
+            }
+            if best.is_none_or(|(_, b)| count < b) {
+                best = Some((i, count));
+            }
+        }
+        let i = match best {
+            Some((i, _)) => i,
+            None => return true,
+        };
+
+        let slot = &self.slots[i];
+        let saved = slot
+            .cells
+            .iter()
+            .map(|&idx| self.cells[idx])
+            .collect::<Vec<_>>();
+        self.assigned[i] = true;
+        for word in &self.candidates[i] {
+            if self.tries > self.max_tries {
+                break;
+            }
+            if !self.fits(slot, word) {
+                continue;
+            }
+            self.tries += 1;
+            self.used.insert(word);
+            for (&idx, &c) in slot.cells.iter().zip(word) {
+                self.cells[idx] = Cell::Letter(c);
+            }
+            if self.search() {
+                return true;
+            }
+            self.used.remove(&word[..]);
+            for (&idx, &cell) in slot.cells.iter().zip(&saved) {
+                self.cells[idx] = cell;
+            }
+        }
+        self.assigned[i] = false;
+        false
+    }
+}
@@ -0,0 +1,253 @@
+//! Suggesting guesses for Wordle and multi-board variants like Quordle.
+//!
+//! Each board has its own hidden target word, but every guess is played on
+//! all boards at once. We track the candidates remaining on each board, and
+//! suggest the guess which we expect to tell us the most about all the unsolved
+//! boards combined.
+
+use std::{collections::HashMap, str::FromStr};
+
+use crate::dictionary::Dictionary;
+use crate::error::{Error, Result};
+use crate::probability::Dist;
+
+/// The color shown for a single letter of a guess.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Mark {
+    /// The letter is in the target word in this position.
+    Green,
+    /// The letter is in the target word in a different position.
+    Yellow,
+    /// The letter is not in the target word (or all copies are accounted for).
+    Gray,
+}
+
+/// Compute the marks Wordle would show for `guess` against `target`. Both
+/// words must have the same length.
+pub fn marks(guess: &[u8], target: &[u8]) -> Vec<Mark> {
+    let code = mark_code(guess, target);
+    (0..guess.len())
+        .rev()
+        .map(|i| match code / 3u32.pow(i as u32) % 3 {
+            2 => Mark::Green,
+            1 => Mark::Yellow,
+            _ => Mark::Gray,
+        })
+        .collect()
+}
+
+/// Pack the marks for `guess` against `target` into a single base-3 number,
+/// with the first letter as the most significant digit, so that we can
+/// quickly count how often each pattern occurs. This is the inner loop of
+/// guess scoring, so it avoids allocating.
+fn mark_code(guess: &[u8], target: &[u8]) -> u32 {
+    // Count the letters of `target` which aren't matched exactly.
+    let mut unmatched = [0u8; 256];
+    for (&g, &t) in guess.iter().zip(target) {
+        if g != t {
+            unmatched[t as usize] += 1;
+        }
+    }
+    let mut code = 0;
+    for (&g, &t) in guess.iter().zip(target) {
+        let digit = if g == t {
+            2
+        } else if unmatched[g as usize] > 0 {
+            unmatched[g as usize] -= 1;
+            1
+        } else {
+            0
+        };
+        code = code * 3 + digit;
+    }
+    code
+}
+
+/// The guesses played so far on one board, and the marks each received.
+#[derive(Clone, Debug, Default)]
+pub struct Board {
+    pub guesses: Vec<(String, Vec<Mark>)>,
+}
+
+impl Board {
+    /// Is `word` consistent with every guess on this board?
+    fn allows(&self, word: &str) -> bool {
+        self.guesses
+            .iter()
+            .all(|(guess, m)| marks(guess.as_bytes(), word.as_bytes()) == *m)
+    }
+
+    /// Has this board been solved?
+    pub fn is_solved(&self) -> bool {
+        self.guesses
+            .iter()
+            .any(|(_, m)| m.iter().all(|&mark| mark == Mark::Green))
+    }
+}
+
+/// Parse a board written as comma-separated `guess:marks` pairs, where the
+/// marks use `g` for green, `y` for yellow and `.` for gray. For example,
+/// `crane:..gy.,doubt:g....`.
+impl FromStr for Board {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let mut guesses = vec![];
+        for entry in s.split(',').map(str::trim).filter(|e| !e.is_empty()) {
+            let (guess, mark_str) = entry.split_once(':').ok_or_else(|| {
+                Error::InvalidPuzzle(format!(
+                    "expected \"guess:marks\", found {:?}",
+                    entry
+                ))
+            })?;
+            let guess = guess.to_ascii_lowercase();
+            let m = mark_str
+                .chars()
+                .map(|c| match c.to_ascii_lowercase() {
+                    'g' => Ok(Mark::Green),
+                    'y' => Ok(Mark::Yellow),
+                    '.' | 'b' | 'x' | '-' => Ok(Mark::Gray),
+                    c => Err(Error::InvalidPuzzle(format!(
+                        "unknown mark {:?} in {:?}",
+                        c, entry
+                    ))),
+                })
+                .collect::<Result<Vec<_>>>()?;
+            if m.len() != guess.len() {
+                return Err(Error::InvalidPuzzle(format!(
+                    "{:?} has {} marks for a {}-letter guess",
+                    entry,
+                    m.len(),
+                    guess.len(),
+                )));
+            }
+            guesses.push((guess, m));
+        }
+        Ok(Board { guesses })
+    }
+}
+
+/// Options for `analyze`.
+#[derive(Clone, Debug)]
+pub struct WordleOptions {
+    /// The length of the target words.
+    pub word_len: usize,
+    /// How many of the most probable words to consider as guesses, in addition
+    /// to the remaining candidates.
+    pub guess_pool: usize,
+}
+
+impl Default for WordleOptions {
+    fn default() -> Self {
+        WordleOptions {
+            word_len: 5,
+            guess_pool: 2000,
+        }
+    }
+}
+
+/// The result of analyzing a game.
+pub struct Analysis {
+    /// The remaining candidates for each board, most probable first. Solved
+    /// boards have no candidates.
+    pub candidates: Vec<Dist<String>>,
+    /// Suggested guesses and the total number of bits of information we
+    /// expect each to reveal across all unsolved boards, best first.
+    pub suggestions: Vec<(f64, String)>,
+}
+
+/// Find the remaining candidates on each board, and rank possible guesses by
+/// how much we expect them to tell us.
+pub fn analyze(
+    dict: &Dictionary,
+    boards: &[Board],
+    opts: &WordleOptions,
+) -> Result<Analysis> {
+    // Longer words would overflow `mark_code`.
+    if opts.word_len == 0 || opts.word_len > 20 {
+        return Err(Error::InvalidPuzzle(
+            "words must be between 1 and 20 letters long".to_owned(),
+        ));
+    }
+    let pattern = format!("[a-z]{{{}}}", opts.word_len);
+    let words = dict.find_matches(&pattern)?.into_vec();
+    for board in boards {
+        if let Some((guess, _)) =
+            board.guesses.iter().find(|(g, _)| g.len() != opts.word_len)
+        {
+            return Err(Error::InvalidPuzzle(format!(
+                "{:?} is not {} letters long",
+                guess, opts.word_len
+            )));
+        }
+    }
+
+    let candidates = boards
+        .iter()
+        .map(|board| {
+            let remaining = if board.is_solved() {
+                vec![]
+            } else {
+                words
+                    .iter()
+                    .filter(|(_, w)| board.allows(w))
+                    .cloned()
+                    .collect()
+            };
+            Dist::from_vec(remaining)
+        })
+        .collect::<Vec<_>>();
+
+    // Consider the most probable words plus every remaining candidate.
+    let mut guesses = words
+        .iter()
+        .take(opts.guess_pool)
+        .map(|(_, w)| w.as_str())
+        .collect::<Vec<_>>();
+    for dist in &candidates {
+        guesses.extend(dist.into_iter().map(|(_, w)| w.as_str()));
+    }
+    guesses.sort_unstable();
+    guesses.dedup();
+
+    let mut suggestions = guesses
+        .into_iter()
+        .map(|guess| {
+            let bits = candidates
+                .iter()
+                .map(|dist| expected_information(guess, dist))
+                .sum::<f64>();
+            (bits, guess.to_owned())
+        })
+        .collect::<Vec<_>>();
+    suggestions
+        .sort_by(|(b1, w1), (b2, w2)| b2.total_cmp(b1).then_with(|| w1.cmp(w2)));
+    Ok(Analysis {
+        candidates,
+        suggestions,
+    })
+}
+
+/// The expected number of bits of information revealed by playing `guess`
+/// against the weighted candidates in `dist`.
+fn expected_information(guess: &str, dist: &Dist<String>) -> f64 {
+    if dist.is_empty() {
+        return 0.0;
+    }
+    let mut pattern_weights = HashMap::<u32, f64>::new();
+    let mut total = 0.0;
+    for (prob, target) in dist {
+        let weight = prob.to_probability();
+        *pattern_weights
+            .entry(mark_code(guess.as_bytes(), target.as_bytes()))
+            .or_default() += weight;
+        total += weight;
+    }
+    pattern_weights
+        .values()
+        .map(|&w| {
+            let p = w / total;
+            -p * p.log2()
+        })
+        .sum()
+}
@@ -0,0 +1,153 @@
+//! Build small dictionaries and load them again.
+
+use std::{env, fs, path::PathBuf, process};
+
+use letter_permutations::dictionary::{BuildOptions, Dictionary};
+
+/// A scratch directory for one test, which we remove when we're dropped.
+struct ScratchDir(PathBuf);
+
+impl ScratchDir {
+    fn new(name: &str) -> ScratchDir {
+        let path =
+            env::temp_dir().join(format!("word-puzzler-{}-{}", name, process::id()));
+        let _ = fs::remove_dir_all(&path);
+        fs::create_dir_all(&path).unwrap();
+        ScratchDir(path)
+    }
+}
+
+impl Drop for ScratchDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
+
+/// Build a dictionary from `words`, a "count word" list, and load it.
+fn build_and_load(name: &str, words: &str, opts: &BuildOptions) -> Dictionary {
+    let dir = ScratchDir::new(name);
+    let words_path = dir.0.join("words.txt");
+    let dict_path = dir.0.join("d.fst");
+    fs::write(&words_path, words).unwrap();
+    Dictionary::build(&words_path, &dict_path, opts).unwrap();
+    Dictionary::load(&dict_path).unwrap()
+}
+
+#[test]
+fn loads_word_list_with_zero_count() {
+    let dict = build_and_load(
+        "zero-count",
+        "10 cat\n5 dog\n0 zebra\n",
+        &BuildOptions::default(),
+    );
+    assert!(dict.probability_of("cat").is_some());
+    dict.length_totals().unwrap();
+}
+
+#[test]
+fn quantizes_word_list_with_zero_count() {
+    let opts = BuildOptions {
+        quantize_bits: Some(8),
+        ..Default::default()
+    };
+    let dict =
+        build_and_load("zero-count-quantized", "10 cat\n5 dog\n0 zebra\n", &opts);
+    let cat = dict.probability_of("cat").unwrap().to_probability();
+    let dog = dict.probability_of("dog").unwrap().to_probability();
+    assert!((cat - 10.0 / 15.0).abs() < 1e-6, "cat: {}", cat);
+    assert!((dog - 5.0 / 15.0).abs() < 1e-6, "dog: {}", dog);
+    if let Some(zebra) = dict.probability_of("zebra") {
+        assert_eq!(zebra.to_probability(), 0.0);
+    }
+}
+
+#[test]
+fn kept_inflected_forms_sum_to_one() {
+    let opts = BuildOptions {
+        fold_inflections: true,
+        keep_inflected_forms: true,
+        ..Default::default()
+    };
+    let dict = build_and_load("keep-inflected", "6 cat\n4 cats\n5 dog\n", &opts);
+    let total: f64 = ["cat", "cats", "dog"]
+        .iter()
+        .map(|word| dict.probability_of(word).unwrap().to_probability())
+        .sum();
+    assert!((total - 1.0).abs() < 1e-9, "total: {}", total);
+}
+
+#[test]
+fn sharded_dictionary_answers_like_one_file() {
+    let words = "10 cat\n8 car\n7 act\n6 bat\n5 tab\n4 dog\n3 god\n2 école\n\
+                 1 zebra\n";
+    let opts = BuildOptions {
+        substrings: true,
+        gaddag: true,
+        ..Default::default()
+    };
+    let one = build_and_load("unsharded", words, &opts);
+    // With a shard size of 0, every first byte gets its own shard.
+    let sharded_opts = BuildOptions {
+        shard_size: Some(0),
+        ..opts
+    };
+    let sharded = build_and_load("sharded", words, &sharded_opts);
+    assert!(sharded.metadata().unwrap().shards[""] > 1);
+
+    let all = |dict: &Dictionary| dict.words().collect::<Result<Vec<_>, _>>().unwrap();
+    assert_eq!(all(&one), all(&sharded));
+    assert_eq!(one.len(), sharded.len());
+    for word in &["cat", "école", "zebra", "cow"] {
+        assert_eq!(one.probability_of(word), sharded.probability_of(word));
+        assert_eq!(one.rank_of(word).unwrap(), sharded.rank_of(word).unwrap());
+    }
+    let prefixed = |dict: &Dictionary| {
+        dict.words_with_prefix("ca")
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap()
+    };
+    assert_eq!(prefixed(&one), prefixed(&sharded));
+    for query in &["(c|d|é).*", "..t"] {
+        assert_eq!(
+            one.find_matches(query).unwrap().into_vec(),
+            sharded.find_matches(query).unwrap().into_vec(),
+        );
+    }
+    assert_eq!(
+        one.find_by_suffix("at").unwrap().into_vec(),
+        sharded.find_by_suffix("at").unwrap().into_vec(),
+    );
+    assert_eq!(
+        one.find_containing("a").unwrap().into_vec(),
+        sharded.find_containing("a").unwrap().into_vec(),
+    );
+    let through = |dict: &Dictionary| {
+        let mut matches = dict
+            .words_through("o")
+            .unwrap()
+            .into_iter()
+            .map(|m| (m.word, m.anchor))
+            .collect::<Vec<_>>();
+        matches.sort();
+        matches
+    };
+    assert_eq!(through(&one), through(&sharded));
+
+    // Cursors start in every shard, and pick one with the first letter.
+    let letters = |dict: &Dictionary| {
+        dict.cursor()
+            .children()
+            .into_iter()
+            .map(|(letter, _)| letter)
+            .collect::<String>()
+    };
+    assert_eq!(letters(&one), "abcdgtzé");
+    assert_eq!(letters(&sharded), "abcdgtzé");
+    for word in &["cat", "école", "do"] {
+        let prob =
+            |dict: &Dictionary| dict.cursor().advance_str(word).and_then(|c| c.prob());
+        assert_eq!(prob(&one), prob(&sharded));
+    }
+    assert!(sharded.cursor().advance_str("école").unwrap().is_word());
+    assert!(sharded.cursor().advance('x').is_none());
+}
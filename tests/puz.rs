@@ -0,0 +1,72 @@
+//! Check that we write `.puz` files which Across Lite will accept, by
+//! comparing them to a fixture whose checksums were computed independently.
+
+use letter_permutations::grid::{Direction, Grid};
+use letter_permutations::puz::{read_puz, write_puz};
+use letter_permutations::puzzle::{Clue, Puzzle};
+
+/// A 3×3 puzzle with a title, author, notes and no copyright, so that the
+/// checksums cover both present and missing strings.
+const FIXTURE: &[u8] = include_bytes!("fixtures/small.puz");
+
+/// The length of a `.puz` header, which holds the checksums.
+const HEADER_LEN: usize = 0x34;
+
+fn clue(number: usize, direction: Direction, text: &str) -> Clue {
+    Clue {
+        number,
+        direction,
+        text: text.to_owned(),
+    }
+}
+
+/// The puzzle stored in `FIXTURE`.
+fn fixture_puzzle() -> Puzzle {
+    let solution = "cat\na#o\nton\n".parse::<Grid>().unwrap();
+    let mut puzzle = Puzzle::new(solution.blank())
+        .with_solution(solution)
+        .unwrap();
+    puzzle.title = "Test Puzzle".to_owned();
+    puzzle.author = "A. Constructor".to_owned();
+    puzzle.notes = "Some notes".to_owned();
+    puzzle.clues = vec![
+        clue(1, Direction::Across, "Feline"),
+        clue(1, Direction::Down, "Kitty"),
+        clue(2, Direction::Down, "Heavy weight"),
+        clue(3, Direction::Across, "Unit of mass"),
+    ];
+    puzzle
+}
+
+#[test]
+fn written_checksums_match_fixture() {
+    let bytes = write_puz(&fixture_puzzle()).unwrap();
+    assert_eq!(&bytes[..HEADER_LEN], &FIXTURE[..HEADER_LEN]);
+    assert_eq!(bytes, FIXTURE);
+}
+
+#[test]
+fn round_trip_preserves_fixture() {
+    let puzzle = read_puz(FIXTURE).unwrap();
+    assert_eq!(puzzle.solution.to_string(), "cat\na#o\nton\n");
+    assert_eq!(puzzle.progress.to_string(), "...\n.#.\n...\n");
+    assert_eq!(puzzle.clues, fixture_puzzle().clues);
+    assert_eq!(write_puz(&puzzle).unwrap(), FIXTURE);
+}
+
+#[test]
+fn rejects_letters_without_a_single_uppercase_letter() {
+    let solution = "aß\n".parse::<Grid>().unwrap();
+    let err = write_puz(&Puzzle::new(solution)).unwrap_err();
+    assert!(
+        err.to_string().contains("can't hold the letter 'ß'"),
+        "{}",
+        err
+    );
+}
+
+#[test]
+fn text_grids_lowercase_accented_letters() {
+    let grid = "ÉTÉ\nÖ#a\n".parse::<Grid>().unwrap();
+    assert_eq!(grid.to_string(), "été\nö#a\n");
+}